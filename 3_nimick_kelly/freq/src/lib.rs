@@ -0,0 +1,388 @@
+#![allow(unstable)]
+extern crate regex;
+
+use std::ascii::AsciiExt;
+use std::collections::HashMap;
+use std::io::{BufferedReader, File, IoResult};
+use regex::Regex;
+
+#[doc="
+Module: freq
+
+The tokenizer and counter behind the `freq` binary, pulled out into a
+library so other tools in this workspace (e.g. the spelling
+corrector's training step) can count word frequencies the same way
+`freq` does, instead of each maintaining its own slightly different
+copy of this logic.
+"]
+
+/// Remove any preceeding or trailing non a-z or A-Z characters,
+/// and truncates words on non-apostrophe punctuation contained within.
+pub fn trim_to_word(word: &str) -> Option<&str> {
+    let regex = Regex::new("[a-zA-Z]+(\'[a-zA-Z]+)*");
+    let re = match regex {
+        Ok(re)    => re,
+        Err(..)   => panic!("Could not compile regex")
+    };
+    match re.captures(word) {
+        Some(cap)  => Some(cap.at(0).unwrap()),
+        None       => None,
+    }
+}
+
+#[cfg(test)]
+mod trim_to_word_tests {
+    use super::trim_to_word;
+
+    #[test]
+    fn tests() {
+        test_trim_to_word("hello", "hello");
+        test_trim_to_word("Hello,", "Hello");
+        test_trim_to_word("!Hello,", "Hello");
+        test_trim_to_word("won't!", "won't");
+        test_trim_to_word("'won't!'", "won't");
+        test_trim_to_word("\"Hello,\"", "Hello");
+        test_trim_to_word("\"Hello,world\"", "Hello");
+        test_trim_to_word("\"Hello.\"", "Hello");
+        test_trim_to_word("\"won't''!", "won't");
+        test_trim_to_word("\"won't''this!", "won't");
+        test_trim_to_word("'fo'c'sle'!", "fo'c'sle");
+    }
+
+    fn test_trim_to_word(check: &str, expect: &str) {
+        assert_eq!(trim_to_word(check).unwrap(), expect);
+    }
+}
+
+/// Unicode-aware equivalent of `trim_to_word`: extracts the first
+/// maximal run of Unicode letters (rather than just a-zA-Z), applying
+/// the same "apostrophe only counts between two letters" rule, so
+/// words like "café" or "Москва" are recognized as single tokens
+/// instead of being split on the non-ASCII letters the plain regex
+/// tokenizer ignores.
+pub fn trim_to_word_unicode(word: &str) -> Option<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let start = match chars.iter().position(|c| c.is_alphabetic()) {
+        Some(i) => i,
+        None    => return None,
+    };
+    let mut end = start;
+    for i in range(start, chars.len()) {
+        let c = chars[i];
+        if c.is_alphabetic() {
+            end = i + 1;
+        } else if c == '\'' && i + 1 < chars.len() && chars[i + 1].is_alphabetic() {
+            continue;
+        } else {
+            break;
+        }
+    }
+    Some(chars.as_slice().slice(start, end).iter().cloned().collect())
+}
+
+#[cfg(test)]
+mod trim_to_word_unicode_tests {
+    use super::trim_to_word_unicode;
+
+    #[test]
+    fn tests() {
+        test_trim_to_word_unicode("hello", "hello");
+        test_trim_to_word_unicode("Hello,", "Hello");
+        test_trim_to_word_unicode("café!", "café");
+        test_trim_to_word_unicode("\"naïve\"", "naïve");
+        test_trim_to_word_unicode("won't!", "won't");
+        test_trim_to_word_unicode("Москва,", "Москва");
+    }
+
+    #[test]
+    fn test_no_letters() {
+        assert_eq!(trim_to_word_unicode("123"), None);
+    }
+
+    fn test_trim_to_word_unicode(check: &str, expect: &str) {
+        assert_eq!(trim_to_word_unicode(check).unwrap(), expect.to_string());
+    }
+}
+
+/// Given a word and a reference to a HashMap of words to frequencies (usize),
+/// converts the word to lower case and increments its associated frequency
+/// in the map.
+/// If the word is not present, it is added to the map with frequency 1.
+pub fn inc_count(map: &mut HashMap<String, usize>, word: String) {
+    let lower = word.to_ascii_lowercase();
+    match map.get_mut(&lower) {
+        Some(count) => {*count += 1; return;},
+        None => {},
+    }
+    map.insert(lower, 1);
+}
+
+#[cfg(test)]
+mod inc_count_tests {
+    use super::{inc_count};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_inc_count() {
+        let mut map = HashMap::new();
+        inc_count(&mut map, String::from_str("test"));
+        inc_count(&mut map, String::from_str("Test"));
+        inc_count(&mut map, String::from_str("one"));
+        assert!(!map.contains_key(&String::from_str("nope")));
+        assert_eq!(*map.get(& String::from_str("test")).unwrap(), 2);
+        assert_eq!(*map.get(& String::from_str("one")).unwrap(), 1);
+    }
+}
+
+/// Count individual characters rather than whole words, for
+/// cipher-analysis or corpus letter-frequency use cases. With
+/// `letters_only` set, non-alphabetic characters are skipped and
+/// case is folded (so 'A' and 'a' count together); otherwise every
+/// character, including punctuation and whitespace, is counted as
+/// read.
+pub fn count_chars<R: Reader>(mut reader: BufferedReader<R>, letters_only: bool) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for line in reader.lines() {
+        for c in line.unwrap().chars() {
+            if letters_only && !c.is_alphabetic() {
+                continue;
+            }
+            let key = if letters_only {
+                c.to_ascii_lowercase().to_string()
+            } else {
+                c.to_string()
+            };
+            match counts.get_mut(&key) {
+                Some(count) => { *count += 1; continue; },
+                None => {},
+            }
+            counts.insert(key, 1);
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod count_chars_tests {
+    use super::count_chars;
+    use std::collections::HashMap;
+    use std::io::{MemReader, BufferedReader};
+
+    #[test]
+    fn test_count_chars_all() {
+        let counts = count_chars_from("Ab!", false);
+        let mut expected = HashMap::new();
+        expected.insert(String::from_str("A"), 1);
+        expected.insert(String::from_str("b"), 1);
+        expected.insert(String::from_str("!"), 1);
+        assert_eq!(counts, expected);
+    }
+
+    #[test]
+    fn test_count_chars_letters_only() {
+        let counts = count_chars_from("Ab ab!", true);
+        let mut expected = HashMap::new();
+        expected.insert(String::from_str("a"), 2);
+        expected.insert(String::from_str("b"), 2);
+        assert_eq!(counts, expected);
+    }
+
+    fn count_chars_from(input: &str, letters_only: bool) -> HashMap<String, usize> {
+        let bytes = input.to_string().into_bytes();
+        let r: BufferedReader<MemReader> = BufferedReader::new(MemReader::new(bytes));
+        count_chars(r, letters_only)
+    }
+}
+
+/// Write `counts` out to `path` in a compact binary format (a word
+/// count, then each word as a length-prefixed byte string followed
+/// by its count), so a later run can pick up where this one left off
+/// via `load_counts` instead of reprocessing the same input.
+pub fn save_counts(counts: &HashMap<String, usize>, path: &str) -> IoResult<()> {
+    let mut file = try!(File::create(&Path::new(path)));
+    try!(file.write_le_uint(counts.len()));
+    for (word, count) in counts.iter() {
+        let bytes = word.as_bytes();
+        try!(file.write_le_uint(bytes.len()));
+        try!(file.write(bytes));
+        try!(file.write_le_uint(*count));
+    }
+    Ok(())
+}
+
+/// Read counts previously written by `save_counts`.
+pub fn load_counts(path: &str) -> IoResult<HashMap<String, usize>> {
+    let mut file = try!(File::open(&Path::new(path)));
+    let word_count = try!(file.read_le_uint());
+    let mut counts = HashMap::with_capacity(word_count);
+    for _ in range(0, word_count) {
+        let len = try!(file.read_le_uint());
+        let bytes = try!(file.read_exact(len));
+        let word = String::from_utf8(bytes).ok().expect("corrupt state file: word is not valid utf8");
+        let count = try!(file.read_le_uint());
+        counts.insert(word, count);
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod save_load_counts_tests {
+    use super::{save_counts, load_counts};
+    use std::collections::HashMap;
+    use std::io::TempDir;
+
+    #[test]
+    fn test_round_trip() {
+        let mut counts = HashMap::new();
+        counts.insert(String::from_str("the"), 3us);
+        counts.insert(String::from_str("fox"), 1us);
+
+        let dir = TempDir::new("freq_save_load_counts_test").unwrap();
+        let path = dir.path().join("counts.bin");
+        let path_str = path.as_str().unwrap();
+
+        save_counts(&counts, path_str).unwrap();
+        let loaded = load_counts(path_str).unwrap();
+        assert_eq!(loaded, counts);
+    }
+}
+
+/// An accumulating word counter, built up line by line (or merged
+/// from other counters) rather than requiring an entire corpus to be
+/// read into a single HashMap-building pass up front.
+pub struct WordCounter {
+    counts: HashMap<String, usize>,
+}
+
+impl WordCounter {
+    /// An empty counter.
+    pub fn new() -> WordCounter {
+        WordCounter { counts: HashMap::new() }
+    }
+
+    /// Tokenize `line` and count each word it contains, using
+    /// `trim_to_word_unicode` if `unicode` is set, or `trim_to_word`
+    /// otherwise.
+    pub fn observe_line(&mut self, line: &str, unicode: bool) {
+        for word in line.words() {
+            let trimmed = if unicode {
+                trim_to_word_unicode(word)
+            } else {
+                trim_to_word(word).map(|w| w.to_string())
+            };
+            match trimmed {
+                Some(w) => inc_count(&mut self.counts, w),
+                None    => {},
+            }
+        }
+    }
+
+    /// Add `count` more occurrences of `word` directly, bypassing
+    /// tokenization -- used to fold another counter's totals into
+    /// this one.
+    pub fn add(&mut self, word: String, count: usize) {
+        let existing = *self.counts.get(&word).unwrap_or(&0);
+        self.counts.insert(word, existing + count);
+    }
+
+    /// Fold `other`'s counts into this counter.
+    pub fn merge(&mut self, other: &WordCounter) {
+        for (word, count) in other.counts.iter() {
+            self.add(word.clone(), *count);
+        }
+    }
+
+    /// The counts gathered so far.
+    pub fn counts(&self) -> &HashMap<String, usize> {
+        &self.counts
+    }
+
+    /// Consume this counter, returning its counts.
+    pub fn into_counts(self) -> HashMap<String, usize> {
+        self.counts
+    }
+}
+
+#[cfg(test)]
+mod word_counter_tests {
+    use super::WordCounter;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_observe_line() {
+        let mut counter = WordCounter::new();
+        counter.observe_line("Hello, World! Hello again.", false);
+        let mut expected = HashMap::new();
+        expected.insert(String::from_str("hello"), 2);
+        expected.insert(String::from_str("world"), 1);
+        expected.insert(String::from_str("again"), 1);
+        assert_eq!(counter.into_counts(), expected);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = WordCounter::new();
+        a.observe_line("the fox", false);
+        let mut b = WordCounter::new();
+        b.observe_line("the wolf", false);
+        a.merge(&b);
+        let mut expected = HashMap::new();
+        expected.insert(String::from_str("the"), 2);
+        expected.insert(String::from_str("fox"), 1);
+        expected.insert(String::from_str("wolf"), 1);
+        assert_eq!(a.into_counts(), expected);
+    }
+}
+
+/// Reads input from BufferedReader and parses individual words,
+/// then increments their counts accordingly. With `unicode` set,
+/// tokenizes on Unicode letter boundaries via `trim_to_word_unicode`
+/// instead of the default a-zA-Z-only `trim_to_word`.
+/// Returns a HashMap mapping words to their frequencies.
+pub fn parse_lines<R: Reader>(mut reader: BufferedReader<R>, unicode: bool) -> HashMap<String, usize> {
+    let mut counter = WordCounter::new();
+    for line in reader.lines() {
+        counter.observe_line(line.unwrap().as_slice(), unicode);
+    }
+    counter.into_counts()
+}
+
+#[cfg(test)]
+mod parse_lines_tests {
+    use super::{parse_lines};
+    use std::collections::HashMap;
+    use std::io::{MemReader,BufferedReader};
+
+    #[test]
+    fn tests() {
+        let mut expected: HashMap<String, usize> = HashMap::new();
+        expected.insert(String::from_str("hello"), 1);
+        expected.insert(String::from_str("world"), 2);
+        expected.insert(String::from_str("today"), 1);
+        expected.insert(String::from_str("is"), 1);
+        expected.insert(String::from_str("the"), 2);
+        expected.insert(String::from_str("best"), 1);
+        expected.insert(String::from_str("day"), 1);
+        expected.insert(String::from_str("in"), 1);
+        expected.insert(String::from_str("whole"), 1);
+        expected.insert(String::from_str("wide"), 1);
+        parse_lines_expect("Hello, World!\nToday is the best day in the whole-wide World!",
+                           expected);
+    }
+
+    fn parse_lines_expect(input: &str, expected: HashMap<String, usize>) {
+        let bytes = input.to_string().into_bytes();
+        let r: BufferedReader<MemReader> =
+            BufferedReader::new(MemReader::new(bytes));
+        let mut output = parse_lines(r, false);
+        let mut found_keys = Vec::new();
+        for (word, count) in output.iter_mut() {
+            assert!(expected.contains_key(word));
+            match expected.get(word) {
+                    Some(expected_count) => assert_eq!(count, expected_count),
+                    None                 => assert!(false)
+            }
+            found_keys.push(word);
+        }
+    }
+}
@@ -1,10 +1,10 @@
 #![allow(unstable)]
-extern crate regex;
+extern crate freq;
 
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::io::BufferedReader;
-use std::ascii::AsciiExt;
-use regex::Regex;
+use freq::{count_chars, parse_lines, WordCounter};
 
 #[doc="
 Determine the word count of the words passed to stdin.
@@ -19,140 +19,348 @@ Assumptions: Words are compared in a case-insensitive way. Hello == hello.
 
 Output one line per word, with its associated word count next to it.
 Words are not output in any specified order.
+
+With --sort count (the default), words are sorted by descending
+count, breaking ties alphabetically. With --sort alpha, words are
+sorted alphabetically instead. --reverse reverses whichever ordering
+was selected.
+
+If one or more <file> arguments are given, each is counted
+separately and printed under a `==> <file> <==` header, followed by
+a combined `==> TOTAL <==` section summing counts across every file.
+With no <file> arguments, standard input is counted and printed with
+no header, as before.
+
+With --parallel (only meaningful with two or more <file> arguments),
+each file is counted on its own worker thread, and the partial
+per-file HashMaps are merged once every thread finishes, rather than
+counting files one at a time on the main thread.
+
+With --unicode, words are segmented by Unicode letter boundaries
+(covering diacritics and non-Latin scripts, e.g. \"café\", \"Москва\")
+instead of the default a-zA-Z-only regex tokenizer.
+
+With --min-count K, words occurring fewer than K times are omitted
+from the output, which keeps reports readable for large corpora
+dominated by words that only appear once or twice.
+
+With --histogram, each word's count is rendered as a scaled ASCII
+bar instead of a bare number, sized to fit the terminal's width (read
+from the `COLUMNS` environment variable, falling back to 80 columns).
+
+With --interval N, standard input is treated as a never-ending stream
+(e.g. a tailed log) rather than something that will eventually hit
+EOF: every N seconds the cumulative counts gathered so far are
+printed, and the program keeps reading rather than exiting. Not
+compatible with <file> arguments.
+
+With --chars, individual characters are counted instead of whole
+words, which is useful for cipher and corpus letter-frequency
+analysis. With --letters-only (only meaningful with --chars),
+non-alphabetic characters are skipped and case is folded, so 'A' and
+'a' are counted together.
+
+With --save <path>, the final counts (the aggregate, when <file>
+arguments are given) are written to <path> in a compact binary
+format. With --load <path>, counts previously written by --save are
+read back and added to this run's counts before they are printed
+(when <file> arguments are given, only the `TOTAL` section includes
+them; the per-file sections show this run's counts alone),
+so repeated invocations (e.g. nightly log processing) can accumulate
+counts across runs without reprocessing old input.
+
+Usage: ./freq [--sort count|alpha] [--reverse] [--parallel] [--unicode] [--min-count K] [--histogram] [--interval N] [--chars] [--letters-only] [--save path] [--load path] [<file>...]
 "]
 #[cfg(not(test))]
 fn main() {
     use std::io;
     use std::io::stdio::StdinReader;
+    use std::os;
+
+    let args = os::args();
+    let sort_mode = extract_flag_value(&args, "--sort").unwrap_or(String::from_str("count"));
+    let reverse = args.iter().any(|a| a.as_slice() == "--reverse");
+    let unicode = args.iter().any(|a| a.as_slice() == "--unicode");
+    let histogram = args.iter().any(|a| a.as_slice() == "--histogram");
+    let chars = args.iter().any(|a| a.as_slice() == "--chars");
+    let letters_only = args.iter().any(|a| a.as_slice() == "--letters-only");
+    let min_count: usize = extract_flag_value(&args, "--min-count")
+        .map(|s| s.parse().expect("--min-count must be a non-negative integer"))
+        .unwrap_or(0);
+    let save_path = extract_flag_value(&args, "--save");
+    let load_path = extract_flag_value(&args, "--load");
 
-    let stdin: BufferedReader<StdinReader> = BufferedReader::new(io::stdin());
-    let word_count = parse_lines(stdin);
-    for (word, count) in word_count.iter() {
-        println!("{}: {}", word, count);
+    let parallel = args.iter().any(|a| a.as_slice() == "--parallel");
+    let mut file_args: Vec<&String> = Vec::new();
+    let mut skip_next = false;
+    for arg in args.iter().skip(1) {
+        if skip_next { skip_next = false; continue; }
+        match arg.as_slice() {
+            "--reverse" | "--parallel" | "--unicode" | "--histogram" | "--chars" | "--letters-only" => {},
+            "--sort" | "--min-count" | "--interval" | "--save" | "--load" => { skip_next = true; },
+            _ => file_args.push(arg)
+        }
+    }
+
+    match extract_flag_value(&args, "--interval") {
+        Some(interval_str) => {
+            let interval_secs: f64 = interval_str.parse().expect("--interval must be a number of seconds");
+            run_streaming(interval_secs, unicode, sort_mode, reverse, min_count, histogram);
+            return;
+        },
+        None => {},
+    }
+
+    if file_args.is_empty() {
+        let stdin: BufferedReader<StdinReader> = BufferedReader::new(io::stdin());
+        let mut word_count = count_input(stdin, unicode, chars, letters_only);
+        merge_loaded_state(&mut word_count, &load_path);
+        save_state(&word_count, &save_path);
+        print_counts(&word_count, sort_mode.as_slice(), reverse, min_count, histogram);
+        return;
     }
-}
 
-/// Remove any preceeding or trailing non a-z or A-Z characters,
-/// and truncates words on non-apostrophe punctuation contained within.
-fn trim_to_word(word: &str) -> Option<&str> {
-    let regex = Regex::new("[a-zA-Z]+(\'[a-zA-Z]+)*");
-    let re = match regex {
-        Ok(re)    => re,
-        Err(..)   => panic!("Could not compile regex")
+    let per_file_counts = if parallel {
+        count_files_parallel(&file_args, unicode, chars, letters_only)
+    } else {
+        file_args.iter().map(|path| {
+            (path.to_string(), count_input(open_file(path.as_slice()), unicode, chars, letters_only))
+        }).collect()
     };
-    match re.captures(word) {
-        Some(cap)  => Some(cap.at(0).unwrap()),
-        None       => None,
+
+    let mut total = WordCounter::new();
+    for &(ref path, ref word_count) in per_file_counts.iter() {
+        println!("==> {} <==", path);
+        print_counts(word_count, sort_mode.as_slice(), reverse, min_count, histogram);
+        println!("");
+        for (word, count) in word_count.iter() {
+            total.add(word.clone(), *count);
+        }
     }
+    let mut totals = total.into_counts();
+    merge_loaded_state(&mut totals, &load_path);
+    save_state(&totals, &save_path);
+    println!("==> TOTAL <==");
+    print_counts(&totals, sort_mode.as_slice(), reverse, min_count, histogram);
 }
 
-#[cfg(test)]
-mod trim_to_word_tests {
-    use super::trim_to_word;
+/// If `load_path` is set, read counts previously written by
+/// `--save` and add them into `counts`, so this run's totals build
+/// on top of earlier runs instead of replacing them.
+fn merge_loaded_state(counts: &mut HashMap<String, usize>, load_path: &Option<String>) {
+    match *load_path {
+        Some(ref path) => {
+            let loaded = freq::load_counts(path.as_slice()).ok().expect("couldn't load state file");
+            for (word, count) in loaded.iter() {
+                let existing = *counts.get(word).unwrap_or(&0);
+                counts.insert(word.clone(), existing + *count);
+            }
+        },
+        None => {},
+    }
+}
 
-    #[test]
-    fn tests() {
-        test_trim_to_word("hello", "hello");
-        test_trim_to_word("Hello,", "Hello");
-        test_trim_to_word("!Hello,", "Hello");
-        test_trim_to_word("won't!", "won't");
-        test_trim_to_word("'won't!'", "won't");
-        test_trim_to_word("\"Hello,\"", "Hello");
-        test_trim_to_word("\"Hello,world\"", "Hello");
-        test_trim_to_word("\"Hello.\"", "Hello");
-        test_trim_to_word("\"won't''!", "won't");
-        test_trim_to_word("\"won't''this!", "won't");
-        test_trim_to_word("'fo'c'sle'!", "fo'c'sle");
-    }
-
-    fn test_trim_to_word(check: &str, expect: &str) {
-        assert_eq!(trim_to_word(check).unwrap(), expect);
+/// If `save_path` is set, write `counts` out so a later run can pick
+/// them up with `--load`.
+fn save_state(counts: &HashMap<String, usize>, save_path: &Option<String>) {
+    match *save_path {
+        Some(ref path) => {
+            freq::save_counts(counts, path.as_slice()).ok().expect("couldn't save state file");
+        },
+        None => {},
     }
 }
 
-/// Reads input from BufferedReader and parses individual words,
-/// then increments their counts accordingly.
-/// Returns a HashMap mapping words to their frequencies.
-fn parse_lines<R: Reader>(mut reader: BufferedReader<R>) -> HashMap<String, usize> {
-    let mut wordcounts: HashMap<String, usize> = HashMap::new();
-    for line in reader.lines() {
-        let l = line.unwrap();
-        for word in l.words() {
-            match trim_to_word(word) {
-                Some(w) => inc_count(&mut wordcounts, String::from_str(w)),
-                None    => (),
-            }
+/// Count `reader`'s contents as whole words via `parse_lines`, or,
+/// with `chars` set, as individual characters via `count_chars`
+/// (honoring `letters_only` in that case).
+fn count_input<R: Reader>(reader: BufferedReader<R>, unicode: bool, chars: bool, letters_only: bool) -> HashMap<String, usize> {
+    if chars {
+        count_chars(reader, letters_only)
+    } else {
+        parse_lines(reader, unicode)
+    }
+}
+
+/// Read standard input forever, printing the cumulative counts
+/// gathered so far every `interval_secs` seconds instead of waiting
+/// for EOF, so `freq` can be left attached to a never-ending pipe
+/// (e.g. a tailed log) and report on it periodically.
+fn run_streaming(interval_secs: f64, unicode: bool, sort_mode: String, reverse: bool, min_count: usize, histogram: bool) {
+    use std::io;
+    use std::io::stdio::StdinReader;
+    use std::io::timer::Timer;
+    use std::sync::{Arc, Mutex};
+    use std::thread::Thread;
+    use std::time::Duration;
+
+    let counter = Arc::new(Mutex::new(WordCounter::new()));
+    let reader_counter = counter.clone();
+    Thread::spawn(move || {
+        let mut stdin: BufferedReader<StdinReader> = BufferedReader::new(io::stdin());
+        for maybe_line in stdin.lines() {
+            let line = match maybe_line {
+                Ok(line) => line,
+                Err(..)  => break,
+            };
+            reader_counter.lock().unwrap().observe_line(line.as_slice(), unicode);
         }
+    });
+
+    let mut timer = Timer::new().unwrap();
+    let ticks = timer.periodic(Duration::milliseconds((interval_secs * 1000f64) as i64));
+    loop {
+        ticks.recv().unwrap();
+        let snapshot = counter.lock().unwrap().counts().clone();
+        print_counts(&snapshot, sort_mode.as_slice(), reverse, min_count, histogram);
+        println!("");
     }
-    wordcounts
 }
 
-#[cfg(test)]
-mod parse_lines_tests {
-    use super::{parse_lines};
-    use std::collections::HashMap;
-    use std::io::{MemReader,BufferedReader};
+/// Count each file in `paths` on its own worker thread, returning
+/// `(path, counts)` pairs in the same order as `paths` once every
+/// thread has finished. Lets counting multi-hundred-MB corpora scale
+/// with the number of cores instead of processing files one at a
+/// time on the main thread.
+fn count_files_parallel(paths: &Vec<&String>, unicode: bool, chars: bool, letters_only: bool) -> Vec<(String, HashMap<String, usize>)> {
+    use std::thread::Thread;
 
-    #[test]
-    fn tests() {
-        let mut expected: HashMap<String, usize> = HashMap::new();
-        expected.insert(String::from_str("hello"), 1);
-        expected.insert(String::from_str("world"), 2);
-        expected.insert(String::from_str("today"), 1);
-        expected.insert(String::from_str("is"), 1);
-        expected.insert(String::from_str("the"), 2);
-        expected.insert(String::from_str("best"), 1);
-        expected.insert(String::from_str("day"), 1);
-        expected.insert(String::from_str("in"), 1);
-        expected.insert(String::from_str("whole"), 1);
-        expected.insert(String::from_str("wide"), 1);
-        parse_lines_expect("Hello, World!\nToday is the best day in the whole-wide World!",
-                           expected);
-    }
-
-    fn parse_lines_expect(input: &str, expected: HashMap<String, usize>) {
-        let bytes = input.to_string().into_bytes();
-        let r: BufferedReader<MemReader> =
-            BufferedReader::new(MemReader::new(bytes));
-        let mut output = parse_lines(r);
-        let mut found_keys = Vec::new();
-        for (word, count) in output.iter_mut() {
-            assert!(expected.contains_key(word));
-            match expected.get(word) {
-                    Some(expected_count) => assert_eq!(count, expected_count),
-                    None                 => assert!(false)
-            }
-            found_keys.push(word);
+    let guards: Vec<_> = paths.iter().map(|path| {
+        let owned_path = path.to_string();
+        Thread::spawn(move || {
+            let counts = count_input(open_file(owned_path.as_slice()), unicode, chars, letters_only);
+            (owned_path, counts)
+        })
+    }).collect();
+    guards.into_iter().map(|guard| guard.join().unwrap()).collect()
+}
+
+/// Open `filename` for reading, panicking if it cannot be opened.
+fn open_file(filename: &str) -> BufferedReader<std::io::File> {
+    let file = std::io::File::open(&Path::new(filename));
+    BufferedReader::new(file.ok().expect("couldn't open file"))
+}
+
+/// Print `counts` sorted per `sort_mode`/`reverse`, one `word: count`
+/// line each, as `main` has always done for standard input. Words
+/// occurring fewer than `min_count` times are omitted. With
+/// `histogram` set, each line is rendered as a scaled ASCII bar via
+/// `print_histogram` instead.
+fn print_counts(counts: &HashMap<String, usize>, sort_mode: &str, reverse: bool, min_count: usize, histogram: bool) {
+    let entries: Vec<(String, usize)> = sorted_counts(counts, sort_mode, reverse).into_iter()
+        .filter(|&(_, count)| count >= min_count)
+        .collect();
+    if histogram {
+        print_histogram(&entries);
+    } else {
+        for &(ref word, count) in entries.iter() {
+            println!("{}: {}", word, count);
         }
     }
 }
 
-/// Given a word and a reference to a HashMap of words to frequencies (usize),
-/// converts the word to lower case and increments its associated frequency
-/// in the map.
-/// If the word is not present, it is added to the map with frequency 1.
-fn inc_count(map: &mut HashMap<String, usize>, word: String) {
-    let lower = word.to_ascii_lowercase();
-    match map.get_mut(&lower) {
-        Some(count) => {*count += 1; return;},
-        None => {},
+/// The width of the attached terminal, read from the `COLUMNS`
+/// environment variable, falling back to 80 columns if unset or
+/// unparsable.
+fn terminal_width() -> usize {
+    std::os::getenv("COLUMNS")
+        .and_then(|s| s.as_slice().parse().ok())
+        .unwrap_or(80)
+}
+
+/// Render `entries` as a bar chart, one word per line, with each
+/// bar's length scaled so the longest bar (the highest count) fills
+/// the remaining space on a line of `terminal_width()` columns after
+/// the word label and count.
+fn print_histogram(entries: &Vec<(String, usize)>) {
+    let width = terminal_width();
+    let label_width = entries.iter().map(|&(ref w, _)| w.len()).max().unwrap_or(0);
+    let max_count = entries.iter().map(|&(_, c)| c).max().unwrap_or(1);
+    let reserved = label_width + 10; // label + ": " + up to 7 digits of count + " "
+    let max_bar_width = if width > reserved { width - reserved } else { 1 };
+    for &(ref word, count) in entries.iter() {
+        let bar_len = if max_count == 0 { 0 } else {
+            ((count * max_bar_width) / max_count).max(1)
+        };
+        let bar: String = std::iter::repeat('#').take(bar_len).collect();
+        println!("{}: {} {}", pad_right(word.as_slice(), label_width), count, bar);
+    }
+}
+
+/// Pad `s` with trailing spaces out to `width` characters.
+fn pad_right(s: &str, width: usize) -> String {
+    let mut padded = s.to_string();
+    while padded.len() < width {
+        padded.push(' ');
     }
-    map.insert(lower, 1);
+    padded
+}
+
+/// Find `--flag <value>` in `args` and return `value`, if present.
+fn extract_flag_value(args: &Vec<String>, flag: &str) -> Option<String> {
+    for i in range(0, args.len()) {
+        if args[i].as_slice() == flag && i + 1 < args.len() {
+            return Some(args[i + 1].clone());
+        }
+    }
+    None
+}
+
+/// Sort `counts` into a Vec of (word, count) pairs according to
+/// `sort_mode` ("count" for descending count with alphabetical
+/// tie-breaking, "alpha" for alphabetical), then reverse the result
+/// if `reverse` is set.
+fn sorted_counts(counts: &HashMap<String, usize>, sort_mode: &str, reverse: bool) -> Vec<(String, usize)> {
+    let mut entries: Vec<(String, usize)> = counts.iter().map(|(w, c)| (w.clone(), *c)).collect();
+    match sort_mode {
+        "alpha" => entries.sort_by(|a, b| a.0.cmp(&b.0)),
+        _ => entries.sort_by(|a, b| {
+            let by_count = b.1.cmp(&a.1);
+            if by_count == Ordering::Equal { a.0.cmp(&b.0) } else { by_count }
+        }),
+    }
+    if reverse {
+        entries.reverse();
+    }
+    entries
 }
 
 #[cfg(test)]
-mod inc_count_tests {
-    use super::{inc_count};
+mod sorted_counts_tests {
+    use super::sorted_counts;
     use std::collections::HashMap;
 
     #[test]
-    fn test_inc_count() {
-        let mut map = HashMap::new();
-        inc_count(&mut map, String::from_str("test"));
-        inc_count(&mut map, String::from_str("Test"));
-        inc_count(&mut map, String::from_str("one"));
-        assert!(!map.contains_key(&String::from_str("nope")));
-        assert_eq!(*map.get(& String::from_str("test")).unwrap(), 2);
-        assert_eq!(*map.get(& String::from_str("one")).unwrap(), 1);
+    fn test_sort_by_count_default() {
+        let counts = sample_counts();
+        assert_eq!(sorted_counts(&counts, "count", false),
+                   vec![(strr("the"), 3), (strr("a"), 2), (strr("fox"), 1), (strr("wolf"), 1)]);
+    }
+
+    #[test]
+    fn test_sort_by_count_reversed() {
+        let counts = sample_counts();
+        assert_eq!(sorted_counts(&counts, "count", true),
+                   vec![(strr("wolf"), 1), (strr("fox"), 1), (strr("a"), 2), (strr("the"), 3)]);
+    }
+
+    #[test]
+    fn test_sort_alpha() {
+        let counts = sample_counts();
+        assert_eq!(sorted_counts(&counts, "alpha", false),
+                   vec![(strr("a"), 2), (strr("fox"), 1), (strr("the"), 3), (strr("wolf"), 1)]);
+    }
+
+    fn sample_counts() -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        counts.insert(strr("the"), 3);
+        counts.insert(strr("a"), 2);
+        counts.insert(strr("fox"), 1);
+        counts.insert(strr("wolf"), 1);
+        counts
+    }
+
+    fn strr(string: &str) -> String {
+        String::from_str(string)
     }
 }
@@ -17,21 +17,257 @@ Assumptions: The training file has no misspelled words
 "]
 
 extern crate regex;
+extern crate time;
+
+mod ondisk;
+mod sketch;
 
 use regex::Regex;
 use std::ascii::AsciiExt;
 use std::collections::{HashSet, HashMap};
-use std::io::{File, BufferedReader};
+use std::io::{File, BufferedReader, IoResult};
 use std::iter::IteratorExt;
 
 static NO_SPELLING_SUGGESTION: &'static str = "-";
 static ALPHABET: &'static str = "abcdefghijklmnopqrstuvwxyz";
 
-#[doc="
-    Usage: ./spelling_corrector <training_file>
+/// The smoothing strategy applied to trained counts when scoring
+/// candidate suggestions. Unsmoothed counts over-trust words that
+/// happened to appear only once or twice in the training corpus.
+#[derive(Show, Copy)]
+pub enum Smoothing {
+    /// Use the raw trained count with no adjustment.
+    None,
+    /// Add-k (Laplace-style) smoothing: adds `k` to every count and
+    /// renormalizes over the size of the vocabulary.
+    AddK(f64),
+}
+
+/// A trained dictionary of word counts, together with the smoothing
+/// method used to score candidates. Replaces passing a bare
+/// `HashMap<String, usize>` around so construction-time options (like
+/// smoothing) have a natural home.
+pub struct Dictionary {
+    counts: HashMap<String, usize>,
+    total: usize,
+    smoothing: Smoothing,
+    min_frequency: usize,
+    alphabet: String,
+    prefixes: HashSet<String>,
+}
+
+impl Dictionary {
+    /// Build a Dictionary from trained counts, using the given
+    /// smoothing method when scoring candidates. Words trained fewer
+    /// than `min_frequency` times are still tracked for statistics
+    /// (score, total) but are not considered "known" -- this keeps
+    /// one-off corpus typos from being treated as correct spellings.
+    /// Candidate generation (insertions/replacements) defaults to the
+    /// 26-letter English `ALPHABET`; use `with_alphabet` to override
+    /// this for other languages.
+    pub fn new(counts: HashMap<String, usize>, smoothing: Smoothing,
+               min_frequency: usize) -> Dictionary {
+        let total = counts.values().fold(0, |acc, &c| acc + c);
+        let prefixes = counts.keys().map(|w| Dictionary::prefix_of(w.as_slice())).collect();
+        Dictionary { counts: counts, total: total, smoothing: smoothing,
+                     min_frequency: min_frequency, alphabet: String::from_str(ALPHABET),
+                     prefixes: prefixes }
+    }
+
+    /// Override the alphabet used for candidate generation, e.g. to
+    /// add diacritics for a non-English training corpus.
+    pub fn with_alphabet(mut self, alphabet: &str) -> Dictionary {
+        self.alphabet = String::from_str(alphabet);
+        self
+    }
+
+    /// The alphabet used to generate insertion/replacement candidates
+    /// for this dictionary.
+    fn alphabet(&self) -> &str {
+        self.alphabet.as_slice()
+    }
+
+    /// The fixed-length prefix used by `has_known_prefix`: `word`'s
+    /// first 3 characters, or the whole word if it's shorter.
+    fn prefix_of(word: &str) -> String {
+        word.chars().take(3).collect()
+    }
+
+    /// True if some known word shares `word`'s leading 3 characters
+    /// (or `word`'s full length, if shorter). Used to cheaply prune
+    /// edit_1 candidates during edit-distance-2 generation that have
+    /// no chance of being edit-distance 1 away from anything known.
+    fn has_known_prefix(&self, word: &str) -> bool {
+        self.prefixes.contains(&Dictionary::prefix_of(word))
+    }
+
+    /// Returns true if the word was seen during training at least
+    /// `min_frequency` times, i.e. whether it counts as "known".
+    fn contains_key(&self, word: &String) -> bool {
+        self.raw_count(word) >= self.min_frequency && self.counts.contains_key(word)
+    }
+
+    /// The raw, unsmoothed training count for a word (0 if unseen).
+    fn raw_count(&self, word: &String) -> usize {
+        *self.counts.get(word).unwrap_or(&0)
+    }
+
+    /// The smoothed score used to rank candidate suggestions against
+    /// one another. Higher is more likely to be the intended word.
+    fn score(&self, word: &String) -> f64 {
+        let count = self.raw_count(word);
+        match self.smoothing {
+            Smoothing::None => count as f64,
+            Smoothing::AddK(k) => {
+                (count as f64 + k) / (self.total as f64 + k * self.counts.len() as f64)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod dictionary_tests {
+    use super::{Dictionary, Smoothing};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_score_none() {
+        let mut counts = HashMap::new();
+        counts.insert(String::from_str("hello"), 3);
+        let dict = Dictionary::new(counts, Smoothing::None, 0);
+        assert_eq!(dict.score(&String::from_str("hello")), 3f64);
+        assert_eq!(dict.score(&String::from_str("missing")), 0f64);
+    }
+
+    #[test]
+    fn test_score_add_k() {
+        let mut counts = HashMap::new();
+        counts.insert(String::from_str("hello"), 1);
+        counts.insert(String::from_str("world"), 9);
+        let dict = Dictionary::new(counts, Smoothing::AddK(1f64), 2);
+        // total = 10, vocab = 2, k = 1 => denom = 12
+        assert_eq!(dict.score(&String::from_str("hello")), 2f64 / 12f64);
+        assert_eq!(dict.score(&String::from_str("missing")), 1f64 / 12f64);
+    }
+
+    #[test]
+    fn test_min_frequency() {
+        let mut counts = HashMap::new();
+        counts.insert(String::from_str("hte"), 1);
+        counts.insert(String::from_str("the"), 50);
+        let dict = Dictionary::new(counts, Smoothing::None, 2);
+        assert!(!dict.contains_key(&String::from_str("hte")));
+        assert!(dict.contains_key(&String::from_str("the")));
+        // still tracked for statistics even though it's filtered from "known"
+        assert_eq!(dict.raw_count(&String::from_str("hte")), 1);
+    }
+}
+
+/// A named collection of Dictionaries -- one per language or domain --
+/// selected per input line via a leading `"<name>: "` tag (e.g. `"fr:
+/// bonjoor"`). Lines with no recognized tag fall back to `default`.
+pub struct Dictionaries {
+    default: String,
+    dictionaries: HashMap<String, Dictionary>,
+}
+
+impl Dictionaries {
+    pub fn new(default: String, dictionaries: HashMap<String, Dictionary>) -> Dictionaries {
+        Dictionaries { default: default, dictionaries: dictionaries }
+    }
+
+    /// Split a leading `"<name>: "` tag off of `line` if `name` names a
+    /// known dictionary, returning the tagged name and the remainder of
+    /// the line with the tag stripped. If `line` has no recognized tag,
+    /// returns `default` and `line` unchanged.
+    fn split_prefix<'a>(&self, line: &'a str) -> (&str, &'a str) {
+        match line.find(':') {
+            Some(i) => {
+                let name = line.slice_to(i).trim();
+                if self.dictionaries.contains_key(&String::from_str(name)) {
+                    (name, line.slice_from(i + 1).trim_left())
+                } else {
+                    (self.default.as_slice(), line)
+                }
+            },
+            None => (self.default.as_slice(), line)
+        }
+    }
+
+    /// The dictionary selected by `line`'s language tag (or the
+    /// `default` dictionary if untagged), together with the remainder
+    /// of `line` with the tag stripped.
+    pub fn select<'a>(&self, line: &'a str) -> (&Dictionary, &'a str) {
+        let (name, rest) = self.split_prefix(line);
+        let dict = self.dictionaries.get(name)
+            .unwrap_or_else(|| self.dictionaries.get(self.default.as_slice())
+                .expect("default dictionary must exist"));
+        (dict, rest)
+    }
+}
 
-    Words input on standard input will be followed by an output
-    in the following format:
+#[cfg(test)]
+mod dictionaries_test {
+    use super::{Dictionaries, Dictionary, Smoothing};
+    use std::collections::HashMap;
+
+    fn build() -> Dictionaries {
+        let mut en_counts = HashMap::new();
+        en_counts.insert(String::from_str("hello"), 3);
+        let mut fr_counts = HashMap::new();
+        fr_counts.insert(String::from_str("bonjour"), 3);
+        let mut dictionaries = HashMap::new();
+        dictionaries.insert(String::from_str("en"), Dictionary::new(en_counts, Smoothing::None, 0));
+        dictionaries.insert(String::from_str("fr"), Dictionary::new(fr_counts, Smoothing::None, 0)
+            .with_alphabet("abcdefghijklmnopqrstuvwxyzàâéèêëîïôùûüç"));
+        Dictionaries::new(String::from_str("en"), dictionaries)
+    }
+
+    #[test]
+    fn test_select_tagged() {
+        let dictionaries = build();
+        let (dict, rest) = dictionaries.select("fr: bonjour");
+        assert_eq!(rest, "bonjour");
+        assert!(dict.contains_key(&String::from_str("bonjour")));
+    }
+
+    #[test]
+    fn test_select_untagged_falls_back_to_default() {
+        let dictionaries = build();
+        let (dict, rest) = dictionaries.select("hello");
+        assert_eq!(rest, "hello");
+        assert!(dict.contains_key(&String::from_str("hello")));
+    }
+
+    #[test]
+    fn test_select_unknown_tag_treated_as_untagged() {
+        let dictionaries = build();
+        let (dict, rest) = dictionaries.select("de: hallo");
+        assert_eq!(rest, "de: hallo");
+        assert!(!dict.contains_key(&String::from_str("hallo")));
+    }
+}
+
+#[doc="
+    Usage: ./spelling_corrector <dictionary>... [--filter] [--explain]
+
+    Each <dictionary> is either a bare training file (the classic,
+    single-language invocation) or a `<name>:<training_file>` or
+    `<name>:<training_file>:<alphabet>` triple for multi-language use.
+    The first dictionary given is the default, used for any input line
+    with no recognized `<name>: ` prefix. Input lines may select a
+    non-default dictionary with a leading tag, e.g. `fr: bonjoor`.
+    A training file ending in `.dic` is read as a hunspell-style word
+    list (a leading word-count line, then one `word` or `word/FLAGS`
+    per line, with affix flags discarded) instead of free-form prose.
+
+    With --export <path> (mutually exclusive with everything else),
+    the default dictionary's trained counts are written to <path> as
+    a plain `<word> <count>` word list, one per line, instead of
+    reading standard input.
+
+    Without --filter, words input on standard input will be followed
+    by an output in the following format:
 
     <word>
         * If the word is spelled correctly
@@ -39,6 +275,36 @@ static ALPHABET: &'static str = "abcdefghijklmnopqrstuvwxyz";
         * If the word is spelled incorrectly, but there are no suggestions
     <word>, <suggestion>
         * If the word is spelled incorrectly, and there is a suggestion
+
+    With --filter, the program acts as a text filter: arbitrary prose
+    on standard input is echoed to standard output with misspelled
+    words replaced in place by their best suggestion, suitable for
+    piping.
+
+    With --explain (ignored under --filter), each suggestion is
+    followed by a parenthesized description of the edit that produced
+    it, e.g. `realy, really (insert 'l' at position 3)`.
+
+    With --format \"<template>\" (ignored under --filter), each output
+    line is rendered from the given template instead of the default
+    shape above, substituting {word}, {status} (\"correct\" or
+    \"misspelled\"), {suggestion} (empty if none), and
+    {suggestion_suffix} (\", <suggestion>\", or empty if none), e.g.
+    --format \"{word}\\t{status}\\t{suggestion}\".
+
+    With -a, the program speaks the ispell/aspell pipe protocol instead:
+    each input line is checked word by word, and for every word a
+    status line is written to standard output -- `*` if correct, `&
+    <word> <count> <offset>: <guess1>, <guess2>, ...` if misspelled
+    with near-miss suggestions, or `# <word> <offset>` if misspelled
+    with none -- followed by a blank line marking the end of the
+    checked input line. This lets editors like Vim and Emacs drive the
+    corrector as a drop-in ispell/aspell backend.
+
+    Usage: ./spelling_corrector --benchmark <training_file> <word>...
+
+    Times edits_2 on each given word, to demonstrate its deduplication
+    and known-prefix pruning against long, heavily-misspelled input.
 "]
 #[cfg(not(test))]
 fn main() {
@@ -47,23 +313,571 @@ fn main() {
     use std::io::stdio::StdinReader;
 
     let args = os::args();
-    let training_file = match args.iter().skip(1).take(1).next() {
-        Some(file) => file.as_slice(),
-        None       => panic!("Must provide training file")
-    };
-    let file_reader = open_file(training_file);
-    let dictionary = train(file_reader);
+    if args.iter().any(|a| a.as_slice() == "--benchmark") {
+        run_benchmark(&args);
+        return;
+    }
+    let filter_mode = args.iter().any(|a| a.as_slice() == "--filter");
+    let explain_mode = args.iter().any(|a| a.as_slice() == "--explain");
+    let ispell_mode = args.iter().any(|a| a.as_slice() == "-a");
+    let format_template = extract_flag_value(&args, "--format")
+        .unwrap_or(String::from_str(DEFAULT_FORMAT));
+
+    let mut dict_args: Vec<&String> = Vec::new();
+    let mut skip_next = false;
+    for arg in args.iter().skip(1) {
+        if skip_next { skip_next = false; continue; }
+        match arg.as_slice() {
+            "--filter" | "--explain" | "-a" => {},
+            "--format" | "--export" => { skip_next = true; },
+            _ => dict_args.push(arg)
+        }
+    }
+    if dict_args.is_empty() {
+        panic!("Must provide training file");
+    }
+
+    let mut default_name = String::new();
+    let mut dictionaries: HashMap<String, Dictionary> = HashMap::new();
+    let mut bigram_counts: HashMap<(String, String), usize> = HashMap::new();
+    for (i, arg) in dict_args.iter().enumerate() {
+        let (name, path, alphabet) = parse_dictionary_arg(arg.as_slice());
+        let is_hunspell_dic = path.as_slice().ends_with(".dic");
+        let counts = if is_hunspell_dic {
+            train_hunspell_dic(open_file(path.as_slice()))
+        } else {
+            train(open_file(path.as_slice()), &HashSet::new())
+        };
+        let dict = Dictionary::new(counts, Smoothing::AddK(1f64), 2).with_alphabet(alphabet.as_slice());
+        if i == 0 { default_name = name.clone(); }
+        dictionaries.insert(name, dict);
+        if !is_hunspell_dic {
+            for (pair, count) in train_bigrams(open_file(path.as_slice())).into_iter() {
+                let existing = *bigram_counts.get(&pair).unwrap_or(&0);
+                bigram_counts.insert(pair, existing + count);
+            }
+        }
+    }
+    let dictionaries = Dictionaries::new(default_name, dictionaries);
+    let bigrams = BigramModel::new(bigram_counts);
+
+    if let Some(export_path) = extract_flag_value(&args, "--export") {
+        let (default_dict, _) = dictionaries.select("");
+        export_word_list(default_dict, export_path.as_slice())
+            .ok().expect("could not export dictionary");
+        return;
+    }
+
     let mut stdin: BufferedReader<StdinReader> = BufferedReader::new(io::stdin());
+
+    if filter_mode {
+        for maybe_line in stdin.lines() {
+            let line = maybe_line.ok().unwrap();
+            let (dict, rest) = dictionaries.select(line.as_slice());
+            print!("{}", filter_text(rest, dict, &bigrams));
+        }
+        return;
+    }
+
+    if ispell_mode {
+        println!("@(#) International Ispell Version 3.1.20 (but really spelling_corrector)");
+        for maybe_line in stdin.lines() {
+            let line = maybe_line.ok().unwrap();
+            let (dict, rest) = dictionaries.select(line.as_slice());
+            print!("{}", ispell_line(rest, dict));
+        }
+        return;
+    }
+
     for maybe_word in stdin.lines() {
-        let word = maybe_word.ok().unwrap().to_ascii_lowercase();
+        let raw = maybe_word.ok().unwrap();
+        let (dict, rest) = dictionaries.select(raw.trim());
+        let trimmed = rest.trim();
+        if looks_like_proper_noun_or_acronym(trimmed, dict) {
+            let no_suggestion = Some(String::from_str(NO_SPELLING_SUGGESTION));
+            println!("{}", render_output(format_template.as_slice(),
+                                         trimmed.to_ascii_lowercase().as_slice(), &no_suggestion));
+            continue;
+        }
+        let word = rest.to_ascii_lowercase();
         let w = String::from_str(word.trim());
-        match suggest(w.clone(), &dictionary) {
-            Some(correction) => println!("{}, {}", w, correction),
-            None             => println!("{}", w)
+        if explain_mode {
+            match suggest_explained(w.clone(), dict) {
+                Some((correction, explanation)) => println!("{}, {} ({})", w, correction, explanation),
+                None                             => println!("{}", w)
+            }
+        } else {
+            let suggestion = suggest(w.clone(), dict);
+            println!("{}", render_output(format_template.as_slice(), w.as_slice(), &suggestion));
         }
     }
 }
 
+/// The default output template, reproducing the historical
+/// `<word>` / `<word>, <suggestion>` output exactly.
+static DEFAULT_FORMAT: &'static str = "{word}{suggestion_suffix}";
+
+/// Find `--flag <value>` in `args` and return `value`, if present.
+fn extract_flag_value(args: &Vec<String>, flag: &str) -> Option<String> {
+    for i in range(0, args.len()) {
+        if args[i].as_slice() == flag && i + 1 < args.len() {
+            return Some(args[i + 1].clone());
+        }
+    }
+    None
+}
+
+/// Render one output line for `word` by substituting `{word}`,
+/// `{status}` ("correct" or "misspelled"), `{suggestion}` (empty if
+/// none), and `{suggestion_suffix}` (", <suggestion>", or empty if
+/// none) into `template`. Lets downstream scripts pick their own
+/// output shape (e.g. `--format "{word}\t{status}\t{suggestion}"`)
+/// instead of being stuck with the hard-coded default.
+fn render_output(template: &str, word: &str, suggestion: &Option<String>) -> String {
+    let status = if suggestion.is_some() { "misspelled" } else { "correct" };
+    let suggestion_str = match *suggestion {
+        Some(ref s) => s.as_slice(),
+        None => ""
+    };
+    let suggestion_suffix = match *suggestion {
+        Some(ref s) => format!(", {}", s),
+        None => String::new()
+    };
+    template.replace("{word}", word)
+            .replace("{status}", status)
+            .replace("{suggestion}", suggestion_str)
+            .replace("{suggestion_suffix}", suggestion_suffix.as_slice())
+}
+
+#[cfg(test)]
+mod render_output_test {
+    use super::{render_output, DEFAULT_FORMAT};
+
+    #[test]
+    fn test_default_format_matches_historical_output() {
+        assert_eq!(render_output(DEFAULT_FORMAT, "hello", &None), "hello".to_string());
+        assert_eq!(render_output(DEFAULT_FORMAT, "helo", &Some("hello".to_string())),
+                   "helo, hello".to_string());
+    }
+
+    #[test]
+    fn test_custom_format() {
+        let template = "{word}\t{status}\t{suggestion}";
+        assert_eq!(render_output(template, "hello", &None), "hello\tcorrect\t".to_string());
+        assert_eq!(render_output(template, "helo", &Some("hello".to_string())),
+                   "helo\tmisspelled\thello".to_string());
+    }
+}
+
+/// Times `edits_2` on each given word against the dictionary trained
+/// from `training_file`, demonstrating the latency win from its
+/// deduplication and known-prefix pruning: the naive version
+/// regenerates and re-looks-up the same candidates many times over,
+/// which gets dramatically worse as the word (and so the number of
+/// edit_1 candidates) gets longer.
+fn run_benchmark(args: &Vec<String>) {
+    let rest: Vec<&String> = args.iter().filter(|a| a.as_slice() != "--benchmark").collect();
+    if rest.len() < 3 {
+        panic!("Usage: --benchmark <training_file> <word>...");
+    }
+    let counts = train(open_file(rest[1].as_slice()), &HashSet::new());
+    let dict = Dictionary::new(counts, Smoothing::AddK(1f64), 2);
+
+    for word in rest.iter().skip(2) {
+        let word = String::from_str(word.as_slice());
+        let one = edits_1(&word, dict.alphabet());
+        let start = time::precise_time_ns();
+        let two = edits_2(&one, &dict);
+        let elapsed_ms = (time::precise_time_ns() - start) as f64 / 1_000_000f64;
+        println!("{} ({} edit_1 candidates, {} edit_2 results): {:.3}ms",
+                 word, one.len(), two.len(), elapsed_ms);
+    }
+}
+
+/// Parse a `<name>:<training_file>[:<alphabet>]` dictionary argument.
+/// A bare training file with no `:` is given the name "default" and
+/// the default `ALPHABET`.
+fn parse_dictionary_arg(arg: &str) -> (String, String, String) {
+    let parts: Vec<&str> = arg.split(':').collect();
+    match parts.len() {
+        1 => (String::from_str("default"), String::from_str(parts[0]), String::from_str(ALPHABET)),
+        2 => (String::from_str(parts[0]), String::from_str(parts[1]), String::from_str(ALPHABET)),
+        _ => (String::from_str(parts[0]), String::from_str(parts[1]), String::from_str(parts[2])),
+    }
+}
+
+#[cfg(test)]
+mod parse_dictionary_arg_test {
+    use super::{parse_dictionary_arg, ALPHABET};
+
+    #[test]
+    fn test_bare_training_file() {
+        let (name, path, alphabet) = parse_dictionary_arg("train.txt");
+        assert_eq!(name, "default".to_string());
+        assert_eq!(path, "train.txt".to_string());
+        assert_eq!(alphabet, ALPHABET.to_string());
+    }
+
+    #[test]
+    fn test_named_training_file() {
+        let (name, path, alphabet) = parse_dictionary_arg("fr:train_fr.txt");
+        assert_eq!(name, "fr".to_string());
+        assert_eq!(path, "train_fr.txt".to_string());
+        assert_eq!(alphabet, ALPHABET.to_string());
+    }
+
+    #[test]
+    fn test_named_training_file_with_alphabet() {
+        let (name, path, alphabet) = parse_dictionary_arg("fr:train_fr.txt:abcàâéè");
+        assert_eq!(name, "fr".to_string());
+        assert_eq!(path, "train_fr.txt".to_string());
+        assert_eq!(alphabet, "abcàâéè".to_string());
+    }
+}
+
+/// Given a line of arbitrary prose, returns it with misspelled words
+/// replaced in place by their best suggestion (case of the original
+/// token is not preserved), leaving unknown-with-no-suggestion words
+/// and all non-word characters untouched. Correctly-spelled words that
+/// look like a real-word error in context (see `real_word_error`) are
+/// flagged in place as `word[?candidate]` rather than silently passed
+/// through. Tokens that look like proper nouns or acronyms (see
+/// `looks_like_proper_noun_or_acronym`) are passed through untouched,
+/// case and all, rather than being lowercased and "corrected" into a
+/// nonsensical common word.
+fn filter_text(line: &str, dict: &Dictionary, bigrams: &BigramModel) -> String {
+    let regex = Regex::new("[a-zA-Z]+").unwrap();
+    let mut result = String::new();
+    let mut last_end: usize = 0;
+    let mut prev_token: Option<String> = None;
+    for (start, end) in regex.find_iter(line) {
+        result.push_str(line.slice(last_end, start));
+        let original = line.slice(start, end);
+        if looks_like_proper_noun_or_acronym(original, dict) {
+            result.push_str(original);
+            prev_token = Some(original.to_ascii_lowercase());
+            last_end = end;
+            continue;
+        }
+        let token = original.to_ascii_lowercase();
+        match suggest(token.clone(), dict) {
+            Some(ref correction) if correction.as_slice() != NO_SPELLING_SUGGESTION => {
+                result.push_str(correction.as_slice());
+            },
+            Some(_) => {
+                result.push_str(token.as_slice());
+            },
+            None => {
+                let prev = prev_token.as_ref().map(|s| s.as_slice());
+                match real_word_error(prev, token.as_slice(), bigrams) {
+                    Some(candidate) => {
+                        result.push_str(token.as_slice());
+                        result.push_str("[?");
+                        result.push_str(candidate.as_slice());
+                        result.push_str("]");
+                    },
+                    None => { result.push_str(token.as_slice()); }
+                }
+            }
+        }
+        prev_token = Some(token);
+        last_end = end;
+    }
+    result.push_str(line.slice_from(last_end));
+    result
+}
+
+#[cfg(test)]
+mod filter_text_tests {
+    use super::{filter_text, open_file, train, train_bigrams, Dictionary, Smoothing, BigramModel};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_filter_text() {
+        let file = open_file("train.txt");
+        let bigrams = BigramModel::new(train_bigrams(open_file("train.txt")));
+        let dict = Dictionary::new(train(file, &HashSet::new()), Smoothing::AddK(1f64), 2);
+        let line = "I was permanintly accomplishher.";
+        assert_eq!(filter_text(line, &dict, &bigrams),
+                   String::from_str("i was permanently accomplished."));
+    }
+}
+
+/// Render one line of ispell/aspell `-a` pipe-protocol output for
+/// `line`, checking each alphabetic token against `dict` and
+/// emitting one status line per word -- `*` if correct, `&` with a
+/// ranked list of near-miss suggestions if misspelled with
+/// candidates, `#` if misspelled with none -- followed by the blank
+/// line ispell uses to mark the end of a checked input line.
+fn ispell_line(line: &str, dict: &Dictionary) -> String {
+    let regex = Regex::new("[a-zA-Z]+").unwrap();
+    let mut result = String::new();
+    for (start, end) in regex.find_iter(line) {
+        let original = line.slice(start, end);
+        let token = original.to_ascii_lowercase();
+        match get_suggestion_set(token, dict) {
+            None => { result.push_str("*\n"); },
+            Some(suggestions) => {
+                if suggestions.is_empty() {
+                    result.push_str(format!("# {} {}\n", original, start).as_slice());
+                } else {
+                    let mut ranked: Vec<String> = suggestions.into_iter().collect();
+                    ranked.sort_by(|a, b| dict.score(b).partial_cmp(&dict.score(a)).unwrap());
+                    let guesses: Vec<String> = ranked.into_iter().take(10).collect();
+                    result.push_str(format!("& {} {} {}: {}\n", original, guesses.len(), start,
+                                             guesses.connect(", ")).as_slice());
+                }
+            }
+        }
+    }
+    result.push_str("\n");
+    result
+}
+
+#[cfg(test)]
+mod ispell_line_tests {
+    use super::{ispell_line, open_file, train, Dictionary, Smoothing};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_ispell_line() {
+        let dict = Dictionary::new(train(open_file("train.txt"), &HashSet::new()),
+                                    Smoothing::AddK(1f64), 2);
+        assert_eq!(ispell_line("the", &dict), String::from_str("*\n\n"));
+        let result = ispell_line("permanintly", &dict);
+        assert!(result.starts_with("& permanintly "));
+        assert!(result.ends_with("\n\n"));
+    }
+}
+
+/// Tracks how often each ordered pair of adjacent words ("bigram")
+/// appeared in the training corpus, used to flag real-word errors --
+/// words that are spelled correctly but are the wrong word for their
+/// context (e.g. "their" where "there" was meant).
+pub struct BigramModel {
+    counts: HashMap<(String, String), usize>,
+}
+
+impl BigramModel {
+    pub fn new(counts: HashMap<(String, String), usize>) -> BigramModel {
+        BigramModel { counts: counts }
+    }
+
+    /// How many times `word` was trained as immediately following `prev`.
+    fn count(&self, prev: &str, word: &str) -> usize {
+        let key = (String::from_str(prev), String::from_str(word));
+        *self.counts.get(&key).unwrap_or(&0)
+    }
+}
+
+/// Train a BigramModel from the same corpus `train` reads, counting
+/// each adjacent pair of trimmed words.
+fn train_bigrams<R: Reader>(mut file: BufferedReader<R>) -> HashMap<(String, String), usize> {
+    let mut counts: HashMap<(String, String), usize> = HashMap::new();
+    let mut prev: Option<String> = None;
+    for line in file.lines() {
+        for word in line.unwrap().words() {
+            match trim_to_word(word.as_slice()) {
+                Some(w) => {
+                    match prev {
+                        Some(p) => {
+                            let key = (p, w.clone());
+                            let count = *counts.get(&key).unwrap_or(&0);
+                            counts.insert(key, count + 1);
+                        },
+                        None => {}
+                    }
+                    prev = Some(w);
+                },
+                None => {}
+            }
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod train_bigrams_test {
+    use super::train_bigrams;
+    use std::io::{MemReader, BufferedReader};
+
+    #[test]
+    fn test_train_bigrams() {
+        let input = "over there the deer ran there and there again";
+        let bytes = input.to_string().into_bytes();
+        let r: BufferedReader<MemReader> = BufferedReader::new(MemReader::new(bytes));
+        let counts = train_bigrams(r);
+        assert_eq!(*counts.get(&(strr("over"), strr("there"))).unwrap(), 1);
+        assert_eq!(*counts.get(&(strr("ran"), strr("there"))).unwrap(), 1);
+        assert_eq!(*counts.get(&(strr("there"), strr("again"))).unwrap(), 1);
+        assert!(counts.get(&(strr("there"), strr("over"))).is_none());
+    }
+
+    fn strr(string: &str) -> String {
+        String::from_str(string)
+    }
+}
+
+/// Groups of commonly-confused real words -- correctly spelled on
+/// their own, but frequently typed in place of one another (e.g.
+/// "their" for "there"). `real_word_error` uses bigram context to
+/// decide whether a better-fitting sibling was probably meant.
+static CONFUSABLE_GROUPS: [&'static [&'static str]; 5] = [
+    &["their", "there", "they're"],
+    &["its", "it's"],
+    &["your", "you're"],
+    &["then", "than"],
+    &["to", "too", "two"],
+];
+
+/// The confusable group containing `word`, if any.
+fn confusable_group(word: &str) -> Option<&'static [&'static str]> {
+    for group in CONFUSABLE_GROUPS.iter() {
+        if group.iter().any(|&w| w == word) {
+            return Some(*group);
+        }
+    }
+    None
+}
+
+/// Given a word and the word immediately preceding it, checks whether
+/// a commonly-confused alternative (e.g. "there" in place of "their")
+/// fits the bigram context much better than `word` as typed. A word
+/// being correctly spelled is not sufficient to rule out this kind of
+/// error, since the mistake is a real (but wrong) word.
+fn real_word_error(prev: Option<&str>, word: &str, bigrams: &BigramModel) -> Option<String> {
+    let prev = match prev {
+        Some(p) => p,
+        None => return None
+    };
+    let group = match confusable_group(word) {
+        Some(g) => g,
+        None => return None
+    };
+    let current_count = bigrams.count(prev, word);
+    let mut best: Option<(&str, usize)> = None;
+    for &candidate in group.iter() {
+        if candidate == word { continue; }
+        let count = bigrams.count(prev, candidate);
+        if count > current_count {
+            let is_better = match best {
+                Some((_, best_count)) => count > best_count,
+                None => true
+            };
+            if is_better {
+                best = Some((candidate, count));
+            }
+        }
+    }
+    best.map(|(candidate, _)| String::from_str(candidate))
+}
+
+#[cfg(test)]
+mod real_word_error_test {
+    use super::{real_word_error, train_bigrams, BigramModel};
+    use std::io::{MemReader, BufferedReader};
+
+    fn build_bigrams(input: &str) -> BigramModel {
+        let bytes = input.to_string().into_bytes();
+        let r: BufferedReader<MemReader> = BufferedReader::new(MemReader::new(bytes));
+        BigramModel::new(train_bigrams(r))
+    }
+
+    #[test]
+    fn test_flags_likely_confusion() {
+        let bigrams = build_bigrams("over there the deer ran there and there again");
+        assert_eq!(real_word_error(Some("over"), "their", &bigrams), Some(String::from_str("there")));
+    }
+
+    #[test]
+    fn test_no_flag_when_word_fits_context() {
+        let bigrams = build_bigrams("their dog ran away and their cat stayed");
+        assert_eq!(real_word_error(Some("and"), "their", &bigrams), None);
+    }
+
+    #[test]
+    fn test_no_flag_outside_confusable_groups() {
+        let bigrams = build_bigrams("the quick fox");
+        assert_eq!(real_word_error(Some("the"), "fox", &bigrams), None);
+    }
+
+    #[test]
+    fn test_no_flag_with_no_previous_word() {
+        let bigrams = build_bigrams("over there");
+        assert_eq!(real_word_error(None, "their", &bigrams), None);
+    }
+}
+
+/// True for tokens like "Boston" or "Mccarthy": an initial uppercase
+/// letter followed only by lowercase letters.
+fn is_titlecase(token: &str) -> bool {
+    if token.chars().filter(|c| c.is_alphabetic()).count() < 2 {
+        return false;
+    }
+    let mut chars = token.chars();
+    match chars.next() {
+        Some(c) if c.is_uppercase() => chars.filter(|c| c.is_alphabetic()).all(|c| c.is_lowercase()),
+        _ => false
+    }
+}
+
+/// True for tokens like "NASA" or "FBI": more than one letter, all
+/// uppercase.
+fn is_all_caps(token: &str) -> bool {
+    token.chars().filter(|c| c.is_alphabetic()).count() > 1
+        && token.chars().all(|c| c.is_uppercase() || !c.is_alphabetic())
+}
+
+/// Heuristically decides whether `token`, as typed (before
+/// lowercasing), is likely a proper noun or acronym rather than a
+/// misspelling: it's capitalized or all-caps, and never seen as an
+/// ordinary lowercase word in the trained corpus. Such tokens are
+/// reported as unknown but are not run through `suggest`, which would
+/// otherwise lowercase them into a nonsensical common-word suggestion.
+fn looks_like_proper_noun_or_acronym(token: &str, dict: &Dictionary) -> bool {
+    if !is_titlecase(token) && !is_all_caps(token) {
+        return false;
+    }
+    dict.raw_count(&token.to_ascii_lowercase()) == 0
+}
+
+#[cfg(test)]
+mod looks_like_proper_noun_or_acronym_test {
+    use super::{looks_like_proper_noun_or_acronym, Dictionary, Smoothing};
+    use std::collections::HashMap;
+
+    fn build_dict() -> Dictionary {
+        let mut counts = HashMap::new();
+        counts.insert(String::from_str("hello"), 5);
+        Dictionary::new(counts, Smoothing::None, 0)
+    }
+
+    #[test]
+    fn test_titlecase_unseen_word_is_proper_noun() {
+        assert!(looks_like_proper_noun_or_acronym("Boston", &build_dict()));
+    }
+
+    #[test]
+    fn test_all_caps_unseen_word_is_acronym() {
+        assert!(looks_like_proper_noun_or_acronym("NASA", &build_dict()));
+    }
+
+    #[test]
+    fn test_lowercase_word_is_not_flagged() {
+        assert!(!looks_like_proper_noun_or_acronym("hello", &build_dict()));
+    }
+
+    #[test]
+    fn test_titlecase_word_seen_lowercase_is_not_flagged() {
+        assert!(!looks_like_proper_noun_or_acronym("Hello", &build_dict()));
+    }
+
+    #[test]
+    fn test_single_capital_letter_is_not_acronym() {
+        assert!(!looks_like_proper_noun_or_acronym("A", &build_dict()));
+    }
+}
+
 /// Open the file as given by filename in the form of a Buffered Reader
 fn open_file(filename: &str) -> BufferedReader<File> {
     let file = File::open(&Path::new(filename));
@@ -137,14 +951,21 @@ mod inc_count_tests {
 }
 
 /// Train the program to identify words based on the corpus of passed-in data
-/// Each word in the BufferedReader is counted for frequency
-fn train<R: Reader>(mut file: BufferedReader<R>) -> HashMap<String, usize> {
+/// Each word in the BufferedReader is counted for frequency, except for
+/// words present in `exclude` (boilerplate markers, chapter headings,
+/// roman numerals, etc.), which are skipped entirely so they neither
+/// count as correct words nor pollute suggestion ranking.
+fn train<R: Reader>(mut file: BufferedReader<R>, exclude: &HashSet<String>) -> HashMap<String, usize> {
     let mut x: HashMap<String, usize> = HashMap::new();
 
     for line in file.lines() {
         for word in line.unwrap().words() {
             match trim_to_word(word.as_slice()) {
-                Some(w) => inc_count(&mut x, w),
+                Some(w) => {
+                    if !exclude.contains(&w) {
+                        inc_count(&mut x, w);
+                    }
+                },
                 None    => {}
             }
         }
@@ -152,11 +973,110 @@ fn train<R: Reader>(mut file: BufferedReader<R>) -> HashMap<String, usize> {
     x
 }
 
+/// Train like `train`, but within a fixed memory budget: counts are
+/// approximated with a count-min sketch (of `width` x `depth`
+/// counters) instead of an exact HashMap, and only the top `k` words
+/// by estimated count are retained. Suitable for corpora too large to
+/// count exactly in memory.
+fn train_bounded<R: Reader>(mut file: BufferedReader<R>, exclude: &HashSet<String>,
+                            k: usize, width: usize, depth: usize) -> HashMap<String, usize> {
+    let mut hitters = sketch::HeavyHitters::new(k, width, depth);
+    for line in file.lines() {
+        for word in line.unwrap().words() {
+            match trim_to_word(word.as_slice()) {
+                Some(w) => {
+                    if !exclude.contains(&w) {
+                        hitters.observe(w.as_slice());
+                    }
+                },
+                None    => {}
+            }
+        }
+    }
+    hitters.top().into_iter().collect()
+}
+
+/// Train from a hunspell-style `.dic` word list: the first line is a
+/// word count hunspell uses as a size hint (ignored here), and every
+/// line after that is `word` or `word/FLAGS`, where `FLAGS` are affix
+/// flags this corrector has no use for and simply discards. Since
+/// hunspell word lists carry no frequency information, every word is
+/// given a count of 1.
+fn train_hunspell_dic<R: Reader>(mut file: BufferedReader<R>) -> HashMap<String, usize> {
+    let mut x: HashMap<String, usize> = HashMap::new();
+    let mut lines = file.lines();
+    lines.next();
+    for line in lines {
+        let raw = line.unwrap();
+        let word = raw.as_slice().trim().split('/').next().unwrap_or("");
+        match trim_to_word(word) {
+            Some(w) => { inc_count(&mut x, w); },
+            None    => {}
+        }
+    }
+    x
+}
+
+#[cfg(test)]
+mod train_hunspell_dic_test {
+    use super::train_hunspell_dic;
+    use std::io::{MemReader, BufferedReader};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_train_hunspell_dic() {
+        let input = "3\nhello/S\nworld\napple/SM\n";
+        let bytes = input.to_string().into_bytes();
+        let r: BufferedReader<MemReader> = BufferedReader::new(MemReader::new(bytes));
+        let mut expected = HashMap::new();
+        expected.insert(strr("hello"), 1);
+        expected.insert(strr("world"), 1);
+        expected.insert(strr("apple"), 1);
+        assert_eq!(train_hunspell_dic(r), expected);
+    }
+
+    fn strr(string: &str) -> String {
+        String::from_str(string)
+    }
+}
+
+/// Export `dict`'s trained counts to `path` as a plain word list, one
+/// `<word> <count>` pair per line sorted alphabetically, so the
+/// trained dictionary can be inspected or re-used outside this
+/// program.
+fn export_word_list(dict: &Dictionary, path: &str) -> IoResult<()> {
+    let mut words: Vec<&String> = dict.counts.keys().collect();
+    words.sort();
+    let mut file = try!(File::create(&Path::new(path)));
+    for word in words.iter() {
+        let count = *dict.counts.get(*word).unwrap();
+        try!(write!(&mut file, "{} {}\n", word, count));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod export_word_list_test {
+    use super::{export_word_list, open_file, train, Dictionary, Smoothing};
+    use std::collections::HashSet;
+    use std::io::{File, BufferedReader};
+
+    #[test]
+    fn test_export_word_list() {
+        let dict = Dictionary::new(train(open_file("train.txt"), &HashSet::new()),
+                                    Smoothing::None, 0);
+        export_word_list(&dict, "export_word_list_test.txt").unwrap();
+        let exported = BufferedReader::new(File::open(&Path::new("export_word_list_test.txt")).unwrap())
+            .lines().map(|l| l.unwrap()).count();
+        assert_eq!(exported, dict.counts.len());
+    }
+}
+
 #[cfg(test)]
 mod train_test {
     use super::train;
     use std::io::{MemReader, BufferedReader};
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
 
     #[test]
     fn test_train() {
@@ -223,12 +1143,28 @@ mod train_test {
         let bytes = input.to_string().into_bytes();
         let r: BufferedReader<MemReader> =
             BufferedReader::new(MemReader::new(bytes));
-        assert_eq!(train(r), expected);
+        assert_eq!(train(r, &HashSet::new()), expected);
     }
 
     fn strr(string: &str) -> String {
         String::from_str(string)
     }
+
+    #[test]
+    fn test_train_with_exclusions() {
+        let input = "Chapter One: the quick the quick fox";
+        let mut exclude = HashSet::new();
+        exclude.insert(strr("chapter"));
+        exclude.insert(strr("one"));
+        let mut expected = HashMap::new();
+        expected.insert(strr("the"), 2);
+        expected.insert(strr("quick"), 2);
+        expected.insert(strr("fox"), 1);
+        let bytes = input.to_string().into_bytes();
+        let r: BufferedReader<MemReader> =
+            BufferedReader::new(MemReader::new(bytes));
+        assert_eq!(train(r, &exclude), expected);
+    }
 }
 
 /// Given a word, returns a vector containing slices of the word from
@@ -331,10 +1267,10 @@ mod transpositions_test {
 
 /// Given a split word, returns a HashSet containing all permutations of the
 /// word resulting from inserting an additional letter at any position.
-fn insertions(splits: &Vec<(&str, &str)>) -> HashSet<String> {
+fn insertions(splits: &Vec<(&str, &str)>, alphabet: &str) -> HashSet<String> {
     let mut results = HashSet::new();
     for &(front, back) in splits.iter() {
-        for c in ALPHABET.chars() {
+        for c in alphabet.chars() {
             let mut s = String::from_str(front);
             s.push(c);
             s.push_str(back);
@@ -346,7 +1282,7 @@ fn insertions(splits: &Vec<(&str, &str)>) -> HashSet<String> {
 
 #[cfg(test)]
 mod insertions_test {
-    use super::insertions;
+    use super::{insertions, ALPHABET};
     use super::split_word;
     use std::collections::HashSet;
 
@@ -459,7 +1395,7 @@ mod insertions_test {
         expect.insert(strr("fooz"));
         let foo = strr("foo");
         let input = split_word(&foo);
-        let output = insertions(&input);
+        let output = insertions(&input, ALPHABET);
         assert_eq!(output.len(), expect.len());
         assert_eq!(output, expect);
     }
@@ -471,10 +1407,10 @@ mod insertions_test {
 
 /// Given a split word, returns a HashMap containing all permutations of the
 /// word resulting from replacing a letter at any position.
-fn replacements(splits: &Vec<(&str, &str)>) -> HashSet<String> {
+fn replacements(splits: &Vec<(&str, &str)>, alphabet: &str) -> HashSet<String> {
     let mut results = HashSet::new();
     for &(front, back) in splits.iter() {
-        for c in ALPHABET.chars() {
+        for c in alphabet.chars() {
             if back.len() > 0 {
                 let mut s = String::from_str(front);
                 s.push(c);
@@ -488,7 +1424,7 @@ fn replacements(splits: &Vec<(&str, &str)>) -> HashSet<String> {
 
 #[cfg(test)]
 mod replacements_test {
-    use super::replacements;
+    use super::{replacements, ALPHABET};
     use super::split_word;
     use std::collections::HashSet;
 
@@ -575,7 +1511,7 @@ mod replacements_test {
         expect.insert(strr("foz"));
         let foo = strr("foo");
         let input = split_word(&foo);
-        let output = replacements(&input);
+        let output = replacements(&input, ALPHABET);
         assert_eq!(output.len(), expect.len());
         assert_eq!(output, expect);
     }
@@ -587,7 +1523,7 @@ mod replacements_test {
 
 /// Given a set of words, returns a HashSet containing only words that are in
 /// the dictionary. If no words are valid, returns an empty HashSet.
-fn known(words: &HashSet<String>, dict: &HashMap<String, usize>) -> HashSet<String> {
+fn known(words: &HashSet<String>, dict: &Dictionary) -> HashSet<String> {
     let mut recognized = HashSet::new();
     for word in words.iter() {
         if dict.contains_key(word) {
@@ -599,14 +1535,15 @@ fn known(words: &HashSet<String>, dict: &HashMap<String, usize>) -> HashSet<Stri
 
 #[cfg(test)]
 mod known_test {
-    use super::known;
+    use super::{known, Dictionary, Smoothing};
     use std::collections::{HashSet, HashMap};
 
     #[test]
     fn test_known() {
-        let mut dict = HashMap::new();
-        dict.insert(strr("hello"), 2);
-        dict.insert(strr("world"), 1);
+        let mut counts = HashMap::new();
+        counts.insert(strr("hello"), 2);
+        counts.insert(strr("world"), 1);
+        let dict = Dictionary::new(counts, Smoothing::None, 0);
         let mut words = HashSet::new();
         words.insert(strr("hello"));
         words.insert(strr("word"));
@@ -622,11 +1559,11 @@ mod known_test {
 
 /// Given a word, returns a hashmap containing all possible words with edit
 /// distance 1 from the given word.
-fn edits_1(word: &String) -> HashSet<String> {
+fn edits_1(word: &String, alphabet: &str) -> HashSet<String> {
     let splits = &split_word(word);
     let results = deletions(splits).into_iter()
-        .chain(insertions(splits).into_iter())
-        .chain(replacements(splits).into_iter())
+        .chain(insertions(splits, alphabet).into_iter())
+        .chain(replacements(splits, alphabet).into_iter())
         .chain(transpositions(splits).into_iter())
         .collect();
     results
@@ -635,7 +1572,7 @@ fn edits_1(word: &String) -> HashSet<String> {
 #[cfg(test)]
 mod edits_1_test {
     use super::{edits_1, split_word, deletions,
-        insertions, replacements, transpositions};
+        insertions, replacements, transpositions, ALPHABET};
     use std::collections::HashSet;
 
     #[test]
@@ -644,10 +1581,10 @@ mod edits_1_test {
         let word = split_word(&foo);
         let mut expect = HashSet::new();
         expect.extend(deletions(&word).into_iter());
-        expect.extend(insertions(&word).into_iter());
+        expect.extend(insertions(&word, ALPHABET).into_iter());
         expect.extend(transpositions(&word).into_iter());
-        expect.extend(replacements(&word).into_iter());
-        let output = edits_1(&foo);
+        expect.extend(replacements(&word, ALPHABET).into_iter());
+        let output = edits_1(&foo, ALPHABET);
         assert_eq!(output.len(), expect.len());
         assert_eq!(output, expect);
     }
@@ -659,31 +1596,56 @@ mod edits_1_test {
 
 /// Given a set of words with edit distance 1, return a set of words
 /// edit distance 2 away from the original source word.
-/// Only produces words that are found in the dictionary (to save memory)
-fn edits_2(edit_1_set: &HashSet<String>, dict: &HashMap<String, usize>) -> HashSet<String> {
+/// Only produces words that are found in the dictionary (to save memory).
+///
+/// Two things make the naive version of this expensive on long words:
+/// most edit_1 candidates share no prefix with anything in the
+/// dictionary, and so are unlikely to reach a known word in one more
+/// edit (pruned via `dict.has_known_prefix` before generating their
+/// edit_1-of-edit_1 candidates at all -- this is a deliberate
+/// approximation, like the sketch module's count-min counters: a
+/// correction whose only edit falls within the first 3 characters can
+/// in principle be missed, but in practice almost all corrections are
+/// found this way, and the pruning cuts candidate generation
+/// dramatically); and many different edit_1 candidates regenerate the
+/// same edit_2 candidate (deduplicated via `seen` so each one is only
+/// looked up in the dictionary once).
+fn edits_2(edit_1_set: &HashSet<String>, dict: &Dictionary) -> HashSet<String> {
     let mut results = HashSet::new();
+    let mut seen = HashSet::new();
     for edit_1 in edit_1_set.iter() {
-        results.extend(edits_1(edit_1).into_iter().filter(|w| dict.contains_key(w)))
+        if !dict.has_known_prefix(edit_1.as_slice()) {
+            continue;
+        }
+        for candidate in edits_1(edit_1, dict.alphabet()).into_iter() {
+            if !seen.insert(candidate.clone()) {
+                continue;
+            }
+            if dict.contains_key(&candidate) {
+                results.insert(candidate);
+            }
+        }
     }
     results
 }
 
 #[cfg(test)]
 mod edits_2_test {
-    use super::edits_2;
+    use super::{edits_2, Dictionary, Smoothing};
     use std::collections::{HashSet, HashMap};
 
     #[test]
     fn test_edits_2() {
         let mut edit_1_set = HashSet::new();
         edit_1_set.insert(strr("foo"));
-        let mut dict = HashMap::new();
-        dict.insert(strr("of"), 5);
-        dict.insert(strr("food"), 3);
-        dict.insert(strr("coo"), 1);
-        dict.insert(strr("roof"), 2);
-        dict.insert(strr("bar"), 1);
-        dict.insert(strr("bard"), 1);
+        let mut counts = HashMap::new();
+        counts.insert(strr("of"), 5);
+        counts.insert(strr("food"), 3);
+        counts.insert(strr("coo"), 1);
+        counts.insert(strr("roof"), 2);
+        counts.insert(strr("bar"), 1);
+        counts.insert(strr("bard"), 1);
+        let dict = Dictionary::new(counts, Smoothing::None, 0);
         let mut expect = HashSet::new();
         expect.insert(strr("food"));
         expect.insert(strr("coo"));
@@ -695,36 +1657,114 @@ mod edits_2_test {
     }
 }
 
+/// Lazily generates edit-distance-1 candidates for `word` and filters
+/// them against `dict` as they are produced, rather than materializing
+/// the full (mostly-unknown) edit-1 candidate set up front as
+/// `edits_1` does. Cheap for the common case where a known
+/// one-edit suggestion exists, since the caller can stop pulling from
+/// the iterator without paying for the candidates that were never
+/// generated.
+fn known_edits_1_lazy<'a>(word: &'a String, dict: &'a Dictionary) -> Box<Iterator<Item=String> + 'a> {
+    let splits = split_word(word);
+    let alphabet = dict.alphabet();
+
+    let deletions = splits.clone().into_iter().filter_map(|(front, back)| {
+        if back.len() > 0 {
+            Some(String::from_str(front) + back.slice_from(1))
+        } else { None }
+    });
+
+    let transpositions = splits.clone().into_iter().filter_map(|(front, back)| {
+        if back.len() > 1 {
+            let (one, s1) = back.slice_shift_char().unwrap();
+            let (two, s2) = s1.slice_shift_char().unwrap();
+            let mut s = String::from_str(front);
+            s.push(two);
+            s.push(one);
+            s.push_str(s2);
+            Some(s)
+        } else { None }
+    });
+
+    let insertions = splits.clone().into_iter().flat_map(move |(front, back)| {
+        alphabet.chars().map(move |c| {
+            let mut s = String::from_str(front);
+            s.push(c);
+            s.push_str(back);
+            s
+        })
+    });
+
+    let replacements = splits.into_iter().filter(|&(_, back)| back.len() > 0)
+        .flat_map(move |(front, back)| {
+            alphabet.chars().map(move |c| {
+                let mut s = String::from_str(front);
+                s.push(c);
+                s.push_str(back.slice_from(1));
+                s
+            })
+        });
+
+    Box::new(deletions.chain(transpositions).chain(insertions).chain(replacements)
+        .filter(move |w| dict.contains_key(w)))
+}
+
+#[cfg(test)]
+mod known_edits_1_lazy_test {
+    use super::{known_edits_1_lazy, Dictionary, Smoothing};
+    use std::collections::{HashSet, HashMap};
+
+    #[test]
+    fn test_known_edits_1_lazy() {
+        let mut counts = HashMap::new();
+        counts.insert(strr("food"), 1);
+        counts.insert(strr("room"), 1);
+        counts.insert(strr("fo"), 1);
+        let dict = Dictionary::new(counts, Smoothing::None, 0);
+        let word = strr("foo");
+        let output: HashSet<String> = known_edits_1_lazy(&word, &dict).collect();
+        let mut expect = HashSet::new();
+        expect.insert(strr("food"));
+        expect.insert(strr("fo"));
+        assert_eq!(output, expect);
+    }
+
+    fn strr(string: &str) -> String {
+        String::from_str(string)
+    }
+}
+
 /// Given a word and a dictionary, returns an option:
 /// Some(HashSet) if the word is misspelled, with the HashSet
 /// giving possible suggestions from edit distance 1 or 2.
 /// None if the word is not misspelled.
-fn get_suggestion_set(word: String, dict: &HashMap<String, usize>) -> Option<HashSet<String>> {
+fn get_suggestion_set(word: String, dict: &Dictionary) -> Option<HashSet<String>> {
     let mut word_set = HashSet::new();
     word_set.insert(word.clone());
     let no_change = known(&word_set, dict);
     if !no_change.is_empty() {
         return None
     }
-    let one = edits_1(&word);
-    let one_known = known(&one, dict);
+    let one_known: HashSet<String> = known_edits_1_lazy(&word, dict).collect();
     Some(if !one_known.is_empty() {
         one_known
     } else {
+        let one = edits_1(&word, dict.alphabet());
         edits_2(&one, dict)
     })
 }
 
 #[cfg(test)]
 mod get_suggestion_set_test {
-    use super::get_suggestion_set;
+    use super::{get_suggestion_set, Dictionary, Smoothing};
     use std::collections::{HashSet, HashMap};
 
     #[test]
     fn test_get_suggestion_set() {
-        let mut dict = HashMap::new();
-        dict.insert(strr("food"), 1);
-        dict.insert(strr("room"), 1);
+        let mut counts = HashMap::new();
+        counts.insert(strr("food"), 1);
+        counts.insert(strr("room"), 1);
+        let dict = Dictionary::new(counts, Smoothing::None, 0);
         let mut expected1 = HashSet::new();
         expected1.insert(strr("food"));
         let mut expected2 = HashSet::new();
@@ -743,18 +1783,14 @@ mod get_suggestion_set_test {
 /// Given a non-empty HashMap and a dictionary,
 /// returns the String that represents the best spelling suggestion.
 fn get_best_suggestion(corrected_set: HashSet<String>,
-                       dict: &HashMap<String, usize>) -> String {
-    let mut max_freq: usize = 0;
+                       dict: &Dictionary) -> String {
+    let mut max_score: f64 = -1f64;
     let mut best_word = String::new();
     for possibility in corrected_set.into_iter() {
-        match dict.get(&possibility) {
-            Some(&frequency) => {
-                if frequency > max_freq {
-                    max_freq = frequency;
-                    best_word = possibility;
-                }
-            },
-            None => {}
+        let score = dict.score(&possibility);
+        if score > max_score {
+            max_score = score;
+            best_word = possibility;
         }
     }
     best_word
@@ -762,15 +1798,16 @@ fn get_best_suggestion(corrected_set: HashSet<String>,
 
 #[cfg(test)]
 mod get_best_suggestion_test {
-    use super::get_best_suggestion;
+    use super::{get_best_suggestion, Dictionary, Smoothing};
     use std::collections::{HashSet, HashMap};
 
     #[test]
     fn test_get_best_suggestion() {
-        let mut dict = HashMap::new();
-        dict.insert(strr("hello"), 3);
-        dict.insert(strr("hell"), 2);
-        dict.insert(strr("jello"), 1);
+        let mut counts = HashMap::new();
+        counts.insert(strr("hello"), 3);
+        counts.insert(strr("hell"), 2);
+        counts.insert(strr("jello"), 1);
+        let dict = Dictionary::new(counts, Smoothing::None, 0);
         let mut suggestions = HashSet::new();
         suggestions.insert(strr("hello"));
         suggestions.insert(strr("hell"));
@@ -783,31 +1820,87 @@ mod get_best_suggestion_test {
     }
 }
 
+/// Given a word not found in the dictionary, attempt to split it into
+/// two known dictionary words (e.g. "spellingcorrector" ->
+/// "spelling corrector"), ranking candidate splits by the product of
+/// the component words' raw training counts. Returns None if no split
+/// point yields two known words.
+fn compound_split_suggestion(word: &String, dict: &Dictionary) -> Option<String> {
+    let mut best: Option<(String, usize)> = None;
+    for &(front, back) in split_word(word).iter() {
+        if front.is_empty() || back.is_empty() { continue; }
+        let front_word = String::from_str(front);
+        let back_word = String::from_str(back);
+        if !dict.contains_key(&front_word) || !dict.contains_key(&back_word) {
+            continue;
+        }
+        let product = dict.raw_count(&front_word) * dict.raw_count(&back_word);
+        let is_better = match best {
+            Some((_, best_product)) => product > best_product,
+            None => true
+        };
+        if is_better {
+            best = Some((front_word + " " + back_word.as_slice(), product));
+        }
+    }
+    best.map(|(candidate, _)| candidate)
+}
+
+#[cfg(test)]
+mod compound_split_suggestion_test {
+    use super::{compound_split_suggestion, Dictionary, Smoothing};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_compound_split_suggestion() {
+        let mut counts = HashMap::new();
+        counts.insert(String::from_str("spelling"), 5);
+        counts.insert(String::from_str("corrector"), 3);
+        counts.insert(String::from_str("spell"), 1);
+        counts.insert(String::from_str("ingcorrector"), 1);
+        let dict = Dictionary::new(counts, Smoothing::None, 0);
+        let word = String::from_str("spellingcorrector");
+        assert_eq!(compound_split_suggestion(&word, &dict),
+                   Some(String::from_str("spelling corrector")));
+    }
+
+    #[test]
+    fn test_compound_split_suggestion_none() {
+        let dict = Dictionary::new(HashMap::new(), Smoothing::None, 0);
+        let word = String::from_str("wharrgarbl");
+        assert_eq!(compound_split_suggestion(&word, &dict), None);
+    }
+}
+
 /// Given a word and a dictionary, returns an option:
 /// Some(String) if the word is misspelled, with the String indicating the
 /// best replacement;
 /// None if the word is not misspelled.
-fn suggest(word: String, dict: &HashMap<String, usize>) -> Option<String> {
+fn suggest(word: String, dict: &Dictionary) -> Option<String> {
     let mut corrected_set: HashSet<String>;
-    match get_suggestion_set(word, dict) {
+    match get_suggestion_set(word.clone(), dict) {
         Some(set) => { corrected_set = set},
         None => { return None; }
     };
 
     if corrected_set.is_empty() {
-        return Some(String::from_str(NO_SPELLING_SUGGESTION));
+        return Some(match compound_split_suggestion(&word, dict) {
+            Some(split) => split,
+            None => String::from_str(NO_SPELLING_SUGGESTION)
+        });
     }
     Some(get_best_suggestion(corrected_set, dict))
 }
 
 #[cfg(test)]
 mod suggest_test {
-    use super::{open_file, train, suggest};
+    use super::{open_file, train, suggest, Dictionary, Smoothing};
+    use std::collections::HashSet;
 
     #[test]
     fn test_suggest() {
         let file = open_file("train.txt");
-        let dict = train(file);
+        let dict = Dictionary::new(train(file, &HashSet::new()), Smoothing::AddK(1f64), 2);
 
         let rights = vec!["really", "accomplished", "spelling", "correction", "permanently", "-"];
         let wrongs = vec!["realy", "accomplishher", "spelingg", "correcttio", "permanintly", "wharrgarbl"];
@@ -819,3 +1912,103 @@ mod suggest_test {
 
     }
 }
+
+/// Describes the single edit-distance-1 operation transforming `from`
+/// into `to`: an insertion, deletion, transposition, or replacement of
+/// one character, reported with its 0-based position in `from`.
+/// `suggest`'s candidates are at most edit-distance 2, and the
+/// intermediate word along that path isn't tracked, so distance-2
+/// suggestions fall back to a generic description.
+fn describe_edit(from: &str, to: &str) -> String {
+    if from.len() + 1 == to.len() {
+        for i in range(0, from.len() + 1) {
+            if from.slice_to(i) == to.slice_to(i) && from.slice_from(i) == to.slice_from(i + 1) {
+                return format!("insert '{}' at position {}", to.char_at(i), i);
+            }
+        }
+    } else if from.len() == to.len() + 1 {
+        for i in range(0, to.len() + 1) {
+            if from.slice_to(i) == to.slice_to(i) && from.slice_from(i + 1) == to.slice_from(i) {
+                return format!("delete '{}' at position {}", from.char_at(i), i);
+            }
+        }
+    } else if from.len() == to.len() {
+        let diffs: Vec<usize> = range(0, from.len())
+            .filter(|&i| from.char_at(i) != to.char_at(i)).collect();
+        if diffs.len() == 2 && diffs[1] == diffs[0] + 1
+                && from.char_at(diffs[0]) == to.char_at(diffs[1])
+                && from.char_at(diffs[1]) == to.char_at(diffs[0]) {
+            return format!("transpose '{}{}' to '{}{}' at position {}",
+                           from.char_at(diffs[0]), from.char_at(diffs[1]),
+                           to.char_at(diffs[0]), to.char_at(diffs[1]), diffs[0]);
+        } else if diffs.len() == 1 {
+            return format!("replace '{}' with '{}' at position {}",
+                           from.char_at(diffs[0]), to.char_at(diffs[0]), diffs[0]);
+        }
+    }
+    format!("multiple edits from '{}' to '{}'", from, to)
+}
+
+#[cfg(test)]
+mod describe_edit_test {
+    use super::describe_edit;
+
+    #[test]
+    fn test_insertion() {
+        assert_eq!(describe_edit("fo", "foo"), "insert 'o' at position 1".to_string());
+    }
+
+    #[test]
+    fn test_deletion() {
+        assert_eq!(describe_edit("fooo", "foo"), "delete 'o' at position 1".to_string());
+    }
+
+    #[test]
+    fn test_replacement() {
+        assert_eq!(describe_edit("fox", "foo"), "replace 'x' with 'o' at position 2".to_string());
+    }
+
+    #[test]
+    fn test_transposition() {
+        assert_eq!(describe_edit("recieve", "receive"), "transpose 'ie' to 'ei' at position 3".to_string());
+    }
+
+    #[test]
+    fn test_multiple_edits() {
+        assert_eq!(describe_edit("foo", "bard"), "multiple edits from 'foo' to 'bard'".to_string());
+    }
+}
+
+/// Like `suggest`, but also returns a human-readable description of
+/// the edit(s) connecting `word` to the suggestion -- useful for
+/// educational tooling built on the corrector.
+fn suggest_explained(word: String, dict: &Dictionary) -> Option<(String, String)> {
+    suggest(word.clone(), dict).map(|correction| {
+        let explanation = if correction.as_slice() == NO_SPELLING_SUGGESTION {
+            String::from_str("no suggestion available")
+        } else {
+            describe_edit(word.as_slice(), correction.as_slice())
+        };
+        (correction, explanation)
+    })
+}
+
+#[cfg(test)]
+mod suggest_explained_test {
+    use super::{open_file, train, suggest_explained, Dictionary, Smoothing};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_suggest_explained() {
+        let file = open_file("train.txt");
+        let dict = Dictionary::new(train(file, &HashSet::new()), Smoothing::AddK(1f64), 2);
+
+        let (correction, explanation) = suggest_explained(String::from_str("realy"), &dict).unwrap();
+        assert_eq!(correction, "really".to_string());
+        assert_eq!(explanation, "insert 'l' at position 3".to_string());
+
+        let (correction, explanation) = suggest_explained(String::from_str("wharrgarbl"), &dict).unwrap();
+        assert_eq!(correction, "-".to_string());
+        assert_eq!(explanation, "no suggestion available".to_string());
+    }
+}
@@ -0,0 +1,107 @@
+#[doc="
+    Module: ondisk
+
+    A memory-mapped, on-disk dictionary format for deployments where
+    the trained vocabulary is too large to comfortably keep as a
+    HashMap<String, usize> resident in memory (e.g. a long-running
+    server process). The format is a sorted word table: every trained
+    word, sorted lexicographically, written back to back to a data
+    file, with a small fixed-size index file recording each word's
+    (offset, length, count). Only the index is read eagerly; word
+    bytes are served directly out of the memory-mapped data file, so
+    resident memory is proportional to vocabulary size, not corpus
+    size.
+"]
+
+use std::collections::HashMap;
+use std::io::{File, IoResult};
+use std::io::fs::PathExtensions;
+use std::os::MemoryMap;
+use std::os::MapOption::{MapReadable, MapFd};
+
+/// One entry in the index: the word's byte range within the
+/// memory-mapped data file, and its trained count.
+struct Entry {
+    offset: usize,
+    length: usize,
+    count: usize,
+}
+
+/// A dictionary backed by a memory-mapped data file and a small
+/// in-memory index, rather than a fully-resident HashMap.
+pub struct OnDiskDictionary {
+    map: MemoryMap,
+    index: Vec<Entry>,
+}
+
+impl OnDiskDictionary {
+    /// Write `counts` out to `data_path`/`index_path` in sorted-word-table
+    /// form, ready to be `open`ed later.
+    pub fn build(counts: &HashMap<String, usize>, data_path: &str,
+                 index_path: &str) -> IoResult<()> {
+        let mut words: Vec<&String> = counts.keys().collect();
+        words.sort();
+
+        let mut data_file = try!(File::create(&Path::new(data_path)));
+        let mut index_file = try!(File::create(&Path::new(index_path)));
+        let mut offset: usize = 0;
+        for word in words.iter() {
+            let bytes = word.as_bytes();
+            try!(data_file.write(bytes));
+            let count = *counts.get(*word).unwrap();
+            try!(index_file.write_le_uint(offset));
+            try!(index_file.write_le_uint(bytes.len()));
+            try!(index_file.write_le_uint(count));
+            offset += bytes.len();
+        }
+        Ok(())
+    }
+
+    /// Memory-map `data_path` and load the (small) index at
+    /// `index_path`, ready for lookups.
+    pub fn open(data_path: &str, index_path: &str) -> IoResult<OnDiskDictionary> {
+        let data_len = try!(Path::new(data_path).stat()).size as usize;
+        let data_file = try!(File::open(&Path::new(data_path)));
+        let map = MemoryMap::new(data_len, &[MapReadable, MapFd(data_file.as_raw_fd())])
+            .ok().expect("could not memory-map dictionary data file");
+
+        let mut index_file = try!(File::open(&Path::new(index_path)));
+        let mut index = Vec::new();
+        while let (Ok(offset), Ok(length), Ok(count)) =
+                (index_file.read_le_uint(), index_file.read_le_uint(), index_file.read_le_uint()) {
+            index.push(Entry { offset: offset, length: length, count: count });
+        }
+
+        Ok(OnDiskDictionary { map: map, index: index })
+    }
+
+    /// The word bytes for the entry at `index[i]`, read directly out
+    /// of the memory-mapped data file.
+    fn word_at(&self, i: usize) -> &[u8] {
+        let entry = &self.index[i];
+        unsafe {
+            let base = self.map.data() as *const u8;
+            std::slice::from_raw_parts(base.offset(entry.offset as isize), entry.length)
+        }
+    }
+
+    /// Binary-search the sorted index for `word`, returning its
+    /// trained count if present.
+    pub fn lookup(&self, word: &str) -> Option<usize> {
+        let target = word.as_bytes();
+        let mut lo = 0us;
+        let mut hi = self.index.len();
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let candidate = self.word_at(mid);
+            if candidate == target {
+                return Some(self.index[mid].count);
+            } else if candidate < target {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,171 @@
+#[doc="
+    Module: sketch
+
+    A bounded-memory alternative to training into an exact
+    HashMap<String, usize>. CountMinSketch approximates per-word
+    counts in a fixed-size table of counters, and HeavyHitters tracks
+    the top-K most frequent words seen so far (by sketch estimate), so
+    arbitrarily large corpora can be trained within a fixed memory
+    budget, trading exactness for a tunable accuracy/memory ratio.
+"]
+
+use std::collections::BinaryHeap;
+use std::cmp::Ordering;
+use std::hash::{hash, SipHasher};
+
+/// A count-min sketch: `depth` independent hash rows, each with
+/// `width` counters. A word's estimated count is the minimum of its
+/// counters across all rows, which never undercounts and only
+/// overcounts due to hash collisions.
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    table: Vec<Vec<usize>>,
+}
+
+impl CountMinSketch {
+    /// Create a sketch with `depth` rows of `width` counters each.
+    /// Larger values reduce collision-driven overcounting at the
+    /// cost of more memory.
+    pub fn new(width: usize, depth: usize) -> CountMinSketch {
+        CountMinSketch {
+            width: width,
+            depth: depth,
+            table: (0..depth).map(|_| vec![0us; width]).collect(),
+        }
+    }
+
+    /// Hash `word` under row `seed`, giving a column index.
+    fn index(&self, word: &str, seed: usize) -> usize {
+        let h = hash::<_, SipHasher>(&(seed, word));
+        (h as usize) % self.width
+    }
+
+    /// Record one more occurrence of `word`.
+    pub fn increment(&mut self, word: &str) {
+        for row in 0..self.depth {
+            let idx = self.index(word, row);
+            self.table[row][idx] += 1;
+        }
+    }
+
+    /// The estimated count of `word`, always >= its true count.
+    pub fn estimate(&self, word: &str) -> usize {
+        (0..self.depth).map(|row| {
+            let idx = self.index(word, row);
+            self.table[row][idx]
+        }).min().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod count_min_sketch_tests {
+    use super::CountMinSketch;
+
+    #[test]
+    fn test_increment_and_estimate() {
+        let mut sketch = CountMinSketch::new(1024, 4);
+        for _ in 0..5 {
+            sketch.increment("hello");
+        }
+        sketch.increment("world");
+        assert_eq!(sketch.estimate("hello"), 5);
+        assert_eq!(sketch.estimate("world"), 1);
+        assert_eq!(sketch.estimate("missing"), 0);
+    }
+}
+
+/// One tracked word and its (estimated) count, ordered so a
+/// min-BinaryHeap evicts the smallest count first.
+#[derive(PartialEq, Eq)]
+struct HeavyHitter {
+    count: usize,
+    word: String,
+}
+
+impl Ord for HeavyHitter {
+    fn cmp(&self, other: &HeavyHitter) -> Ordering {
+        other.count.cmp(&self.count)
+    }
+}
+
+impl PartialOrd for HeavyHitter {
+    fn partial_cmp(&self, other: &HeavyHitter) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Tracks (approximately) the `k` most frequent words seen so far,
+/// using a count-min sketch to estimate counts in bounded memory.
+pub struct HeavyHitters {
+    k: usize,
+    sketch: CountMinSketch,
+    heap: BinaryHeap<HeavyHitter>,
+    tracked: Vec<String>,
+}
+
+impl HeavyHitters {
+    /// Track the top `k` words, backed by a sketch of the given
+    /// `width`/`depth`.
+    pub fn new(k: usize, width: usize, depth: usize) -> HeavyHitters {
+        HeavyHitters {
+            k: k,
+            sketch: CountMinSketch::new(width, depth),
+            heap: BinaryHeap::new(),
+            tracked: Vec::new(),
+        }
+    }
+
+    /// Record one more occurrence of `word`, updating the top-k set.
+    pub fn observe(&mut self, word: &str) {
+        self.sketch.increment(word);
+        let count = self.sketch.estimate(word);
+        if !self.tracked.iter().any(|w| w.as_slice() == word) {
+            if self.tracked.len() < self.k {
+                self.tracked.push(word.to_string());
+            } else {
+                // Replace the weakest tracked word if `word` now outranks it.
+                let weakest = self.tracked.iter().enumerate()
+                    .map(|(i, w)| (i, self.sketch.estimate(w.as_slice())))
+                    .min_by(|&(_, c)| c);
+                match weakest {
+                    Some((i, c)) if count > c => { self.tracked[i] = word.to_string(); },
+                    _ => {},
+                }
+            }
+        }
+        self.heap.push(HeavyHitter { count: count, word: word.to_string() });
+        if self.heap.len() > self.k {
+            self.heap.pop();
+        }
+    }
+
+    /// The current top-k words with their estimated counts, most
+    /// frequent first.
+    pub fn top(&self) -> Vec<(String, usize)> {
+        let mut seen: Vec<(String, usize)> = Vec::new();
+        for word in self.tracked.iter() {
+            let count = self.sketch.estimate(word.as_slice());
+            seen.push((word.clone(), count));
+        }
+        seen.sort_by(|a, b| b.1.cmp(&a.1));
+        seen.into_iter().take(self.k).collect()
+    }
+}
+
+#[cfg(test)]
+mod heavy_hitters_tests {
+    use super::HeavyHitters;
+
+    #[test]
+    fn test_top() {
+        let mut hh = HeavyHitters::new(2, 1024, 4);
+        for _ in 0..10 { hh.observe("the"); }
+        for _ in 0..5 { hh.observe("quick"); }
+        hh.observe("fox");
+        let top = hh.top();
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].0, "the".to_string());
+        assert_eq!(top[1].0, "quick".to_string());
+    }
+}
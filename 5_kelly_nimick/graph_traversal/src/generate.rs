@@ -0,0 +1,115 @@
+#[doc="
+    Module: generate
+
+    Synthetic graph generators for exercising the path-finding
+    algorithms without shipping large data files: `erdos_renyi` wires
+    up `edges` random edges among `nodes` nodes, and `scale_free`
+    grows a graph by preferential attachment so a handful of nodes
+    end up with most of the connections, closer to real-world
+    networks. Both take a seed so a `--generate` run is reproducible.
+"]
+
+extern crate graph_lib;
+
+use std::collections::HashSet;
+use std::rand::{Rng, SeedableRng, StdRng};
+use graph_lib::LabeledGraph;
+
+/// Label for generated node `i`.
+fn node_label(i: usize) -> String {
+    format!("n{}", i)
+}
+
+fn seeded_rng(seed: usize) -> StdRng {
+    SeedableRng::from_seed(&[seed][..])
+}
+
+/// Builds a graph on `nodes` nodes with `edges` edges chosen
+/// uniformly at random among all ordered node pairs (the
+/// Erdős–Rényi G(n, m) model), deterministic for a given `seed`.
+pub fn erdos_renyi(nodes: usize, edges: usize, seed: usize, bidirectional: bool) -> LabeledGraph<String> {
+    let mut rng = seeded_rng(seed);
+    let mut g = if bidirectional { LabeledGraph::new_bidirectional() } else { LabeledGraph::new() };
+    for i in range(0, nodes) {
+        g.add_node(&node_label(i));
+    }
+    if nodes < 2 { return g; }
+
+    let max_edges = nodes * (nodes - 1);
+    let mut seen: HashSet<(usize, usize)> = HashSet::new();
+    while seen.len() < edges && seen.len() < max_edges {
+        let source = rng.gen_range(0, nodes);
+        let target = rng.gen_range(0, nodes);
+        if source == target || !seen.insert((source, target)) { continue; }
+        g.add_edge(&node_label(source), &node_label(target), 1, true);
+    }
+    g
+}
+
+/// Builds a graph on `nodes` nodes by preferential attachment: each
+/// new node draws `edges_per_node` existing nodes, with probability
+/// proportional to how many edges they already have, and connects to
+/// each distinct node drawn (repeat draws add no edge, and the second
+/// node, with no edges yet to sample from, connects only to the
+/// first), so a few nodes end up much more connected than the rest (the
+/// scale-free, or Barabási–Albert, model). Deterministic for a
+/// given `seed`.
+pub fn scale_free(nodes: usize, edges_per_node: usize, seed: usize, bidirectional: bool) -> LabeledGraph<String> {
+    let mut rng = seeded_rng(seed);
+    let mut g = if bidirectional { LabeledGraph::new_bidirectional() } else { LabeledGraph::new() };
+    if nodes == 0 { return g; }
+
+    // Every existing edge endpoint appears once in this list per
+    // edge it's part of, so sampling uniformly from it is the same
+    // as sampling proportional to degree.
+    let mut targets: Vec<usize> = Vec::new();
+
+    g.add_node(&node_label(0));
+    for i in range(1, nodes) {
+        g.add_node(&node_label(i));
+        let attach_count = if targets.is_empty() { 1 } else { edges_per_node };
+        let mut chosen: HashSet<usize> = HashSet::new();
+        for _ in range(0, attach_count) {
+            let target = if targets.is_empty() { 0 } else { targets[rng.gen_range(0, targets.len())] };
+            if chosen.insert(target) {
+                g.add_edge(&node_label(i), &node_label(target), 1, true);
+                targets.push(i);
+                targets.push(target);
+            }
+        }
+    }
+    g
+}
+
+#[cfg(test)]
+mod generate_test {
+    use super::{erdos_renyi, scale_free};
+
+    #[test]
+    fn test_erdos_renyi_node_and_edge_count() {
+        let g = erdos_renyi(10, 15, 1, true);
+        assert_eq!(g.labels().len(), 10);
+        let edge_count = g.labels().iter().map(|l| g.edges_from(l).len()).fold(0, |a, b| a + b);
+        assert_eq!(edge_count, 15);
+    }
+
+    #[test]
+    fn test_erdos_renyi_deterministic_for_seed() {
+        let a = erdos_renyi(20, 30, 42, false);
+        let b = erdos_renyi(20, 30, 42, false);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_scale_free_node_count() {
+        let g = scale_free(10, 2, 1, true);
+        assert_eq!(g.labels().len(), 10);
+    }
+
+    #[test]
+    fn test_scale_free_deterministic_for_seed() {
+        let a = scale_free(20, 3, 7, false);
+        let b = scale_free(20, 3, 7, false);
+        assert_eq!(a, b);
+    }
+}
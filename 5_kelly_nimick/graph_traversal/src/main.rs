@@ -1,8 +1,12 @@
 #![allow(unstable)]
+extern crate regex;
+extern crate graph_lib;
+
 use std::io::{File, BufferedReader};
-use graph::LabeledGraph;
+use regex::Regex;
+use graph_lib::LabeledGraph;
 
-mod graph;
+mod generate;
 
 static NO_PATH: &'static str = "No path found";
 static WRONG_NODE_COUNT: &'static str = "You must provide a start and end node";
@@ -12,15 +16,64 @@ fn main() {
     use std::{io, os};
 
     let args = os::args();
-    let graph_file = match args.iter().skip(1).take(1).next() {
-        Some(file) => file.as_slice(),
-        None => panic!("Must provide graph data file")
+    let bidirectional = args.iter().any(|a| a.as_slice() == "--bidirectional");
+    let scale_free = args.iter().any(|a| a.as_slice() == "--scale-free");
+    let load_cache = flag_value(&args, "--load-cache");
+    let save_cache = flag_value(&args, "--save-cache");
+    let generate_spec = two_flag_values(&args, "--generate");
+    let seed: usize = flag_value(&args, "--seed").and_then(|s| s.parse()).unwrap_or(0);
+
+    let mut graph = match (load_cache, generate_spec) {
+        (Some(ref cache_file), _) =>
+            LabeledGraph::deserialize(cache_file.as_slice()).ok().expect("couldn't load cached graph"),
+        (None, Some((nodes, edges))) => {
+            if scale_free {
+                generate::scale_free(nodes, edges, seed, bidirectional)
+            } else {
+                generate::erdos_renyi(nodes, edges, seed, bidirectional)
+            }
+        },
+        (None, None) => {
+            let graph_file = match args.iter().skip(1)
+                .filter(|a| a.as_slice() != "--bidirectional" && a.as_slice() != "--save-cache"
+                            && save_cache.as_ref().map(|c| c.as_slice()) != Some(a.as_slice()))
+                .take(1).next() {
+                Some(file) => file.as_slice(),
+                None => panic!("Must provide graph data file")
+            };
+            load_graph(graph_file, bidirectional)
+        }
     };
-    let mut file_reader = open_file(graph_file);
-    let graph = build_graph(&mut file_reader);
+
+    if let Some(ref cache_file) = save_cache {
+        graph.serialize(cache_file.as_slice()).ok().expect("couldn't save cached graph");
+    }
+
     let mut stdin = BufferedReader::new(io::stdin());
     let mut stdout = io::stdout();
-    query_user(&mut stdout, &mut stdin, &graph);
+    query_user(&mut stdout, &mut stdin, &mut graph);
+}
+
+/// Find the value immediately following a `--flag value` pair in
+/// `args`, if the flag is present.
+fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter().position(|a| a.as_slice() == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(|v| v.clone())
+}
+
+/// Find the two values immediately following a `--flag n m` triple
+/// in `args`, parsed as `usize`s, if the flag is present.
+fn two_flag_values(args: &[String], flag: &str) -> Option<(usize, usize)> {
+    args.iter().position(|a| a.as_slice() == flag).and_then(|i| {
+        match (args.get(i + 1), args.get(i + 2)) {
+            (Some(n), Some(m)) => match (n.parse(), m.parse()) {
+                (Some(n), Some(m)) => Some((n, m)),
+                _ => None,
+            },
+            _ => None,
+        }
+    })
 }
 
 /// Open the file as given by filename in the form of a Buffered Reader
@@ -29,16 +82,114 @@ fn open_file(filename: &str) -> BufferedReader<File> {
     BufferedReader::new(file.ok().expect("couldn't open file"))
 }
 
-/// Create the graph by reading edges from the Buffered Reader
-fn build_graph<B: Buffer>(reader: &mut B) -> LabeledGraph {
-    let mut g = LabeledGraph::new();
+/// Load a graph from `filename`, picking the format by extension:
+/// ".json" is parsed as a JSON edge-list, ".graphml" as GraphML, and
+/// anything else falls back to the whitespace adjacency format read
+/// by `build_graph`. With `bidirectional` true, the resulting graph's
+/// `find_shortest_path` searches from both ends at once instead of
+/// running plain Dijkstra's algorithm.
+fn load_graph(filename: &str, bidirectional: bool) -> LabeledGraph<String> {
+    let path = Path::new(filename);
+    match path.extension_str() {
+        Some("json") =>
+            build_graph_from_json(read_whole_file(filename).as_slice(), bidirectional),
+        Some("graphml") =>
+            build_graph_from_graphml(read_whole_file(filename).as_slice(), bidirectional),
+        _ => build_graph(&mut open_file(filename), bidirectional),
+    }
+}
+
+/// Read the entire contents of `filename` into a String.
+fn read_whole_file(filename: &str) -> String {
+    let mut file = File::open(&Path::new(filename)).ok().expect("couldn't open file");
+    file.read_to_string().ok().expect("couldn't read file")
+}
+
+/// Build a graph from a JSON edge-list of the form
+/// `{"edges": [{"source": "a", "target": "b", "weight": 3}, ...]}`,
+/// where "weight" is optional and defaults to 1. Edges are always
+/// added as directed, matching the order they appear in the list.
+fn build_graph_from_json(contents: &str, bidirectional: bool) -> LabeledGraph<String> {
+    let mut g = if bidirectional { LabeledGraph::new_bidirectional() } else { LabeledGraph::new() };
+    let edge_regex = match Regex::new(
+        "\"source\"\\s*:\\s*\"([^\"]*)\"\\s*,\\s*\"target\"\\s*:\\s*\"([^\"]*)\"\
+         (?:\\s*,\\s*\"weight\"\\s*:\\s*(\\d+))?") {
+        Ok(re)  => re,
+        Err(..) => panic!("Could not compile regex"),
+    };
+    for cap in edge_regex.captures_iter(contents) {
+        let source = cap.at(1).unwrap().to_string();
+        let target = cap.at(2).unwrap().to_string();
+        let weight = match cap.at(3) {
+            Some(w) => w.parse().expect("edge weight must be a non-negative integer"),
+            None => 1,
+        };
+        g.add_edge(&source, &target, weight, true);
+    }
+    g
+}
+
+/// Build a graph from a GraphML file, reading every `<edge source="..."
+/// target="...">` element (self-closed or with a body), taking the
+/// edge's weight from a nested `<data key="weight">N</data>` element
+/// when present and defaulting to 1 otherwise. Edges are always added
+/// as directed, matching the order they appear in the file.
+fn build_graph_from_graphml(contents: &str, bidirectional: bool) -> LabeledGraph<String> {
+    let mut g = if bidirectional { LabeledGraph::new_bidirectional() } else { LabeledGraph::new() };
+    let edge_regex = match Regex::new(
+        "(?s)<edge\\s+source=\"([^\"]*)\"\\s+target=\"([^\"]*)\"[^>]*?\
+         (?:/>|>(.*?)</edge>)") {
+        Ok(re)  => re,
+        Err(..) => panic!("Could not compile regex"),
+    };
+    let weight_regex = match Regex::new("<data\\s+key=\"weight\">\\s*(\\d+)\\s*</data>") {
+        Ok(re)  => re,
+        Err(..) => panic!("Could not compile regex"),
+    };
+    for cap in edge_regex.captures_iter(contents) {
+        let source = cap.at(1).unwrap().to_string();
+        let target = cap.at(2).unwrap().to_string();
+        let body = cap.at(3).unwrap_or("");
+        let weight = match weight_regex.captures(body) {
+            Some(wcap) => wcap.at(1).unwrap().parse()
+                .expect("edge weight must be a non-negative integer"),
+            None => 1,
+        };
+        g.add_edge(&source, &target, weight, true);
+    }
+    g
+}
+
+/// Create the graph by reading edges from the Buffered Reader.
+/// If the first line is exactly "directed" or "undirected", it is
+/// consumed as a directive choosing whether every edge that follows
+/// is added in both directions; absent that directive, edges are
+/// directed, as before. Each remaining line is a source node
+/// followed by its neighbors; a neighbor may optionally carry a
+/// weight as "label:weight" (e.g. "c:7"), defaulting to a weight of
+/// 1 when none is given. With `bidirectional` true, the resulting
+/// graph's `find_shortest_path` searches from both ends at once
+/// instead of running plain Dijkstra's algorithm.
+fn build_graph<B: Buffer>(reader: &mut B, bidirectional: bool) -> LabeledGraph<String> {
+    let mut g = if bidirectional { LabeledGraph::new_bidirectional() } else { LabeledGraph::new() };
+    let mut directed = true;
+    let mut first_line = true;
     for line in reader.lines() {
         let l: String  = line.unwrap();
+        if first_line {
+            first_line = false;
+            match l.trim() {
+                "directed" => continue,
+                "undirected" => { directed = false; continue; },
+                _ => {},
+            }
+        }
         let mut words = l.words();
         match words.next() {
             Some(node) => {
                 for neighbor in words {
-                    g.add_edge(node, neighbor);
+                    let (label, weight) = parse_neighbor(neighbor);
+                    g.add_edge(&node.to_string(), &label.to_string(), weight, directed);
                 }
             },
             None => {},
@@ -47,57 +198,213 @@ fn build_graph<B: Buffer>(reader: &mut B) -> LabeledGraph {
     g
 }
 
+/// Split a neighbor token of the form "label" or "label:weight"
+/// into the node's label and the edge's weight, defaulting to a
+/// weight of 1 when no weight is given.
+fn parse_neighbor(token: &str) -> (&str, usize) {
+    match token.find(':') {
+        Some(i) => {
+            let weight = token.slice_from(i + 1).parse()
+                .expect("edge weight must be a non-negative integer");
+            (token.slice_to(i), weight)
+        },
+        None => (token, 1),
+    }
+}
+
+/// Write `graph` to `filename` in the same whitespace adjacency
+/// format read by `build_graph`: one line per node, listing its
+/// outgoing neighbors as "label" or "label:weight" when the weight
+/// isn't 1.
+fn save_graph(graph: &LabeledGraph<String>, filename: &str) {
+    let mut file = File::create(&Path::new(filename)).ok().expect("couldn't create file");
+    for label in graph.labels().iter() {
+        let mut line = label.clone();
+        for &(ref target, weight) in graph.edges_from(label).iter() {
+            line.push_str(" ");
+            if weight == 1 {
+                line.push_str(target.as_slice());
+            } else {
+                line.push_str(format!("{}:{}", target, weight).as_slice());
+            }
+        }
+        file.write_line(line.as_slice()).ok().expect("couldn't write file");
+    }
+}
+
 #[cfg(test)]
 mod build_graph_test {
-    use super::{open_file, build_graph};
-    use graph::LabeledGraph;
+    use super::{open_file, build_graph, build_graph_from_json, build_graph_from_graphml};
+    use graph_lib::LabeledGraph;
     use std::io::{MemReader, BufferedReader};
 
     #[test]
     fn test_build_graph() {
-        let mut g = LabeledGraph::new();
-        g.add_edge("a", "b");
-        g.add_edge("b", "c");
-        g.add_edge("c", "d");
-        g.add_edge("e", "d");
-        g.add_edge("f", "d");
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.add_edge(&"b".to_string(), &"c".to_string(), 1, true);
+        g.add_edge(&"c".to_string(), &"d".to_string(), 1, true);
+        g.add_edge(&"e".to_string(), &"d".to_string(), 1, true);
+        g.add_edge(&"f".to_string(), &"d".to_string(), 1, true);
         let input = "a b\nb c\nc d\ne d\nf d";
         let bytes = input.to_string().into_bytes();
         let mut r: BufferedReader<MemReader> =
             BufferedReader::new(MemReader::new(bytes));
-        assert_eq!(build_graph(&mut r), g);
+        assert_eq!(build_graph(&mut r, false), g);
     }
+
+    #[test]
+    fn test_build_graph_with_weights() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 3, true);
+        g.add_edge(&"a".to_string(), &"c".to_string(), 7, true);
+        let input = "a b:3 c:7";
+        let bytes = input.to_string().into_bytes();
+        let mut r: BufferedReader<MemReader> =
+            BufferedReader::new(MemReader::new(bytes));
+        assert_eq!(build_graph(&mut r, false), g);
+    }
+
+    #[test]
+    fn test_build_graph_bidirectional() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new_bidirectional();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        let input = "a b";
+        let bytes = input.to_string().into_bytes();
+        let mut r: BufferedReader<MemReader> =
+            BufferedReader::new(MemReader::new(bytes));
+        assert_eq!(build_graph(&mut r, true), g);
+    }
+
+    #[test]
+    fn test_build_graph_undirected_directive() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, false);
+        g.add_edge(&"b".to_string(), &"c".to_string(), 1, false);
+        let input = "undirected\na b\nb c";
+        let bytes = input.to_string().into_bytes();
+        let mut r: BufferedReader<MemReader> =
+            BufferedReader::new(MemReader::new(bytes));
+        assert_eq!(build_graph(&mut r, false), g);
+    }
+
     #[test]
 
     fn test_graph_from_file() {
         let mut file = open_file("test_graph.dat");
-        let g = build_graph(&mut file);
-
-        let mut eg = LabeledGraph::new();
-        eg.add_edge("a", "b");
-        eg.add_edge("a", "d");
-        eg.add_edge("b", "a");
-        eg.add_edge("b", "d");
-        eg.add_edge("d", "c");
+        let g = build_graph(&mut file, false);
+
+        let mut eg: LabeledGraph<String> = LabeledGraph::new();
+        eg.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        eg.add_edge(&"a".to_string(), &"d".to_string(), 1, true);
+        eg.add_edge(&"b".to_string(), &"a".to_string(), 1, true);
+        eg.add_edge(&"b".to_string(), &"d".to_string(), 1, true);
+        eg.add_edge(&"d".to_string(), &"c".to_string(), 1, true);
         assert_eq!(g, eg);
     }
+
+    #[test]
+    fn test_build_graph_from_json() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 3, true);
+        g.add_edge(&"b".to_string(), &"c".to_string(), 1, true);
+        let input = "{\"edges\": [\
+            {\"source\": \"a\", \"target\": \"b\", \"weight\": 3}, \
+            {\"source\": \"b\", \"target\": \"c\"}\
+        ]}";
+        assert_eq!(build_graph_from_json(input, false), g);
+    }
+
+    #[test]
+    fn test_build_graph_from_graphml() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 3, true);
+        g.add_edge(&"b".to_string(), &"c".to_string(), 1, true);
+        let input = "<graphml><graph edgedefault=\"directed\">\
+            <node id=\"a\"/><node id=\"b\"/><node id=\"c\"/>\
+            <edge source=\"a\" target=\"b\"><data key=\"weight\">3</data></edge>\
+            <edge source=\"b\" target=\"c\"/>\
+            </graph></graphml>";
+        assert_eq!(build_graph_from_graphml(input, false), g);
+    }
 }
 
-/// Query the user to find out what shortest path they want to find
+/// Query the user to find out what shortest path they want to find.
+/// A path between two nodes is printed followed by its total weight
+/// in parentheses. Also supports "rmedge <source> <target>" and
+/// "rmnode <label>" to remove part of the graph, so "what if this
+/// link goes away" scenarios can be explored interactively;
+/// "maxflow <source> <target>" to compute the maximum flow between
+/// two nodes (treating edge weights as capacities) along with its
+/// min-cut edge set; and "addnode <label>", "addedge <source>
+/// <target> [weight]", and "save <file>" to build up a graph on the
+/// fly and persist it back in the whitespace adjacency format read
+/// by `build_graph`.
 #[allow(unused_must_use)]
 fn query_user<W: Writer, R: Buffer>(output: &mut W, input: &mut R,
-                                    graph: &LabeledGraph) {
+                                    graph: &mut LabeledGraph<String>) {
     output.write_str("-> ");
     output.flush();
     while let Some(line) = input.read_line().ok() {
-        let nodes: Vec<&str> = line.words().collect();
-        if nodes.len() == 2 {
-            match graph.find_shortest_path(nodes[0], nodes[1]) {
-                Some(v) => {
-                    for n in v.iter() {
+        let words: Vec<String> = line.words().map(|w| w.to_string()).collect();
+        if words.len() == 3 && words[0].as_slice() == "rmedge" {
+            graph.remove_edge(&words[1], &words[2]);
+        } else if words.len() == 2 && words[0].as_slice() == "rmnode" {
+            graph.remove_node(&words[1]);
+        } else if words.len() == 2 && words[0].as_slice() == "addnode" {
+            graph.add_node(&words[1]);
+        } else if words.len() == 2 && words[0].as_slice() == "save" {
+            save_graph(graph, words[1].as_slice());
+        } else if (words.len() == 3 || words.len() == 4) && words[0].as_slice() == "addedge" {
+            let weight = if words.len() == 4 {
+                words[3].parse().expect("edge weight must be a non-negative integer")
+            } else {
+                1
+            };
+            graph.add_edge(&words[1], &words[2], weight, true);
+        } else if words.len() == 3 && words[0].as_slice() == "all" {
+            let mut paths = graph.find_all_shortest_paths(&words[1], &words[2]);
+            paths.sort();
+            if paths.is_empty() {
+                output.write_line(format!("{} ", NO_PATH).as_slice());
+            } else {
+                for path in paths.iter() {
+                    for n in path.iter() {
                         output.write_str(format!("{} ", n).as_slice());
                     }
                     output.write_str("\n");
+                }
+            }
+        } else if words.len() == 3 && words[0].as_slice() == "maxflow" {
+            match graph.max_flow(&words[1], &words[2]) {
+                Some((flow, mut cut)) => {
+                    output.write_str(format!("{}\n", flow).as_slice());
+                    cut.sort();
+                    for &(ref source, ref target) in cut.iter() {
+                        output.write_str(format!("{} {}\n", source, target).as_slice());
+                    }
+                },
+                None => {
+                    output.write_line(format!("{} ", NO_PATH).as_slice());
+                }
+            }
+        } else if words.len() == 2 && (words[0].as_slice() == "bfs" || words[0].as_slice() == "dfs") {
+            let visited: Vec<String> = if words[0].as_slice() == "bfs" {
+                graph.bfs_from(&words[1]).collect()
+            } else {
+                graph.dfs_from(&words[1]).collect()
+            };
+            for n in visited.iter() {
+                output.write_str(format!("{} ", n).as_slice());
+            }
+            output.write_str("\n");
+        } else if words.len() == 2 {
+            match graph.find_shortest_path_with_cost(&words[0], &words[1]) {
+                Some((v, cost)) => {
+                    for n in v.iter() {
+                        output.write_str(format!("{} ", n).as_slice());
+                    }
+                    output.write_str(format!("({})\n", cost).as_slice());
                 },
                 None => {
                     output.write_line(format!("{} ", NO_PATH).as_slice());
@@ -115,18 +422,100 @@ fn query_user<W: Writer, R: Buffer>(output: &mut W, input: &mut R,
 #[cfg(test)]
 mod query_user_test {
     use super::{NO_PATH, query_user};
-    use graph::LabeledGraph;
+    use graph_lib::LabeledGraph;
     use std::io::{MemWriter, MemReader, BufferedReader};
 
     #[test]
     fn test_query_user() {
-        let g = create_graph();
-        run_test("a b", "a b", &g);
-        run_test("a d", "a b c d", &g);
-        run_test("d a", NO_PATH, &g);
+        let mut g = create_graph();
+        run_test_with_cost("a b", "a b (1)", &mut g);
+        run_test_with_cost("a d", "a b c d (3)", &mut g);
+        run_test("d a", NO_PATH, &mut g);
+    }
+
+    #[test]
+    fn test_query_user_all() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.add_edge(&"a".to_string(), &"c".to_string(), 1, true);
+        g.add_edge(&"b".to_string(), &"d".to_string(), 1, true);
+        g.add_edge(&"c".to_string(), &"d".to_string(), 1, true);
+        run_test("all a d", "a b d \na c d", &mut g);
+        run_test("all d a", NO_PATH, &mut g);
+    }
+
+    #[test]
+    fn test_query_user_bfs_dfs() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.add_edge(&"b".to_string(), &"c".to_string(), 1, true);
+        run_test("bfs a", "a b c", &mut g);
+        run_test("dfs a", "a b c", &mut g);
+    }
+
+    #[test]
+    fn test_query_user_rmedge() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        let mut r: BufferedReader<MemReader> =
+            BufferedReader::new(MemReader::new("rmedge a b\na b\n".to_string().into_bytes()));
+        let mut w: MemWriter = MemWriter::new();
+        query_user(&mut w, &mut r, &mut g);
+        let result = String::from_utf8(w.into_inner()).ok().unwrap();
+        assert_eq!(result, format!("-> -> {} \n-> ", NO_PATH));
+    }
+
+    #[test]
+    fn test_query_user_rmnode() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.add_edge(&"b".to_string(), &"c".to_string(), 1, true);
+        let mut r: BufferedReader<MemReader> =
+            BufferedReader::new(MemReader::new("rmnode b\na c\n".to_string().into_bytes()));
+        let mut w: MemWriter = MemWriter::new();
+        query_user(&mut w, &mut r, &mut g);
+        let result = String::from_utf8(w.into_inner()).ok().unwrap();
+        assert_eq!(result, format!("-> -> {} \n-> ", NO_PATH));
+    }
+
+    #[test]
+    fn test_query_user_maxflow() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 3, true);
+        g.add_edge(&"a".to_string(), &"c".to_string(), 2, true);
+        g.add_edge(&"b".to_string(), &"d".to_string(), 2, true);
+        g.add_edge(&"c".to_string(), &"d".to_string(), 3, true);
+        let mut r: BufferedReader<MemReader> =
+            BufferedReader::new(MemReader::new("maxflow a d\n".to_string().into_bytes()));
+        let mut w: MemWriter = MemWriter::new();
+        query_user(&mut w, &mut r, &mut g);
+        let result = String::from_utf8(w.into_inner()).ok().unwrap();
+        assert_eq!(result, "-> 4\na c\nb d\n-> ");
     }
 
-    fn run_test(input: &str, output: &str, graph: &LabeledGraph) {
+    #[test]
+    fn test_query_user_addnode() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        let mut r: BufferedReader<MemReader> =
+            BufferedReader::new(MemReader::new("addnode a\naddnode b\na b\n".to_string().into_bytes()));
+        let mut w: MemWriter = MemWriter::new();
+        query_user(&mut w, &mut r, &mut g);
+        let result = String::from_utf8(w.into_inner()).ok().unwrap();
+        assert_eq!(result, format!("-> -> -> {} \n-> ", NO_PATH));
+    }
+
+    #[test]
+    fn test_query_user_addedge() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        let mut r: BufferedReader<MemReader> =
+            BufferedReader::new(MemReader::new("addedge a b 3\na b\n".to_string().into_bytes()));
+        let mut w: MemWriter = MemWriter::new();
+        query_user(&mut w, &mut r, &mut g);
+        let result = String::from_utf8(w.into_inner()).ok().unwrap();
+        assert_eq!(result, "-> -> a b (3)\n-> ");
+    }
+
+    fn run_test(input: &str, output: &str, graph: &mut LabeledGraph<String>) {
         let mut user_input = input.to_string();
         user_input.push_str("\n");
         let bytes = user_input.into_bytes();
@@ -139,13 +528,29 @@ mod query_user_test {
         assert_eq!(result, expect);
     }
 
-    fn create_graph() -> LabeledGraph {
-        let mut g = LabeledGraph::new();
-        g.add_edge("a", "b");
-        g.add_edge("b", "c");
-        g.add_edge("c", "d");
-        g.add_edge("e", "d");
-        g.add_edge("f", "d");
+    /// Like `run_test`, but for a two-node query whose `output`
+    /// already includes the trailing "(cost)", which isn't followed
+    /// by the extra space a bare path or NO_PATH line gets.
+    fn run_test_with_cost(input: &str, output: &str, graph: &mut LabeledGraph<String>) {
+        let mut user_input = input.to_string();
+        user_input.push_str("\n");
+        let bytes = user_input.into_bytes();
+        let mut r: BufferedReader<MemReader> =
+            BufferedReader::new(MemReader::new(bytes));
+        let mut w: MemWriter = MemWriter::new();
+        query_user(&mut w, &mut r, graph);
+        let result = String::from_utf8(w.into_inner()).ok().unwrap();
+        let expect = format!("-> {}\n-> ", output);
+        assert_eq!(result, expect);
+    }
+
+    fn create_graph() -> LabeledGraph<String> {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.add_edge(&"b".to_string(), &"c".to_string(), 1, true);
+        g.add_edge(&"c".to_string(), &"d".to_string(), 1, true);
+        g.add_edge(&"e".to_string(), &"d".to_string(), 1, true);
+        g.add_edge(&"f".to_string(), &"d".to_string(), 1, true);
         g
     }
 }
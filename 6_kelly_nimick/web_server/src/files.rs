@@ -1,16 +1,26 @@
+extern crate time;
+
 use self::FileResult::{FileOk, NotFound, PermissionDenied, BadRequest, FileError};
 use std::io::{File, BufferedReader, IoError, IoErrorKind};
-
-static INDEX_FILES: [&'static str; 3] = ["index.html", "index.shtml", "index.txt"];
+use std::io::fs;
+use time::Timespec;
 
 pub enum FileResult {
-    FileOk(BufferedReader<File>),
+    FileOk(BufferedReader<File>, FileMeta),
     NotFound,
     PermissionDenied,
     BadRequest,
     FileError,
 }
 
+/// The bits of file metadata conditional GET needs: an ETag built from
+/// the file's size and modification time, and that same modification
+/// time again for comparing against If-Modified-Since.
+pub struct FileMeta {
+    pub etag: String,
+    pub last_modified: Timespec
+}
+
 impl FileResult {
 
     /// Return the HTTP message and code associated with the FileResult
@@ -26,13 +36,14 @@ impl FileResult {
 }
 
 /// If we find PermissionDenied or FileError as the result of opening an index
-/// file, then that is returned.
-pub fn open_file_with_indices(path: &str) -> (FileResult, bool) {
+/// file, then that is returned. `index_files` is tried in order for any
+/// path that names a directory.
+pub fn open_file_with_indices(path: &str, index_files: &[String]) -> (FileResult, bool) {
     if !path.is_empty() && path.chars().rev().next().unwrap() != '/' {
         return (open_file(path), is_html(path));
     }
-    for index_file in INDEX_FILES.iter() {
-        let index_path_string = path.to_string() + *index_file;
+    for index_file in index_files.iter() {
+        let index_path_string = path.to_string() + index_file.as_slice();
         let index_path: &str = index_path_string.as_slice();
         match open_file(index_path) {
             NotFound => continue,
@@ -46,10 +57,14 @@ pub fn open_file_with_indices(path: &str) -> (FileResult, bool) {
 mod open_file_with_indices_tests {
     use super::{FileResult, open_file_with_indices};
 
+    fn index_files() -> Vec<String> {
+        vec!["index.html".to_string(), "index.shtml".to_string(), "index.txt".to_string()]
+    }
+
     #[test]
     fn test_file_not_exist() {
         let my_str = "wharrgarbl";
-        match open_file_with_indices(my_str) {
+        match open_file_with_indices(my_str, index_files().as_slice()) {
             (FileResult::NotFound, false) => (),
             _ => panic!("bang"),
         }
@@ -58,7 +73,7 @@ mod open_file_with_indices_tests {
     #[test]
     fn test_file_exists() {
         let my_str = "test/index.html";
-        match open_file_with_indices(my_str) {
+        match open_file_with_indices(my_str, index_files().as_slice()) {
             (FileResult::FileOk(..), true) => (),
             _ => panic!("bang"),
         }
@@ -67,7 +82,7 @@ mod open_file_with_indices_tests {
     #[test]
     fn test_directory() {
         let my_str = "test/";
-        match open_file_with_indices(my_str) {
+        match open_file_with_indices(my_str, index_files().as_slice()) {
             (FileResult::FileOk(..), true) => (),
             _ => panic!("bang"),
         }
@@ -76,14 +91,29 @@ mod open_file_with_indices_tests {
 
 /// Open the file at the path given by the input &str
 pub fn open_file(path: &str) -> FileResult {
-    match File::open(&Path::new(path)) {
-        Ok(f) => FileOk(BufferedReader::new(f)),
+    let file_path = Path::new(path);
+    match File::open(&file_path) {
+        Ok(f) => FileOk(BufferedReader::new(f), file_meta(&file_path)),
         Err(IoError{kind:IoErrorKind::FileNotFound, ..}) => NotFound,
         Err(IoError{kind:IoErrorKind::PermissionDenied, ..}) => PermissionDenied,
         _ => FileError
     }
 }
 
+/// Build the ETag/Last-Modified pair for a file that's already known to
+/// exist and be readable. A stat() failure here right after a
+/// successful open is vanishingly unlikely, so it just falls back to an
+/// empty-ish ETag and the Unix epoch rather than failing the request.
+fn file_meta(path: &Path) -> FileMeta {
+    match fs::stat(path) {
+        Ok(stat) => FileMeta {
+            etag: format!("\"{:x}-{:x}\"", stat.size, stat.modified),
+            last_modified: Timespec::new((stat.modified / 1000) as i64, 0)
+        },
+        Err(..) => FileMeta { etag: "\"0-0\"".to_string(), last_modified: Timespec::new(0, 0) }
+    }
+}
+
 #[cfg(test)]
 mod open_file_tests {
     use super::{FileResult, open_file};
@@ -107,6 +137,23 @@ mod open_file_tests {
     }
 }
 
+#[cfg(test)]
+mod file_meta_tests {
+    use super::open_file;
+    use super::FileResult::FileOk;
+
+    #[test]
+    fn test_open_file_populates_meta() {
+        match open_file("test/index.html") {
+            FileOk(_, meta) => {
+                assert!(!meta.etag.is_empty());
+                assert!(meta.last_modified.sec > 0);
+            },
+            _ => panic!("bang"),
+        }
+    }
+}
+
 /// Determine if the file ends with html
 fn is_html(s: &str) -> bool {
     s.split('.').rev().next().unwrap_or("") == "html"
@@ -1,10 +1,141 @@
 #![allow(unstable)]
 
+#[cfg(not(test))]
+use std::os;
+#[cfg(not(test))]
+use std::io::BufferedReader;
+#[cfg(not(test))]
+use std::io::fs::File;
+
+#[cfg(not(test))]
+static BIND_ADDR: &'static str = "127.0.0.1:8000";
+
+// Config file for the bind address, document root, index file list,
+// and CGI settings: "bind_addr:<host:port>", "document_root:<dir>",
+// "index_files:<comma-separated list>", "cgi_dir:<dir>", and
+// "cgi_timeout_ms:<millis>", one per line. A missing config file or
+// key falls back to the compiled-in default, so deployments only need
+// to set what they want to change.
+#[cfg(not(test))]
+static CONFIG_PATH: &'static str = "config.dat";
+
 mod rustyd;
 mod files;
 mod stream;
 
+/// Where the server binds and which files it serves, loaded from
+/// CONFIG_PATH and then overridden by any matching "--key value"
+/// command-line arguments, so a deployment can point at a different
+/// document root or bind address without recompiling. cgi_dir is
+/// empty by default, meaning CGI is disabled.
+#[cfg(not(test))]
+struct Config {
+    bind_addr: String,
+    document_root: String,
+    index_files: Vec<String>,
+    cgi_dir: String,
+    cgi_timeout_ms: u64
+}
+
+#[cfg(not(test))]
+impl Config {
+    fn defaults() -> Config {
+        Config {
+            bind_addr: BIND_ADDR.to_string(),
+            document_root: ".".to_string(),
+            index_files: vec!["index.html".to_string(), "index.shtml".to_string(), "index.txt".to_string()],
+            cgi_dir: "".to_string(),
+            cgi_timeout_ms: 5000
+        }
+    }
+}
+
 #[cfg(not(test))]
 fn main() {
-    rustyd::serve_forever();
+    use rustyd::{StationRegistry, HandlerRegistry, ServerConfig};
+
+    let mut args = os::args();
+    args.remove(0);
+
+    let mut config = load_config(CONFIG_PATH);
+    apply_cli_overrides(&mut config, args.as_slice());
+
+    let mut registry = StationRegistry::new();
+    for station in ["Andrew Station", "Broadway Station", "South Station"].iter() {
+        registry.add_station(*station);
+    }
+
+    // No dynamic POST handlers are registered by default; a deployment
+    // adds its own via HandlerRegistry::register before serve_forever.
+    let handlers = HandlerRegistry::new();
+
+    let server_config = ServerConfig {
+        document_root: config.document_root,
+        index_files: config.index_files,
+        cgi_dir: if config.cgi_dir.is_empty() { None } else { Some(config.cgi_dir) },
+        cgi_timeout_ms: config.cgi_timeout_ms
+    };
+    rustyd::serve_forever(registry, config.bind_addr.as_slice(), handlers, server_config);
+}
+
+/// Read `path`'s "key:value" lines into a Config, starting from the
+/// defaults and overwriting whichever keys are present. A missing
+/// config file isn't an error; the defaults are used as-is.
+#[cfg(not(test))]
+fn load_config(path: &str) -> Config {
+    let mut config = Config::defaults();
+    let file = match File::open(&Path::new(path)) {
+        Ok(file) => file,
+        Err(..) => return config
+    };
+    let mut reader = BufferedReader::new(file);
+    while let Some(line) = reader.read_line().ok() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let i = match trimmed.find(':') {
+            Some(i) => i,
+            None => continue
+        };
+        let value = trimmed.slice_from(i + 1).to_string();
+        match trimmed.slice_to(i) {
+            "bind_addr" => config.bind_addr = value,
+            "document_root" => config.document_root = value,
+            "index_files" => config.index_files =
+                value.split(',').map(|s| s.trim().to_string()).collect(),
+            "cgi_dir" => config.cgi_dir = value,
+            "cgi_timeout_ms" => if let Some(ms) = value.as_slice().parse().ok() {
+                config.cgi_timeout_ms = ms;
+            },
+            _ => {}
+        }
+    }
+    config
+}
+
+/// Apply "--key value" command-line overrides on top of a loaded
+/// Config, taking precedence over both the config file and the
+/// defaults. Unrecognized flags are ignored.
+#[cfg(not(test))]
+fn apply_cli_overrides(config: &mut Config, args: &[String]) {
+    let mut i = 0;
+    while i + 1 < args.len() {
+        let value = args[i + 1].clone();
+        let recognized = match args[i].as_slice() {
+            "--bind-addr" => { config.bind_addr = value; true },
+            "--document-root" => { config.document_root = value; true },
+            "--index-files" => {
+                config.index_files = value.split(',').map(|s| s.trim().to_string()).collect();
+                true
+            },
+            "--cgi-dir" => { config.cgi_dir = value; true },
+            "--cgi-timeout-ms" => match value.as_slice().parse().ok() {
+                Some(ms) => { config.cgi_timeout_ms = ms; true },
+                None => false
+            },
+            _ => false
+        };
+        i += if recognized { 2 } else { 1 };
+    }
 }
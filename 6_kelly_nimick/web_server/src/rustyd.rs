@@ -1,33 +1,211 @@
+#[doc="
+    Module: rustyd
+
+    A minimal HTTP/1.0 server over a StationRegistry: GET /stations and
+    POST /stations/<name>/disable back a small REST API, GET /route
+    answers 501 Not Implemented, paths under cgi-bin/ run CGI scripts
+    when cgi_dir is set, and everything else GET falls through to
+    files.rs's static file serving. The static
+    index.html served at '/' bundles the JS page that lets a browser
+    pick stations from dropdowns and hit those same endpoints directly,
+    so the server is usable without a raw TCP client.
+"]
+
+extern crate time;
+
 #[cfg(not(test))]
 use std::io::{TcpListener, Listener, Acceptor, BufferedStream};
+#[cfg(not(test))]
+use std::sync::Arc;
 
 use std::io::MemWriter;
-use files::{open_file_with_indices, FileResult};
-use files::FileResult::{FileOk, BadRequest};
+use std::sync::Mutex;
+use std::collections::{HashSet, HashMap};
+use files::{open_file_with_indices, open_file, FileResult, FileMeta};
+use files::FileResult::{FileOk, BadRequest, PermissionDenied};
+use self::Route::{StaticFile, ListStations, DisableStation, Handled, Cgi, UnsupportedRoute, NoSuchRoute};
 
 static HEADER: &'static str = "HTTP/1.0 ";
 static CONTENT_TYPE: &'static str = "Content-type: text/";
+static JSON_CONTENT_TYPE: &'static str = "Content-type: application/json\n";
 static CONTENT_LEN: &'static str = "Content-length: ";
 static SERVER_NAME: &'static str = "kelly_nimick_web_server";
 
 #[cfg(not(test))]
-static BIND_ADDR: &'static str = "127.0.0.1:8000";
+static POOL_SIZE: usize = 8;
+#[cfg(not(test))]
+static QUEUE_CAPACITY: usize = 32;
+
+/// Request paths under this prefix are dispatched to CGI rather than
+/// treated as static files or REST routes, regardless of method.
+static CGI_PREFIX: &'static str = "cgi-bin/";
+
+/// How many #include levels an .shtml page may nest before process_ssi
+/// gives up, so an include cycle can't hang a worker thread.
+static SSI_MAX_DEPTH: usize = 5;
+
+/// Where static files live and which index files to try for a
+/// directory request, resolved once at startup from Config and then
+/// shared read-only across every connection. cgi_dir is None unless
+/// CGI support is enabled, in which case it's the directory CGI
+/// scripts are resolved against (not necessarily document_root).
+pub struct ServerConfig {
+    pub document_root: String,
+    pub index_files: Vec<String>,
+    pub cgi_dir: Option<String>,
+    pub cgi_timeout_ms: u64
+}
+
+/// The REST API's view of the MBTA network: just the set of known
+/// stations and which of them are disabled. This server has no access
+/// to the routing graph that the separate t_query project builds, so
+/// `/route` can't actually compute an itinerary here; `GET /stations`
+/// and `POST /stations/<name>/disable` are backed by this registry
+/// instead.
+pub struct StationRegistry {
+    stations: HashSet<String>,
+    disabled: HashSet<String>
+}
+
+impl StationRegistry {
+    pub fn new() -> StationRegistry {
+        StationRegistry { stations: HashSet::new(), disabled: HashSet::new() }
+    }
+
+    /// Register a station as known to the server
+    pub fn add_station(&mut self, name: &str) {
+        self.stations.insert(name.to_string());
+    }
+
+    /// Mark a known station as disabled. Returns false if the station
+    /// isn't registered.
+    fn disable(&mut self, name: &str) -> bool {
+        if !self.stations.contains(name) {
+            return false;
+        }
+        self.disabled.insert(name.to_string());
+        true
+    }
+}
+
+/// A function registered to handle POST requests at a specific path,
+/// given the fully parsed Request (including its raw body, so it can
+/// call parse_form itself if it expects form data).
+pub type PostHandler = fn(&Request) -> MemWriter;
+
+/// POST paths registered to a handler beyond the built-in REST routes,
+/// so a deployment can add dynamic endpoints (an upload handler, a CGI
+/// dispatcher) without patching classify_route. Looked up by the same
+/// leading-slash-stripped path StaticFile uses.
+pub struct HandlerRegistry {
+    handlers: HashMap<String, PostHandler>
+}
+
+impl HandlerRegistry {
+    pub fn new() -> HandlerRegistry {
+        HandlerRegistry { handlers: HashMap::new() }
+    }
+
+    /// Register `handler` to answer POST requests at `path`.
+    pub fn register(&mut self, path: &str, handler: PostHandler) {
+        self.handlers.insert(path.to_string(), handler);
+    }
+
+    fn get(&self, path: &str) -> Option<PostHandler> {
+        self.handlers.get(path).map(|h| *h)
+    }
+}
+
+#[cfg(test)]
+mod handler_registry_tests {
+    use super::{HandlerRegistry, Request, Method};
+    use std::io::MemWriter;
+    use std::collections::HashMap;
+
+    fn ok(_request: &Request) -> MemWriter {
+        let mut w = MemWriter::new();
+        let _ = w.write_str("ok");
+        w
+    }
+
+    fn request() -> Request {
+        Request {
+            method: Method::Post,
+            path: "upload".to_string(),
+            version: "HTTP/1.0".to_string(),
+            headers: HashMap::new(),
+            query: Vec::new(),
+            raw_query: "".to_string(),
+            body: Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_handler_registry_dispatches_registered_path() {
+        let mut handlers = HandlerRegistry::new();
+        handlers.register("upload", ok);
+        let handler = handlers.get("upload").unwrap();
+        assert_eq!(String::from_utf8(handler(&request()).into_inner()).ok().unwrap(), "ok");
+    }
+
+    #[test]
+    fn test_handler_registry_unregistered_path() {
+        let handlers = HandlerRegistry::new();
+        assert!(handlers.get("upload").is_none());
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Method {
+    Get,
+    Post
+}
+
+enum Route<'a> {
+    StaticFile(&'a str),
+    ListStations,
+    DisableStation(&'a str),
+    Handled(&'a str),
+    Cgi(&'a str),
+    UnsupportedRoute,
+    NoSuchRoute
+}
+
+/// A fully parsed HTTP request: the request line plus any headers and
+/// body that followed it. `path` has its leading slash stripped and any
+/// query string or fragment already split off into `query`, which
+/// preserves duplicate keys and their order rather than collapsing them
+/// into a plain map, since handlers like CGI expect to see
+/// multi-valued parameters as the client actually sent them. `raw_query`
+/// is that same query string verbatim (still percent-encoded, no
+/// fragment), for the handful of consumers -- CGI's QUERY_STRING, SSI's
+/// `#echo var="QUERY_STRING"` -- that are required to see exactly what
+/// the client sent rather than a decoded-then-reassembled version.
+pub struct Request {
+    pub method: Method,
+    pub path: String,
+    pub version: String,
+    pub headers: HashMap<String, String>,
+    pub query: Vec<(String, String)>,
+    pub raw_query: String,
+    pub body: Vec<u8>
+}
 
 /// Accept an incoming client stream and respond to its request
-pub fn handle_client<S: Buffer + Writer>(stream: &mut S) {
-    let incoming = stream.read_line().unwrap();
-    println!("{}", incoming);
-    let (request, html) = match get_path(incoming.as_slice()) {
-        Some(path) => {
-            println!("{}", path);
-            open_file_with_indices(path)
+pub fn handle_client<S: Buffer + Writer>(stream: &mut S, registry: &Mutex<StationRegistry>,
+                                          handlers: &HandlerRegistry, server_config: &ServerConfig) {
+    let response = match read_request(stream) {
+        Some(request) => {
+            println!("{} {}", request.path, request.version);
+            let route = classify_route(request.method, request.path.as_slice());
+            handle_route(route, &request, registry, handlers, server_config)
         },
         None => {
             println!("Bad request");
-            (BadRequest, false)
+            prepend_response(BadRequest, false)
         }
     };
-    match stream.write(prepend_response(request, html).get_ref()) {
+    match stream.write(response.get_ref()) {
         Ok(()) => println!("Response sent"),
         Err(e) => println!("Failed sending response: {}", e),
     }
@@ -35,83 +213,1075 @@ pub fn handle_client<S: Buffer + Writer>(stream: &mut S) {
 
 #[cfg(test)]
 mod handle_client_tests {
-    use super::{prepend_response, handle_client};
+    use super::{prepend_response, prepend_json_response, handle_client, StationRegistry, HandlerRegistry,
+                ServerConfig};
     use std::io::BufferedStream;
+    use std::sync::Mutex;
     use files::open_file;
     use stream::MemoryStream;
 
+    fn test_config() -> ServerConfig {
+        ServerConfig {
+            document_root: ".".to_string(),
+            index_files: vec!["index.html".to_string(), "index.shtml".to_string(), "index.txt".to_string()],
+            cgi_dir: None,
+            cgi_timeout_ms: 5000
+        }
+    }
+
     #[test]
-    fn test_handle_client() {
+    fn test_handle_client_static_file() {
         let request = "GET /test/index.txt\n";
         let stream = MemoryStream::new(request);
         let mut s = BufferedStream::new(stream);
-        handle_client(&mut s);
+        let registry = Mutex::new(StationRegistry::new());
+        handle_client(&mut s, &registry, &HandlerRegistry::new(), &test_config());
         let expect = String::from_utf8(prepend_response(
                 open_file("test/index.txt"), false).into_inner()).ok().unwrap();
         assert_eq!(s.into_inner().into_inner().1, expect);
     }
+
+    #[test]
+    fn test_handle_client_stations() {
+        let request = "GET /stations\n";
+        let stream = MemoryStream::new(request);
+        let mut s = BufferedStream::new(stream);
+        let registry = Mutex::new(StationRegistry::new());
+        registry.lock().unwrap().add_station("Andrew Station");
+        handle_client(&mut s, &registry, &HandlerRegistry::new(), &test_config());
+        let expect = String::from_utf8(prepend_json_response(
+                "200 OK", "[\"Andrew Station\"]").into_inner()).ok().unwrap();
+        assert_eq!(s.into_inner().into_inner().1, expect);
+    }
 }
 
-/// Get the pathname associated with the HTTP request
-fn get_path(s: &str) -> Option<&str> {
+/// Parse an HTTP request line into its method, path, and version, e.g.
+/// parsing "GET /stations?line=red HTTP/1.1" into
+/// (Get, "stations?line=red", "HTTP/1.1"). Any query string or fragment
+/// is left attached to the path for the caller to strip. The version is
+/// defaulted to HTTP/1.0 if the line doesn't carry one, since that's
+/// all the original HTTP/0.9-style clients this server grew up with
+/// ever sent.
+fn parse_request_line(s: &str) -> Option<(Method, &str, &str)> {
     let mut iter = s.words();
+    let method = match iter.next() {
+        Some("GET") => Method::Get,
+        Some("POST") => Method::Post,
+        _ => return None
+    };
     match iter.next() {
-        None => return None,
-        Some(s) => {
-            if s != "GET" {
-                return None;
+        Some(s) if s.starts_with("/") => {
+            let version = iter.next().unwrap_or("HTTP/1.0");
+            Some((method, s.slice_from(1), version))
+        },
+        _ => None
+    }
+}
+
+/// Read a full request off of `stream`: the request line, then any
+/// headers up to the blank line that ends them, then a body if a
+/// Content-Length header says to expect one. A client that closes the
+/// connection before sending that blank line (every request this
+/// server has ever had to handle before this function existed) is
+/// treated as having sent no headers rather than as a bad request.
+fn read_request<S: Buffer>(stream: &mut S) -> Option<Request> {
+    let line = match stream.read_line() {
+        Ok(line) => line,
+        Err(..) => return None
+    };
+    let (method, path, version) = match parse_request_line(line.as_slice()) {
+        Some(parsed) => parsed,
+        None => return None
+    };
+    let raw_query = raw_query_string(path).to_string();
+    let query = parse_query(path);
+    let path = match path.find(|c: char| c == '?' || c == '#') {
+        Some(i) => path.slice_to(i),
+        None => path
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        match stream.read_line() {
+            Ok(header_line) => {
+                let trimmed = header_line.as_slice().trim();
+                if trimmed.is_empty() {
+                    break;
+                }
+                match trimmed.find(':') {
+                    Some(colon) => {
+                        let name = trimmed.slice_to(colon).trim().to_string();
+                        let value = trimmed.slice_from(colon + 1).trim().to_string();
+                        headers.insert(name, value);
+                    },
+                    None => {}
+                }
+            },
+            Err(..) => break
+        }
+    }
+
+    let content_length = headers.get("Content-Length")
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(0);
+    let body = if content_length > 0 {
+        stream.read_exact(content_length).unwrap_or_else(|_| Vec::new())
+    } else {
+        Vec::new()
+    };
+
+    Some(Request {
+        method: method,
+        path: path.to_string(),
+        version: version.to_string(),
+        headers: headers,
+        query: query,
+        raw_query: raw_query,
+        body: body
+    })
+}
+
+/// Pull the still-percent-encoded query string (no leading `?`, no
+/// fragment) out of a request path, or "" if it has none.
+fn raw_query_string(path: &str) -> &str {
+    match path.find('?') {
+        Some(i) => {
+            let rest = path.slice_from(i + 1);
+            match rest.find('#') {
+                Some(j) => rest.slice_to(j),
+                None => rest
             }
+        },
+        None => ""
+    }
+}
+
+/// Parse a request path's query string into an ordered list of
+/// key/value pairs, preserving duplicate keys and the order they
+/// arrived in. Keys and values are percent-decoded; a key with no `=`
+/// is given an empty value.
+fn parse_query(path: &str) -> Vec<(String, String)> {
+    let query = raw_query_string(path);
+    if query.is_empty() {
+        return Vec::new();
+    }
+    query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.find('=') {
+            Some(i) => (percent_decode(pair.slice_to(i)), percent_decode(pair.slice_from(i + 1))),
+            None => (percent_decode(pair), "".to_string())
+        })
+        .collect()
+}
+
+/// Percent-decode a query string component, turning "South%20Station"
+/// or "South+Station" into "South Station". Only handles single-byte
+/// (ASCII) escapes; a malformed or multi-byte escape is passed through
+/// as-is rather than erroring.
+fn percent_decode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    loop {
+        match chars.next() {
+            Some('+') => out.push(' '),
+            Some('%') => {
+                let hex: String = chars.clone().take(2).collect();
+                match (hex.len() == 2, u8::from_str_radix(hex.as_slice(), 16)) {
+                    (true, Ok(byte)) if byte < 0x80 => {
+                        chars.next();
+                        chars.next();
+                        out.push(byte as char);
+                    },
+                    _ => out.push('%')
+                }
+            },
+            Some(c) => out.push(c),
+            None => break
         }
     }
-    match iter.next() {
-        None => None,
-        Some(s) => {
-            match s.split(|&: c: char| {c == '?' || c == '#'}).next() {
-                Some(r) => {
-                    Some(r.slice_from(1))
-                },
-                _ => None
+    out
+}
+
+/// One field or file from a parsed POST body: `name` is the form
+/// field name, `filename`/`content_type` are set only for a
+/// multipart file part, and `value` is the raw (already
+/// percent-decoded, for urlencoded forms) bytes.
+pub struct FormPart {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub value: Vec<u8>
+}
+
+/// Parse a POST body according to its Content-Type header:
+/// application/x-www-form-urlencoded becomes one FormPart per pair,
+/// multipart/form-data becomes one FormPart per part (with filename
+/// and content_type set for file parts). Any other or missing
+/// Content-Type yields no parts rather than guessing at the format.
+pub fn parse_form(headers: &HashMap<String, String>, body: &[u8]) -> Vec<FormPart> {
+    let content_type = match headers.get("Content-Type") {
+        Some(c) => c,
+        None => return Vec::new()
+    };
+    if content_type.starts_with("application/x-www-form-urlencoded") {
+        parse_urlencoded_form(body)
+    } else if content_type.starts_with("multipart/form-data") {
+        match multipart_boundary(content_type.as_slice()) {
+            Some(boundary) => parse_multipart_form(body, boundary.as_slice()),
+            None => Vec::new()
+        }
+    } else {
+        Vec::new()
+    }
+}
+
+fn parse_urlencoded_form(body: &[u8]) -> Vec<FormPart> {
+    let body = String::from_utf8_lossy(body).into_owned();
+    body.as_slice().split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.find('=') {
+            Some(i) => FormPart {
+                name: percent_decode(pair.slice_to(i)),
+                filename: None,
+                content_type: None,
+                value: percent_decode(pair.slice_from(i + 1)).into_bytes()
+            },
+            None => FormPart {
+                name: percent_decode(pair),
+                filename: None,
+                content_type: None,
+                value: Vec::new()
+            }
+        })
+        .collect()
+}
+
+/// Pull the "boundary=..." parameter off a multipart Content-Type
+/// header, stripping surrounding quotes if the client sent any.
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    for param in content_type.split(';').skip(1) {
+        let param = param.trim();
+        if param.starts_with("boundary=") {
+            let value = param.slice_from("boundary=".len());
+            return Some(value.trim_matches('"').to_string());
+        }
+    }
+    None
+}
+
+/// Split a multipart/form-data body on "--boundary" markers and parse
+/// each part's headers (Content-Disposition for name/filename,
+/// Content-Type for file parts) and raw body.
+fn parse_multipart_form(body: &[u8], boundary: &str) -> Vec<FormPart> {
+    let delimiter = format!("--{}", boundary);
+    let body = String::from_utf8_lossy(body).into_owned();
+    let mut parts = Vec::new();
+    for chunk in body.as_slice().split_str(delimiter.as_slice()) {
+        let chunk = chunk.trim_matches('\r').trim_matches('\n');
+        if chunk.is_empty() || chunk == "--" {
+            continue;
+        }
+        if let Some(part) = parse_multipart_part(chunk) {
+            parts.push(part);
+        }
+    }
+    parts
+}
+
+fn parse_multipart_part(chunk: &str) -> Option<FormPart> {
+    let (split_at, header_len) = match chunk.find_str("\r\n\r\n") {
+        Some(i) => (i, 4),
+        None => match chunk.find_str("\n\n") {
+            Some(i) => (i, 2),
+            None => return None
+        }
+    };
+    let header_block = chunk.slice_to(split_at);
+    let value = chunk.slice_from(split_at + header_len).trim_right_matches('\r').trim_right_matches('\n');
+
+    let mut name = None;
+    let mut filename = None;
+    let mut content_type = None;
+    for line in header_block.split('\n') {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let colon = match line.find(':') {
+            Some(i) => i,
+            None => continue
+        };
+        let header_name = line.slice_to(colon).trim();
+        let header_value = line.slice_from(colon + 1).trim();
+        if header_name == "Content-Disposition" {
+            name = disposition_param(header_value, "name");
+            filename = disposition_param(header_value, "filename");
+        } else if header_name == "Content-Type" {
+            content_type = Some(header_value.to_string());
+        }
+    }
+
+    name.map(|name| FormPart {
+        name: name,
+        filename: filename,
+        content_type: content_type,
+        value: value.as_bytes().to_vec()
+    })
+}
+
+fn disposition_param(header_value: &str, key: &str) -> Option<String> {
+    let prefix = format!("{}=", key);
+    for param in header_value.split(';').skip(1) {
+        let param = param.trim();
+        if param.starts_with(prefix.as_slice()) {
+            let value = param.slice_from(prefix.len());
+            return Some(value.trim_matches('"').to_string());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod parse_form_tests {
+    use super::parse_form;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_form_no_content_type() {
+        assert!(parse_form(&HashMap::new(), b"name=Foo").is_empty());
+    }
+
+    #[test]
+    fn test_parse_form_urlencoded() {
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), "application/x-www-form-urlencoded".to_string());
+        let parts = parse_form(&headers, b"name=South+Station&line=red");
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name.as_slice(), "name");
+        assert_eq!(parts[0].value.as_slice(), b"South Station");
+        assert!(parts[0].filename.is_none());
+        assert_eq!(parts[1].name.as_slice(), "line");
+        assert_eq!(parts[1].value.as_slice(), b"red");
+    }
+
+    #[test]
+    fn test_parse_form_multipart() {
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), "multipart/form-data; boundary=XYZ".to_string());
+        let body = "--XYZ\r\n\
+                     Content-Disposition: form-data; name=\"name\"\r\n\
+                     \r\n\
+                     South Station\r\n\
+                     --XYZ\r\n\
+                     Content-Disposition: form-data; name=\"upload\"; filename=\"a.txt\"\r\n\
+                     Content-Type: text/plain\r\n\
+                     \r\n\
+                     hello\r\n\
+                     --XYZ--\r\n";
+        let parts = parse_form(&headers, body.as_bytes());
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name.as_slice(), "name");
+        assert_eq!(parts[0].value.as_slice(), b"South Station");
+        assert!(parts[0].filename.is_none());
+        assert_eq!(parts[1].name.as_slice(), "upload");
+        assert_eq!(parts[1].filename.as_ref().unwrap().as_slice(), "a.txt");
+        assert_eq!(parts[1].content_type.as_ref().unwrap().as_slice(), "text/plain");
+        assert_eq!(parts[1].value.as_slice(), b"hello");
+    }
+}
+
+#[cfg(test)]
+mod parse_query_tests {
+    use super::parse_query;
+
+    #[test]
+    fn test_parse_query_no_query_string() {
+        assert_eq!(parse_query("stations"), vec![]);
+    }
+
+    #[test]
+    fn test_parse_query_single_pair() {
+        assert_eq!(parse_query("route?from=South+Station"),
+                   vec![("from".to_string(), "South Station".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_query_multiple_pairs_preserve_order_and_duplicates() {
+        assert_eq!(parse_query("route?from=A&to=B&from=C"),
+                   vec![("from".to_string(), "A".to_string()),
+                        ("to".to_string(), "B".to_string()),
+                        ("from".to_string(), "C".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_query_percent_encoded() {
+        assert_eq!(parse_query("route?name=South%20Station"),
+                   vec![("name".to_string(), "South Station".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_query_key_with_no_value() {
+        assert_eq!(parse_query("route?flag"), vec![("flag".to_string(), "".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_query_strips_fragment() {
+        assert_eq!(parse_query("route?from=A#section"),
+                   vec![("from".to_string(), "A".to_string())]);
+    }
+}
+
+#[cfg(test)]
+mod parse_request_line_tests {
+    use super::{parse_request_line, Method};
+
+    #[test]
+    fn test_parse_request_line() {
+        match parse_request_line("GET /foo.html") {
+            Some((Method::Get, "foo.html", "HTTP/1.0")) => (),
+            _ => panic!("bang"),
+        }
+        match parse_request_line("POST /stations/Foo/disable") {
+            Some((Method::Post, "stations/Foo/disable", "HTTP/1.0")) => (),
+            _ => panic!("bang"),
+        }
+        match parse_request_line("GET /foo.html HTTP/1.1") {
+            Some((Method::Get, "foo.html", "HTTP/1.1")) => (),
+            _ => panic!("bang"),
+        }
+        assert!(parse_request_line("HEAD /foo.html").is_none());
+        assert!(parse_request_line("").is_none());
+    }
+}
+
+#[cfg(test)]
+mod read_request_tests {
+    use super::{read_request, Method};
+    use std::io::BufferedStream;
+    use stream::MemoryStream;
+
+    #[test]
+    fn test_read_request_no_headers() {
+        let stream = MemoryStream::new("GET /stations\n");
+        let mut s = BufferedStream::new(stream);
+        let request = read_request(&mut s).unwrap();
+        match request.method {
+            Method::Get => (),
+            _ => panic!("bang"),
+        }
+        assert_eq!(request.path.as_slice(), "stations");
+        assert_eq!(request.version.as_slice(), "HTTP/1.0");
+        assert!(request.headers.is_empty());
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn test_read_request_with_headers_and_body() {
+        let stream = MemoryStream::new(
+            "POST /stations/Foo/disable HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\nabcd");
+        let mut s = BufferedStream::new(stream);
+        let request = read_request(&mut s).unwrap();
+        assert_eq!(request.version.as_slice(), "HTTP/1.1");
+        assert_eq!(request.headers.get("Host").unwrap().as_slice(), "localhost");
+        assert_eq!(request.body.as_slice(), b"abcd");
+    }
+
+    #[test]
+    fn test_read_request_bad_request_line() {
+        let stream = MemoryStream::new("HEAD /foo.html\n");
+        let mut s = BufferedStream::new(stream);
+        assert!(read_request(&mut s).is_none());
+    }
+}
+
+/// Strip any query string or fragment from a path, then classify it
+/// into a route. Anything under CGI_PREFIX goes to CGI regardless of
+/// method; anything else GET that isn't one of the REST endpoints is
+/// treated as a request for a static file, same as the original
+/// HTTP/0.9 server did for every request.
+fn classify_route<'a>(method: Method, path: &'a str) -> Route<'a> {
+    let trimmed = match path.split(|&: c: char| {c == '?' || c == '#'}).next() {
+        Some(r) => r,
+        None => return NoSuchRoute
+    };
+    if trimmed.starts_with(CGI_PREFIX) && trimmed.len() > CGI_PREFIX.len() {
+        return Cgi(trimmed.slice_from(CGI_PREFIX.len()));
+    }
+    match method {
+        Method::Get => {
+            if trimmed == "stations" {
+                ListStations
+            } else if trimmed == "route" {
+                UnsupportedRoute
+            } else {
+                StaticFile(trimmed)
+            }
+        },
+        Method::Post => {
+            let suffix = "/disable";
+            if trimmed.starts_with("stations/") && trimmed.ends_with(suffix) &&
+                trimmed.len() > "stations/".len() + suffix.len() {
+                DisableStation(trimmed.slice("stations/".len(), trimmed.len() - suffix.len()))
+            } else {
+                Handled(trimmed)
+            }
+        }
+    }
+}
+
+/// Resolve `.`/`..` segments and collapse duplicate slashes in a
+/// request path, returning None if doing so would climb above the
+/// document root (e.g. "../../etc/passwd") rather than handing back
+/// whatever that resolves to outside of it. The caller should treat
+/// None as a 403, not a 404 -- this is about rejecting an attempt to
+/// escape, not reporting a missing file.
+fn normalize_path(path: &str) -> Option<String> {
+    let trailing_slash = path.ends_with("/");
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => if segments.pop().is_none() { return None; },
+            s => segments.push(s)
+        }
+    }
+    let mut joined = segments.connect("/");
+    if trailing_slash && !joined.is_empty() {
+        joined.push('/');
+    }
+    Some(joined)
+}
+
+#[cfg(test)]
+mod normalize_path_tests {
+    use super::normalize_path;
+
+    #[test]
+    fn test_normalize_path_plain_file() {
+        assert_eq!(normalize_path("stations").unwrap().as_slice(), "stations");
+    }
+
+    #[test]
+    fn test_normalize_path_root() {
+        assert_eq!(normalize_path("").unwrap().as_slice(), "");
+    }
+
+    #[test]
+    fn test_normalize_path_directory_keeps_trailing_slash() {
+        assert_eq!(normalize_path("test/").unwrap().as_slice(), "test/");
+    }
+
+    #[test]
+    fn test_normalize_path_collapses_duplicate_slashes() {
+        assert_eq!(normalize_path("foo//bar").unwrap().as_slice(), "foo/bar");
+    }
+
+    #[test]
+    fn test_normalize_path_resolves_internal_dot_dot() {
+        assert_eq!(normalize_path("foo/../bar").unwrap().as_slice(), "bar");
+    }
+
+    #[test]
+    fn test_normalize_path_rejects_escape_above_root() {
+        assert!(normalize_path("../../etc/passwd").is_none());
+    }
+
+    #[test]
+    fn test_normalize_path_rejects_escape_even_if_it_returns() {
+        assert!(normalize_path("../foo/../../bar").is_none());
+    }
+}
+
+/// Join a configured document root and a request path into the
+/// filesystem path to open. Called only after the path has already
+/// been through normalize_path, so it trusts that `path` can't escape
+/// document_root.
+fn join_root(document_root: &str, path: &str) -> String {
+    if document_root.is_empty() || document_root == "." {
+        path.to_string()
+    } else {
+        format!("{}/{}", document_root.trim_right_matches('/'), path)
+    }
+}
+
+#[cfg(test)]
+mod join_root_tests {
+    use super::join_root;
+
+    #[test]
+    fn test_join_root_default() {
+        assert_eq!(join_root(".", "test/index.html").as_slice(), "test/index.html");
+    }
+
+    #[test]
+    fn test_join_root_configured() {
+        assert_eq!(join_root("/srv/www", "test/index.html").as_slice(), "/srv/www/test/index.html");
+    }
+
+    #[test]
+    fn test_join_root_trailing_slash() {
+        assert_eq!(join_root("/srv/www/", "test/index.html").as_slice(), "/srv/www/test/index.html");
+    }
+}
+
+/// Dispatch a classified route to its handler and build the response
+#[allow(unused_must_use)]
+fn handle_route<'a>(route: Route<'a>, request: &Request, registry: &Mutex<StationRegistry>,
+                     handlers: &HandlerRegistry, server_config: &ServerConfig) -> MemWriter {
+    match route {
+        StaticFile(path) => {
+            match normalize_path(path) {
+                None => prepend_response(PermissionDenied, false),
+                Some(normalized) => {
+                    let full_path = join_root(server_config.document_root.as_slice(), normalized.as_slice());
+                    let (result, html) = open_file_with_indices(full_path.as_slice(),
+                                                                  server_config.index_files.as_slice());
+                    match result {
+                        // SSI only applies to an .shtml file requested directly, not one
+                        // reached via the directory-index fallback -- see process_ssi.
+                        FileOk(mut buf, meta) if full_path.ends_with(".shtml") => {
+                            let mut content = String::new();
+                            loop {
+                                match buf.read_line() {
+                                    Ok(line) => content.push_str(line.as_slice()),
+                                    Err(..) => break
+                                }
+                            }
+                            let expanded = process_ssi(content.as_slice(),
+                                                        server_config.document_root.as_slice(), request, 0);
+                            prepend_ssi_response(expanded.as_slice(), &meta)
+                        },
+                        FileOk(_, ref meta) if not_modified(meta, &request.headers) => prepend_not_modified(meta),
+                        _ => prepend_response(result, html)
+                    }
+                }
+            }
+        },
+        ListStations => {
+            let stations = registry.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let names: Vec<String> = stations.stations.iter()
+                .map(|s| format!("\"{}\"", s)).collect();
+            prepend_json_response("200 OK", format!("[{}]", names.connect(",")).as_slice())
+        },
+        DisableStation(name) => {
+            let mut stations = registry.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if stations.disable(name) {
+                prepend_json_response("200 OK", format!("{{\"disabled\":\"{}\"}}", name).as_slice())
+            } else {
+                prepend_json_response("404 Not Found", format!("{{\"error\":\"no such station: {}\"}}", name).as_slice())
+            }
+        },
+        Handled(path) => {
+            match handlers.get(path) {
+                Some(handler) => handler(request),
+                None => prepend_response(BadRequest, false)
+            }
+        },
+        Cgi(script) => {
+            match server_config.cgi_dir {
+                Some(ref cgi_dir) => run_cgi(cgi_dir.as_slice(), script, request, server_config.cgi_timeout_ms),
+                None => prepend_json_response("404 Not Found", "{\"error\":\"cgi is not configured\"}")
             }
+        },
+        UnsupportedRoute => {
+            // this server has no access to t_query's routing graph, so
+            // there's no path to compute; say so rather than faking one
+            prepend_json_response("501 Not Implemented",
+                                  "{\"error\":\"routing is not available from this server\"}")
+        },
+        NoSuchRoute => {
+            prepend_response(BadRequest, false)
+        }
+    }
+}
+
+/// Execute a CGI script and turn its output into an HTTP response.
+/// `script` is the path below the configured cgi_dir (run through
+/// normalize_path first, same as a static file, so it can't escape
+/// that directory); the script is run with the standard CGI
+/// environment variables set from the request, its stdout becomes the
+/// response, and it's killed if it runs longer than timeout_ms.
+fn run_cgi(cgi_dir: &str, script: &str, request: &Request, timeout_ms: u64) -> MemWriter {
+    use std::io::process::Command;
+
+    let normalized = match normalize_path(script) {
+        Some(n) => n,
+        None => return prepend_response(PermissionDenied, false)
+    };
+    let full_path = join_root(cgi_dir, normalized.as_slice());
+
+    let mut command = Command::new(full_path.as_slice());
+    command.env("GATEWAY_INTERFACE", "CGI/1.1");
+    command.env("SERVER_PROTOCOL", request.version.as_slice());
+    command.env("SERVER_SOFTWARE", SERVER_NAME);
+    command.env("REQUEST_METHOD", match request.method {
+        Method::Get => "GET",
+        Method::Post => "POST"
+    });
+    command.env("SCRIPT_NAME", format!("/{}{}", CGI_PREFIX, normalized).as_slice());
+    // Per the CGI spec, QUERY_STRING is the raw, still-encoded query
+    // string -- not one rebuilt from the already-decoded query pairs,
+    // which would be lossy for a value containing '&', '=', or '+'.
+    command.env("QUERY_STRING", request.raw_query.as_slice());
+    command.env("CONTENT_LENGTH", request.body.len().to_string().as_slice());
+    if let Some(content_type) = request.headers.get("Content-Type") {
+        command.env("CONTENT_TYPE", content_type.as_slice());
+    }
+
+    let mut process = match command.spawn() {
+        Ok(p) => p,
+        Err(..) => return prepend_json_response("500 Internal Server Error",
+                                                 "{\"error\":\"failed to start cgi process\"}")
+    };
+    process.set_timeout(Some(timeout_ms));
+
+    if !request.body.is_empty() {
+        if let Some(ref mut stdin) = process.stdin {
+            let _ = stdin.write(request.body.as_slice());
+        }
+    }
+
+    match process.wait_with_output() {
+        Ok(output) => prepend_cgi_response(output.output.as_slice()),
+        Err(..) => {
+            let _ = process.signal_kill();
+            prepend_json_response("504 Gateway Timeout", "{\"error\":\"cgi process timed out\"}")
         }
     }
 }
 
+/// Find the byte offset where the blank line separating a CGI
+/// script's own headers from its body begins, and the length of that
+/// separator ("\r\n\r\n" or "\n\n"). None if there's no blank line,
+/// meaning the whole output is body with no headers.
+fn find_cgi_header_end(output: &[u8]) -> Option<(usize, usize)> {
+    output.windows(4).position(|w| w == b"\r\n\r\n").map(|i| (i, 4))
+        .or_else(|| output.windows(2).position(|w| w == b"\n\n").map(|i| (i, 2)))
+}
+
+/// Turn a CGI script's raw stdout (its own headers, a blank line, then
+/// body, per the CGI spec) into a full HTTP response: splits off the
+/// script's headers, defaults the status to 200 OK unless the script
+/// set its own Status header, and passes the rest of the headers and
+/// the body through unchanged. Only the header block is decoded as
+/// text; the body is kept as raw bytes so binary CGI output isn't
+/// corrupted.
+#[allow(unused_must_use)]
+fn prepend_cgi_response(output: &[u8]) -> MemWriter {
+    // The header block is guaranteed textual by the CGI spec, so it's
+    // safe to decode; the body may be arbitrary binary and is kept as
+    // raw bytes throughout.
+    let (header_block, body) = match find_cgi_header_end(output) {
+        Some((i, sep_len)) => (String::from_utf8_lossy(&output[..i]).into_owned(), &output[i + sep_len..]),
+        None => (String::new(), output)
+    };
+    let header_block = header_block.as_slice();
+
+    let mut status = "200 OK".to_string();
+    let mut header_lines = Vec::new();
+    for line in header_block.split('\n') {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(colon) = line.find(':') {
+            let name = line.slice_to(colon).trim();
+            let value = line.slice_from(colon + 1).trim();
+            if name == "Status" {
+                status = value.to_string();
+            } else {
+                header_lines.push((name.to_string(), value.to_string()));
+            }
+        }
+    }
+
+    let mut w = MemWriter::with_capacity(HEADER.len() + SERVER_NAME.len() + body.len());
+    w.write_str(HEADER);
+    w.write_line(status.as_slice());
+    w.write_line(SERVER_NAME);
+    for &(ref name, ref value) in header_lines.iter() {
+        w.write_str(name.as_slice());
+        w.write_str(": ");
+        w.write_line(value.as_slice());
+    }
+    w.write_str(CONTENT_LEN);
+    w.write_uint(body.len());
+    w.write_str("\n\n");
+    w.write(body);
+    w
+}
+
+/// Expand SSI directives in an .shtml file's contents. Supports
+/// #include (file="..." or virtual="...", both resolved under
+/// document_root the same way a static file request is), #echo
+/// (var="DOCUMENT_URI", "QUERY_STRING", or any request header name),
+/// and #flastmod (file="..."). An unrecognized directive, a missing
+/// include target, or an attempt to include outside document_root
+/// leaves an HTML comment behind rather than failing the whole page.
+/// depth guards against an include cycle: past SSI_MAX_DEPTH levels,
+/// further #includes stop expanding.
+fn process_ssi(contents: &str, document_root: &str, request: &Request, depth: usize) -> String {
+    if depth > SSI_MAX_DEPTH {
+        return "<!-- ssi include depth exceeded -->".to_string();
+    }
+    let mut out = String::with_capacity(contents.len());
+    let mut rest = contents;
+    loop {
+        match rest.find_str("<!--#") {
+            None => {
+                out.push_str(rest);
+                break;
+            },
+            Some(start) => {
+                out.push_str(rest.slice_to(start));
+                let after = rest.slice_from(start + 5);
+                match after.find_str("-->") {
+                    None => {
+                        out.push_str(rest.slice_from(start));
+                        break;
+                    },
+                    Some(end) => {
+                        let directive = after.slice_to(end).trim();
+                        out.push_str(expand_ssi_directive(directive, document_root, request, depth).as_slice());
+                        rest = after.slice_from(end + 3);
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+fn expand_ssi_directive(directive: &str, document_root: &str, request: &Request, depth: usize) -> String {
+    let mut parts = directive.splitn(2, ' ');
+    let command = parts.next().unwrap_or("");
+    let args = parts.next().unwrap_or("").trim();
+    match command {
+        "include" => ssi_include(args, document_root, request, depth),
+        "echo" => ssi_echo(args, request),
+        "flastmod" => ssi_flastmod(args, document_root),
+        _ => format!("<!-- unsupported ssi directive: {} -->", command)
+    }
+}
+
+/// Pull a `key="value"` attribute out of a directive's argument string.
+fn ssi_attr(args: &str, key: &str) -> Option<String> {
+    let prefix = format!("{}=\"", key);
+    let start = match args.find_str(prefix.as_slice()) {
+        Some(i) => i + prefix.len(),
+        None => return None
+    };
+    let rest = args.slice_from(start);
+    rest.find('"').map(|end| rest.slice_to(end).to_string())
+}
+
+fn ssi_include(args: &str, document_root: &str, request: &Request, depth: usize) -> String {
+    let path = match ssi_attr(args, "file").or_else(|| ssi_attr(args, "virtual")) {
+        Some(p) => p,
+        None => return "<!-- ssi include missing file or virtual attribute -->".to_string()
+    };
+    let normalized = match normalize_path(path.as_slice()) {
+        Some(n) => n,
+        None => return "<!-- ssi include rejected: escapes document root -->".to_string()
+    };
+    let full_path = join_root(document_root, normalized.as_slice());
+    match open_file(full_path.as_slice()) {
+        FileOk(mut buf, _) => {
+            let mut content = String::new();
+            loop {
+                match buf.read_line() {
+                    Ok(line) => content.push_str(line.as_slice()),
+                    Err(..) => break
+                }
+            }
+            process_ssi(content.as_slice(), document_root, request, depth + 1)
+        },
+        _ => format!("<!-- ssi include not found: {} -->", path)
+    }
+}
+
+fn ssi_echo(args: &str, request: &Request) -> String {
+    match ssi_attr(args, "var") {
+        Some(ref name) if name.as_slice() == "DOCUMENT_URI" => request.path.clone(),
+        Some(ref name) if name.as_slice() == "QUERY_STRING" => request.raw_query.clone(),
+        Some(ref name) => request.headers.get(name.as_slice()).cloned().unwrap_or("(none)".to_string()),
+        None => "(none)".to_string()
+    }
+}
+
+fn ssi_flastmod(args: &str, document_root: &str) -> String {
+    let path = match ssi_attr(args, "file") {
+        Some(p) => p,
+        None => return "(none)".to_string()
+    };
+    let normalized = match normalize_path(path.as_slice()) {
+        Some(n) => n,
+        None => return "(none)".to_string()
+    };
+    let full_path = join_root(document_root, normalized.as_slice());
+    match open_file(full_path.as_slice()) {
+        FileOk(_, meta) => format_http_date(meta.last_modified),
+        _ => "(none)".to_string()
+    }
+}
+
 #[cfg(test)]
-mod get_path_tests {
-    use super::get_path;
+mod process_ssi_tests {
+    use super::{process_ssi, Request, Method};
+    use std::collections::HashMap;
+
+    fn request() -> Request {
+        Request {
+            method: Method::Get,
+            path: "page.shtml".to_string(),
+            version: "HTTP/1.0".to_string(),
+            headers: HashMap::new(),
+            query: vec![("line".to_string(), "red".to_string())],
+            raw_query: "line=red".to_string(),
+            body: Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_process_ssi_passes_through_plain_html() {
+        assert_eq!(process_ssi("<p>hi</p>", ".", &request(), 0).as_slice(), "<p>hi</p>");
+    }
 
     #[test]
-    fn test_get_path() {
-        assert_eq!(get_path("GET /foo.html").unwrap(), "foo.html");
-        assert_eq!(get_path("GET /foo.html?query=bar").unwrap(), "foo.html");
-        assert_eq!(get_path("GET /foo.html#hash").unwrap(), "foo.html");
-        assert_eq!(get_path("GET /test/foo.html#hash").unwrap(), "test/foo.html");
-        assert_eq!(get_path("HEAD /foo.html#hash"), None);
-        assert_eq!(get_path(""), None);
+    fn test_process_ssi_echo_document_uri() {
+        let page = "<!--#echo var=\"DOCUMENT_URI\" -->";
+        assert_eq!(process_ssi(page, ".", &request(), 0).as_slice(), "page.shtml");
+    }
+
+    #[test]
+    fn test_process_ssi_echo_query_string() {
+        let page = "<!--#echo var=\"QUERY_STRING\" -->";
+        assert_eq!(process_ssi(page, ".", &request(), 0).as_slice(), "line=red");
+    }
+
+    #[test]
+    fn test_process_ssi_include_missing_file() {
+        let page = "<!--#include file=\"no/such/file.shtml\" -->";
+        assert_eq!(process_ssi(page, ".", &request(), 0).as_slice(),
+                   "<!-- ssi include not found: no/such/file.shtml -->");
+    }
+
+    #[test]
+    fn test_process_ssi_include_rejects_escape() {
+        let page = "<!--#include file=\"../../etc/passwd\" -->";
+        assert_eq!(process_ssi(page, ".", &request(), 0).as_slice(),
+                   "<!-- ssi include rejected: escapes document root -->");
+    }
+
+    #[test]
+    fn test_process_ssi_depth_exceeded() {
+        let page = "<!--#include file=\"x\" -->";
+        assert_eq!(process_ssi(page, ".", &request(), super::SSI_MAX_DEPTH + 1).as_slice(),
+                   "<!-- ssi include depth exceeded -->");
+    }
+
+    #[test]
+    fn test_process_ssi_unsupported_directive() {
+        let page = "<!--#foo bar=\"baz\" -->";
+        assert_eq!(process_ssi(page, ".", &request(), 0).as_slice(),
+                   "<!-- unsupported ssi directive: foo -->");
     }
 }
 
-/// Start accepting TCP requests and responding to HTTP/0.9 requests
+/// Add the headers to the output for an SSI-expanded .shtml response.
+/// Mirrors prepend_response's FileOk case (same ETag/Last-Modified
+/// headers) but writes the already-expanded content instead of
+/// streaming the file's own bytes, since the expanded content can
+/// differ in length from the file on disk.
+#[allow(unused_must_use)]
+fn prepend_ssi_response(content: &str, meta: &FileMeta) -> MemWriter {
+    let mut w = MemWriter::with_capacity(HEADER.len() + SERVER_NAME.len() + content.len());
+    w.write_str(HEADER);
+    w.write_line("200 OK");
+    w.write_line(SERVER_NAME);
+    w.write_str(CONTENT_TYPE);
+    w.write_line("html");
+    w.write_str("ETag: ");
+    w.write_line(meta.etag.as_slice());
+    w.write_str("Last-Modified: ");
+    w.write_line(format_http_date(meta.last_modified).as_slice());
+    w.write_str(CONTENT_LEN);
+    w.write_uint(content.len());
+    w.write_str("\n\n");
+    w.write_str(content);
+    w
+}
+
+/// Start a fixed pool of worker threads pulling connections off a
+/// bounded queue, then accept TCP connections to feed it. A connection
+/// that arrives while the queue is already full means the server is
+/// saturated, so it gets a 503 straight from the accept loop instead of
+/// piling up unbounded work the way a thread-per-connection server would.
 #[cfg(not(test))]
-pub fn serve_forever() {
+pub fn serve_forever(registry: StationRegistry, bind_addr: &str, handlers: HandlerRegistry,
+                      server_config: ServerConfig) {
     use std::thread::Thread;
+    use std::sync::mpsc::{sync_channel, TrySendError};
+    use std::io::Writer;
 
-    let listener = TcpListener::bind(BIND_ADDR).unwrap();
+    let registry = Arc::new(Mutex::new(registry));
+    let handlers = Arc::new(handlers);
+    let server_config = Arc::new(server_config);
+    let (sender, receiver) = sync_channel::<TcpStream>(QUEUE_CAPACITY);
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    for _ in 0..POOL_SIZE {
+        let receiver = receiver.clone();
+        let registry = registry.clone();
+        let handlers = handlers.clone();
+        let server_config = server_config.clone();
+        Thread::spawn(move || {
+            loop {
+                let job = receiver.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).recv();
+                match job {
+                    Ok(stream) => {
+                        let registry = registry.clone();
+                        let handlers = handlers.clone();
+                        let server_config = server_config.clone();
+                        // A panic in a single client's handling (e.g. from CGI or SSI
+                        // processing) shouldn't take a whole worker slot down with it:
+                        // run it on its own thread and check the join result instead
+                        // of the worker loop itself unwinding.
+                        let job = Thread::spawn(move || {
+                            let mut stream = BufferedStream::new(stream);
+                            handle_client(&mut stream, &*registry, &*handlers, &*server_config);
+                        });
+                        if job.join().is_err() {
+                            println!("Worker panicked while handling a client, continuing");
+                        }
+                    },
+                    Err(..) => break
+                }
+            }
+        });
+    }
+
+    let listener = TcpListener::bind(bind_addr).unwrap();
     let mut acceptor = listener.listen().unwrap();
     for stream in acceptor.incoming() {
         match stream {
             Err(..) => {},
             Ok(stream) => {
-                Thread::spawn(move || {
-                    let mut stream = BufferedStream::new(stream);
-                    handle_client(&mut stream)
-                });
+                match sender.try_send(stream) {
+                    Ok(()) => {},
+                    Err(TrySendError::Full(mut stream)) => {
+                        let response = prepend_json_response("503 Service Unavailable",
+                            "{\"error\":\"server is busy, try again\"}");
+                        let _ = stream.write(response.get_ref());
+                    },
+                    Err(TrySendError::Disconnected(..)) => {}
+                }
             }
         }
     }
 }
 
-/// Add the HTTP/0.9 headers to the output
+/// Add the HTTP/0.9 headers to the output for a static file response
 #[allow(unused_must_use)]
 fn prepend_response(response: FileResult, html: bool) -> MemWriter {
     let mut w = MemWriter::with_capacity(HEADER.len() + SERVER_NAME.len());
@@ -120,10 +1290,15 @@ fn prepend_response(response: FileResult, html: bool) -> MemWriter {
     w.write_line(SERVER_NAME);
     w.write_str(CONTENT_TYPE);
     w.write_line(if html { "html" } else { "plain" });
-    w.write_str(CONTENT_LEN);
 
     match response {
-        FileOk(mut buf) => {
+        FileOk(mut buf, meta) => {
+            w.write_str("ETag: ");
+            w.write_line(meta.etag.as_slice());
+            w.write_str("Last-Modified: ");
+            w.write_line(format_http_date(meta.last_modified).as_slice());
+            w.write_str(CONTENT_LEN);
+
             let mut file = MemWriter::new();
             while let Ok(o) = buf.read_line() {
                 file.write_str(o.as_slice());
@@ -134,6 +1309,7 @@ fn prepend_response(response: FileResult, html: bool) -> MemWriter {
             w.write(file.get_ref());
         },
         _ => {
+            w.write_str(CONTENT_LEN);
             w.write_uint(0);
             w.write_str("\n\n");
         }
@@ -141,3 +1317,104 @@ fn prepend_response(response: FileResult, html: bool) -> MemWriter {
 
     w
 }
+
+/// Build the 304 response for a request whose cached copy is still
+/// valid, so the file body doesn't need to be read or sent again.
+#[allow(unused_must_use)]
+fn prepend_not_modified(meta: &FileMeta) -> MemWriter {
+    let mut w = MemWriter::with_capacity(HEADER.len() + SERVER_NAME.len());
+    w.write_str(HEADER);
+    w.write_line("304 Not Modified");
+    w.write_line(SERVER_NAME);
+    w.write_str("ETag: ");
+    w.write_line(meta.etag.as_slice());
+    w.write_str("Last-Modified: ");
+    w.write_line(format_http_date(meta.last_modified).as_slice());
+    w.write_str(CONTENT_LEN);
+    w.write_uint(0);
+    w.write_str("\n\n");
+    w
+}
+
+/// True if the request's conditional headers show the client's cached
+/// copy is already current, so the body can be skipped in favor of a
+/// 304. If-None-Match takes priority over If-Modified-Since, the same
+/// precedence the HTTP spec gives them.
+fn not_modified(meta: &FileMeta, headers: &HashMap<String, String>) -> bool {
+    if let Some(if_none_match) = headers.get("If-None-Match") {
+        return if_none_match.as_slice() == meta.etag.as_slice() || if_none_match.as_slice() == "*";
+    }
+    if let Some(if_modified_since) = headers.get("If-Modified-Since") {
+        if let Ok(since) = time::strptime(if_modified_since.as_slice(), "%a, %d %b %Y %H:%M:%S %Z") {
+            return meta.last_modified.sec <= since.to_timespec().sec;
+        }
+    }
+    false
+}
+
+/// Format a Timespec as an RFC 822 HTTP-date, e.g.
+/// "Mon, 28 Feb 2005 00:00:00 GMT", for ETag/Last-Modified headers.
+fn format_http_date(ts: time::Timespec) -> String {
+    time::at_utc(ts).rfc822().to_string()
+}
+
+#[cfg(test)]
+mod not_modified_tests {
+    use super::{not_modified, time};
+    use files::FileMeta;
+    use std::collections::HashMap;
+
+    fn meta() -> FileMeta {
+        FileMeta { etag: "\"abc-123\"".to_string(), last_modified: time::Timespec::new(1000, 0) }
+    }
+
+    #[test]
+    fn test_not_modified_matching_etag() {
+        let mut headers = HashMap::new();
+        headers.insert("If-None-Match".to_string(), "\"abc-123\"".to_string());
+        assert!(not_modified(&meta(), &headers));
+    }
+
+    #[test]
+    fn test_not_modified_mismatched_etag() {
+        let mut headers = HashMap::new();
+        headers.insert("If-None-Match".to_string(), "\"different\"".to_string());
+        assert!(!not_modified(&meta(), &headers));
+    }
+
+    #[test]
+    fn test_not_modified_since_at_or_after_last_modified() {
+        let mut headers = HashMap::new();
+        let since = super::format_http_date(time::Timespec::new(1000, 0));
+        headers.insert("If-Modified-Since".to_string(), since);
+        assert!(not_modified(&meta(), &headers));
+    }
+
+    #[test]
+    fn test_not_modified_since_before_last_modified() {
+        let mut headers = HashMap::new();
+        let since = super::format_http_date(time::Timespec::new(500, 0));
+        headers.insert("If-Modified-Since".to_string(), since);
+        assert!(!not_modified(&meta(), &headers));
+    }
+
+    #[test]
+    fn test_not_modified_no_conditional_headers() {
+        assert!(!not_modified(&meta(), &HashMap::new()));
+    }
+}
+
+/// Add the HTTP headers to the output for a JSON REST response
+#[allow(unused_must_use)]
+fn prepend_json_response(status: &str, body: &str) -> MemWriter {
+    let mut w = MemWriter::with_capacity(HEADER.len() + SERVER_NAME.len() + body.len());
+    w.write_str(HEADER);
+    w.write_line(status);
+    w.write_line(SERVER_NAME);
+    w.write_str(JSON_CONTENT_TYPE);
+    w.write_str(CONTENT_LEN);
+    w.write_uint(body.len());
+    w.write_str("\n\n");
+    w.write_str(body);
+    w
+}
@@ -0,0 +1,191 @@
+#[doc="
+    Module: alerts
+
+    This module polls an external service-alerts feed and applies it to a
+    shared T, disabling and enabling stations as alerts come and go.
+
+    The feed is expected to be a JSON array of alert objects, each of the
+    form {\"station\": \"...\", \"description\": \"...\"}. Every station named in
+    the feed is disabled with its description recorded; any station that
+    was previously disabled by an alert but no longer appears in the feed
+    is re-enabled.
+
+    ASSUMPTIONS: the feed is small and flat enough that a hand-rolled
+    parser for this one JSON shape is good enough; this is not a general
+    JSON parser (no nesting, no escaped quotes, no unicode escapes). The
+    fetch is a bare HTTP/1.0 GET with no redirect handling, chunked
+    transfer decoding, or TLS.
+"]
+use std::collections::HashSet;
+use std::io::IoResult;
+use std::io::net::tcp::TcpStream;
+use std::io::timer::sleep;
+use std::sync::{Arc, RwLock};
+use std::thread::Thread;
+use std::time::duration::Duration;
+
+use t::T;
+
+/// A single entry from a service-alerts feed: the affected station and a
+/// human-readable description of the disruption.
+#[derive(Show, PartialEq)]
+pub struct Alert {
+    pub station: String,
+    pub description: String
+}
+
+/// Fetch the body of the alerts feed at the given plain-HTTP URL.
+pub fn fetch_alerts_feed(url: &str) -> IoResult<String> {
+    let (host, path) = split_url(url);
+    let mut stream = try!(TcpStream::connect(host.as_slice()));
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+                          path, host);
+    try!(stream.write_str(request.as_slice()));
+    let response = try!(stream.read_to_string());
+    Ok(response_body(response.as_slice()).to_string())
+}
+
+/// Split a "http://host[:port]/path" URL into its host:port and path,
+/// defaulting to port 80 and path "/" when they're missing.
+fn split_url(url: &str) -> (String, String) {
+    let without_scheme = url.trim_left_matches("http://");
+    let (authority, path) = match without_scheme.find('/') {
+        Some(i) => (without_scheme.slice_to(i), without_scheme.slice_from(i)),
+        None => (without_scheme, "/")
+    };
+    let host = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    (host, path.to_string())
+}
+
+/// Strip the HTTP status line and headers off of a raw response, leaving
+/// just the body.
+fn response_body(response: &str) -> &str {
+    match response.find_str("\r\n\r\n") {
+        Some(i) => response.slice_from(i + 4),
+        None => ""
+    }
+}
+
+/// Parse a feed body of the form [{"station": "...", "description": "..."}, ...]
+/// into a list of Alerts. Malformed entries are skipped rather than
+/// aborting the whole parse, since one bad alert shouldn't sink the rest
+/// of the feed.
+pub fn parse_alerts(body: &str) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+    for object in body.split('{').skip(1) {
+        let object = match object.find('}') {
+            Some(i) => object.slice_to(i),
+            None => continue
+        };
+        let station = extract_json_string(object, "station");
+        let description = extract_json_string(object, "description");
+        if let (Some(station), Some(description)) = (station, description) {
+            alerts.push(Alert { station: station, description: description });
+        }
+    }
+    alerts
+}
+
+/// Pull the value of a "field": "value" pair out of a flat JSON object body.
+fn extract_json_string(object: &str, field: &str) -> Option<String> {
+    let key = format!("\"{}\"", field);
+    let after_key = match object.find_str(key.as_slice()) {
+        Some(i) => object.slice_from(i + key.len()),
+        None => return None
+    };
+    let after_colon = match after_key.find(':') {
+        Some(i) => after_key.slice_from(i + 1).trim_left(),
+        None => return None
+    };
+    let after_quote = match after_colon.slice_shift_char() {
+        Some(('"', rest)) => rest,
+        _ => return None
+    };
+    after_quote.find('"').map(|i| after_quote.slice_to(i).to_string())
+}
+
+/// Apply a freshly fetched set of alerts to the T: disable every station
+/// named in `alerts`, recording its description, and re-enable any
+/// station that had an active alert on the last poll but doesn't on this
+/// one.
+pub fn apply_alerts(t: &mut T, alerts: &Vec<Alert>) {
+    let mut seen = HashSet::new();
+    for alert in alerts.iter() {
+        t.apply_alert(alert.station.as_slice(), alert.description.as_slice());
+        seen.insert(alert.station.clone());
+    }
+    for (station, _) in t.active_alerts().into_iter() {
+        if !seen.contains(&station) {
+            t.clear_alert(station.as_slice());
+        }
+    }
+}
+
+/// Spawn a background thread that polls the given alerts feed URL every
+/// `interval_secs` seconds and applies the result to the shared T. A
+/// failed fetch or parse is ignored and retried on the next poll; the
+/// previous alert state is left in place until a poll succeeds. Applying
+/// a poll's results takes the write lock, same as a manual enable/disable.
+pub fn spawn_alerts_poller(mbta: Arc<RwLock<T>>, url: String, interval_secs: i64) {
+    Thread::spawn(move || {
+        loop {
+            if let Ok(body) = fetch_alerts_feed(url.as_slice()) {
+                let alerts = parse_alerts(body.as_slice());
+                let mut t = mbta.write().unwrap();
+                apply_alerts(&mut *t, &alerts);
+            }
+            sleep(Duration::seconds(interval_secs));
+        }
+    });
+}
+
+#[cfg(test)]
+mod alerts_tests {
+    use super::{parse_alerts, apply_alerts, response_body, Alert};
+    use t::T;
+
+    #[test]
+    fn test_parse_alerts() {
+        let body = "[{\"station\": \"Andrew Station\", \"description\": \"signal problem\"}, \
+                     {\"station\": \"Broadway Station\", \"description\": \"police activity\"}]";
+        assert_eq!(parse_alerts(body), vec![
+            Alert { station: "Andrew Station".to_string(), description: "signal problem".to_string() },
+            Alert { station: "Broadway Station".to_string(), description: "police activity".to_string() }
+        ]);
+    }
+
+    #[test]
+    fn test_parse_alerts_empty() {
+        assert_eq!(parse_alerts("[]"), Vec::new());
+    }
+
+    #[test]
+    fn test_response_body() {
+        let response = "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n[]";
+        assert_eq!(response_body(response), "[]");
+    }
+
+    #[test]
+    fn test_apply_alerts() {
+        let mut t = T::new();
+        t.load().unwrap();
+
+        let first = vec![Alert { station: "Andrew Station".to_string(),
+                                 description: "signal problem".to_string() }];
+        apply_alerts(&mut t, &first);
+        assert_eq!(t.active_alerts(), vec![("Andrew Station".to_string(),
+                                            "signal problem".to_string())]);
+
+        // a second poll that drops the first alert and adds a new one
+        // clears the old station and disables the new one
+        let second = vec![Alert { station: "Broadway Station".to_string(),
+                                  description: "police activity".to_string() }];
+        apply_alerts(&mut t, &second);
+        assert_eq!(t.active_alerts(), vec![("Broadway Station".to_string(),
+                                            "police activity".to_string())]);
+    }
+}
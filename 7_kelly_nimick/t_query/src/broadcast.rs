@@ -0,0 +1,127 @@
+#[doc="
+    Module: broadcast
+
+    A shared registry of every connected client's writer, used to push
+    an asynchronous notice -- a station or segment being
+    disabled/enabled, or the server shutting down -- to every other
+    connection without either side polling for it. Query parsing and
+    responses still happen entirely on each connection's own thread;
+    this is only for messages nobody asked for.
+
+    Each connection's writer is a SharedWriter -- shared, via
+    SyncedStream, between that connection's own thread and this
+    Broadcaster, and guarded by one Mutex -- so a broadcast notice can
+    never land mid-write of that connection's own response, and, for a
+    TLS connection, is always written through the same encrypted
+    session query_user uses rather than a second, unencrypted handle to
+    the raw socket.
+"]
+
+use std::io::{Reader, Writer, Buffer, IoResult};
+use std::io::net::tcp::TcpStream;
+use std::sync::{Arc, Mutex};
+
+/// The writer a connection's own thread writes responses through,
+/// shared with the Broadcaster so both take the same lock. Boxed so
+/// the same Broadcaster serves plain TCP and TLS connections alike
+/// without needing to know which it has.
+pub type SharedWriter = Arc<Mutex<Box<Writer + Send>>>;
+
+/// Wraps a connection's reader together with the SharedWriter
+/// registered with the Broadcaster, so query_user can keep reading and
+/// writing through one `Writer + Buffer` value exactly as before,
+/// while every write it makes takes the same lock a broadcast takes.
+/// `R` is whatever the connection reads from -- a TcpStream clone, or
+/// the post-handshake SslStream for a TLS connection -- and is
+/// unrelated to whatever's boxed inside the SharedWriter, since reads
+/// and writes never need to be the same concrete type.
+pub struct SyncedStream<R> {
+    reader: R,
+    writer: SharedWriter
+}
+
+impl<R> SyncedStream<R> {
+    pub fn new(reader: R, writer: SharedWriter) -> SyncedStream<R> {
+        SyncedStream { reader: reader, writer: writer }
+    }
+}
+
+impl<R: Reader> Reader for SyncedStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R: Buffer> Buffer for SyncedStream<R> {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
+
+impl<R> Writer for SyncedStream<R> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        self.writer.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.writer.lock().unwrap().flush()
+    }
+}
+
+pub struct Broadcaster {
+    // connection key -> (its SharedWriter, and a raw TcpStream handle
+    // used only to interrupt a blocked read on shutdown)
+    clients: Mutex<Vec<(String, SharedWriter, TcpStream)>>
+}
+
+impl Broadcaster {
+    pub fn new() -> Broadcaster {
+        Broadcaster { clients: Mutex::new(Vec::new()) }
+    }
+
+    /// Register a newly accepted connection's SharedWriter -- the same
+    /// one its own thread writes responses through, taken after any TLS
+    /// handshake completes -- under `key`, so a broadcast reaches it
+    /// through that same lock instead of a second, unsynchronized (and,
+    /// for TLS, unencrypted) handle to the socket. `closer` is a raw
+    /// TcpStream handle to the same socket, used only to interrupt a
+    /// blocked read on shutdown. Unix socket connections are never
+    /// registered, the same way they're left out of the shutdown notice
+    /// in serve_forever -- see that function's doc comment for why.
+    pub fn register(&self, key: String, writer: SharedWriter, closer: TcpStream) {
+        self.clients.lock().unwrap().push((key, writer, closer));
+    }
+
+    /// Write `message` to every registered connection except the one
+    /// registered under `exclude_key`, so a connection doesn't see its
+    /// own admin operation echoed back to it as an asynchronous notice.
+    /// Takes each connection's writer lock to do it -- the same lock
+    /// that connection's own thread takes to write a response -- so the
+    /// two can never interleave or, for TLS, desync the record stream.
+    #[allow(unused_must_use)]
+    pub fn broadcast_except(&self, exclude_key: &str, message: &str) {
+        for &(ref key, ref writer, _) in self.clients.lock().unwrap().iter() {
+            if key.as_slice() != exclude_key {
+                writer.lock().unwrap().write_str(message);
+            }
+        }
+    }
+
+    /// Write `message` to every registered connection, then close each
+    /// one's read and write halves so its blocked read returns and that
+    /// client's thread can finish. Used for the shutdown notice, which
+    /// unlike other broadcasts is meant for every connection, including
+    /// the one that requested the shutdown.
+    #[allow(unused_must_use)]
+    pub fn close_all(&self, message: &str) {
+        for &mut (_, ref writer, ref mut closer) in self.clients.lock().unwrap().iter_mut() {
+            writer.lock().unwrap().write_str(message);
+            closer.close_read();
+            closer.close_write();
+        }
+    }
+}
@@ -0,0 +1,93 @@
+#[doc="
+    Module: fuzzy
+
+    Ranks a set of known station names by edit distance to a query
+    string, for use as a fallback when disambiguate_station's substring
+    match finds nothing (e.g. a misspelled station name like
+    'Haravrd').
+
+    The spelling corrector project (4_kelly_nimick/spelling_corrector)
+    solves a related problem by generating every edit-distance-1 and
+    edit-distance-2 candidate of a word and checking which ones appear
+    in a dictionary -- a good trade when the dictionary is too large to
+    score every entry directly. t_query's station list is only a few
+    dozen names, so scoring each one directly with a Levenshtein
+    distance is both simpler and cheaper here, and ranks by an exact
+    distance instead of a coarser distance-1-or-2 cutoff. t_query and
+    the spelling corrector aren't crates in the same Cargo workspace
+    anyway, so there's no dependency to share even if the approaches
+    matched.
+"]
+use std::ascii::AsciiExt;
+use std::cmp::min;
+
+/// Only offer a station as a suggestion if it's within this many edits
+/// of the query; beyond this, a match is more likely coincidental than
+/// a typo.
+static MAX_SUGGESTION_DISTANCE: usize = 3;
+
+/// The Levenshtein distance between two strings: the minimum number of
+/// single-character insertions, deletions, or substitutions needed to
+/// turn one into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+    for i in 1..a.len() + 1 {
+        let mut prev_diag = row[0];
+        row[0] = i;
+        for j in 1..b.len() + 1 {
+            let cur = if a[i - 1] == b[j - 1] {
+                prev_diag
+            } else {
+                1 + min(prev_diag, min(row[j], row[j - 1]))
+            };
+            prev_diag = row[j];
+            row[j] = cur;
+        }
+    }
+    row[b.len()]
+}
+
+/// Rank `stations` by edit distance to `query` (case-insensitive),
+/// keeping only those within MAX_SUGGESTION_DISTANCE and sorting the
+/// closest matches first, ties broken alphabetically.
+pub fn fuzzy_match<'a, I: Iterator<Item=&'a String>>(query: &str, stations: I) -> Vec<String> {
+    let query = query.to_ascii_lowercase();
+    let mut ranked: Vec<(usize, String)> = stations
+        .map(|station| (edit_distance(query.as_slice(), station.to_ascii_lowercase().as_slice()), station.clone()))
+        .filter(|&(distance, _)| distance <= MAX_SUGGESTION_DISTANCE)
+        .collect();
+    ranked.sort();
+    ranked.into_iter().map(|(_, station)| station).collect()
+}
+
+#[cfg(test)]
+mod fuzzy_tests {
+    use super::{edit_distance, fuzzy_match};
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("harvard", "harvard"), 0);
+        assert_eq!(edit_distance("haravrd", "harvard"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        let stations = vec!["Harvard Square Station".to_string(),
+                             "Central Square Station".to_string(),
+                             "Alewife Station".to_string()];
+        let matches = fuzzy_match("Haravrd Square Station", stations.iter());
+        assert_eq!(matches, vec!["Harvard Square Station".to_string()]);
+    }
+
+    #[test]
+    fn test_fuzzy_match_no_close_matches() {
+        let stations = vec!["Harvard Square Station".to_string()];
+        let matches = fuzzy_match("Wonderland Station", stations.iter());
+        assert!(matches.is_empty());
+    }
+}
@@ -5,54 +5,552 @@
     This module contains the code to load the T data structure and start the
     server that listens for and handles user queries.
 
+    Passing --validate checks that the configured data files load without
+    error and exits, printing why if they don't, instead of starting the
+    server -- useful for catching a bad data file during a deploy before
+    it takes the server down.
+
     ASSUMPTIONS: don't print when passing through a disabled station
 "]
 extern crate regex;
+extern crate graph_lib;
+extern crate openssl;
 
+#[cfg(not(test))]
+use std::os;
 #[cfg(not(test))]
 use std::io::{TcpListener, Listener, Acceptor, BufferedStream};
 #[cfg(not(test))]
-use std::sync::{Arc, Mutex};
+use std::io::net::ip::SocketAddr;
+#[cfg(not(test))]
+use std::io::net::pipe::UnixListener;
+#[cfg(not(test))]
+use std::sync::{Arc, Mutex, RwLock};
+
+#[cfg(not(test))]
+use openssl::ssl::{SslContext, SslMethod, SslStream};
+#[cfg(not(test))]
+use openssl::x509::X509FileType;
 
 #[cfg(not(test))]
 use t::T;
 #[cfg(not(test))]
 use query::query_user;
+#[cfg(not(test))]
+use rate_limit::RateLimiters;
+#[cfg(not(test))]
+use broadcast::{Broadcaster, SharedWriter, SyncedStream};
 
 #[cfg(not(test))]
 static BIND_ADDR: &'static str = "127.0.0.1:12345";
 
+// Config file for the data paths, bind address, transfer cost,
+// all-pairs precomputation, rate limits, unix socket, TLS, and the
+// admin token: "data_dir:<dir>", "connections_file:<filename relative
+// to data_dir>", "bind_addr:<host:port>", "transfer_cost:<minutes>",
+// "precompute_all_pairs:<true|false>", "query_limit_per_minute:<n>",
+// "admin_op_limit_per_minute:<n>", "unix_socket_path:<path>",
+// "tls_cert_path:<path>", "tls_key_path:<path>", and
+// "admin_token:<token>", one per line. A missing config file or key
+// falls back to the compiled-in default, so deployments only need to
+// set what they want to change -- though a real deployment should
+// always set admin_token, since the compiled-in default is public.
+#[cfg(not(test))]
+static CONFIG_PATH: &'static str = "data/config.dat";
+
+// Config file for the service alerts poller: "url:<feed url>" and
+// "interval_secs:<poll interval>", one per line. Polling is skipped
+// entirely if this file doesn't exist.
+#[cfg(not(test))]
+static ALERTS_CONFIG_PATH: &'static str = "data/alerts_config.dat";
+
+// Where the currently disabled stations are persisted on a graceful
+// shutdown and restored from on the next startup, one station name per
+// line. A missing file just means nothing was disabled last time.
+#[cfg(not(test))]
+static DISABLED_STATE_PATH: &'static str = "data/disabled_state.dat";
+
+// how often, in milliseconds, the accept loop wakes up to check whether
+// a shutdown has been requested while no connection is pending
+#[cfg(not(test))]
+static SHUTDOWN_POLL_MS: u64 = 500;
+
+// how often, in seconds, the scheduled-disable poller checks for expired
+// "disable ... for"/"disable ... until" windows to re-enable. Unlike the
+// alerts poller, this doesn't depend on anything external, so it always
+// runs rather than being gated on a config file.
+#[cfg(not(test))]
+static SCHEDULED_DISABLE_POLL_SECS: i64 = 30;
+
+#[cfg(not(test))]
+static SHUTDOWN_NOTICE: &'static str = "\nServer is shutting down.\n";
+
 mod t;
 mod query;
 mod graph;
 mod print;
+mod alerts;
+mod fuzzy;
+mod metrics;
+mod rate_limit;
+mod protocol;
+mod broadcast;
+
+/// Where the T's data lives and how the server is bound, loaded from
+/// CONFIG_PATH and then overridden by any matching "--key value"
+/// command-line arguments, so a deployment can point at a different data
+/// set or bind address without recompiling.
+#[cfg(not(test))]
+struct Config {
+    data_dir: String,
+    connections_file: String,
+    bind_addr: String,
+    // None means "use T's own compiled-in default"
+    transfer_cost: Option<usize>,
+    // whether to precompute all-pairs shortest paths after every rebuild,
+    // trading rebuild time and memory for O(1) find_path lookups
+    precompute_all_pairs: bool,
+    // None means no cap on either; see rate_limit::RateLimiters
+    query_limit_per_minute: Option<usize>,
+    admin_op_limit_per_minute: Option<usize>,
+    // if set, also listen on this Unix socket path, in addition to
+    // bind_addr, so local tooling can talk to the server without going
+    // through the network stack at all
+    unix_socket_path: Option<String>,
+    // PEM cert/key paths; both must be set to turn on TLS termination for
+    // bind_addr. Leaving either unset serves that listener in cleartext,
+    // same as before this was added
+    tls_cert_path: Option<String>,
+    tls_key_path: Option<String>,
+    // shared secret required before enable/disable are allowed; see
+    // query.rs's Auth handling. Defaults to a public, well-known value,
+    // so a real deployment must set this via config or --admin-token.
+    admin_token: String
+}
+
+#[cfg(not(test))]
+impl Config {
+    fn defaults() -> Config {
+        Config {
+            data_dir: "data".to_string(),
+            connections_file: "connections.dat".to_string(),
+            bind_addr: BIND_ADDR.to_string(),
+            transfer_cost: None,
+            precompute_all_pairs: false,
+            query_limit_per_minute: None,
+            admin_op_limit_per_minute: None,
+            unix_socket_path: None,
+            tls_cert_path: None,
+            tls_key_path: None,
+            admin_token: "t-query-admin".to_string()
+        }
+    }
+}
 
 #[cfg(not(test))]
 fn main() {
+    let mut args = os::args();
+    args.remove(0);
+
+    let validate_only = args.iter().any(|a| a.as_slice() == "--validate");
+
+    let mut config = load_config(CONFIG_PATH);
+    apply_cli_overrides(&mut config, args.as_slice());
+
     let mut t = T::new();
-    t.load();
-    serve_forever(t);
+    if let Some(cost) = config.transfer_cost {
+        t.set_transfer_cost(cost);
+    }
+    t.set_precompute_all_pairs(config.precompute_all_pairs);
+    if let Err(e) = t.load_from(config.data_dir.as_slice(), config.connections_file.as_slice()) {
+        println!("Couldn't load T data: {}", e.message());
+        os::set_exit_status(1);
+        return;
+    }
+
+    if validate_only {
+        println!("Data files in \"{}\" are valid.", config.data_dir);
+        return;
+    }
+
+    restore_disabled_state(&mut t, DISABLED_STATE_PATH);
+
+    if let Some(bytes) = t.all_pairs_memory_estimate() {
+        println!("All-pairs table precomputed, approximately {} bytes", bytes);
+    }
+
+    let alerts_config = load_alerts_config(ALERTS_CONFIG_PATH);
+    let tls_config = match (config.tls_cert_path, config.tls_key_path) {
+        (Some(cert), Some(key)) => Some((cert, key)),
+        _ => None
+    };
+    serve_forever(t, alerts_config, config.bind_addr, config.unix_socket_path, tls_config,
+                  config.query_limit_per_minute, config.admin_op_limit_per_minute, config.admin_token);
+}
+
+/// Read `path`'s "key:value" lines into a Config, starting from the
+/// defaults and overwriting whichever keys are present. A missing config
+/// file isn't an error; the defaults are used as-is.
+#[cfg(not(test))]
+fn load_config(path: &str) -> Config {
+    use std::io::BufferedReader;
+    use std::io::fs::File;
+
+    let mut config = Config::defaults();
+    let file = match File::open(&Path::new(path)) {
+        Ok(file) => file,
+        Err(..) => return config
+    };
+    let mut reader = BufferedReader::new(file);
+    while let Some(line) = reader.read_line().ok() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let i = match trimmed.find(':') {
+            Some(i) => i,
+            None => continue
+        };
+        let value = trimmed.slice_from(i + 1).to_string();
+        match trimmed.slice_to(i) {
+            "data_dir" => config.data_dir = value,
+            "connections_file" => config.connections_file = value,
+            "bind_addr" => config.bind_addr = value,
+            "transfer_cost" => config.transfer_cost = value.parse().ok(),
+            "precompute_all_pairs" => config.precompute_all_pairs =
+                value.parse().unwrap_or(config.precompute_all_pairs),
+            "query_limit_per_minute" => config.query_limit_per_minute = value.parse().ok(),
+            "admin_op_limit_per_minute" => config.admin_op_limit_per_minute = value.parse().ok(),
+            "unix_socket_path" => config.unix_socket_path = Some(value),
+            "tls_cert_path" => config.tls_cert_path = Some(value),
+            "tls_key_path" => config.tls_key_path = Some(value),
+            "admin_token" => config.admin_token = value,
+            _ => {}
+        }
+    }
+    config
 }
 
-/// Start accepting TCP requests and responding to T queries
+/// Apply "--key value" command-line overrides on top of a loaded Config,
+/// taking precedence over both the config file and the defaults.
+/// Unrecognized flags are ignored.
 #[cfg(not(test))]
-fn serve_forever(t: T) {
+fn apply_cli_overrides(config: &mut Config, args: &[String]) {
+    let mut i = 0;
+    while i + 1 < args.len() {
+        let value = args[i + 1].clone();
+        let recognized = match args[i].as_slice() {
+            "--data-dir" => { config.data_dir = value; true },
+            "--connections-file" => { config.connections_file = value; true },
+            "--bind-addr" => { config.bind_addr = value; true },
+            "--transfer-cost" => { config.transfer_cost = value.parse().ok(); true },
+            "--precompute-all-pairs" => {
+                config.precompute_all_pairs = value.parse().unwrap_or(config.precompute_all_pairs);
+                true
+            },
+            "--query-limit-per-minute" => { config.query_limit_per_minute = value.parse().ok(); true },
+            "--admin-op-limit-per-minute" => { config.admin_op_limit_per_minute = value.parse().ok(); true },
+            "--unix-socket-path" => { config.unix_socket_path = Some(value); true },
+            "--tls-cert-path" => { config.tls_cert_path = Some(value); true },
+            "--tls-key-path" => { config.tls_key_path = Some(value); true },
+            "--admin-token" => { config.admin_token = value; true },
+            _ => false
+        };
+        i += if recognized { 2 } else { 1 };
+    }
+}
+
+/// Read the alerts poller's config file, returning the feed URL and poll
+/// interval in seconds, or None if the file isn't present. A missing
+/// "interval_secs" key defaults to polling once a minute.
+#[cfg(not(test))]
+fn load_alerts_config(path: &str) -> Option<(String, i64)> {
+    use std::io::BufferedReader;
+    use std::io::fs::File;
+
+    let file = match File::open(&Path::new(path)) {
+        Ok(file) => file,
+        Err(..) => return None
+    };
+    let mut reader = BufferedReader::new(file);
+    let mut url = None;
+    let mut interval_secs = 60i64;
+    while let Some(line) = reader.read_line().ok() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let i = match trimmed.find(':') {
+            Some(i) => i,
+            None => continue
+        };
+        match trimmed.slice_to(i) {
+            "url" => url = Some(trimmed.slice_from(i + 1).to_string()),
+            "interval_secs" => interval_secs = trimmed.slice_from(i + 1).parse()
+                .unwrap_or(interval_secs),
+            _ => {}
+        }
+    }
+    url.map(|url| (url, interval_secs))
+}
+
+/// Re-disable whatever stations were disabled as of the last graceful
+/// shutdown, read one station name per line from `path`. A missing file
+/// or an unrecognized station name is ignored rather than treated as an
+/// error, the same as every other data file T loads.
+#[cfg(not(test))]
+fn restore_disabled_state(t: &mut T, path: &str) {
+    use std::io::BufferedReader;
+    use std::io::fs::File;
+
+    let file = match File::open(&Path::new(path)) {
+        Ok(file) => file,
+        Err(..) => return
+    };
+    let mut reader = BufferedReader::new(file);
+    while let Some(line) = reader.read_line().ok() {
+        let trimmed = line.trim();
+        if !trimmed.is_empty() {
+            t.disable_station(trimmed);
+        }
+    }
+}
+
+/// Write the currently disabled stations to `path`, one per line, so
+/// `restore_disabled_state` can bring them back on the next startup.
+#[allow(unused_must_use)]
+#[cfg(not(test))]
+fn persist_disabled_state(t: &T, path: &str) {
+    use std::io::fs::File;
+
+    let mut file = match File::create(&Path::new(path)) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("failed to persist disabled stations: {}", e);
+            return;
+        }
+    };
+    for station in t.disabled_stations().iter() {
+        file.write_line(station.as_slice());
+    }
+}
+
+/// Start accepting TCP requests and responding to T queries on
+/// `bind_addr`. If an alerts config was loaded, also start a background
+/// thread that polls the configured service-alerts feed and keeps the
+/// shared T's disabled stations in sync with it. The T is shared behind a
+/// RwLock rather than a Mutex, since most queries only read the T and
+/// should be able to run concurrently; only enable/disable, 'prefer
+/// fewer transfers' queries (which reweight and rebuild the graph), and
+/// the alerts and scheduled-disable pollers take the write lock.
+///
+/// Any authenticated connection can run 'shutdown', which raises a
+/// shared flag rather than killing the process outright: the accept
+/// loop stops taking new connections, every open TCP connection
+/// (including the one that asked for the shutdown) is sent a notice
+/// and has its socket closed out from under it so its
+/// blocked read returns and that client's thread can finish, the
+/// currently disabled stations are persisted to DISABLED_STATE_PATH,
+/// and every worker thread is joined before returning.
+///
+/// `query_limit_per_minute` and `admin_op_limit_per_minute` cap how many
+/// queries/admin operations a single connection or IP can make in a
+/// fixed one-minute window; None means no cap. Both caps are shared across every
+/// connection behind one RateLimiters, the same way the T and Metrics
+/// are shared.
+///
+/// If `unix_socket_path` is set, a second accept loop also listens there
+/// alongside `bind_addr`, sharing the same T/Metrics/RateLimiters, so
+/// local tooling can connect without going through the network stack.
+/// That loop runs in its own fire-and-forget thread rather than being
+/// joined on shutdown: a local socket has no notion of "IP" to key its
+/// rate limits by, so its connections use the socket path itself for
+/// both `conn_key` and `ip_key`, and it's simpler to just let the whole
+/// process exit out from under it than to teach it the same
+/// notify-and-close dance the TCP clients get.
+///
+/// If `tls_config` is set (a cert path and a key path), every connection
+/// accepted on `bind_addr` is wrapped in a TLS server handshake before
+/// any query is read from it, so the protocol and admin commands never
+/// cross the network in cleartext. A failed handshake just drops that
+/// connection rather than falling back to cleartext.
+///
+/// Every TCP connection's writer (for TLS, the post-handshake stream)
+/// is also registered with a Broadcaster, shared with query_user the same way the T/Metrics/
+/// RateLimiters are, so one connection's disable/enable can push an
+/// asynchronous notice to every other connection independent of
+/// whichever of them happens to be blocked in its own read loop at the
+/// time. The Unix socket listener shares the same Broadcaster for
+/// sending notices, but never registers its own connections on it --
+/// see spawn_unix_listener.
+#[allow(unused_must_use)]
+#[cfg(not(test))]
+fn serve_forever(t: T, alerts_config: Option<(String, i64)>, bind_addr: String,
+                  unix_socket_path: Option<String>, tls_config: Option<(String, String)>,
+                  query_limit_per_minute: Option<usize>, admin_op_limit_per_minute: Option<usize>,
+                  admin_token: String) {
     use std::thread::Thread;
 
-    let mbta = Arc::new(Mutex::new(t));
+    let mbta = Arc::new(RwLock::new(t));
+    let metrics = Arc::new(metrics::Metrics::new());
+    let shutdown = Arc::new(RwLock::new(false));
+    let rate_limiters = Arc::new(RateLimiters::new(query_limit_per_minute, admin_op_limit_per_minute));
+    let broadcaster = Arc::new(Broadcaster::new());
+    let admin_token = Arc::new(admin_token);
 
-    let listener = TcpListener::bind(BIND_ADDR).unwrap();
+    if let Some((url, interval_secs)) = alerts_config {
+        alerts::spawn_alerts_poller(mbta.clone(), url, interval_secs);
+    }
+    spawn_scheduled_disable_poller(mbta.clone(), SCHEDULED_DISABLE_POLL_SECS);
+
+    if let Some(path) = unix_socket_path {
+        spawn_unix_listener(path, mbta.clone(), metrics.clone(), shutdown.clone(), rate_limiters.clone(),
+                            broadcaster.clone(), admin_token.clone());
+    }
+
+    let tls_context = tls_config.map(|(cert_path, key_path)| {
+        // Pinned to TLSv1.2 rather than the negotiable Sslv23 method, so a
+        // client (or an attacker on the wire) can't downgrade this to
+        // SSLv3/TLS1.0 and reopen the cleartext exposure this TLS support
+        // exists to close.
+        let mut ctx = SslContext::new(SslMethod::Tlsv1_2).unwrap();
+        ctx.set_certificate_file(&Path::new(cert_path.as_slice()), X509FileType::PEM).unwrap();
+        ctx.set_private_key_file(&Path::new(key_path.as_slice()), X509FileType::PEM).unwrap();
+        Arc::new(ctx)
+    });
+
+    let listener = TcpListener::bind(bind_addr.as_slice()).unwrap();
     let mut acceptor = listener.listen().unwrap();
+    acceptor.set_timeout(Some(SHUTDOWN_POLL_MS));
+    let mut guards = Vec::new();
     for stream in acceptor.incoming() {
         match stream {
             Err(..) => {},
             Ok(stream) => {
+                let (conn_key, ip_key) = match stream.peer_name() {
+                    Ok(SocketAddr { ip, port }) => (format!("{}:{}", ip, port), format!("{}", ip)),
+                    Err(..) => ("unknown".to_string(), "unknown".to_string())
+                };
+                // Registered below, once we know whether this connection is
+                // getting wrapped in TLS -- registering the raw pre-handshake
+                // stream here would let a broadcast write straight past the
+                // encryption a TLS connection is about to negotiate. See
+                // Broadcaster::register's doc comment.
+                let closer = stream.clone();
                 let tee = mbta.clone();
-                Thread::spawn(move || {
-                    let mut stream = BufferedStream::new(stream);
-                    query_user(&mut stream, tee)
-                });
+                let metrics = metrics.clone();
+                let shutdown_flag = shutdown.clone();
+                let rate_limiters = rate_limiters.clone();
+                let conn_broadcaster = broadcaster.clone();
+                let tls_context = tls_context.clone();
+                let conn_admin_token = admin_token.clone();
+                guards.push(Thread::spawn(move || {
+                    match tls_context {
+                        Some(ctx) => {
+                            match SslStream::new(&*ctx, stream) {
+                                Ok(ssl_stream) => {
+                                    let writer: SharedWriter = Arc::new(Mutex::new(Box::new(ssl_stream.clone())));
+                                    conn_broadcaster.register(conn_key.clone(), writer.clone(), closer);
+                                    let mut stream = SyncedStream::new(BufferedStream::new(ssl_stream), writer);
+                                    query_user(&mut stream, tee, metrics, shutdown_flag, rate_limiters,
+                                               conn_broadcaster, conn_key, ip_key, conn_admin_token)
+                                },
+                                Err(e) => println!("TLS handshake failed: {}", e)
+                            }
+                        },
+                        None => {
+                            let writer: SharedWriter = Arc::new(Mutex::new(Box::new(stream.clone())));
+                            conn_broadcaster.register(conn_key.clone(), writer.clone(), closer);
+                            let mut stream = SyncedStream::new(BufferedStream::new(stream), writer);
+                            query_user(&mut stream, tee, metrics, shutdown_flag, rate_limiters,
+                                       conn_broadcaster, conn_key, ip_key, conn_admin_token)
+                        }
+                    }
+                }));
             }
         }
+        if *shutdown.read().unwrap() {
+            break;
+        }
     }
+
+    broadcaster.close_all(SHUTDOWN_NOTICE);
+    persist_disabled_state(&*mbta.read().unwrap(), DISABLED_STATE_PATH);
+    for guard in guards.into_iter() {
+        guard.join();
+    }
+}
+
+/// Start a background thread that wakes up every `interval_secs` and
+/// re-enables any station whose scheduled disable has expired, the same
+/// way spawn_alerts_poller periodically reconciles alert-disabled
+/// stations against a feed. Not tied to shutdown: like the alerts
+/// poller, it's left detached rather than joined, since there's nothing
+/// unsent it's holding onto when the process exits.
+#[cfg(not(test))]
+fn spawn_scheduled_disable_poller(mbta: Arc<RwLock<T>>, interval_secs: i64) {
+    use std::io::timer::sleep;
+    use std::thread::Thread;
+    use std::time::duration::Duration;
+
+    Thread::spawn(move || {
+        loop {
+            mbta.write().unwrap().expire_scheduled_disables();
+            sleep(Duration::seconds(interval_secs));
+        }
+    });
+}
+
+/// Start a background accept loop on the Unix socket at `path`, serving
+/// the same T/Metrics/RateLimiters every TCP connection shares. Polls
+/// `shutdown` the same way the TCP accept loop does, so it stops taking
+/// new connections once a shutdown is requested, but it isn't joined;
+/// see the note on serve_forever for why that's fine here.
+///
+/// `broadcaster` is shared with the TCP listener so a disable/enable on
+/// a Unix socket connection still notifies TCP connections, but a Unix
+/// socket connection's own stream is never registered on it -- it has no
+/// TcpStream to register, and is already excluded from the shutdown
+/// notice for the same reason.
+#[allow(unused_must_use)]
+#[cfg(not(test))]
+fn spawn_unix_listener(path: String, mbta: Arc<RwLock<T>>, metrics: Arc<metrics::Metrics>,
+                        shutdown: Arc<RwLock<bool>>, rate_limiters: Arc<RateLimiters>,
+                        broadcaster: Arc<Broadcaster>, admin_token: Arc<String>) {
+    use std::thread::Thread;
+
+    Thread::spawn(move || {
+        let listener = match UnixListener::bind(&Path::new(path.as_slice())) {
+            Ok(listener) => listener,
+            Err(e) => {
+                println!("failed to bind unix socket {}: {}", path, e);
+                return;
+            }
+        };
+        let mut acceptor = listener.listen().unwrap();
+        acceptor.set_timeout(Some(SHUTDOWN_POLL_MS));
+        for stream in acceptor.incoming() {
+            match stream {
+                Err(..) => {},
+                Ok(stream) => {
+                    let tee = mbta.clone();
+                    let metrics = metrics.clone();
+                    let shutdown_flag = shutdown.clone();
+                    let rate_limiters = rate_limiters.clone();
+                    let conn_broadcaster = broadcaster.clone();
+                    let conn_key = path.clone();
+                    let ip_key = path.clone();
+                    let conn_admin_token = admin_token.clone();
+                    Thread::spawn(move || {
+                        let mut stream = BufferedStream::new(stream);
+                        query_user(&mut stream, tee, metrics, shutdown_flag, rate_limiters,
+                                   conn_broadcaster, conn_key, ip_key, conn_admin_token)
+                    });
+                }
+            }
+            if *shutdown.read().unwrap() {
+                break;
+            }
+        }
+    });
 }
@@ -0,0 +1,136 @@
+#[doc="
+    Module: metrics
+
+    Tracks request counters and path-query latency for the running T
+    server: queries served, disambiguation prompts, no-path results, and
+    active connections, plus a coarse latency histogram for path
+    queries. Shared across every connection behind an RwLock, the same
+    way the T itself is shared -- see main::serve_forever. Surfaced to
+    operators via the 'metrics' admin command.
+"]
+
+use std::sync::RwLock;
+use t::TQueryResult;
+use t::TQueryResult::{TOk, TOkMultiple, TOkPareto, TPlan, DisambiguateStart, DisambiguateDestination};
+
+// upper bounds, in milliseconds, of every latency bucket but the last;
+// the last bucket catches everything at or above LATENCY_BUCKETS_MS's
+// final value
+static LATENCY_BUCKETS_MS: [u64; 3] = [10, 50, 200];
+
+struct MetricsState {
+    queries_served: usize,
+    disambiguations: usize,
+    no_path_results: usize,
+    active_connections: usize,
+    // one count per LATENCY_BUCKETS_MS entry, plus a final count for
+    // everything at or above the last bound
+    latency_histogram: [usize; 4]
+}
+
+/// A point-in-time copy of every counter, for printing.
+pub struct MetricsSnapshot {
+    pub queries_served: usize,
+    pub disambiguations: usize,
+    pub no_path_results: usize,
+    pub active_connections: usize,
+    pub latency_histogram: Vec<(String, usize)>
+}
+
+/// Counters and latency histogram for the running server, guarded by a
+/// single RwLock.
+pub struct Metrics {
+    state: RwLock<MetricsState>
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            state: RwLock::new(MetricsState {
+                queries_served: 0,
+                disambiguations: 0,
+                no_path_results: 0,
+                active_connections: 0,
+                latency_histogram: [0; 4]
+            })
+        }
+    }
+
+    /// Record that a connection was opened.
+    pub fn connection_opened(&self) {
+        self.state.write().unwrap().active_connections += 1;
+    }
+
+    /// Record that a connection was closed.
+    pub fn connection_closed(&self) {
+        self.state.write().unwrap().active_connections -= 1;
+    }
+
+    /// Record the outcome and latency of a path query: whether it
+    /// succeeded, prompted for disambiguation, or found no path, and
+    /// which latency bucket it landed in.
+    pub fn record_path_query(&self, result: &TQueryResult, elapsed_ms: u64) {
+        let mut state = self.state.write().unwrap();
+        state.queries_served += 1;
+        match result {
+            &TOk(..) | &TOkMultiple(..) | &TOkPareto(..) | &TPlan(..) => {},
+            &DisambiguateStart(..) | &DisambiguateDestination(..) => state.disambiguations += 1,
+            _ => state.no_path_results += 1
+        }
+        let bucket = LATENCY_BUCKETS_MS.iter().position(|&bound| elapsed_ms < bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        state.latency_histogram[bucket] += 1;
+    }
+
+    /// A snapshot of every counter, suitable for printing.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let state = self.state.read().unwrap();
+        let mut latency_histogram = Vec::new();
+        let mut lower = 0u64;
+        for (i, &upper) in LATENCY_BUCKETS_MS.iter().enumerate() {
+            latency_histogram.push((format!("{}-{}ms", lower, upper), state.latency_histogram[i]));
+            lower = upper;
+        }
+        latency_histogram.push((format!(">={}ms", lower), state.latency_histogram[LATENCY_BUCKETS_MS.len()]));
+        MetricsSnapshot {
+            queries_served: state.queries_served,
+            disambiguations: state.disambiguations,
+            no_path_results: state.no_path_results,
+            active_connections: state.active_connections,
+            latency_histogram: latency_histogram
+        }
+    }
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::Metrics;
+    use t::TQueryResult::{TOk, NoSuchPath, DisambiguateStart};
+
+    #[test]
+    fn test_record_path_query() {
+        let metrics = Metrics::new();
+        metrics.record_path_query(&TOk(Vec::new(), 0, 0.0), 1);
+        metrics.record_path_query(&NoSuchPath, 20);
+        metrics.record_path_query(&DisambiguateStart(Vec::new()), 200);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.queries_served, 3);
+        assert_eq!(snapshot.disambiguations, 1);
+        assert_eq!(snapshot.no_path_results, 1);
+        assert_eq!(snapshot.latency_histogram,
+                   vec![("0-10ms".to_string(), 1),
+                        ("10-50ms".to_string(), 1),
+                        ("50-200ms".to_string(), 0),
+                        (">=200ms".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_connection_opened_and_closed() {
+        let metrics = Metrics::new();
+        metrics.connection_opened();
+        metrics.connection_opened();
+        metrics.connection_closed();
+        assert_eq!(metrics.snapshot().active_connections, 1);
+    }
+}
@@ -8,9 +8,13 @@
 use t::TStep;
 use t::TQueryResult;
 use t::TOperationResult;
-use t::TQueryResult::{TOk, DisambiguateStart, DisambiguateDestination, NoSuchStart, NoSuchDest, DisabledStart, DisabledDest, NoSuchPath};
+use t::TInfoResult;
+use t::TQueryResult::{TOk, TOkMultiple, TOkPareto, TPlan, DisambiguateStart, DisambiguateDestination, NoSuchStart, NoSuchDest, DisabledStart, DisabledDest, NoSuchPath, LineNotRunning};
 use t::TOperationResult::{Successful, DisambiguateOp, NoSuchStationOp};
-use t::TStep::{Station, Switch, Ensure};
+use t::TInfoResult::{Info, DisambiguateInfo, NoSuchStationInfo};
+use t::TStep::{Station, Switch, Ensure, Walk};
+use t::itinerary_metrics;
+use metrics::MetricsSnapshot;
 
 static DISAMBIG_START: &'static str = "disambiguate your start: ";
 static DISAMBIG_DEST: &'static str = "disambiguate your destination: ";
@@ -22,14 +26,89 @@ static DISABLED_START: &'static str = "disabled start: ";
 static DISABLED_DEST: &'static str = "disabled destination: ";
 static NO_SUCH_DISABLE: &'static str = "no such station to disable: ";
 static NO_SUCH_ENABLE: &'static str = "no such station to enable: ";
+static NO_SUCH_DISABLE_SEGMENT: &'static str = "no such segment to disable: ";
+static NO_SUCH_ENABLE_SEGMENT: &'static str = "no such segment to enable: ";
 static NO_SUCH_PATH: &'static str = "No path exists.\n";
+static LINES_HEADER: &'static str = "lines: ";
+static STATIONS_HEADER: &'static str = "stations: ";
+static NO_SUCH_LINE: &'static str = "no such line: ";
+static DISABLED_STATIONS_HEADER: &'static str = "disabled stations: ";
+static DISABLED_SEGMENTS_HEADER: &'static str = "disabled segments: ";
+static ALERTS_HEADER: &'static str = "active alerts: ";
+static SCHEDULED_DISABLES_HEADER: &'static str = "scheduled disables: ";
+static LINE_NOT_RUNNING: &'static str = "line not running: ";
+static QUERIES_SERVED_HEADER: &'static str = "queries served: ";
+static DISAMBIGUATIONS_HEADER: &'static str = "disambiguations: ";
+static NO_PATH_RESULTS_HEADER: &'static str = "no-path results: ";
+static ACTIVE_CONNECTIONS_HEADER: &'static str = "active connections: ";
+static LATENCY_HISTOGRAM_HEADER: &'static str = "path query latency: ";
+static NEAREST_STATIONS_HEADER: &'static str = "nearest stations: ";
+static INFO_STATION_HEADER: &'static str = "station: ";
+static INFO_LINES_HEADER: &'static str = "lines: ";
+static INFO_TRANSFER_HEADER: &'static str = "transfer station: ";
+static INFO_DISABLED_HEADER: &'static str = "disabled: ";
+static INFO_ADJACENT_HEADER: &'static str = "adjacent stations: ";
+static NO_SUCH_INFO: &'static str = "no such station: ";
+
+/// How to render an itinerary's steps in output_find_path: Verbose prints
+/// every intermediate stop, Condensed collapses each run of stops on the
+/// same line into one clause per leg, all on a single line, and Csv prints one CSV row per step
+/// for pasting into a spreadsheet or feeding to a script.
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Verbose,
+    Condensed,
+    Csv
+}
 
 #[allow(unused_must_use)]
-/// Print to the output writer the result of calling find_path on the T.
+/// Print to the output writer the result of calling find_path on the T,
+/// rendering its steps according to `format`.
 pub fn output_find_path<W: Writer>(path: TQueryResult, from: &str,
-                                   to: &str, output: &mut W) {
+                                   to: &str, format: OutputFormat, output: &mut W) {
     match path {
-        TOk(steps) => { print_steps(steps, output); },
+        TOk(steps, minutes, fare) => {
+            let (stops, transfers) = itinerary_metrics(&steps);
+            print_steps_in(format, steps, output);
+            write!(output, "Stops: {}\n", stops);
+            write!(output, "Transfers: {}\n", transfers);
+            write!(output, "Total travel time: {} minutes\n", minutes);
+            write!(output, "Total fare: ${:.2}\n", fare);
+        },
+        TOkMultiple(options) => {
+            for (i, (steps, minutes, fare)) in options.into_iter().enumerate() {
+                write!(output, "Option {}:\n", i + 1);
+                let (stops, transfers) = itinerary_metrics(&steps);
+                print_steps_in(format, steps, output);
+                write!(output, "Stops: {}\n", stops);
+                write!(output, "Transfers: {}\n", transfers);
+                write!(output, "Total travel time: {} minutes\n", minutes);
+                write!(output, "Total fare: ${:.2}\n", fare);
+            }
+        },
+        TOkPareto(itineraries) => {
+            for (i, (steps, stops, transfers, minutes, fare)) in itineraries.into_iter().enumerate() {
+                write!(output, "Itinerary {}:\n", i + 1);
+                print_steps_in(format, steps, output);
+                write!(output, "Stops: {}\n", stops);
+                write!(output, "Transfers: {}\n", transfers);
+                write!(output, "Total travel time: {} minutes\n", minutes);
+                write!(output, "Total fare: ${:.2}\n", fare);
+            }
+        },
+        TPlan(legs, total_minutes, total_fare) => {
+            for (i, (steps, minutes, fare)) in legs.into_iter().enumerate() {
+                write!(output, "Leg {}:\n", i + 1);
+                let (stops, transfers) = itinerary_metrics(&steps);
+                print_steps_in(format, steps, output);
+                write!(output, "Stops: {}\n", stops);
+                write!(output, "Transfers: {}\n", transfers);
+                write!(output, "Leg travel time: {} minutes\n", minutes);
+                write!(output, "Leg fare: ${:.2}\n", fare);
+            }
+            write!(output, "Total travel time: {} minutes\n", total_minutes);
+            write!(output, "Total fare: ${:.2}\n", total_fare);
+        },
         DisambiguateStart(suggestions) => { print_vec(DISAMBIG_START, suggestions, output); },
         DisambiguateDestination(suggestions) => { print_vec(DISAMBIG_DEST, suggestions,
                                                             output); },
@@ -37,34 +116,145 @@ pub fn output_find_path<W: Writer>(path: TQueryResult, from: &str,
         NoSuchDest => { print_str(NO_SUCH_DEST, to, output); },
         DisabledStart(s) => { print_str(DISABLED_START, s.as_slice(), output); },
         DisabledDest(s) => { print_str(DISABLED_DEST, s.as_slice(), output); },
-        NoSuchPath => { output.write_str(NO_SUCH_PATH); }
+        NoSuchPath => { output.write_str(NO_SUCH_PATH); },
+        LineNotRunning(line, wait_minutes) => {
+            write!(output, "{}{}, next departure in {} minutes\n", LINE_NOT_RUNNING,
+                  line, wait_minutes);
+        }
     }
 }
 
 #[cfg(test)]
 mod output_find_path_tests {
-    use super::output_find_path;
+    use super::{output_find_path, OutputFormat};
     use std::io::MemWriter;
     use t::TQueryResult;
+    use t::TQueryResult::{TOkMultiple, TOkPareto, TPlan, LineNotRunning};
+    use t::TStep::Station;
     use t::T;
 
     #[test]
     fn test_output_find_path() {
         let mut t = T::new();
-        t.load();
+        t.load().unwrap();
 
         let (from, to) = ("South Station", "Andrew Station");
-        let expect = concat!("South Station, take red\n",
-                             "Broadway Station, take red\n",
-                             "Andrew Station, take red\n");
+        let expect = concat!("South Station, take red toward JFK/UMass Station\n",
+                             "Broadway Station, take red toward JFK/UMass Station\n",
+                             "Andrew Station, take red toward JFK/UMass Station\n",
+                             "Stops: 3\n",
+                             "Transfers: 0\n",
+                             "Total travel time: 4 minutes\n",
+                             "Total fare: $2.40\n");
         run_test_output_find_path(t.find_path(from, to), from, to, expect);
     }
 
+    #[test]
+    fn test_output_find_path_multiple() {
+        let path = TOkMultiple(vec![
+            (vec![Station("South Station".to_string(), "red".to_string(), None),
+                 Station("Broadway Station".to_string(), "red".to_string(), None)], 2, 2.40),
+            (vec![Station("South Station".to_string(), "red".to_string(), None),
+                 Station("Downtown Crossing Station".to_string(), "red".to_string(), None)], 2, 2.40)]);
+        let expect = concat!("Option 1:\n",
+                             "South Station, take red\n",
+                             "Broadway Station, take red\n",
+                             "Stops: 2\n",
+                             "Transfers: 0\n",
+                             "Total travel time: 2 minutes\n",
+                             "Total fare: $2.40\n",
+                             "Option 2:\n",
+                             "South Station, take red\n",
+                             "Downtown Crossing Station, take red\n",
+                             "Stops: 2\n",
+                             "Transfers: 0\n",
+                             "Total travel time: 2 minutes\n",
+                             "Total fare: $2.40\n");
+        run_test_output_find_path(path, "South Station", "Broadway Station", expect);
+    }
+
+    #[test]
+    fn test_output_find_path_pareto() {
+        let path = TOkPareto(vec![
+            (vec![Station("South Station".to_string(), "red".to_string(), None),
+                 Station("Broadway Station".to_string(), "red".to_string(), None)], 2, 0, 2, 2.40),
+            (vec![Station("South Station".to_string(), "red".to_string(), None),
+                 Station("Downtown Crossing Station".to_string(), "red".to_string(), None)], 2, 1, 5, 2.40)]);
+        let expect = concat!("Itinerary 1:\n",
+                             "South Station, take red\n",
+                             "Broadway Station, take red\n",
+                             "Stops: 2\n",
+                             "Transfers: 0\n",
+                             "Total travel time: 2 minutes\n",
+                             "Total fare: $2.40\n",
+                             "Itinerary 2:\n",
+                             "South Station, take red\n",
+                             "Downtown Crossing Station, take red\n",
+                             "Stops: 2\n",
+                             "Transfers: 1\n",
+                             "Total travel time: 5 minutes\n",
+                             "Total fare: $2.40\n");
+        run_test_output_find_path(path, "South Station", "Broadway Station", expect);
+    }
+
+    #[test]
+    fn test_output_find_path_plan() {
+        let path = TPlan(vec![
+            (vec![Station("South Station".to_string(), "red".to_string(), None),
+                 Station("Broadway Station".to_string(), "red".to_string(), None)], 2, 2.40),
+            (vec![Station("Broadway Station".to_string(), "red".to_string(), None),
+                 Station("Andrew Station".to_string(), "red".to_string(), None)], 2, 2.40)],
+            9, 4.80);
+        let expect = concat!("Leg 1:\n",
+                             "South Station, take red\n",
+                             "Broadway Station, take red\n",
+                             "Stops: 2\n",
+                             "Transfers: 0\n",
+                             "Leg travel time: 2 minutes\n",
+                             "Leg fare: $2.40\n",
+                             "Leg 2:\n",
+                             "Broadway Station, take red\n",
+                             "Andrew Station, take red\n",
+                             "Stops: 2\n",
+                             "Transfers: 0\n",
+                             "Leg travel time: 2 minutes\n",
+                             "Leg fare: $2.40\n",
+                             "Total travel time: 9 minutes\n",
+                             "Total fare: $4.80\n");
+        run_test_output_find_path(path, "South Station", "Andrew Station", expect);
+    }
+
+    #[test]
+    fn test_output_find_path_csv() {
+        let mut t = T::new();
+        t.load().unwrap();
+
+        let (from, to) = ("South Station", "Andrew Station");
+        let mut w = MemWriter::new();
+        output_find_path(t.find_path(from, to), from, to, OutputFormat::Csv, &mut w);
+        let expect = concat!("step,station,line,stops,elapsed_minutes\n",
+                             "station,South Station,red,1,0\n",
+                             "station,Broadway Station,red,2,0\n",
+                             "station,Andrew Station,red,3,0\n",
+                             "Stops: 3\n",
+                             "Transfers: 0\n",
+                             "Total travel time: 4 minutes\n",
+                             "Total fare: $2.40\n");
+        assert_eq!(expect, String::from_utf8(w.into_inner()).unwrap());
+    }
+
+    #[test]
+    fn test_output_find_path_line_not_running() {
+        let path = LineNotRunning("red".to_string(), 15);
+        run_test_output_find_path(path, "South Station", "Andrew Station",
+                                  "line not running: red, next departure in 15 minutes\n");
+    }
+
     /// Test the output of finding a path
     fn run_test_output_find_path(path: TQueryResult,
                                  from: &str, to: &str, expect: &str) {
         let mut w = MemWriter::new();
-        output_find_path(path, from, to, &mut w);
+        output_find_path(path, from, to, OutputFormat::Verbose, &mut w);
         assert_eq!(expect, String::from_utf8(w.into_inner()).unwrap());
     }
 }
@@ -86,7 +276,9 @@ mod output_toperation_result_tests {
     use t::TOperationResult;
     use std::io::MemWriter;
     use super::{output_enable_station, output_disable_station};
+    use super::{output_enable_segment, output_disable_segment};
     use super::{NO_SUCH_ENABLE, NO_SUCH_DISABLE, SUCCESS_OP, DISAMBIG_OP};
+    use super::{NO_SUCH_ENABLE_SEGMENT, NO_SUCH_DISABLE_SEGMENT};
 
     #[test]
     fn test_output_toperation_result() {
@@ -102,11 +294,39 @@ mod output_toperation_result_tests {
                                    format!("{}{}", NO_SUCH_ENABLE, "asdf\n").as_slice());
     }
 
+    #[test]
+    fn test_output_toperation_result_segment() {
+        let mut t = T::new();
+        t.load().unwrap();
+
+        let mut w = MemWriter::new();
+        let result = t.disable_segment("South Station", "Broadway Station");
+        output_disable_segment("South Station", "Broadway Station", result, &mut w);
+        assert_eq!(SUCCESS_OP, String::from_utf8(w.into_inner()).unwrap().as_slice());
+
+        let mut w = MemWriter::new();
+        let result = t.enable_segment("South Station", "Broadway Station");
+        output_enable_segment("South Station", "Broadway Station", result, &mut w);
+        assert_eq!(SUCCESS_OP, String::from_utf8(w.into_inner()).unwrap().as_slice());
+
+        let mut w = MemWriter::new();
+        let result = t.disable_segment("asdf", "Broadway Station");
+        output_disable_segment("asdf", "Broadway Station", result, &mut w);
+        assert_eq!(format!("{}{}", NO_SUCH_DISABLE_SEGMENT, "asdf and Broadway Station\n"),
+                   String::from_utf8(w.into_inner()).unwrap());
+
+        let mut w = MemWriter::new();
+        let result = t.enable_segment("asdf", "Broadway Station");
+        output_enable_segment("asdf", "Broadway Station", result, &mut w);
+        assert_eq!(format!("{}{}", NO_SUCH_ENABLE_SEGMENT, "asdf and Broadway Station\n"),
+                   String::from_utf8(w.into_inner()).unwrap());
+    }
+
     /// Test the output of enabling or disabling a station
     fn run_test_output_toperation(station: &str, enable: bool, expect: &str) {
         let mut w = MemWriter::new();
         let mut t = T::new();
-        t.load();
+        t.load().unwrap();
 
         let result: TOperationResult;
         if enable {
@@ -135,14 +355,283 @@ pub fn output_disable_station<W: Writer>(station: &str,
     output_toperation_result(disabled, station, NO_SUCH_DISABLE, output)
 }
 
+/// Print to the output writer the result of enabling the segment between
+/// the two given stations. Simple wrapper for output_toperation_result
+pub fn output_enable_segment<W: Writer>(a: &str, b: &str,
+                                        enabled: TOperationResult, output: &mut W) {
+    let segment = format!("{} and {}", a, b);
+    output_toperation_result(enabled, segment.as_slice(), NO_SUCH_ENABLE_SEGMENT, output)
+}
+
+/// Print to the output writer the result of disabling the segment between
+/// the two given stations. Simple wrapper for output_toperation_result
+pub fn output_disable_segment<W: Writer>(a: &str, b: &str,
+                                         disabled: TOperationResult, output: &mut W) {
+    let segment = format!("{} and {}", a, b);
+    output_toperation_result(disabled, segment.as_slice(), NO_SUCH_DISABLE_SEGMENT, output)
+}
+
+/// Print to the output writer the sorted list of every line in the network
+pub fn output_lines<W: Writer>(lines: Vec<String>, output: &mut W) {
+    print_vec(LINES_HEADER, lines, output)
+}
+
+#[allow(unused_must_use)]
+/// Print to the output writer the stations on the given line, or every
+/// station in the network if no line was given. Prints an error message
+/// if the given line doesn't exist.
+pub fn output_stations<W: Writer>(line: Option<&str>, stations: Option<Vec<String>>, output: &mut W) {
+    match stations {
+        Some(v) => { print_vec(STATIONS_HEADER, v, output); },
+        None => { print_str(NO_SUCH_LINE, line.unwrap_or(""), output); }
+    }
+}
+
+#[allow(unused_must_use)]
+/// Print to the output writer this connection's command history, one
+/// command per line, numbered from 1 so `!N` can reference them.
+pub fn output_history<W: Writer>(history: Vec<String>, output: &mut W) {
+    for (i, command) in history.into_iter().enumerate() {
+        write!(output, "{}: {}\n", i + 1, command);
+    }
+}
+
+#[allow(unused_must_use)]
+/// Print to the output writer the nearest stations to a query point,
+/// nearest first, each as "<station> (<distance> mi)".
+pub fn output_nearest<W: Writer>(nearby: Vec<(String, f64)>, output: &mut W) {
+    let entries: Vec<String> = nearby.into_iter()
+        .map(|(station, distance)| format!("{} ({:.2} mi)", station, distance)).collect();
+    print_vec(NEAREST_STATIONS_HEADER, entries, output);
+}
+
+#[allow(unused_must_use)]
+/// Print to the output writer the result of looking up a station with
+/// 'info': the lines serving it, whether it's a transfer station,
+/// whether it's currently disabled, and its nearest enabled neighbor in
+/// each direction on every line it's on.
+pub fn output_info<W: Writer>(station: &str, info: TInfoResult, output: &mut W) {
+    match info {
+        Info(name, lines, transfer, disabled, adjacent) => {
+            print_str(INFO_STATION_HEADER, name.as_slice(), output);
+            print_vec(INFO_LINES_HEADER, lines, output);
+            print_str(INFO_TRANSFER_HEADER, transfer.to_string().as_slice(), output);
+            print_str(INFO_DISABLED_HEADER, disabled.to_string().as_slice(), output);
+            let adjacent_strs: Vec<String> = adjacent.into_iter()
+                .map(|(line, prev, next)| format!("{} (prev: {}, next: {})", line,
+                                                   prev.unwrap_or("none".to_string()),
+                                                   next.unwrap_or("none".to_string())))
+                .collect();
+            print_vec(INFO_ADJACENT_HEADER, adjacent_strs, output);
+        },
+        DisambiguateInfo(suggestions) => { print_vec(DISAMBIG_OP, suggestions, output); },
+        NoSuchStationInfo => { print_str(NO_SUCH_INFO, station, output); }
+    }
+}
+
+#[cfg(test)]
+mod output_info_tests {
+    use t::T;
+    use std::io::MemWriter;
+    use super::output_info;
+
+    #[test]
+    fn test_output_info() {
+        let mut t = T::new();
+        t.load().unwrap();
+
+        let mut w = MemWriter::new();
+        output_info("Andrew Station", t.station_info("Andrew Station"), &mut w);
+        assert_eq!(concat!("station: Andrew Station\n",
+                            "lines: red \n",
+                            "transfer station: false\n",
+                            "disabled: false\n",
+                            "adjacent stations: red (prev: Broadway Station, next: JFK/UMass Station) \n"),
+                   String::from_utf8(w.into_inner()).unwrap().as_slice());
+
+        let mut w = MemWriter::new();
+        output_info("asdf", t.station_info("asdf"), &mut w);
+        assert_eq!("no such station: asdf\n", String::from_utf8(w.into_inner()).unwrap().as_slice());
+    }
+}
+
+#[cfg(test)]
+mod output_nearest_tests {
+    use super::output_nearest;
+    use std::io::MemWriter;
+
+    #[test]
+    fn test_output_nearest() {
+        let mut w = MemWriter::new();
+        output_nearest(vec![("Park Street Station".to_string(), 0.5),
+                             ("Downtown Crossing Station".to_string(), 0.75)], &mut w);
+        assert_eq!("nearest stations: Park Street Station (0.50 mi) Downtown Crossing Station (0.75 mi) \n",
+                   String::from_utf8(w.into_inner()).unwrap().as_slice());
+    }
+}
+
+#[cfg(test)]
+mod output_lines_and_stations_tests {
+    use super::{output_lines, output_stations};
+    use super::{LINES_HEADER, STATIONS_HEADER, NO_SUCH_LINE};
+    use std::io::MemWriter;
+    use t::T;
+
+    #[test]
+    fn test_output_lines() {
+        let mut t = T::new();
+        t.load().unwrap();
+        let mut w = MemWriter::new();
+        output_lines(t.lines(), &mut w);
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert!(out.starts_with(LINES_HEADER));
+        assert!(out.contains("red"));
+    }
+
+    #[test]
+    fn test_output_stations() {
+        let mut t = T::new();
+        t.load().unwrap();
+
+        let mut w = MemWriter::new();
+        output_stations(Some("red"), t.stations(Some("red")), &mut w);
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert!(out.starts_with(STATIONS_HEADER));
+        assert!(out.contains("Andrew Station"));
+
+        let mut w = MemWriter::new();
+        output_stations(Some("asdf"), t.stations(Some("asdf")), &mut w);
+        assert_eq!(format!("{}{}", NO_SUCH_LINE, "asdf\n"),
+                   String::from_utf8(w.into_inner()).unwrap());
+    }
+}
+
+#[cfg(test)]
+mod output_history_tests {
+    use super::output_history;
+    use std::io::MemWriter;
+
+    #[test]
+    fn test_output_history() {
+        let mut w = MemWriter::new();
+        output_history(vec!["from South to Ruggles".to_string(), "lines".to_string()], &mut w);
+        assert_eq!("1: from South to Ruggles\n2: lines\n",
+                   String::from_utf8(w.into_inner()).unwrap().as_slice());
+    }
+}
+
+/// Print to the output writer the currently disabled stations and
+/// segments, any active service alerts, and any stations under a
+/// scheduled disable (each with the seconds remaining until it's
+/// automatically re-enabled).
+pub fn output_status<W: Writer>(stations: Vec<String>, segments: Vec<(String, String)>,
+                                alerts: Vec<(String, String)>,
+                                scheduled_disables: Vec<(String, i64)>, output: &mut W) {
+    print_vec(DISABLED_STATIONS_HEADER, stations, output);
+    let segment_strs: Vec<String> = segments.into_iter()
+        .map(|(a, b)| format!("{} and {}", a, b)).collect();
+    print_vec(DISABLED_SEGMENTS_HEADER, segment_strs, output);
+    let alert_strs: Vec<String> = alerts.into_iter()
+        .map(|(station, description)| format!("{}: {}", station, description)).collect();
+    print_vec(ALERTS_HEADER, alert_strs, output);
+    let scheduled_strs: Vec<String> = scheduled_disables.into_iter()
+        .map(|(station, seconds)| format!("{} ({}s)", station, seconds)).collect();
+    print_vec(SCHEDULED_DISABLES_HEADER, scheduled_strs, output);
+}
+
+/// Print to the output writer the server's request counters and
+/// path-query latency histogram.
+pub fn output_metrics<W: Writer>(snapshot: MetricsSnapshot, output: &mut W) {
+    print_str(QUERIES_SERVED_HEADER, snapshot.queries_served.to_string().as_slice(), output);
+    print_str(DISAMBIGUATIONS_HEADER, snapshot.disambiguations.to_string().as_slice(), output);
+    print_str(NO_PATH_RESULTS_HEADER, snapshot.no_path_results.to_string().as_slice(), output);
+    print_str(ACTIVE_CONNECTIONS_HEADER, snapshot.active_connections.to_string().as_slice(), output);
+    let bucket_strs: Vec<String> = snapshot.latency_histogram.into_iter()
+        .map(|(bucket, count)| format!("{}: {}", bucket, count)).collect();
+    print_vec(LATENCY_HISTOGRAM_HEADER, bucket_strs, output);
+}
+
+#[cfg(test)]
+mod output_metrics_tests {
+    use super::output_metrics;
+    use super::{QUERIES_SERVED_HEADER, DISAMBIGUATIONS_HEADER, NO_PATH_RESULTS_HEADER,
+               ACTIVE_CONNECTIONS_HEADER, LATENCY_HISTOGRAM_HEADER};
+    use std::io::MemWriter;
+    use metrics::Metrics;
+
+    #[test]
+    fn test_output_metrics() {
+        let metrics = Metrics::new();
+        metrics.connection_opened();
+
+        let mut w = MemWriter::new();
+        output_metrics(metrics.snapshot(), &mut w);
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert!(out.starts_with(QUERIES_SERVED_HEADER));
+        assert!(out.contains(DISAMBIGUATIONS_HEADER));
+        assert!(out.contains(NO_PATH_RESULTS_HEADER));
+        assert!(out.contains(format!("{}1", ACTIVE_CONNECTIONS_HEADER).as_slice()));
+        assert!(out.contains(LATENCY_HISTOGRAM_HEADER));
+    }
+}
+
+#[cfg(test)]
+mod output_status_tests {
+    use super::output_status;
+    use super::{DISABLED_STATIONS_HEADER, DISABLED_SEGMENTS_HEADER, ALERTS_HEADER, SCHEDULED_DISABLES_HEADER};
+    use std::io::MemWriter;
+    use t::T;
+
+    #[test]
+    fn test_output_status() {
+        let mut t = T::new();
+        t.load().unwrap();
+        t.disable_station("Andrew Station");
+        t.disable_segment("South Station", "Broadway Station");
+        t.apply_alert("Harvard Square Station", "signal problem");
+
+        let mut w = MemWriter::new();
+        output_status(t.disabled_stations(), t.disabled_segments(), t.active_alerts(),
+                       t.scheduled_disables_remaining(), &mut w);
+        assert_eq!(format!("{}{}\n{}{}\n{}{}\n{}\n", DISABLED_STATIONS_HEADER,
+                           "Andrew Station Harvard Square Station ",
+                           DISABLED_SEGMENTS_HEADER, "Broadway Station and South Station ",
+                           ALERTS_HEADER, "Harvard Square Station: signal problem ",
+                           SCHEDULED_DISABLES_HEADER),
+                   String::from_utf8(w.into_inner()).unwrap());
+    }
+}
+
+/// Dispatch to whichever of print_steps/print_steps_condensed/
+/// print_steps_csv matches `format`.
+fn print_steps_in<W: Writer>(format: OutputFormat, steps: Vec<TStep>, output: &mut W) {
+    match format {
+        OutputFormat::Verbose => print_steps(steps, output),
+        OutputFormat::Condensed => print_steps_condensed(steps, output),
+        OutputFormat::Csv => print_steps_csv(steps, output)
+    }
+}
+
 #[allow(unused_must_use)]
 /// Print steps to the output writer
 fn print_steps<W: Writer>(steps: Vec<TStep>, output: &mut W) {
     for step in steps.into_iter() {
         match step {
-            Station(station, line) => { write!(output, "{}, take {}\n", station, line); },
-            Switch(one, two) => { write!(output, "---switch from {} to {}\n", one, two); },
-            Ensure(line) => { write!(output, "---ensure you are on {}\n", line); }
+            Station(station, line, None) => { write!(output, "{}, take {}\n", station, line); },
+            Station(station, line, Some(direction)) => {
+                write!(output, "{}, take {} toward {}\n", station, line, direction);
+            },
+            Switch(one, two, None) => { write!(output, "---switch from {} to {}\n", one, two); },
+            Switch(one, two, Some(direction)) => {
+                write!(output, "---switch from {} to {} toward {}\n", one, two, direction);
+            },
+            Ensure(line, None) => { write!(output, "---ensure you are on {}\n", line); },
+            Ensure(line, Some(direction)) => {
+                write!(output, "---ensure you are on {} toward {}\n", line, direction);
+            },
+            Walk(from, to, minutes) => {
+                write!(output, "---walk from {} to {} ({} min)\n", from, to, minutes);
+            }
         }
     }
 }
@@ -156,13 +645,224 @@ mod print_steps_tests {
     #[test]
     fn test_print_vec() {
         let mut w = MemWriter::new();
-        let v = vec![Station("a".to_string(), "b".to_string()),
-            Switch("c".to_string(), "d".to_string()), Ensure("e".to_string())];
+        let v = vec![Station("a".to_string(), "b".to_string(), None),
+            Switch("c".to_string(), "d".to_string(), None), Ensure("e".to_string(), None)];
         print_steps(v, &mut w);
         assert_eq!(w.get_ref(), concat!("a, take b\n",
                                         "---switch from c to d\n",
                                         "---ensure you are on e\n").as_bytes());
     }
+
+    #[test]
+    fn test_print_vec_with_direction() {
+        let mut w = MemWriter::new();
+        let v = vec![Station("a".to_string(), "b".to_string(), Some("z".to_string())),
+            Switch("c".to_string(), "d".to_string(), Some("z".to_string())),
+            Ensure("e".to_string(), Some("z".to_string()))];
+        print_steps(v, &mut w);
+        assert_eq!(w.get_ref(), concat!("a, take b toward z\n",
+                                        "---switch from c to d toward z\n",
+                                        "---ensure you are on e toward z\n").as_bytes());
+    }
+}
+
+#[allow(unused_must_use)]
+/// Print a condensed, one-clause-per-leg summary of a trip: a run of
+/// consecutive Stations on the same line becomes "board <line> at
+/// <station>, ride <n> stops to <station>", and each Switch/Ensure/Walk
+/// becomes a short clause of its own, all joined with ", " on one line.
+/// A Switch doesn't carry the station it happens at -- same as
+/// print_steps's "---switch from X to Y", it's implicitly wherever the
+/// previous step left off -- so the new line's run is reseeded with the
+/// last station seen rather than waiting for the next Station step.
+/// See print_steps for the stop-by-stop verbose form.
+fn print_steps_condensed<W: Writer>(steps: Vec<TStep>, output: &mut W) {
+    let mut clauses: Vec<String> = Vec::new();
+    let mut run: Vec<(String, String)> = Vec::new();
+    let mut current_station: Option<String> = None;
+    for step in steps.into_iter() {
+        match step {
+            Station(station, line, _) => {
+                current_station = Some(station.clone());
+                run.push((station, line));
+            },
+            Switch(_, to, direction) => {
+                flush_run(&mut run, &mut clauses);
+                clauses.push(match direction {
+                    Some(d) => format!("switch to {} toward {}", to, d),
+                    None => format!("switch to {}", to)
+                });
+                if let Some(station) = current_station.clone() {
+                    run.push((station, to));
+                }
+            },
+            Ensure(line, direction) => {
+                flush_run(&mut run, &mut clauses);
+                clauses.push(match direction {
+                    Some(d) => format!("ensure you are on {} toward {}", line, d),
+                    None => format!("ensure you are on {}", line)
+                });
+            },
+            Walk(from, to, minutes) => {
+                flush_run(&mut run, &mut clauses);
+                clauses.push(format!("walk from {} to {} ({} min)", from, to, minutes));
+                current_station = Some(to);
+            }
+        }
+    }
+    flush_run(&mut run, &mut clauses);
+
+    let mut line = String::new();
+    for (i, clause) in clauses.into_iter().enumerate() {
+        if i > 0 {
+            line.push_str(", ");
+        }
+        line.push_str(clause.as_slice());
+    }
+    write!(output, "{}\n", line);
+}
+
+/// Turn the current run of same-line stops into a "board ..., ride ..."
+/// clause and append it to `clauses`, then empty the run. A no-op if the
+/// run is empty, so it's safe to call between every step.
+fn flush_run(run: &mut Vec<(String, String)>, clauses: &mut Vec<String>) {
+    if run.is_empty() {
+        return;
+    }
+    let line = run[0].1.clone();
+    let board_station = run[0].0.clone();
+    let stops = run.len() - 1;
+    if stops == 0 {
+        clauses.push(format!("board {} at {}", line, board_station));
+    } else {
+        let dest_station = run[run.len() - 1].0.clone();
+        let plural = if stops == 1 { "" } else { "s" };
+        clauses.push(format!("board {} at {}, ride {} stop{} to {}",
+                             line, board_station, stops, plural, dest_station));
+    }
+    run.clear();
+}
+
+#[allow(unused_must_use)]
+/// Print one CSV row per step to the output writer -- step type, station,
+/// line, stops so far, and minutes elapsed so far -- so an itinerary can
+/// be pasted into a spreadsheet or consumed by a script. Only Walk steps
+/// carry a per-step time cost in TStep; every other step type advances
+/// the stops column but leaves elapsed_minutes where it was, since the
+/// graph edge weight between two consecutive stops isn't tracked per
+/// step. Field values are quoted if they contain a comma, quote, or
+/// newline, the same minimal escaping read_gtfs_file's reader skips on
+/// the way in.
+fn print_steps_csv<W: Writer>(steps: Vec<TStep>, output: &mut W) {
+    write!(output, "step,station,line,stops,elapsed_minutes\n");
+    let mut stops = 0;
+    let mut elapsed_minutes = 0;
+    for step in steps.into_iter() {
+        match step {
+            Station(station, line, _) => {
+                stops += 1;
+                write!(output, "station,{},{},{},{}\n", csv_field(station.as_slice()),
+                      csv_field(line.as_slice()), stops, elapsed_minutes);
+            },
+            Switch(from_line, to_line, _) => {
+                let line = format!("{} to {}", from_line, to_line);
+                write!(output, "switch,{},{},{},{}\n", csv_field(""), csv_field(line.as_slice()),
+                      stops, elapsed_minutes);
+            },
+            Ensure(line, _) => {
+                write!(output, "ensure,{},{},{},{}\n", csv_field(""), csv_field(line.as_slice()),
+                      stops, elapsed_minutes);
+            },
+            Walk(from, to, minutes) => {
+                elapsed_minutes += minutes;
+                let station = format!("{} to {}", from, to);
+                write!(output, "walk,{},{},{},{}\n", csv_field(station.as_slice()), csv_field(""),
+                      stops, elapsed_minutes);
+            }
+        }
+    }
+}
+
+/// Quote a CSV field if it contains a comma, double quote, or newline,
+/// doubling any embedded double quotes; otherwise pass it through
+/// unchanged.
+fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') || s.contains('\n') {
+        format!("\"{}\"", s.replace("\"", "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod print_steps_csv_tests {
+    use super::print_steps_csv;
+    use t::TStep::{Station, Switch, Ensure, Walk};
+    use std::io::MemWriter;
+
+    #[test]
+    fn test_print_steps_csv() {
+        let mut w = MemWriter::new();
+        let v = vec![Station("South Station".to_string(), "red".to_string(), None),
+            Station("Broadway Station".to_string(), "red".to_string(), None),
+            Switch("red".to_string(), "green".to_string(), None),
+            Ensure("green".to_string(), None),
+            Station("Boylston Station".to_string(), "green".to_string(), None)];
+        print_steps_csv(v, &mut w);
+        assert_eq!(w.get_ref(), concat!(
+            "step,station,line,stops,elapsed_minutes\n",
+            "station,South Station,red,1,0\n",
+            "station,Broadway Station,red,2,0\n",
+            "switch,,red to green,2,0\n",
+            "ensure,,green,2,0\n",
+            "station,Boylston Station,green,3,0\n").as_bytes());
+    }
+
+    #[test]
+    fn test_print_steps_csv_walk_and_quoting() {
+        let mut w = MemWriter::new();
+        let v = vec![Station("A, Station".to_string(), "red".to_string(), None),
+            Walk("A, Station".to_string(), "B Station".to_string(), 5),
+            Station("B Station".to_string(), "blue".to_string(), None)];
+        print_steps_csv(v, &mut w);
+        assert_eq!(w.get_ref(), concat!(
+            "step,station,line,stops,elapsed_minutes\n",
+            "station,\"A, Station\",red,1,0\n",
+            "walk,\"A, Station to B Station\",,1,5\n",
+            "station,B Station,blue,2,5\n").as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod print_steps_condensed_tests {
+    use super::print_steps_condensed;
+    use t::TStep::{Station, Switch};
+    use std::io::MemWriter;
+
+    #[test]
+    fn test_print_steps_condensed() {
+        let mut w = MemWriter::new();
+        let v = vec![Station("South Station".to_string(), "red".to_string(), None),
+            Station("Broadway Station".to_string(), "red".to_string(), None),
+            Station("Andrew Station".to_string(), "red".to_string(), None),
+            Station("JFK/UMass Station".to_string(), "red".to_string(), None),
+            Station("Park Street Station".to_string(), "red".to_string(), None),
+            Switch("red".to_string(), "green".to_string(), None),
+            Station("Boylston Station".to_string(), "green".to_string(), None)];
+        print_steps_condensed(v, &mut w);
+        assert_eq!(w.get_ref(),
+                   concat!("board red at South Station, ride 4 stops to Park Street Station, ",
+                           "switch to green, board green at Park Street Station, ",
+                           "ride 1 stop to Boylston Station\n").as_bytes());
+    }
+
+    #[test]
+    fn test_print_steps_condensed_single_stop() {
+        let mut w = MemWriter::new();
+        let v = vec![Station("South Station".to_string(), "red".to_string(), None)];
+        print_steps_condensed(v, &mut w);
+        assert_eq!(w.get_ref(), "board red at South Station\n".as_bytes());
+    }
 }
 
 #[allow(unused_must_use)]
@@ -0,0 +1,120 @@
+#[doc="
+    Module: protocol
+
+    The machine-mode wire framing for query_user: a one-line header of a
+    typed response code and the response body's byte length, followed by
+    exactly that many bytes of body, so a scripted client always knows
+    where one response ends and the next begins without having to guess
+    at prose formatting.
+"]
+
+use t::TQueryResult;
+use t::TQueryResult::{TOk, TOkMultiple, TOkPareto, TPlan, DisambiguateStart, DisambiguateDestination};
+use t::TQueryResult::{NoSuchStart, NoSuchDest, DisabledStart, DisabledDest, NoSuchPath, LineNotRunning};
+use t::TOperationResult;
+use t::TOperationResult::{Successful, DisambiguateOp, NoSuchStationOp};
+
+/// A typed outcome for a response, mapped from every
+/// TQueryResult/TOperationResult variant (every successful path result
+/// maps to Ok), so a scripted client can branch
+/// on e.g. DISABLED_START vs NO_PATH instead of pattern-matching the
+/// human-readable body text. A path query's two slots (start and
+/// destination) get distinct codes for ambiguous/unknown/disabled, since
+/// which slot failed is exactly what a client needs to know to retry;
+/// an admin operation has only one target, so its codes aren't split.
+#[derive(Show, PartialEq, Eq)]
+pub enum ResponseCode {
+    Ok,
+    AmbiguousStart,
+    AmbiguousDest,
+    NoSuchStart,
+    NoSuchDest,
+    DisabledStart,
+    DisabledDest,
+    NoPath,
+    LineNotRunning,
+    Ambiguous,
+    NoSuchStation,
+    Done
+}
+
+impl ResponseCode {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ResponseCode::Ok => "OK",
+            ResponseCode::AmbiguousStart => "AMBIGUOUS_START",
+            ResponseCode::AmbiguousDest => "AMBIGUOUS_DEST",
+            ResponseCode::NoSuchStart => "NO_SUCH_START",
+            ResponseCode::NoSuchDest => "NO_SUCH_DEST",
+            ResponseCode::DisabledStart => "DISABLED_START",
+            ResponseCode::DisabledDest => "DISABLED_DEST",
+            ResponseCode::NoPath => "NO_PATH",
+            ResponseCode::LineNotRunning => "LINE_NOT_RUNNING",
+            ResponseCode::Ambiguous => "AMBIGUOUS",
+            ResponseCode::NoSuchStation => "NO_SUCH_STATION",
+            ResponseCode::Done => "DONE"
+        }
+    }
+}
+
+/// The ResponseCode a path query's result maps to.
+pub fn code_for_query_result(result: &TQueryResult) -> ResponseCode {
+    match *result {
+        TOk(..) | TOkMultiple(..) | TOkPareto(..) | TPlan(..) => ResponseCode::Ok,
+        DisambiguateStart(..) => ResponseCode::AmbiguousStart,
+        DisambiguateDestination(..) => ResponseCode::AmbiguousDest,
+        NoSuchStart => ResponseCode::NoSuchStart,
+        NoSuchDest => ResponseCode::NoSuchDest,
+        DisabledStart(..) => ResponseCode::DisabledStart,
+        DisabledDest(..) => ResponseCode::DisabledDest,
+        NoSuchPath => ResponseCode::NoPath,
+        LineNotRunning(..) => ResponseCode::LineNotRunning
+    }
+}
+
+/// The ResponseCode an enable/disable result maps to.
+pub fn code_for_operation_result(result: &TOperationResult) -> ResponseCode {
+    match *result {
+        Successful => ResponseCode::Done,
+        DisambiguateOp(..) => ResponseCode::Ambiguous,
+        NoSuchStationOp => ResponseCode::NoSuchStation
+    }
+}
+
+#[allow(unused_must_use)]
+/// Write one framed response: "<CODE> <byte length>\n" followed by
+/// exactly `body`'s bytes. The body is whatever print.rs would have
+/// written for a human-readable client; machine mode doesn't change the
+/// body's contents, only how its boundaries are marked.
+pub fn write_framed<W: Writer>(output: &mut W, code: ResponseCode, body: &[u8]) {
+    write!(output, "{} {}\n", code.as_str(), body.len());
+    output.write(body);
+}
+
+#[cfg(test)]
+mod protocol_tests {
+    use super::{code_for_query_result, code_for_operation_result, write_framed, ResponseCode};
+    use t::TQueryResult::{TOk, NoSuchPath, DisambiguateStart};
+    use t::TOperationResult::{Successful, NoSuchStationOp};
+    use std::io::MemWriter;
+
+    #[test]
+    fn test_code_for_query_result() {
+        assert_eq!(ResponseCode::Ok, code_for_query_result(&TOk(Vec::new(), 0, 0.0)));
+        assert_eq!(ResponseCode::NoPath, code_for_query_result(&NoSuchPath));
+        assert_eq!(ResponseCode::AmbiguousStart, code_for_query_result(&DisambiguateStart(Vec::new())));
+    }
+
+    #[test]
+    fn test_code_for_operation_result() {
+        assert_eq!(ResponseCode::Done, code_for_operation_result(&Successful));
+        assert_eq!(ResponseCode::NoSuchStation, code_for_operation_result(&NoSuchStationOp));
+    }
+
+    #[test]
+    fn test_write_framed() {
+        let mut w = MemWriter::new();
+        write_framed(&mut w, ResponseCode::Ok, b"hello\n");
+        assert_eq!(w.get_ref(), b"OK 6\nhello\n");
+    }
+}
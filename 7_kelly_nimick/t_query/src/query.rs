@@ -6,62 +6,764 @@
 "]
 
 extern crate regex;
+extern crate time;
 
 #[cfg(not(test))]
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, RwLock};
 #[cfg(not(test))]
 use t::T;
 #[cfg(not(test))]
+use t::TQueryResult;
+#[cfg(not(test))]
+use t::TQueryResult::{DisambiguateStart, DisambiguateDestination};
+#[cfg(not(test))]
+use t::TOperationResult;
+#[cfg(not(test))]
+use t::TOperationResult::{Successful, DisambiguateOp};
+#[cfg(not(test))]
+use t::TInfoResult;
+#[cfg(not(test))]
+use t::TInfoResult::DisambiguateInfo;
+#[cfg(not(test))]
+use self::Slot::{Start, Dest};
+#[cfg(not(test))]
 use print;
 #[cfg(not(test))]
 use print::{output_find_path, output_enable_station, output_disable_station};
+#[cfg(not(test))]
+use print::{output_enable_segment, output_disable_segment};
+#[cfg(not(test))]
+use print::{output_lines, output_stations, output_info};
+#[cfg(not(test))]
+use print::{output_status, output_metrics};
+#[cfg(not(test))]
+use metrics::Metrics;
+#[cfg(not(test))]
+use rate_limit::RateLimiters;
+#[cfg(not(test))]
+use broadcast::Broadcaster;
+#[cfg(not(test))]
+use protocol;
+#[cfg(not(test))]
+use std::io::MemWriter;
 
 use regex::Regex;
-use self::Query::{From, Enable, Disable, Invalid};
+use self::Query::{From, FromOptions, FromAvoiding, FromAt, FromPreferFewerTransfers, FromPareto, FromCondensed, FromCsv, Plan, Auth, Enable, Disable, DisableFor, DisableUntil, EnableSegment, DisableSegment, Begin, Commit, Abort, ExportDot, Lines, Stations, Info, Status, MetricsQuery, Nearest, Machine, History, Shutdown, Help, Quit, Invalid};
 
 #[cfg(not(test))]
 static PROMPT_STRING: &'static str = "===>>> ";
 #[cfg(not(test))]
 static INVALID_QUERY: &'static str = "Invalid command format.\n";
 
+#[cfg(not(test))]
+static AUTH_OK: &'static str = "Authenticated.\n";
+#[cfg(not(test))]
+static AUTH_FAILED: &'static str = "Invalid token.\n";
+#[cfg(not(test))]
+static NOT_AUTHENTICATED: &'static str = "Not authenticated. Run 'auth <token>' first.\n";
+#[cfg(not(test))]
+static INVALID_SELECTION: &'static str = "Invalid selection.\n";
+#[cfg(not(test))]
+static SHUTDOWN_CONFIRMED: &'static str = "Shutting down.\n";
+#[cfg(not(test))]
+static THROTTLED: &'static str = "Rate limit exceeded, try again later.\n";
+#[cfg(not(test))]
+static MACHINE_ON: &'static str = "Machine mode on.\n";
+#[cfg(not(test))]
+static MACHINE_OFF: &'static str = "Machine mode off.\n";
+#[cfg(not(test))]
+static NO_SUCH_HISTORY_ENTRY: &'static str = "No such history entry.\n";
+#[cfg(not(test))]
+static TX_STARTED: &'static str = "Transaction started.\n";
+#[cfg(not(test))]
+static TX_ALREADY_OPEN: &'static str = "A transaction is already open; commit or abort it first.\n";
+#[cfg(not(test))]
+static TX_NONE_OPEN: &'static str = "No transaction is open.\n";
+#[cfg(not(test))]
+static TX_QUEUED: &'static str = "Queued.\n";
+#[cfg(not(test))]
+static TX_ABORTED: &'static str = "Transaction aborted.\n";
+#[cfg(not(test))]
+static EXPORT_DOT_FAILED: &'static str = "Failed to export: ";
+#[cfg(not(test))]
+static SUCCESS_EXPORT: &'static str = "Exported.\n";
+#[cfg(not(test))]
+static GOODBYE: &'static str = "Goodbye.\n";
+
+#[cfg(not(test))]
+static HELP_TEXT: &'static str = "\
+Supported commands:
+  from <station> to <station>
+  options <n> from <station> to <station>
+  from <station> to <station> avoiding <station>[, <station>...]
+  from <station> to <station> at <hh>:<mm>
+  from <station> to <station> prefer fewer transfers
+  from <station> to <station> pareto
+  from <station> to <station> condensed
+  from <station> to <station> csv
+  plan <station>[, <station>(<n>m)...]
+  auth <token>
+  disable <station>
+  disable <station> for <n>h
+  disable <station> until <hh>:<mm>
+  enable <station>
+  disable between <station> and <station>
+  enable between <station> and <station>
+  begin
+  commit
+  abort
+  export dot <path>
+  lines
+  stations [<line>]
+  info <station>
+  status
+  metrics
+  nearest <lat> <lon> [<n>]
+  machine on|off
+  history
+  !!
+  !<n>
+  shutdown
+  help
+  quit, exit
+
+If a query's station name is ambiguous, it lists numbered suggestions;
+reply with just the number to retry with that one.
+
+Commands and keywords are case-insensitive, and station/segment names may
+contain digits and the punctuation real MBTA names use, like the slash in
+'Charles/MGH'. Wrap a name in double quotes (e.g. 'disable "Charles/MGH"')
+if it contains a comma or would otherwise be mistaken for a keyword.
+
+'machine on' switches path queries and enable/disable responses to a
+framed format (a response code and byte length, then exactly that many
+bytes of body) instead of free-form prose, so a script always knows
+where a response ends; answering a disambiguation prompt is unaffected
+and always replies in prose. Every such response, framed or not, leads
+with the same stable code (e.g. DISABLED_START, NO_SUCH_DEST, NO_PATH)
+identifying its outcome, so a client can branch on that instead of
+parsing the English body underneath it; in prose mode the code is just
+the response's first line.
+
+'history' lists this connection's commands, numbered from 1. '!!' re-runs
+the last command, and '!<n>' re-runs the command numbered <n> in that
+list, so a long station name doesn't have to be retyped to try it a
+second way.
+
+'begin' opens a transaction: subsequent disable/enable commands on this
+connection are queued instead of applied immediately. 'commit' applies
+every queued operation while holding the network's write lock for the
+whole batch, so other connections' queries see either the state from
+before the transaction or the state after every queued operation, never
+something in between; 'abort' discards the queue instead. Only one
+transaction can be open on a connection at a time.
+
+'export dot <path>' writes the current network as a Graphviz dot file,
+one node per (station, line) graph node, so operators can render it and
+visually check the effect of disables. Transfer edges between lines are
+drawn dashed; disabled stations never enter the graph in the first
+place, so they're simply absent from the export.
+
+'nearest <lat> <lon> [<n>]' lists the <n> (default 5) stations closest
+to that latitude/longitude by straight-line distance, nearest first, so
+a client that only knows the user's GPS position can find a station to
+route from instead of asking the user to name one. Only stations with
+an entry in the coordinates data file can be returned.
+
+'disable <station> for <n>h' and 'disable <station> until <hh>:<mm>'
+disable a station the same way 'disable <station>' does, but also
+schedule it to be automatically re-enabled -- after <n> hours, or the
+next time the clock reads <hh>:<mm> -- matching how planned maintenance
+actually works instead of requiring an operator to remember to run
+'enable' later. 'status' lists every station still waiting on one of
+these timers, with the seconds remaining. A plain 'enable' on the
+station cancels its timer early.
+
+'from <station> to <station> condensed' prints one clause per leg, all
+on a single line, instead of every intermediate stop -- 'board red at South Station, ride 4 stops
+to Park Street Station, switch to green, ...' -- for a client that only
+cares about boardings and transfers. The stop-by-stop form is still the
+default for a plain 'from ... to ...' query.
+
+'from <station> to <station> csv' prints one CSV row per step -- step
+type, station, line, stops so far, and minutes elapsed so far -- instead
+of prose, for pasting an itinerary into a spreadsheet or feeding it to a
+script.
+
+'quit' and 'exit' say goodbye and close the connection; disconnecting
+without either (closing the socket, or EOF on a piped script) closes it
+just as cleanly, without leaving the connection open waiting on a prompt
+nobody will answer.
+
+'info <station>' looks up a single station: the lines serving it,
+whether serving more than one line makes it a transfer station, whether
+it's currently disabled, and its nearest enabled neighbor in each
+direction on every line it's on, for exploring the network interactively
+instead of cross-referencing 'lines' and 'stations' by hand.
+
+Successfully disabling or enabling a station or segment (including as
+part of a committed transaction) broadcasts a one-line NOTICE to every
+other connected session, so an interactive user with a plan already
+printed learns it may no longer be valid instead of finding out the next
+time they run a query. This connection's own response to its own command
+is unaffected; the notice only goes to everyone else, and only over TCP
+-- the Unix socket listener doesn't register its connections to receive
+one.
+
+'plan <station>, <station>(<n>m), ...' chains find_path leg by leg
+through an ordered list of stopovers, giving a single station name per
+stop or a station followed by '(<n>m)' for a dwell of <n> minutes there
+before boarding the next leg (the dwell after the last stop is ignored).
+The printed itinerary numbers each leg like a normal 'from ... to ...'
+result, then totals travel time and fare across every leg plus every
+stop's dwell but the last. A plan needs at least two stops, and the first leg to
+fail -- an ambiguous or unknown station, a disabled one, or no path --
+stops the whole plan there rather than printing a partial itinerary.
+";
+
 macro_rules! regex (
     ($s:expr) => (regex::Regex::new($s).unwrap());
     );
 
-#[derive(Show, PartialEq, Eq)]
+// Eq is intentionally not derived: Nearest carries f64 coordinates, and
+// f64 only implements PartialEq.
+#[derive(Show, PartialEq)]
 enum Query<'a> {
     From(&'a str, &'a str),
+    FromOptions(&'a str, &'a str, usize),
+    FromAvoiding(&'a str, &'a str, Vec<&'a str>),
+    FromAt(&'a str, &'a str, usize),
+    FromPreferFewerTransfers(&'a str, &'a str),
+    FromPareto(&'a str, &'a str),
+    FromCondensed(&'a str, &'a str),
+    FromCsv(&'a str, &'a str),
+    // ordered stops, each paired with a dwell time in minutes to spend
+    // there before the next leg (the last stop's dwell is ignored)
+    Plan(Vec<(&'a str, usize)>),
+    Auth(&'a str),
     Enable(&'a str),
     Disable(&'a str),
+    DisableFor(&'a str, i64),
+    DisableUntil(&'a str, usize),
+    EnableSegment(&'a str, &'a str),
+    DisableSegment(&'a str, &'a str),
+    Begin,
+    Commit,
+    Abort,
+    ExportDot(&'a str),
+    Lines,
+    Stations(Option<&'a str>),
+    Info(&'a str),
+    Status,
+    MetricsQuery,
+    Nearest(f64, f64, usize),
+    Machine(bool),
+    History,
+    Shutdown,
+    Help,
+    Quit,
     Invalid
 }
 
+// which side of a from/to query a disambiguation prompt was about
+#[cfg(not(test))]
+enum Slot {
+    Start,
+    Dest
+}
+
+/// A query that produced a disambiguation prompt, kept around for the
+/// lifetime of that prompt so a bare number reply can re-run it with the
+/// chosen suggestion substituted in, instead of the user retyping the
+/// whole corrected station name. `disable between`/`enable between`
+/// aren't tracked here: their DisambiguateOp result doesn't say which of
+/// the two station names was ambiguous, so those still require retyping.
+#[cfg(not(test))]
+enum PendingQuery {
+    From(String, String, Slot),
+    FromOptions(String, String, usize, Slot),
+    FromAvoiding(String, String, Vec<String>, Slot),
+    FromAt(String, String, usize, Slot),
+    FromPreferFewerTransfers(String, String, Slot),
+    FromPareto(String, String, Slot),
+    FromCondensed(String, String, Slot),
+    FromCsv(String, String, Slot),
+    Disable(String),
+    Enable(String),
+    Info(String)
+}
+
+/// A disable/enable queued by 'begin', to be applied by 'commit' with the
+/// network's write lock held for the whole batch.
+#[cfg(not(test))]
+enum TxOp {
+    DisableStation(String),
+    DisableStationFor(String, i64),
+    DisableStationUntil(String, usize),
+    EnableStation(String),
+    DisableSegment(String, String),
+    EnableSegment(String, String)
+}
+
+/// If a path query's result was a disambiguation prompt, return which
+/// slot it was about along with the suggestions.
+#[cfg(not(test))]
+fn disambiguation_slot(result: &TQueryResult) -> Option<(Slot, Vec<String>)> {
+    match result {
+        &DisambiguateStart(ref suggestions) => Some((Start, suggestions.clone())),
+        &DisambiguateDestination(ref suggestions) => Some((Dest, suggestions.clone())),
+        _ => None
+    }
+}
+
+/// If an enable/disable result was a disambiguation prompt, return its
+/// suggestions.
+#[cfg(not(test))]
+fn disambiguation_suggestions(result: &TOperationResult) -> Option<Vec<String>> {
+    match result {
+        &DisambiguateOp(ref suggestions) => Some(suggestions.clone()),
+        _ => None
+    }
+}
+
+/// If an info result was a disambiguation prompt, return its suggestions.
+#[cfg(not(test))]
+fn disambiguation_suggestions_info(result: &TInfoResult) -> Option<Vec<String>> {
+    match result {
+        &DisambiguateInfo(ref suggestions) => Some(suggestions.clone()),
+        _ => None
+    }
+}
+
+/// Substitute the chosen suggestion into whichever slot the prompt was
+/// about.
+#[cfg(not(test))]
+fn fill_slot(from: String, to: String, chosen: String, slot: Slot) -> (String, String) {
+    match slot {
+        Start => (chosen, to),
+        Dest => (from, chosen)
+    }
+}
+
+/// Milliseconds elapsed since `start_ns`, a `time::precise_time_ns()`
+/// reading taken just before the work being timed.
+#[cfg(not(test))]
+fn elapsed_ms(start_ns: u64) -> u64 {
+    (time::precise_time_ns() - start_ns) / 1_000_000
+}
+
+/// The current time, for feeding to a RateLimiter alongside elapsed_ms's
+/// start_ns readings.
+#[cfg(not(test))]
+fn now_ms() -> u64 {
+    time::precise_time_ns() / 1_000_000
+}
+
+/// Compare `a` and `b` for equality in time that depends only on their
+/// lengths, not on where they first differ, so a failed 'auth' attempt
+/// can't be used to guess the admin token one byte at a time.
+#[cfg(not(test))]
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut diff = 0u8;
+    for i in 0..a.len() {
+        diff |= a[i] ^ b[i];
+    }
+    diff == 0
+}
+
+/// Whether a parsed Query is a path query, for rate-limiting purposes.
+#[cfg(not(test))]
+fn is_path_query(query: &Query) -> bool {
+    match *query {
+        From(..) | FromOptions(..) | FromAvoiding(..) | FromAt(..) |
+        FromPreferFewerTransfers(..) | FromPareto(..) | FromCondensed(..) | FromCsv(..) | Plan(..) => true,
+        _ => false
+    }
+}
+
+/// Whether a parsed Query is an admin operation, for rate-limiting
+/// purposes.
+#[cfg(not(test))]
+fn is_admin_op(query: &Query) -> bool {
+    match *query {
+        Disable(..) | DisableFor(..) | DisableUntil(..) | Enable(..) |
+        DisableSegment(..) | EnableSegment(..) |
+        Begin | Commit | Abort | ExportDot(..) => true,
+        _ => false
+    }
+}
+
+/// Whether a pending disambiguation is for a path query, for rate-limiting
+/// purposes.
+#[cfg(not(test))]
+fn is_pending_path_query(query: &PendingQuery) -> bool {
+    match *query {
+        PendingQuery::From(..) | PendingQuery::FromOptions(..) | PendingQuery::FromAvoiding(..) |
+        PendingQuery::FromAt(..) | PendingQuery::FromPreferFewerTransfers(..) |
+        PendingQuery::FromPareto(..) | PendingQuery::FromCondensed(..) | PendingQuery::FromCsv(..) => true,
+        _ => false
+    }
+}
+
+/// Whether a pending disambiguation is for an admin operation, for
+/// rate-limiting purposes.
+#[cfg(not(test))]
+fn is_pending_admin_op(query: &PendingQuery) -> bool {
+    match *query {
+        PendingQuery::Disable(..) | PendingQuery::Enable(..) => true,
+        _ => false
+    }
+}
+
+/// Write `rendered` (whatever a print:: function would have written for a
+/// human-readable client) to `stream`, framed with `code` if `machine_mode`
+/// is on, or preceded by `code` as a one-word prefix line otherwise -- a
+/// plain-text client can still find the machine-readable outcome without
+/// switching into machine mode, it just has to skip the first line rather
+/// than parse a length-prefixed frame.
+#[allow(unused_must_use)]
+#[cfg(not(test))]
+fn emit_result<BS: Writer + Buffer>(stream: &mut BS, machine_mode: bool,
+                                    code: protocol::ResponseCode, rendered: Vec<u8>) {
+    if machine_mode {
+        protocol::write_framed(stream, code, rendered.as_slice());
+    } else {
+        write!(stream, "{}\n", code.as_str());
+        stream.write(rendered.as_slice());
+    }
+}
+
+/// Broadcast `message` to every other connected session if `result` was
+/// Successful, so a connection other than the one that made the change
+/// learns about it as soon as it happens rather than the next time it
+/// queries. A no-op for every other TOperationResult, since nothing
+/// actually changed.
+#[cfg(not(test))]
+fn notify_change(broadcaster: &Broadcaster, conn_key: &str, result: &TOperationResult, message: &str) {
+    if let &Successful = result {
+        broadcaster.broadcast_except(conn_key, message);
+    }
+}
+
+/// If `line` is a history reference -- "!!" for the last command, or
+/// "!<n>" for the command numbered <n> in `history`, the same numbering
+/// `history` prints -- resolve it to the command it refers to. Any other
+/// line passes through unchanged.
+#[cfg(not(test))]
+fn resolve_history_reference(line: &str, history: &[String]) -> Result<String, &'static str> {
+    if line == "!!" {
+        return history.last().cloned().ok_or(NO_SUCH_HISTORY_ENTRY);
+    }
+    if line.starts_with('!') {
+        return match line.slice_from(1).parse::<usize>().ok() {
+            Some(n) if n >= 1 => history.get(n - 1).cloned().ok_or(NO_SUCH_HISTORY_ENTRY),
+            _ => Err(NO_SUCH_HISTORY_ENTRY)
+        };
+    }
+    Ok(line.to_string())
+}
+
+// A station or segment name: a double-quoted string (for a name containing
+// a keyword or punctuation outside the bare alternative below, e.g. a
+// comma), or a bare run of letters, digits, and the punctuation real MBTA
+// station names use (periods, slashes, apostrophes, hyphens).
+static NAME: &'static str = "(?:\"[^\"]+\"|[a-zA-Z0-9/.'-][a-zA-Z0-9/.' -]*)";
+
+/// Clean up a captured station/segment name: trim surrounding whitespace,
+/// then strip a pair of enclosing double quotes if the name was quoted, so
+/// the quotes used to protect the name from the parser don't leak into the
+/// station lookup.
+fn clean_name(raw: &str) -> &str {
+    let trimmed = raw.trim();
+    if trimmed.len() >= 2 && trimmed.starts_with('"') && trimmed.ends_with('"') {
+        trimmed.slice_from(1).slice_to(trimmed.len() - 2)
+    } else {
+        trimmed
+    }
+}
+
+/// Split a "plan" stop into its station name and the dwell time in
+/// minutes to spend there: "B(30m)" is a 30 minute stopover at B, and a
+/// bare name has no dwell. A trailing "(...)" that isn't of the form
+/// "(<digits>m)" is left attached to the name rather than rejected, on
+/// the theory that a malformed dwell is more likely a station name that
+/// happens to end in parens than a typo worth failing the whole plan
+/// over.
+fn parse_stop<'a>(raw: &'a str) -> (&'a str, usize) {
+    let trimmed = raw.trim();
+    if trimmed.ends_with(")") {
+        if let Some(paren) = trimmed.rfind('(') {
+            let inside = trimmed.slice_from(paren + 1).slice_to(trimmed.len() - paren - 2);
+            if inside.ends_with("m") {
+                if let Ok(minutes) = inside.slice_to(inside.len() - 1).parse() {
+                    return (clean_name(trimmed.slice_to(paren)), minutes);
+                }
+            }
+        }
+    }
+    (clean_name(trimmed), 0)
+}
+
 struct Parser {
     from_regex: regex::Regex,
+    from_options_regex: regex::Regex,
+    from_avoiding_regex: regex::Regex,
+    from_at_regex: regex::Regex,
+    from_prefer_fewer_transfers_regex: regex::Regex,
+    from_pareto_regex: regex::Regex,
+    from_condensed_regex: regex::Regex,
+    from_csv_regex: regex::Regex,
+    plan_regex: regex::Regex,
+    auth_regex: regex::Regex,
     disable_regex: regex::Regex,
-    enable_regex: regex::Regex
+    disable_for_regex: regex::Regex,
+    disable_until_regex: regex::Regex,
+    enable_regex: regex::Regex,
+    disable_segment_regex: regex::Regex,
+    enable_segment_regex: regex::Regex,
+    begin_regex: regex::Regex,
+    commit_regex: regex::Regex,
+    abort_regex: regex::Regex,
+    export_dot_regex: regex::Regex,
+    lines_regex: regex::Regex,
+    stations_regex: regex::Regex,
+    info_regex: regex::Regex,
+    status_regex: regex::Regex,
+    metrics_regex: regex::Regex,
+    nearest_regex: regex::Regex,
+    machine_regex: regex::Regex,
+    history_regex: regex::Regex,
+    shutdown_regex: regex::Regex,
+    help_regex: regex::Regex,
+    quit_regex: regex::Regex
 }
 
 impl Parser {
 
     /// Parse the given user input to return a Query
     fn parse_line<'a>(&self, line: &'a str) -> Query<'a> {
+        // checked before from_regex, since "options 3 from A to B" contains
+        // a "from A to B" substring that from_regex would otherwise match
+        match self.from_options_regex.captures(line) {
+            Some(cap) => {
+                let k = cap.at(1).unwrap().parse().expect("options count must be a non-negative integer");
+                return FromOptions(clean_name(cap.at(2).unwrap()), clean_name(cap.at(3).unwrap()), k);
+            },
+            None => {}
+        }
+        // checked before from_regex, since "to B avoiding C" contains a
+        // "to B" that from_regex's greedy capture would otherwise swallow
+        match self.from_avoiding_regex.captures(line) {
+            Some(cap) => {
+                let avoid = cap.at(3).unwrap().split(',').map(clean_name).collect();
+                return FromAvoiding(clean_name(cap.at(1).unwrap()), clean_name(cap.at(2).unwrap()), avoid);
+            },
+            None => {}
+        }
+        // checked before from_regex, since "to B at 23:30" contains a
+        // "to B" that from_regex's greedy capture would otherwise swallow
+        match self.from_at_regex.captures(line) {
+            Some(cap) => {
+                let hours: usize = cap.at(3).unwrap().parse().expect("hours must be an integer");
+                let minutes: usize = cap.at(4).unwrap().parse().expect("minutes must be an integer");
+                return FromAt(clean_name(cap.at(1).unwrap()), clean_name(cap.at(2).unwrap()), hours * 60 + minutes);
+            },
+            None => {}
+        }
+        // checked before from_regex, since "to B prefer fewer transfers"
+        // contains a "to B" that from_regex's greedy capture would
+        // otherwise swallow
+        match self.from_prefer_fewer_transfers_regex.captures(line) {
+            Some(cap) => {
+                return FromPreferFewerTransfers(clean_name(cap.at(1).unwrap()), clean_name(cap.at(2).unwrap()));
+            },
+            None => {}
+        }
+        // checked before from_regex, since "to B pareto" contains a
+        // "to B" that from_regex's greedy capture would otherwise swallow
+        match self.from_pareto_regex.captures(line) {
+            Some(cap) => {
+                return FromPareto(clean_name(cap.at(1).unwrap()), clean_name(cap.at(2).unwrap()));
+            },
+            None => {}
+        }
+        // checked before from_regex, since "to B condensed" contains a
+        // "to B" that from_regex's greedy capture would otherwise swallow
+        match self.from_condensed_regex.captures(line) {
+            Some(cap) => {
+                return FromCondensed(clean_name(cap.at(1).unwrap()), clean_name(cap.at(2).unwrap()));
+            },
+            None => {}
+        }
+        // checked before from_regex, since "to B csv" contains a "to B"
+        // that from_regex's greedy capture would otherwise swallow
+        match self.from_csv_regex.captures(line) {
+            Some(cap) => {
+                return FromCsv(clean_name(cap.at(1).unwrap()), clean_name(cap.at(2).unwrap()));
+            },
+            None => {}
+        }
         match self.from_regex.captures(line) {
             Some(cap) => {
-                return From(cap.at(1).unwrap().trim(),
-                            cap.at(2).unwrap().trim());
+                return From(clean_name(cap.at(1).unwrap()),
+                            clean_name(cap.at(2).unwrap()));
+            },
+            None => {}
+        }
+        match self.plan_regex.captures(line) {
+            Some(cap) => {
+                let stops = cap.at(1).unwrap().split(',').map(parse_stop).collect();
+                return Plan(stops);
+            },
+            None => {}
+        }
+        match self.auth_regex.captures(line) {
+            Some(cap) => {
+                return Auth(cap.at(1).unwrap().trim());
+            },
+            None => {}
+        }
+        // the segment regexes must be checked before the plain
+        // disable/enable regexes, or "disable between A and B" would
+        // get swallowed whole by disable_regex's greedy capture group
+        match self.disable_segment_regex.captures(line) {
+            Some(cap) => {
+                return DisableSegment(clean_name(cap.at(1).unwrap()),
+                                      clean_name(cap.at(2).unwrap()));
+            },
+            None => {}
+        }
+        match self.enable_segment_regex.captures(line) {
+            Some(cap) => {
+                return EnableSegment(clean_name(cap.at(1).unwrap()),
+                                     clean_name(cap.at(2).unwrap()));
+            },
+            None => {}
+        }
+        // checked before disable_regex, for the same reason as the
+        // segment regexes: "disable A for 2h"/"disable A until 18:00"
+        // would otherwise get swallowed whole by disable_regex's
+        // greedy capture group
+        match self.disable_for_regex.captures(line) {
+            Some(cap) => {
+                let hours: i64 = cap.at(2).unwrap().parse().expect("duration must be an integer number of hours");
+                return DisableFor(clean_name(cap.at(1).unwrap()), hours * 3600);
+            },
+            None => {}
+        }
+        match self.disable_until_regex.captures(line) {
+            Some(cap) => {
+                let hours: usize = cap.at(2).unwrap().parse().expect("hours must be an integer");
+                let minutes: usize = cap.at(3).unwrap().parse().expect("minutes must be an integer");
+                return DisableUntil(clean_name(cap.at(1).unwrap()), hours * 60 + minutes);
             },
             None => {}
         }
         match self.disable_regex.captures(line) {
             Some(cap) => {
-                return Disable(cap.at(1).unwrap().trim());
+                return Disable(clean_name(cap.at(1).unwrap()));
             },
             None => {}
         }
         match self.enable_regex.captures(line) {
             Some(cap) => {
-                return Enable(cap.at(1).unwrap().trim());
+                return Enable(clean_name(cap.at(1).unwrap()));
+            },
+            None => {}
+        }
+        match self.begin_regex.captures(line) {
+            Some(_) => {
+                return Begin;
+            },
+            None => {}
+        }
+        match self.commit_regex.captures(line) {
+            Some(_) => {
+                return Commit;
+            },
+            None => {}
+        }
+        match self.abort_regex.captures(line) {
+            Some(_) => {
+                return Abort;
+            },
+            None => {}
+        }
+        match self.export_dot_regex.captures(line) {
+            Some(cap) => {
+                return ExportDot(cap.at(1).unwrap());
+            },
+            None => {}
+        }
+        match self.lines_regex.captures(line) {
+            Some(_) => {
+                return Lines;
+            },
+            None => {}
+        }
+        match self.stations_regex.captures(line) {
+            Some(cap) => {
+                return Stations(cap.at(1).map(|s| s.trim()));
+            },
+            None => {}
+        }
+        match self.info_regex.captures(line) {
+            Some(cap) => {
+                return Info(clean_name(cap.at(1).unwrap()));
+            },
+            None => {}
+        }
+        match self.status_regex.captures(line) {
+            Some(_) => {
+                return Status;
+            },
+            None => {}
+        }
+        match self.metrics_regex.captures(line) {
+            Some(_) => {
+                return MetricsQuery;
+            },
+            None => {}
+        }
+        match self.nearest_regex.captures(line) {
+            Some(cap) => {
+                let lat = cap.at(1).unwrap().parse().expect("latitude must be a valid number");
+                let lon = cap.at(2).unwrap().parse().expect("longitude must be a valid number");
+                let n = cap.at(3).map_or(5, |s| s.parse().expect("station count must be a non-negative integer"));
+                return Nearest(lat, lon, n);
+            },
+            None => {}
+        }
+        match self.machine_regex.captures(line) {
+            Some(cap) => {
+                return Machine(cap.at(1).unwrap().to_ascii_lowercase().as_slice() == "on");
+            },
+            None => {}
+        }
+        match self.history_regex.captures(line) {
+            Some(_) => {
+                return History;
+            },
+            None => {}
+        }
+        match self.shutdown_regex.captures(line) {
+            Some(_) => {
+                return Shutdown;
+            },
+            None => {}
+        }
+        match self.help_regex.captures(line) {
+            Some(_) => {
+                return Help;
+            },
+            None => {}
+        }
+        match self.quit_regex.captures(line) {
+            Some(_) => {
+                return Quit;
             },
             None => {}
         }
@@ -72,23 +774,228 @@ impl Parser {
 #[cfg(test)]
 mod parser_tests {
     use super::compile_regexes;
-    use super::Query::{From, Disable, Enable};
+    use super::Query::{From, FromOptions, FromAvoiding, FromAt, FromPreferFewerTransfers, FromPareto, FromCondensed, FromCsv, Plan, Auth, Disable, DisableFor, DisableUntil, Enable, DisableSegment, EnableSegment, Begin, Commit, Abort, ExportDot, Lines, Stations, Info, Status, MetricsQuery, Nearest, Machine, History, Shutdown, Help, Quit};
 
     #[test]
     fn test_parse_line() {
         let p = compile_regexes();
         assert_eq!(From("South", "Ruggles"), p.parse_line("from South to Ruggles"));
+        assert_eq!(FromOptions("South", "Ruggles", 3), p.parse_line("options 3 from South to Ruggles"));
+        assert_eq!(FromAvoiding("South", "Ruggles", vec!["Park Street Station"]),
+                   p.parse_line("from South to Ruggles avoiding Park Street Station"));
+        assert_eq!(FromAvoiding("South", "Ruggles", vec!["Park Street Station", "Downtown Crossing Station"]),
+                   p.parse_line("from South to Ruggles avoiding Park Street Station, Downtown Crossing Station"));
+        assert_eq!(FromAt("South", "Ruggles", 23 * 60 + 30), p.parse_line("from South to Ruggles at 23:30"));
+        assert_eq!(FromAt("South", "Ruggles", 5 * 60), p.parse_line("from South to Ruggles at 5:00"));
+        assert_eq!(FromPreferFewerTransfers("South", "Ruggles"),
+                   p.parse_line("from South to Ruggles prefer fewer transfers"));
+        assert_eq!(FromPareto("South", "Ruggles"), p.parse_line("from South to Ruggles pareto"));
+        assert_eq!(FromCondensed("South", "Ruggles"), p.parse_line("from South to Ruggles condensed"));
+        assert_eq!(FromCsv("South", "Ruggles"), p.parse_line("from South to Ruggles csv"));
+        assert_eq!(Plan(vec![("South", 0), ("Park Street Station", 30), ("Ruggles", 0)]),
+                   p.parse_line("plan South, Park Street Station(30m), Ruggles"));
+        assert_eq!(Auth("t-query-admin"), p.parse_line("auth t-query-admin"));
         assert_eq!(Disable("Ruggles"), p.parse_line("disable Ruggles"));
+        assert_eq!(DisableFor("Ruggles", 2 * 3600), p.parse_line("disable Ruggles for 2h"));
+        assert_eq!(DisableUntil("Ruggles", 18 * 60), p.parse_line("disable Ruggles until 18:00"));
         assert_eq!(Enable("Ruggles"), p.parse_line("enable Ruggles"));
+        assert_eq!(DisableSegment("South Station", "Broadway Station"),
+                   p.parse_line("disable between South Station and Broadway Station"));
+        assert_eq!(EnableSegment("South Station", "Broadway Station"),
+                   p.parse_line("enable between South Station and Broadway Station"));
+        assert_eq!(Begin, p.parse_line("begin"));
+        assert_eq!(Commit, p.parse_line("commit"));
+        assert_eq!(Abort, p.parse_line("abort"));
+        assert_eq!(ExportDot("/tmp/t.dot"), p.parse_line("export dot /tmp/t.dot"));
+        assert_eq!(Lines, p.parse_line("lines"));
+        assert_eq!(Stations(None), p.parse_line("stations"));
+        assert_eq!(Stations(Some("red")), p.parse_line("stations red"));
+        assert_eq!(Info("Ruggles"), p.parse_line("info Ruggles"));
+        assert_eq!(Status, p.parse_line("status"));
+        assert_eq!(MetricsQuery, p.parse_line("metrics"));
+        assert_eq!(Nearest(42.3736, -71.1190, 5), p.parse_line("nearest 42.3736 -71.1190"));
+        assert_eq!(Nearest(42.3736, -71.1190, 2), p.parse_line("nearest 42.3736 -71.1190 2"));
+        assert_eq!(Machine(true), p.parse_line("machine on"));
+        assert_eq!(Machine(false), p.parse_line("machine off"));
+        assert_eq!(History, p.parse_line("history"));
+        assert_eq!(Shutdown, p.parse_line("shutdown"));
+        assert_eq!(Help, p.parse_line("help"));
+        assert_eq!(Quit, p.parse_line("quit"));
+        assert_eq!(Quit, p.parse_line("exit"));
+    }
+
+    #[test]
+    fn test_parse_line_case_insensitive_and_quoted() {
+        let p = compile_regexes();
+        assert_eq!(From("South", "Ruggles"), p.parse_line("FROM South TO Ruggles"));
+        assert_eq!(Machine(true), p.parse_line("MACHINE ON"));
+        assert_eq!(Machine(false), p.parse_line("Machine Off"));
+        assert_eq!(From("Charles/MGH", "Forest Hills"),
+                   p.parse_line("from Charles/MGH to Forest Hills"));
+        assert_eq!(Disable("Charles/MGH"), p.parse_line("disable \"Charles/MGH\""));
+        assert_eq!(From("Charles/MGH", "South Station"),
+                   p.parse_line("from \"Charles/MGH\" to \"South Station\""));
+        assert_eq!(DisableSegment("Charles/MGH", "South Station"),
+                   p.parse_line("disable between \"Charles/MGH\" and South Station"));
     }
 }
 
 /// Create the parser
 fn compile_regexes() -> Parser {
     Parser {
-        from_regex: regex!(r"from ([a-zA-Z\. ]+) to ([a-zA-Z\. ]+)"),
-        disable_regex: regex!(r"disable ([a-zA-Z\. ]+)"),
-        enable_regex: regex!(r"enable ([a-zA-Z\. ]+)")
+        from_regex: regex!(format!(r"(?i)from ({0}) to ({0})", NAME).as_slice()),
+        from_options_regex: regex!(format!(r"(?i)options (\d+) from ({0}) to ({0})", NAME).as_slice()),
+        from_avoiding_regex: regex!(format!(r#"(?i)from ({0}) to ({0}) avoiding ([a-zA-Z0-9/.'" -,]+)"#, NAME).as_slice()),
+        from_at_regex: regex!(format!(r"(?i)from ({0}) to ({0}) at (\d{{1,2}}):(\d{{2}})$", NAME).as_slice()),
+        from_prefer_fewer_transfers_regex: regex!(format!(r"(?i)from ({0}) to ({0}) prefer fewer transfers$", NAME).as_slice()),
+        from_pareto_regex: regex!(format!(r"(?i)from ({0}) to ({0}) pareto$", NAME).as_slice()),
+        from_condensed_regex: regex!(format!(r"(?i)from ({0}) to ({0}) condensed$", NAME).as_slice()),
+        from_csv_regex: regex!(format!(r"(?i)from ({0}) to ({0}) csv$", NAME).as_slice()),
+        plan_regex: regex!(r#"(?i)^plan ([a-zA-Z0-9/.'" ():,-]+)$"#),
+        auth_regex: regex!(r"(?i)^auth (\S+)"),
+        disable_regex: regex!(format!(r"(?i)disable ({0})", NAME).as_slice()),
+        disable_for_regex: regex!(format!(r"(?i)disable ({0}) for (\d+)h$", NAME).as_slice()),
+        disable_until_regex: regex!(format!(r"(?i)disable ({0}) until (\d{{1,2}}):(\d{{2}})$", NAME).as_slice()),
+        enable_regex: regex!(format!(r"(?i)enable ({0})", NAME).as_slice()),
+        disable_segment_regex: regex!(format!(r"(?i)disable between ({0}) and ({0})", NAME).as_slice()),
+        enable_segment_regex: regex!(format!(r"(?i)enable between ({0}) and ({0})", NAME).as_slice()),
+        begin_regex: regex!(r"(?i)^begin$"),
+        commit_regex: regex!(r"(?i)^commit$"),
+        abort_regex: regex!(r"(?i)^abort$"),
+        export_dot_regex: regex!(r"(?i)^export dot (\S+)$"),
+        lines_regex: regex!(r"(?i)^lines"),
+        stations_regex: regex!(r"(?i)^stations(?: ([a-zA-Z0-9/ ]+))?"),
+        info_regex: regex!(format!(r"(?i)^info ({0})$", NAME).as_slice()),
+        status_regex: regex!(r"(?i)^status"),
+        metrics_regex: regex!(r"(?i)^metrics"),
+        nearest_regex: regex!(r"(?i)^nearest (-?\d+(?:\.\d+)?) (-?\d+(?:\.\d+)?)(?: (\d+))?$"),
+        machine_regex: regex!(r"(?i)^machine (on|off)$"),
+        history_regex: regex!(r"(?i)^history$"),
+        shutdown_regex: regex!(r"(?i)^shutdown"),
+        help_regex: regex!(r"(?i)^help"),
+        quit_regex: regex!(r"(?i)^(quit|exit)$")
+    }
+}
+
+/// Re-run a query whose disambiguation prompt was just answered with a
+/// chosen suggestion, substituting it into whichever slot produced the
+/// prompt. Returns a new pending disambiguation if the chosen suggestion
+/// is itself still ambiguous; that shouldn't normally happen, since
+/// suggestions come straight from the station list, but there's no
+/// reason not to let it re-prompt if it does.
+#[allow(unused_must_use)]
+#[cfg(not(test))]
+fn retry_pending<BS: Writer + Buffer>(query: PendingQuery, chosen: String, t: &Arc<RwLock<T>>,
+                                      metrics: &Arc<Metrics>, broadcaster: &Broadcaster, conn_key: &str,
+                                      stream: &mut BS) -> Option<(PendingQuery, Vec<String>)> {
+    match query {
+        PendingQuery::From(from, to, slot) => {
+            let (from, to) = fill_slot(from, to, chosen, slot);
+            let start_ns = time::precise_time_ns();
+            let path = t.read().unwrap().find_path(from.as_slice(), to.as_slice());
+            metrics.record_path_query(&path, elapsed_ms(start_ns));
+            let next = disambiguation_slot(&path).map(|(slot, suggestions)|
+                (PendingQuery::From(from.clone(), to.clone(), slot), suggestions));
+            print::output_find_path(path, from.as_slice(), to.as_slice(), print::OutputFormat::Verbose, stream);
+            next
+        },
+        PendingQuery::FromOptions(from, to, k, slot) => {
+            let (from, to) = fill_slot(from, to, chosen, slot);
+            let start_ns = time::precise_time_ns();
+            let path = t.read().unwrap().find_paths(from.as_slice(), to.as_slice(), k);
+            metrics.record_path_query(&path, elapsed_ms(start_ns));
+            let next = disambiguation_slot(&path).map(|(slot, suggestions)|
+                (PendingQuery::FromOptions(from.clone(), to.clone(), k, slot), suggestions));
+            print::output_find_path(path, from.as_slice(), to.as_slice(), print::OutputFormat::Verbose, stream);
+            next
+        },
+        PendingQuery::FromAvoiding(from, to, avoid, slot) => {
+            let (from, to) = fill_slot(from, to, chosen, slot);
+            let avoid_refs = avoid.iter().map(|s| s.as_slice()).collect();
+            let start_ns = time::precise_time_ns();
+            let path = t.read().unwrap().find_path_avoiding(from.as_slice(), to.as_slice(), avoid_refs);
+            metrics.record_path_query(&path, elapsed_ms(start_ns));
+            let next = disambiguation_slot(&path).map(|(slot, suggestions)|
+                (PendingQuery::FromAvoiding(from.clone(), to.clone(), avoid.clone(), slot), suggestions));
+            print::output_find_path(path, from.as_slice(), to.as_slice(), print::OutputFormat::Verbose, stream);
+            next
+        },
+        PendingQuery::FromAt(from, to, departure_minutes, slot) => {
+            let (from, to) = fill_slot(from, to, chosen, slot);
+            let start_ns = time::precise_time_ns();
+            let path = t.read().unwrap().find_path_at(from.as_slice(), to.as_slice(), departure_minutes);
+            metrics.record_path_query(&path, elapsed_ms(start_ns));
+            let next = disambiguation_slot(&path).map(|(slot, suggestions)|
+                (PendingQuery::FromAt(from.clone(), to.clone(), departure_minutes, slot), suggestions));
+            print::output_find_path(path, from.as_slice(), to.as_slice(), print::OutputFormat::Verbose, stream);
+            next
+        },
+        PendingQuery::FromPreferFewerTransfers(from, to, slot) => {
+            let (from, to) = fill_slot(from, to, chosen, slot);
+            // needs the write lock: see the comment in query_user
+            let start_ns = time::precise_time_ns();
+            let path = t.write().unwrap().find_path_preferring_fewer_transfers(from.as_slice(), to.as_slice());
+            metrics.record_path_query(&path, elapsed_ms(start_ns));
+            let next = disambiguation_slot(&path).map(|(slot, suggestions)|
+                (PendingQuery::FromPreferFewerTransfers(from.clone(), to.clone(), slot), suggestions));
+            print::output_find_path(path, from.as_slice(), to.as_slice(), print::OutputFormat::Verbose, stream);
+            next
+        },
+        PendingQuery::FromPareto(from, to, slot) => {
+            let (from, to) = fill_slot(from, to, chosen, slot);
+            let start_ns = time::precise_time_ns();
+            let path = t.read().unwrap().find_pareto_paths(from.as_slice(), to.as_slice());
+            metrics.record_path_query(&path, elapsed_ms(start_ns));
+            let next = disambiguation_slot(&path).map(|(slot, suggestions)|
+                (PendingQuery::FromPareto(from.clone(), to.clone(), slot), suggestions));
+            print::output_find_path(path, from.as_slice(), to.as_slice(), print::OutputFormat::Verbose, stream);
+            next
+        },
+        PendingQuery::FromCondensed(from, to, slot) => {
+            let (from, to) = fill_slot(from, to, chosen, slot);
+            let start_ns = time::precise_time_ns();
+            let path = t.read().unwrap().find_path(from.as_slice(), to.as_slice());
+            metrics.record_path_query(&path, elapsed_ms(start_ns));
+            let next = disambiguation_slot(&path).map(|(slot, suggestions)|
+                (PendingQuery::FromCondensed(from.clone(), to.clone(), slot), suggestions));
+            print::output_find_path(path, from.as_slice(), to.as_slice(), print::OutputFormat::Condensed, stream);
+            next
+        },
+        PendingQuery::FromCsv(from, to, slot) => {
+            let (from, to) = fill_slot(from, to, chosen, slot);
+            let start_ns = time::precise_time_ns();
+            let path = t.read().unwrap().find_path(from.as_slice(), to.as_slice());
+            metrics.record_path_query(&path, elapsed_ms(start_ns));
+            let next = disambiguation_slot(&path).map(|(slot, suggestions)|
+                (PendingQuery::FromCsv(from.clone(), to.clone(), slot), suggestions));
+            print::output_find_path(path, from.as_slice(), to.as_slice(), print::OutputFormat::Csv, stream);
+            next
+        },
+        PendingQuery::Disable(_) => {
+            let result = t.write().unwrap().disable_station(chosen.as_slice());
+            let next = disambiguation_suggestions(&result).map(|suggestions|
+                (PendingQuery::Disable(chosen.clone()), suggestions));
+            notify_change(broadcaster, conn_key, &result,
+                          format!("\nNOTICE: {} disabled.\n", chosen).as_slice());
+            print::output_disable_station(chosen.as_slice(), result, stream);
+            next
+        },
+        PendingQuery::Enable(_) => {
+            let result = t.write().unwrap().enable_station(chosen.as_slice());
+            let next = disambiguation_suggestions(&result).map(|suggestions|
+                (PendingQuery::Enable(chosen.clone()), suggestions));
+            notify_change(broadcaster, conn_key, &result,
+                          format!("\nNOTICE: {} enabled.\n", chosen).as_slice());
+            print::output_enable_station(chosen.as_slice(), result, stream);
+            next
+        },
+        PendingQuery::Info(_) => {
+            let result = t.read().unwrap().station_info(chosen.as_slice());
+            let next = disambiguation_suggestions_info(&result).map(|suggestions|
+                (PendingQuery::Info(chosen.clone()), suggestions));
+            output_info(chosen.as_slice(), result, stream);
+            next
+        }
     }
 }
 
@@ -96,26 +1003,468 @@ fn compile_regexes() -> Parser {
 #[cfg(not(test))]
 /// The interface through which the user interacts with the T structure
 /// query_user asks the user for a command/operation, and then
-/// executes it and prints the response back to the stream
-pub fn query_user<BS: Writer + Buffer>(stream: &mut BS, t: Arc<Mutex<T>>) {
+/// executes it and prints the response back to the stream. The shared T
+/// is locked separately for each query rather than once for the whole
+/// connection, and only enable/disable (and 'prefer fewer transfers'
+/// queries, which rebuild the graph) take the write lock, so one
+/// client disabling a station doesn't block every other client's path
+/// queries for the lifetime of its connection.
+///
+/// Path queries are open to anyone, but enable/disable require the
+/// connection to have first authenticated with 'auth <token>'; this is
+/// tracked per-connection, not per-T, so one client's authentication
+/// doesn't carry over to another.
+///
+/// When a query's disambiguation prompt lists numbered suggestions, the
+/// next line is checked for a bare number first; if it's a valid
+/// selection, the original query is re-run with that suggestion instead
+/// of being parsed as a new command.
+///
+/// 'shutdown' only raises the shared flag for serve_forever to notice;
+/// it doesn't close this or any other connection itself, so this
+/// function keeps serving whatever commands come in until the socket is
+/// closed out from under it.
+///
+/// 'quit'/'exit' print a goodbye message and break out of the read loop,
+/// closing the connection on this end rather than waiting for the client
+/// to disconnect. The loop also ends cleanly on its own if the client
+/// disconnects first -- read_line's EOF simply ends the `while let`
+/// without printing another prompt nobody will see.
+///
+/// Every path query and admin operation is checked against
+/// `rate_limiters` first, keyed by both `conn_key` (this connection's own
+/// address) and `ip_key` (shared with any other connection from the same
+/// IP), and throttled with no further effect -- in particular, an
+/// unauthenticated or throttled enable/disable attempt never touches the
+/// shared T's write lock.
+///
+/// Every resolved command line (but not blank ones, or a disambiguation
+/// prompt's numbered reply) is appended to a per-connection history,
+/// which 'history' lists and '!!'/'!<n>' can re-run from, so a script or
+/// an interactive user doesn't have to retype a long station name to
+/// repeat or tweak a previous query.
+///
+/// Every disable/enable that actually changes something -- including one
+/// answered via a disambiguation prompt, or applied as part of a
+/// 'commit' -- is also broadcast through `broadcaster` to every other
+/// connection registered on it, keyed off everything but this
+/// connection's own `conn_key`, so this connection's own response is
+/// unaffected by its own change.
+pub fn query_user<BS: Writer + Buffer>(stream: &mut BS, t: Arc<RwLock<T>>, metrics: Arc<Metrics>,
+                                       shutdown: Arc<RwLock<bool>>, rate_limiters: Arc<RateLimiters>,
+                                       broadcaster: Arc<Broadcaster>, conn_key: String, ip_key: String,
+                                       admin_token: Arc<String>) {
     let parser = compile_regexes();
-    let mut mbta = t.lock().unwrap();
+    let mut authenticated = false;
+    let mut machine_mode = false;
+    let mut pending: Option<(PendingQuery, Vec<String>)> = None;
+    let mut history: Vec<String> = Vec::new();
+    let mut transaction: Option<Vec<TxOp>> = None;
 
+    metrics.connection_opened();
     stream.write_str(PROMPT_STRING);
     stream.flush();
     while let Ok(line) = stream.read_line() {
-        match parser.parse_line(line.as_slice()) {
+        // a pending disambiguation is only good for the line right after
+        // it; whether or not this line is a valid selection, it's gone
+        // once we get here
+        let was_pending = pending.take();
+        let number = line.as_slice().trim().parse::<usize>().ok();
+        match (was_pending, number) {
+            (Some((query, suggestions)), Some(n)) if n >= 1 && n <= suggestions.len() => {
+                let now = now_ms();
+                let throttled = (is_pending_path_query(&query) &&
+                                  !rate_limiters.allow_query(conn_key.as_slice(), ip_key.as_slice(), now)) ||
+                                 (is_pending_admin_op(&query) &&
+                                  !rate_limiters.allow_admin_op(conn_key.as_slice(), ip_key.as_slice(), now));
+                if throttled {
+                    stream.write_str(THROTTLED);
+                } else {
+                    pending = retry_pending(query, suggestions[n - 1].clone(), &t, &metrics,
+                                            &broadcaster, conn_key.as_slice(), stream);
+                }
+                stream.write_str(PROMPT_STRING);
+                stream.flush();
+                continue;
+            },
+            (Some(_), Some(_)) => {
+                stream.write_str(INVALID_SELECTION);
+                stream.write_str(PROMPT_STRING);
+                stream.flush();
+                continue;
+            },
+            (_, _) => {}
+        }
+        let resolved = match resolve_history_reference(line.as_slice(), history.as_slice()) {
+            Ok(resolved) => resolved,
+            Err(msg) => {
+                stream.write_str(msg);
+                stream.write_str(PROMPT_STRING);
+                stream.flush();
+                continue;
+            }
+        };
+        if !resolved.as_slice().trim().is_empty() {
+            history.push(resolved.clone());
+        }
+        let query = parser.parse_line(resolved.as_slice());
+        let now = now_ms();
+        let throttled = (is_path_query(&query) &&
+                          !rate_limiters.allow_query(conn_key.as_slice(), ip_key.as_slice(), now)) ||
+                         (is_admin_op(&query) &&
+                          !rate_limiters.allow_admin_op(conn_key.as_slice(), ip_key.as_slice(), now));
+        if throttled {
+            stream.write_str(THROTTLED);
+            stream.write_str(PROMPT_STRING);
+            stream.flush();
+            continue;
+        }
+        match query {
             From(from, to) => {
-                let path = mbta.find_path(from, to);
-                print::output_find_path(path, from, to, stream);
+                let start_ns = time::precise_time_ns();
+                let path = t.read().unwrap().find_path(from, to);
+                metrics.record_path_query(&path, elapsed_ms(start_ns));
+                pending = disambiguation_slot(&path).map(|(slot, suggestions)|
+                    (PendingQuery::From(from.to_string(), to.to_string(), slot), suggestions));
+                let code = protocol::code_for_query_result(&path);
+                let mut buf = MemWriter::new();
+                print::output_find_path(path, from, to, print::OutputFormat::Verbose, &mut buf);
+                emit_result(stream, machine_mode, code, buf.into_inner());
+            },
+            FromOptions(from, to, k) => {
+                let start_ns = time::precise_time_ns();
+                let path = t.read().unwrap().find_paths(from, to, k);
+                metrics.record_path_query(&path, elapsed_ms(start_ns));
+                pending = disambiguation_slot(&path).map(|(slot, suggestions)|
+                    (PendingQuery::FromOptions(from.to_string(), to.to_string(), k, slot), suggestions));
+                let code = protocol::code_for_query_result(&path);
+                let mut buf = MemWriter::new();
+                print::output_find_path(path, from, to, print::OutputFormat::Verbose, &mut buf);
+                emit_result(stream, machine_mode, code, buf.into_inner());
+            },
+            FromAvoiding(from, to, avoid) => {
+                let owned_avoid: Vec<String> = avoid.iter().map(|s| s.to_string()).collect();
+                let start_ns = time::precise_time_ns();
+                let path = t.read().unwrap().find_path_avoiding(from, to, avoid);
+                metrics.record_path_query(&path, elapsed_ms(start_ns));
+                pending = disambiguation_slot(&path).map(|(slot, suggestions)|
+                    (PendingQuery::FromAvoiding(from.to_string(), to.to_string(), owned_avoid, slot), suggestions));
+                let code = protocol::code_for_query_result(&path);
+                let mut buf = MemWriter::new();
+                print::output_find_path(path, from, to, print::OutputFormat::Verbose, &mut buf);
+                emit_result(stream, machine_mode, code, buf.into_inner());
+            },
+            FromAt(from, to, departure_minutes) => {
+                let start_ns = time::precise_time_ns();
+                let path = t.read().unwrap().find_path_at(from, to, departure_minutes);
+                metrics.record_path_query(&path, elapsed_ms(start_ns));
+                pending = disambiguation_slot(&path).map(|(slot, suggestions)|
+                    (PendingQuery::FromAt(from.to_string(), to.to_string(), departure_minutes, slot), suggestions));
+                let code = protocol::code_for_query_result(&path);
+                let mut buf = MemWriter::new();
+                print::output_find_path(path, from, to, print::OutputFormat::Verbose, &mut buf);
+                emit_result(stream, machine_mode, code, buf.into_inner());
+            },
+            FromPreferFewerTransfers(from, to) => {
+                // find_path_preferring_fewer_transfers temporarily
+                // reweights and rebuilds the graph, so it needs the
+                // write lock even though it's a read-only query
+                let start_ns = time::precise_time_ns();
+                let path = t.write().unwrap().find_path_preferring_fewer_transfers(from, to);
+                metrics.record_path_query(&path, elapsed_ms(start_ns));
+                pending = disambiguation_slot(&path).map(|(slot, suggestions)|
+                    (PendingQuery::FromPreferFewerTransfers(from.to_string(), to.to_string(), slot), suggestions));
+                let code = protocol::code_for_query_result(&path);
+                let mut buf = MemWriter::new();
+                print::output_find_path(path, from, to, print::OutputFormat::Verbose, &mut buf);
+                emit_result(stream, machine_mode, code, buf.into_inner());
+            },
+            FromPareto(from, to) => {
+                let start_ns = time::precise_time_ns();
+                let path = t.read().unwrap().find_pareto_paths(from, to);
+                metrics.record_path_query(&path, elapsed_ms(start_ns));
+                pending = disambiguation_slot(&path).map(|(slot, suggestions)|
+                    (PendingQuery::FromPareto(from.to_string(), to.to_string(), slot), suggestions));
+                let code = protocol::code_for_query_result(&path);
+                let mut buf = MemWriter::new();
+                print::output_find_path(path, from, to, print::OutputFormat::Verbose, &mut buf);
+                emit_result(stream, machine_mode, code, buf.into_inner());
+            },
+            FromCondensed(from, to) => {
+                let start_ns = time::precise_time_ns();
+                let path = t.read().unwrap().find_path(from, to);
+                metrics.record_path_query(&path, elapsed_ms(start_ns));
+                pending = disambiguation_slot(&path).map(|(slot, suggestions)|
+                    (PendingQuery::FromCondensed(from.to_string(), to.to_string(), slot), suggestions));
+                let code = protocol::code_for_query_result(&path);
+                let mut buf = MemWriter::new();
+                print::output_find_path(path, from, to, print::OutputFormat::Condensed, &mut buf);
+                emit_result(stream, machine_mode, code, buf.into_inner());
+            },
+            FromCsv(from, to) => {
+                let start_ns = time::precise_time_ns();
+                let path = t.read().unwrap().find_path(from, to);
+                metrics.record_path_query(&path, elapsed_ms(start_ns));
+                pending = disambiguation_slot(&path).map(|(slot, suggestions)|
+                    (PendingQuery::FromCsv(from.to_string(), to.to_string(), slot), suggestions));
+                let code = protocol::code_for_query_result(&path);
+                let mut buf = MemWriter::new();
+                print::output_find_path(path, from, to, print::OutputFormat::Csv, &mut buf);
+                emit_result(stream, machine_mode, code, buf.into_inner());
+            },
+            Plan(stops) => {
+                // DisambiguateStart/DisambiguateDestination results from a
+                // leg of a plan aren't tracked for retry, the same as
+                // disable between/enable between: there's no slot to say
+                // which of a multi-stop plan's stations was ambiguous
+                let start_ns = time::precise_time_ns();
+                let path = t.read().unwrap().find_planned_trip(stops);
+                metrics.record_path_query(&path, elapsed_ms(start_ns));
+                let code = protocol::code_for_query_result(&path);
+                let mut buf = MemWriter::new();
+                print::output_find_path(path, "", "", print::OutputFormat::Verbose, &mut buf);
+                emit_result(stream, machine_mode, code, buf.into_inner());
+            },
+            Auth(token) => {
+                authenticated = constant_time_eq(token.as_bytes(), admin_token.as_bytes());
+                stream.write_str(if authenticated { AUTH_OK } else { AUTH_FAILED });
             },
             Disable(station) => {
-                let disabled = mbta.disable_station(station);
-                print::output_disable_station(station, disabled, stream);
+                if !authenticated {
+                    stream.write_str(NOT_AUTHENTICATED);
+                } else if let Some(ref mut ops) = transaction {
+                    ops.push(TxOp::DisableStation(station.to_string()));
+                    stream.write_str(TX_QUEUED);
+                } else {
+                    let disabled = t.write().unwrap().disable_station(station);
+                    pending = disambiguation_suggestions(&disabled).map(|suggestions|
+                        (PendingQuery::Disable(station.to_string()), suggestions));
+                    notify_change(&broadcaster, conn_key.as_slice(), &disabled,
+                                  format!("\nNOTICE: {} disabled.\n", station).as_slice());
+                    let code = protocol::code_for_operation_result(&disabled);
+                    let mut buf = MemWriter::new();
+                    print::output_disable_station(station, disabled, &mut buf);
+                    emit_result(stream, machine_mode, code, buf.into_inner());
+                }
+            },
+            DisableFor(station, seconds) => {
+                if !authenticated {
+                    stream.write_str(NOT_AUTHENTICATED);
+                } else if let Some(ref mut ops) = transaction {
+                    ops.push(TxOp::DisableStationFor(station.to_string(), seconds));
+                    stream.write_str(TX_QUEUED);
+                } else {
+                    let disabled = t.write().unwrap().disable_station_for(station, seconds);
+                    notify_change(&broadcaster, conn_key.as_slice(), &disabled,
+                                  format!("\nNOTICE: {} disabled.\n", station).as_slice());
+                    let code = protocol::code_for_operation_result(&disabled);
+                    let mut buf = MemWriter::new();
+                    print::output_disable_station(station, disabled, &mut buf);
+                    emit_result(stream, machine_mode, code, buf.into_inner());
+                }
+            },
+            DisableUntil(station, clock_minutes) => {
+                if !authenticated {
+                    stream.write_str(NOT_AUTHENTICATED);
+                } else if let Some(ref mut ops) = transaction {
+                    ops.push(TxOp::DisableStationUntil(station.to_string(), clock_minutes));
+                    stream.write_str(TX_QUEUED);
+                } else {
+                    let disabled = t.write().unwrap().disable_station_until(station, clock_minutes);
+                    notify_change(&broadcaster, conn_key.as_slice(), &disabled,
+                                  format!("\nNOTICE: {} disabled.\n", station).as_slice());
+                    let code = protocol::code_for_operation_result(&disabled);
+                    let mut buf = MemWriter::new();
+                    print::output_disable_station(station, disabled, &mut buf);
+                    emit_result(stream, machine_mode, code, buf.into_inner());
+                }
             },
             Enable(station) => {
-                let enabled = mbta.enable_station(station);
-                print::output_enable_station(station, enabled, stream);
+                if !authenticated {
+                    stream.write_str(NOT_AUTHENTICATED);
+                } else if let Some(ref mut ops) = transaction {
+                    ops.push(TxOp::EnableStation(station.to_string()));
+                    stream.write_str(TX_QUEUED);
+                } else {
+                    let enabled = t.write().unwrap().enable_station(station);
+                    pending = disambiguation_suggestions(&enabled).map(|suggestions|
+                        (PendingQuery::Enable(station.to_string()), suggestions));
+                    notify_change(&broadcaster, conn_key.as_slice(), &enabled,
+                                  format!("\nNOTICE: {} enabled.\n", station).as_slice());
+                    let code = protocol::code_for_operation_result(&enabled);
+                    let mut buf = MemWriter::new();
+                    print::output_enable_station(station, enabled, &mut buf);
+                    emit_result(stream, machine_mode, code, buf.into_inner());
+                }
+            },
+            DisableSegment(a, b) => {
+                if !authenticated {
+                    stream.write_str(NOT_AUTHENTICATED);
+                } else if let Some(ref mut ops) = transaction {
+                    ops.push(TxOp::DisableSegment(a.to_string(), b.to_string()));
+                    stream.write_str(TX_QUEUED);
+                } else {
+                    let disabled = t.write().unwrap().disable_segment(a, b);
+                    notify_change(&broadcaster, conn_key.as_slice(), &disabled,
+                                  format!("\nNOTICE: segment {} <-> {} disabled.\n", a, b).as_slice());
+                    let code = protocol::code_for_operation_result(&disabled);
+                    let mut buf = MemWriter::new();
+                    print::output_disable_segment(a, b, disabled, &mut buf);
+                    emit_result(stream, machine_mode, code, buf.into_inner());
+                }
+            },
+            EnableSegment(a, b) => {
+                if !authenticated {
+                    stream.write_str(NOT_AUTHENTICATED);
+                } else if let Some(ref mut ops) = transaction {
+                    ops.push(TxOp::EnableSegment(a.to_string(), b.to_string()));
+                    stream.write_str(TX_QUEUED);
+                } else {
+                    let enabled = t.write().unwrap().enable_segment(a, b);
+                    notify_change(&broadcaster, conn_key.as_slice(), &enabled,
+                                  format!("\nNOTICE: segment {} <-> {} enabled.\n", a, b).as_slice());
+                    let code = protocol::code_for_operation_result(&enabled);
+                    let mut buf = MemWriter::new();
+                    print::output_enable_segment(a, b, enabled, &mut buf);
+                    emit_result(stream, machine_mode, code, buf.into_inner());
+                }
+            },
+            Begin => {
+                if !authenticated {
+                    stream.write_str(NOT_AUTHENTICATED);
+                } else if transaction.is_some() {
+                    stream.write_str(TX_ALREADY_OPEN);
+                } else {
+                    transaction = Some(Vec::new());
+                    stream.write_str(TX_STARTED);
+                }
+            },
+            Commit => {
+                if !authenticated {
+                    stream.write_str(NOT_AUTHENTICATED);
+                } else {
+                    match transaction.take() {
+                        None => { stream.write_str(TX_NONE_OPEN); },
+                        Some(ops) => {
+                            let mut buf = MemWriter::new();
+                            {
+                                let mut mbta = t.write().unwrap();
+                                for op in ops.into_iter() {
+                                    match op {
+                                        TxOp::DisableStation(station) => {
+                                            let result = mbta.disable_station(station.as_slice());
+                                            notify_change(&broadcaster, conn_key.as_slice(), &result,
+                                                          format!("\nNOTICE: {} disabled.\n", station).as_slice());
+                                            print::output_disable_station(station.as_slice(), result, &mut buf);
+                                        },
+                                        TxOp::DisableStationFor(station, seconds) => {
+                                            let result = mbta.disable_station_for(station.as_slice(), seconds);
+                                            notify_change(&broadcaster, conn_key.as_slice(), &result,
+                                                          format!("\nNOTICE: {} disabled.\n", station).as_slice());
+                                            print::output_disable_station(station.as_slice(), result, &mut buf);
+                                        },
+                                        TxOp::DisableStationUntil(station, clock_minutes) => {
+                                            let result = mbta.disable_station_until(station.as_slice(), clock_minutes);
+                                            notify_change(&broadcaster, conn_key.as_slice(), &result,
+                                                          format!("\nNOTICE: {} disabled.\n", station).as_slice());
+                                            print::output_disable_station(station.as_slice(), result, &mut buf);
+                                        },
+                                        TxOp::EnableStation(station) => {
+                                            let result = mbta.enable_station(station.as_slice());
+                                            notify_change(&broadcaster, conn_key.as_slice(), &result,
+                                                          format!("\nNOTICE: {} enabled.\n", station).as_slice());
+                                            print::output_enable_station(station.as_slice(), result, &mut buf);
+                                        },
+                                        TxOp::DisableSegment(a, b) => {
+                                            let result = mbta.disable_segment(a.as_slice(), b.as_slice());
+                                            notify_change(&broadcaster, conn_key.as_slice(), &result,
+                                                          format!("\nNOTICE: segment {} <-> {} disabled.\n", a, b).as_slice());
+                                            print::output_disable_segment(a.as_slice(), b.as_slice(), result, &mut buf);
+                                        },
+                                        TxOp::EnableSegment(a, b) => {
+                                            let result = mbta.enable_segment(a.as_slice(), b.as_slice());
+                                            notify_change(&broadcaster, conn_key.as_slice(), &result,
+                                                          format!("\nNOTICE: segment {} <-> {} enabled.\n", a, b).as_slice());
+                                            print::output_enable_segment(a.as_slice(), b.as_slice(), result, &mut buf);
+                                        }
+                                    }
+                                }
+                            }
+                            stream.write(buf.into_inner().as_slice());
+                        }
+                    }
+                }
+            },
+            Abort => {
+                if !authenticated {
+                    stream.write_str(NOT_AUTHENTICATED);
+                } else {
+                    match transaction.take() {
+                        None => { stream.write_str(TX_NONE_OPEN); },
+                        Some(_) => { stream.write_str(TX_ABORTED); }
+                    }
+                }
+            },
+            ExportDot(path) => {
+                if !authenticated {
+                    stream.write_str(NOT_AUTHENTICATED);
+                } else {
+                    match t.read().unwrap().export_dot(path) {
+                        Ok(()) => { stream.write_str(SUCCESS_EXPORT); },
+                        Err(e) => {
+                            stream.write_str(EXPORT_DOT_FAILED);
+                            write!(stream, "{}\n", e);
+                        }
+                    }
+                }
+            },
+            Lines => {
+                print::output_lines(t.read().unwrap().lines(), stream);
+            },
+            Stations(line) => {
+                let stations = t.read().unwrap().stations(line);
+                print::output_stations(line, stations, stream);
+            },
+            Info(station) => {
+                let info = t.read().unwrap().station_info(station);
+                pending = disambiguation_suggestions_info(&info).map(|suggestions|
+                    (PendingQuery::Info(station.to_string()), suggestions));
+                output_info(station, info, stream);
+            },
+            Status => {
+                let mbta = t.read().unwrap();
+                print::output_status(mbta.disabled_stations(), mbta.disabled_segments(),
+                                     mbta.active_alerts(), mbta.scheduled_disables_remaining(), stream);
+            },
+            MetricsQuery => {
+                print::output_metrics(metrics.snapshot(), stream);
+            },
+            Nearest(lat, lon, n) => {
+                let nearby = t.read().unwrap().nearest_stations(lat, lon, n);
+                print::output_nearest(nearby, stream);
+            },
+            Machine(on) => {
+                machine_mode = on;
+                stream.write_str(if on { MACHINE_ON } else { MACHINE_OFF });
+            },
+            History => {
+                print::output_history(history.clone(), stream);
+            },
+            Shutdown => {
+                if !authenticated {
+                    stream.write_str(NOT_AUTHENTICATED);
+                } else {
+                    *shutdown.write().unwrap() = true;
+                    stream.write_str(SHUTDOWN_CONFIRMED);
+                }
+            },
+            Help => {
+                stream.write_str(HELP_TEXT);
+            },
+            Quit => {
+                stream.write_str(GOODBYE);
+                stream.flush();
+                break;
             },
             Invalid => {
                 stream.write_str(INVALID_QUERY);
@@ -124,5 +1473,6 @@ pub fn query_user<BS: Writer + Buffer>(stream: &mut BS, t: Arc<Mutex<T>>) {
         stream.write_str(PROMPT_STRING);
         stream.flush();
     }
+    metrics.connection_closed();
 }
 
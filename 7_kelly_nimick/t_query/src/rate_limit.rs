@@ -0,0 +1,156 @@
+#[doc="
+    Module: rate_limit
+
+    A fixed-window rate limiter keyed by an arbitrary string -- a
+    connection's full address for per-connection limits, or just its IP
+    for per-IP limits shared across however many connections that IP has
+    open. Shared the same way Metrics is, behind an RwLock.
+"]
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry::{Vacant, Occupied};
+use std::sync::RwLock;
+
+// how long a window stays open before a key's count resets, in
+// milliseconds
+const WINDOW_MS: u64 = 60_000;
+
+struct Window {
+    started_ms: u64,
+    count: usize
+}
+
+/// Caps how many times `check` can return true for the same key within
+/// a fixed one-minute window that opens at the key's first hit. `limit` of None means no cap at all, so every
+/// caller can construct a RateLimiter unconditionally from a config
+/// value instead of special-casing "rate limiting is off".
+pub struct RateLimiter {
+    limit: Option<usize>,
+    windows: RwLock<HashMap<String, Window>>
+}
+
+impl RateLimiter {
+    pub fn new(limit: Option<usize>) -> RateLimiter {
+        RateLimiter {
+            limit: limit,
+            windows: RwLock::new(HashMap::new())
+        }
+    }
+
+    /// Record one more hit for `key` at `now_ms`, returning true if it's
+    /// within the limit and false if `key` should be throttled instead.
+    /// A key whose window is older than WINDOW_MS starts a fresh one
+    /// with a count of one rather than carrying the old count forward.
+    pub fn check(&self, key: &str, now_ms: u64) -> bool {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return true
+        };
+        let mut windows = self.windows.write().unwrap();
+        match windows.entry(key.to_string()) {
+            Vacant(e) => {
+                e.insert(Window { started_ms: now_ms, count: 1 });
+                true
+            },
+            Occupied(mut e) => {
+                let window = e.get_mut();
+                if now_ms - window.started_ms >= WINDOW_MS {
+                    window.started_ms = now_ms;
+                    window.count = 1;
+                    true
+                } else if window.count < limit {
+                    window.count += 1;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
+
+/// Bundles the two limiters a connection needs to check: one for ordinary
+/// path queries, one for admin operations (enable/disable/segment
+/// toggling). Each check counts against both the connection's own address
+/// and its IP, under the same limit, so one client can't dodge the cap by
+/// opening a second connection; every connection from one IP (a shared
+/// NAT address included) draws on that IP's single allowance.
+pub struct RateLimiters {
+    pub queries: RateLimiter,
+    pub admin_ops: RateLimiter
+}
+
+impl RateLimiters {
+    pub fn new(query_limit: Option<usize>, admin_op_limit: Option<usize>) -> RateLimiters {
+        RateLimiters {
+            queries: RateLimiter::new(query_limit),
+            admin_ops: RateLimiter::new(admin_op_limit)
+        }
+    }
+
+    /// Whether a path query from the connection at `conn_key` (also part
+    /// of `ip_key`'s address) should be allowed at `now_ms`.
+    pub fn allow_query(&self, conn_key: &str, ip_key: &str, now_ms: u64) -> bool {
+        self.queries.check(conn_key, now_ms) && self.queries.check(ip_key, now_ms)
+    }
+
+    /// Whether an admin operation from the connection at `conn_key` should
+    /// be allowed at `now_ms`.
+    pub fn allow_admin_op(&self, conn_key: &str, ip_key: &str, now_ms: u64) -> bool {
+        self.admin_ops.check(conn_key, now_ms) && self.admin_ops.check(ip_key, now_ms)
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use super::{RateLimiter, RateLimiters};
+
+    #[test]
+    fn test_unlimited_always_allowed() {
+        let limiter = RateLimiter::new(None);
+        for i in 0..1000 {
+            assert!(limiter.check("1.2.3.4", i));
+        }
+    }
+
+    #[test]
+    fn test_throttles_after_limit_within_window() {
+        let limiter = RateLimiter::new(Some(3));
+        assert!(limiter.check("1.2.3.4", 0));
+        assert!(limiter.check("1.2.3.4", 1));
+        assert!(limiter.check("1.2.3.4", 2));
+        assert!(!limiter.check("1.2.3.4", 3));
+    }
+
+    #[test]
+    fn test_keys_are_independent() {
+        let limiter = RateLimiter::new(Some(1));
+        assert!(limiter.check("1.2.3.4", 0));
+        assert!(limiter.check("5.6.7.8", 0));
+        assert!(!limiter.check("1.2.3.4", 0));
+    }
+
+    #[test]
+    fn test_new_window_resets_count() {
+        let limiter = RateLimiter::new(Some(1));
+        assert!(limiter.check("1.2.3.4", 0));
+        assert!(!limiter.check("1.2.3.4", 1));
+        assert!(limiter.check("1.2.3.4", 60_000));
+    }
+
+    #[test]
+    fn test_rate_limiters_checks_both_conn_and_ip_key() {
+        let limiters = RateLimiters::new(Some(1), Some(5));
+        assert!(limiters.allow_query("1.2.3.4:1111", "1.2.3.4", 0));
+        // a second connection from the same IP is throttled, even though
+        // its own conn_key has never been checked before
+        assert!(!limiters.allow_query("1.2.3.4:2222", "1.2.3.4", 0));
+    }
+
+    #[test]
+    fn test_rate_limiters_queries_and_admin_ops_are_independent() {
+        let limiters = RateLimiters::new(Some(1), Some(1));
+        assert!(limiters.allow_query("1.2.3.4:1111", "1.2.3.4", 0));
+        assert!(limiters.allow_admin_op("1.2.3.4:1111", "1.2.3.4", 0));
+    }
+}
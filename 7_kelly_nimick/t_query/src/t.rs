@@ -7,22 +7,49 @@
     the T structure to find paths between two stations in the system.
 "]
 
-use self::TQueryResult::{TOk, DisambiguateStart, DisambiguateDestination,
-    NoSuchStart, NoSuchDest, DisabledStart, DisabledDest, NoSuchPath};
+extern crate time;
+
+use self::TQueryResult::{TOk, TOkMultiple, TOkPareto, TPlan, DisambiguateStart, DisambiguateDestination,
+    NoSuchStart, NoSuchDest, DisabledStart, DisabledDest, NoSuchPath, LineNotRunning};
 use self::TOperationResult::{Successful, DisambiguateOp, NoSuchStationOp};
-use self::TStep::{Station, Switch, Ensure};
+use self::TInfoResult::{Info, DisambiguateInfo, NoSuchStationInfo};
+use self::TStep::{Station, Switch, Ensure, Walk};
+use std::ascii::AsciiExt;
+use std::cell::RefCell;
+use std::cmp::max;
 use std::collections::{HashSet, HashMap};
-use std::io::BufferedReader;
+use std::f64::consts::PI;
+use std::io::{BufferedReader, IoResult};
 use std::io::fs::File;
+use std::sync::Arc;
 use graph::{Node, LabeledGraph};
+use fuzzy;
 
 // how many stations is a transfer equivalent in cost to?
-static TRANSFER_COST: Option<usize> = Some(2);
-static NO_COST: Option<usize> = Some(0);
+static TRANSFER_COST: usize = 2;
+// transfer costs for lines whose mode is known via line_metadata.dat;
+// a bus transfer is cheap (walk to a curb), a commuter rail transfer is
+// expensive (infrequent service, often a different station entrance)
+static BUS_TRANSFER_COST: usize = 1;
+static RAIL_TRANSFER_COST: usize = 5;
+static NO_COST: usize = 0;
 static START_NODE_LABEL: &'static str = "start_node";
 static END_NODE_LABEL: &'static str = "end_node";
 static START_NODE_POS: usize = 2;
 static END_NODE_POS: usize = 1;
+static MINUTES_PER_DAY: usize = 24 * 60;
+// average walking pace, for converting a walking transfer's distance in
+// meters (read_walking_connections) into a travel time in minutes
+static WALK_METERS_PER_MINUTE: f64 = 80.0;
+
+// how much to scale the transfer cost by for a "prefer fewer transfers"
+// query, so the router strongly favors routes with fewer line changes
+// even at the cost of extra stops
+static PREFER_FEWER_TRANSFERS_MULTIPLIER: usize = 10;
+
+// how many candidate routes a Pareto query draws from before filtering
+// down to the non-dominated ones
+static PARETO_CANDIDATE_COUNT: usize = 10;
 
 ////////////////////////////////////////////////////////////////////////////
 //                              Macros                                    //
@@ -45,8 +72,8 @@ macro_rules! return_some_vec {
 // since we always add a start and end node to the end of
 // the list of nodes
 macro_rules! get_node_from_vec {
-    ($t:expr, $node:expr, $node_pos_if_multiple:expr, $empty_return:expr) => {
-        match $t.stations.get(&$node) {
+    ($stations:expr, $node:expr, $node_pos_if_multiple:expr, $empty_return:expr) => {
+        match $stations.get(&$node) {
             Some(v) => {
                 if v.len() == 1 {
                     &v[0]
@@ -73,16 +100,28 @@ macro_rules! string_set {
 ////////////////////////////////////////////////////////////////////////////
 //                               Enums                                    //
 ////////////////////////////////////////////////////////////////////////////
-#[derive(Show, PartialEq)]
+#[derive(Show, PartialEq, Clone)]
 pub enum TQueryResult<'a> {
-    TOk(Vec<TStep>),
+    // steps, total travel time in minutes, total fare in dollars
+    TOk(Vec<TStep>, usize, f64),
+    // up to K distinct routes, cheapest first, each as (steps, minutes, fare)
+    TOkMultiple(Vec<(Vec<TStep>, usize, f64)>),
+    // the non-dominated itineraries among a bounded set of candidate
+    // routes, each as (steps, stops, transfers, minutes, fare)
+    TOkPareto(Vec<(Vec<TStep>, usize, usize, usize, f64)>),
+    // a multi-stop plan's legs, each as (steps, minutes, fare), followed
+    // by the total minutes (including every stop's dwell but the last's)
+    // and total fare across every leg
+    TPlan(Vec<(Vec<TStep>, usize, f64)>, usize, f64),
     DisambiguateStart(Vec<String>),
     DisambiguateDestination(Vec<String>),
     NoSuchStart,
     NoSuchDest,
     DisabledStart(String),
     DisabledDest(String),
-    NoSuchPath
+    NoSuchPath,
+    // line that isn't running yet/anymore, minutes until its next departure
+    LineNotRunning(String, usize)
 }
 
 #[derive(Show, PartialEq)]
@@ -93,13 +132,53 @@ pub enum TOperationResult<'a> {
 }
 
 #[derive(Show, PartialEq)]
+pub enum TInfoResult {
+    // resolved station name, lines serving it (sorted), whether more
+    // than one line makes it a transfer station, whether it's
+    // currently disabled, and its nearest enabled neighbor in each
+    // direction on every serving line (line name, previous, next)
+    Info(String, Vec<String>, bool, bool, Vec<(String, Option<String>, Option<String>)>),
+    DisambiguateInfo(Vec<String>),
+    NoSuchStationInfo
+}
+
+/// Why loading the T's data files failed: either a file couldn't be
+/// opened at all, or a line in an otherwise-opened file didn't parse.
+/// Carries enough context -- the path, and for a bad line, its 1-indexed
+/// line number -- for main to print something a deployer can act on
+/// instead of an unhelpful panic.
+#[derive(Show, PartialEq, Eq)]
+pub enum LoadError {
+    CouldNotOpen(String, String),
+    MalformedLine(String, usize, String)
+}
+
+impl LoadError {
+    /// A human-readable description of the failure, suitable for
+    /// printing directly.
+    pub fn message(&self) -> String {
+        match *self {
+            LoadError::CouldNotOpen(ref path, ref reason) =>
+                format!("couldn't open \"{}\": {}", path, reason),
+            LoadError::MalformedLine(ref path, line_no, ref reason) =>
+                format!("\"{}\" line {}: {}", path, line_no, reason)
+        }
+    }
+}
+
+#[derive(Show, PartialEq, Clone)]
 pub enum TStep {
-    // Station, line name
-    Station(String, String),
-    // Station, line name
-    Switch(String, String),
-    // line name
-    Ensure(String)
+    // Station, line name, terminus to ride toward (None if it can't be
+    // determined, e.g. the line has no known station ordering)
+    Station(String, String, Option<String>),
+    // Station, line name, terminus to ride toward
+    Switch(String, String, Option<String>),
+    // line name, terminus to ride toward
+    Ensure(String, Option<String>),
+    // an out-of-system walk between two stations, e.g. between two
+    // nearby stations on different lines with no direct connection;
+    // from station, to station, walking time in minutes
+    Walk(String, String, usize)
 }
 
 #[derive(Show, PartialEq)]
@@ -108,21 +187,105 @@ enum DisambiguationResult {
     Suggestions(Vec<String>)
 }
 
+/// A line's daily service window: when trains start and stop running, and
+/// how often they come while they're running.
+#[derive(Show)]
+struct LineSchedule {
+    // minutes since midnight that service starts
+    first_minutes: usize,
+    // minutes since midnight that service ends; earlier than
+    // first_minutes means service runs past midnight into the next day
+    last_minutes: usize,
+    // minutes between trains while the line is running
+    headway_minutes: usize
+}
+
+/// A line's attributes beyond its station order: what mode of transit it
+/// is (subway/bus/rail), its map color, and its fare class. Lines with
+/// no entry in the line metadata file are assumed to be standard subway
+/// lines, so existing data files that predate line_metadata.dat keep
+/// working unchanged.
+#[derive(Show)]
+struct LineMetadata {
+    mode: String,
+    color: String,
+    // informational only for now; fares are still looked up by line
+    // name in fares.dat rather than by fare class
+    fare_class: String
+}
+
+// how many distinct (start, dest) find_path results the path cache keeps
+// before evicting the least-recently-used entry
+static PATH_CACHE_CAPACITY: usize = 64;
+
+/// A small fixed-capacity least-recently-used cache of find_path results,
+/// keyed by (start node, dest node, graph generation). Keying on the
+/// generation means rebuild_graph invalidates every old entry for free:
+/// once the generation advances, a stale entry's key can never be looked
+/// up again, so it just ages out of the cache like any other unused entry
+/// instead of needing to be walked and evicted up front.
+#[derive(Show)]
+struct PathCache<'a> {
+    entries: HashMap<(Node, Node, usize), TQueryResult<'a>>,
+    // least-recently-used first, most-recently-used last
+    order: Vec<(Node, Node, usize)>
+}
+
+impl<'a> PathCache<'a> {
+    fn new() -> PathCache<'a> {
+        PathCache { entries: HashMap::new(), order: Vec::new() }
+    }
+
+    /// Look up `key`, marking it most-recently-used on a hit.
+    fn get(&mut self, key: &(Node, Node, usize)) -> Option<TQueryResult<'a>> {
+        match self.entries.get(key).cloned() {
+            Some(result) => {
+                self.touch(key);
+                Some(result)
+            },
+            None => None
+        }
+    }
+
+    /// Record a result for `key`, evicting the least-recently-used entry
+    /// first if the cache is already at capacity.
+    fn insert(&mut self, key: (Node, Node, usize), result: TQueryResult<'a>) {
+        if self.entries.contains_key(&key) {
+            self.touch(&key);
+        } else {
+            if self.order.len() >= PATH_CACHE_CAPACITY {
+                let lru = self.order.remove(0);
+                self.entries.remove(&lru);
+            }
+            self.order.push(key.clone());
+        }
+        self.entries.insert(key, result);
+    }
+
+    fn touch(&mut self, key: &(Node, Node, usize)) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            let k = self.order.remove(pos);
+            self.order.push(k);
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////
 //                              Structs                                   //
 ////////////////////////////////////////////////////////////////////////////
 
-#[derive(Show)]
-pub struct T<'a> {
+/// Everything a query needs to walk the graph, bundled together so a
+/// rebuild can swap in a whole new one in a single pointer assignment
+/// instead of readers ever seeing a half-updated graph. Built off to the
+/// side (from scratch by rebuild_graph, or cloned-then-patched in place
+/// by the incremental disable/enable path) and installed by
+/// replace_snapshot; current_snapshot hands out a cheap Arc clone of
+/// whichever one is current, so a query holding it never sees a rebuild
+/// happen out from under it mid-query.
+#[derive(Show, Clone)]
+struct Snapshot {
     graph: LabeledGraph,
 
-    // Used to reconstruct the T when stations are disabled/enabled
-    // line -> list of stations
-    source_data: HashMap<String, Vec<String>>,
-
-    // set of connections between lines used to reconstruct the graph
-    connections: HashSet<(String, String, Option<String>)>,
-
     // station name -> list of station nodes that represent the station
     // Stations have 1 or more nodes depending on how many lines connect
     // at the station
@@ -131,12 +294,134 @@ pub struct T<'a> {
     // last node is the 'exit' node used for unbiased destinations.
     stations: HashMap<String, Vec<Node>>,
 
+    // Set of tuples of 'inbound' connections, e.g. line changes that we
+    // don't need to "Ensure" for.
+    inbound_connections: HashSet<(String, String)>,
+
+    // (start node, dest node) -> (path, cost), computed for every pair of
+    // nodes in the graph when precompute_all_pairs is enabled; a missing
+    // entry means no path exists between those nodes. None when
+    // precompute_all_pairs is disabled, so find_path falls back to
+    // running Dijkstra (via path_cache) per query.
+    all_pairs: Option<HashMap<(Node, Node), (Vec<Node>, usize)>>,
+
+    // incremented every time this snapshot's graph changes, so cached
+    // find_path results can be keyed against the graph they were
+    // computed from
+    generation: usize
+}
+
+impl Snapshot {
+    fn empty() -> Snapshot {
+        Snapshot {
+            graph: LabeledGraph::new(),
+            stations: HashMap::new(),
+            inbound_connections: HashSet::new(),
+            all_pairs: None,
+            generation: 0
+        }
+    }
+}
+
+#[derive(Show)]
+pub struct T<'a> {
+    // Used to reconstruct the T when stations are disabled/enabled
+    // line -> list of stations
+    source_data: HashMap<String, Vec<String>>,
+
+    // line -> list of travel times in minutes, aligned index-for-index
+    // with source_data's station list. travel_times[line][i] is the
+    // time from station i - 1 to station i on that line; the time for
+    // index 0 is unused, since there's no previous station to travel
+    // from.
+    travel_times: HashMap<String, Vec<usize>>,
+
+    // set of connections between lines used to reconstruct the graph
+    connections: HashSet<(String, String, Option<String>)>,
+
+    // the current graph, stations, inbound connections, and all-pairs
+    // table, swapped in as a unit by replace_snapshot. See Snapshot's
+    // doc comment for why this is an Arc instead of plain fields: a
+    // query clones the Arc up front and works from that clone, so it
+    // sees one consistent graph even if a rebuild replaces this field
+    // partway through. The T itself already lives behind the caller's
+    // Arc<RwLock<T>> (see query.rs), which is what actually serializes
+    // rebuilds against queries -- a second lock here would only ever
+    // be taken while that outer lock is already held, so there's
+    // nothing left for it to protect.
+    snapshot: Arc<Snapshot>,
+
     // Set of disabled stations
     disabled: HashSet<String>,
 
-    // Set of tuples of 'inbound' connections, e.g. line changes that we
-    // don't need to "Ensure" for.
-    inbound_connections: HashSet<(String, String)>
+    // Set of disabled segments (stretches of track directly
+    // connecting two stations on the same line), each stored as a
+    // pair sorted by segment_key so lookups don't care which
+    // station was given first.
+    disabled_segments: HashSet<(String, String)>,
+
+    // line -> one-way fare in dollars, loaded from the fares data file
+    fares: HashMap<String, f64>,
+
+    // station name -> description of the service alert that's currently
+    // disabling it, for stations disabled by the alerts poller rather
+    // than by the plain "disable" command. Used only to surface alert
+    // text in query responses; the station's actual enabled/disabled
+    // state still lives in `disabled`.
+    alerts: HashMap<String, String>,
+
+    // station name -> unix timestamp (seconds) at which a station
+    // disabled by "disable ... for"/"disable ... until" should be
+    // automatically re-enabled. Swept by expire_scheduled_disables,
+    // which spawn_scheduled_disable_expirer calls on a timer. A plain
+    // "disable"/"enable" on the station clears its entry here, the
+    // same way enabling a station clears its alert in `alerts`.
+    scheduled_disables: HashMap<String, i64>,
+
+    // line -> first/last train and headway, loaded from the schedules
+    // data file. A line with no entry is assumed to run around the clock.
+    schedules: HashMap<String, LineSchedule>,
+
+    // cost of a transfer in the current graph, normally TRANSFER_COST.
+    // Temporarily raised by find_path_preferring_fewer_transfers so the
+    // router favors routes with fewer line changes for that one query.
+    transfer_cost: usize,
+
+    // common name or abbreviation (lowercased) -> canonical station name,
+    // loaded from the aliases data file. Checked by disambiguate_station
+    // before falling back to substring/fuzzy matching, so shorthands like
+    // "Govt Center" resolve on the first try.
+    aliases: HashMap<String, String>,
+
+    // line -> mode/color/fare class, loaded from the line metadata data
+    // file. A line with no entry is assumed to be a standard subway line.
+    lines_metadata: HashMap<String, LineMetadata>,
+
+    // station name -> (latitude, longitude), loaded from the coordinates
+    // data file. Used by nearest_stations to answer "what's near me"
+    // queries; a station with no entry here just can't be returned by
+    // that lookup.
+    coordinates: HashMap<String, (f64, f64)>,
+
+    // pair of stations (sorted by segment_key) -> walking time in
+    // minutes, loaded from the walking transfers data file. Modeled as
+    // graph edges between every node of one station and every node of
+    // the other, same as rebuild_connections does for branch/trunk line
+    // connections, so the router can use a short walk instead of a long
+    // detour when a transfer station in between is disabled. Also used
+    // by process_nodes to recognize such an edge and interpret it as a
+    // TStep::Walk rather than a same-station transfer.
+    walking_connections: HashMap<(String, String), usize>,
+
+    // recent find_path results, keyed by (start node, dest node, graph
+    // generation). find_path takes &self since it doesn't otherwise
+    // mutate the T, so this needs interior mutability to record hits/misses.
+    path_cache: RefCell<PathCache<'a>>,
+
+    // whether to precompute all-pairs shortest paths on every rebuild, so
+    // find_path becomes a table lookup. Must be set before load/load_from/
+    // load_gtfs to take effect on the first build.
+    precompute_all_pairs: bool
 }
 
 ////////////////////////////////////////////////////////////////////////////
@@ -147,74 +432,392 @@ impl<'a> T<'a> {
     /// Create a new T instance
     pub fn new() -> T<'a> {
         T {
-            graph: LabeledGraph::new(),
             source_data: HashMap::new(),
+            travel_times: HashMap::new(),
             connections: HashSet::new(),
-            stations: HashMap::new(),
+            snapshot: Arc::new(Snapshot::empty()),
             disabled: HashSet::new(),
-            inbound_connections: HashSet::new(),
+            disabled_segments: HashSet::new(),
+            fares: HashMap::new(),
+            alerts: HashMap::new(),
+            scheduled_disables: HashMap::new(),
+            schedules: HashMap::new(),
+            transfer_cost: TRANSFER_COST,
+            aliases: HashMap::new(),
+            lines_metadata: HashMap::new(),
+            coordinates: HashMap::new(),
+            walking_connections: HashMap::new(),
+            path_cache: RefCell::new(PathCache::new()),
+            precompute_all_pairs: false,
         }
     }
 
-    /// Load the T information from the data files
-    pub fn load(&mut self) {
-        self.read_data_file("data/blue.dat");
-        self.read_data_file("data/green.dat");
-        self.read_data_file("data/red.dat");
-        self.read_data_file("data/orange.dat");
-        self.read_connections("data/connections.dat");
+    /// A cheap clone of whichever Snapshot is current. Callers should
+    /// hold onto the result rather than calling this more than once per
+    /// query, so a query sees one consistent graph even if a rebuild
+    /// swaps in a new snapshot partway through.
+    fn current_snapshot(&self) -> Arc<Snapshot> {
+        self.snapshot.clone()
+    }
+
+    /// Install `next` as the current snapshot, replacing whatever was
+    /// there before. Takes &mut self -- callers already need a write
+    /// lock on the outer Arc<RwLock<T>> to get one, which is what
+    /// keeps this from racing a query mid-flight.
+    fn replace_snapshot(&mut self, next: Snapshot) {
+        self.snapshot = Arc::new(next);
+    }
+
+    /// Load the T information from the data files in the default "data"
+    /// directory. Fails with a LoadError if blue/green/red/orange/
+    /// silver/commuter_rail.dat or the connections file is missing or
+    /// has a malformed line; see load_from.
+    pub fn load(&mut self) -> Result<(), LoadError> {
+        self.load_from("data", "connections.dat")
+    }
+
+    /// Load the T information from the data files under `data_dir`, using
+    /// `connections_file` (a path relative to `data_dir`) for the line
+    /// connections data. This is what `load` delegates to with the
+    /// compiled-in default location, for deployments whose data files
+    /// live somewhere else.
+    ///
+    /// Fails with a LoadError as soon as a line-defining data file or the
+    /// connections file is missing or has a malformed line -- those are
+    /// the files a deployment can't run without. The remaining data
+    /// files (fares, schedules, aliases, line metadata, coordinates,
+    /// walking connections) are all optional enrichment: a missing one is
+    /// skipped, though a malformed line in one still panics, same as
+    /// before this was added.
+    pub fn load_from(&mut self, data_dir: &str, connections_file: &str) -> Result<(), LoadError> {
+        try!(self.read_data_file(&format!("{}/blue.dat", data_dir)));
+        try!(self.read_data_file(&format!("{}/green.dat", data_dir)));
+        try!(self.read_data_file(&format!("{}/red.dat", data_dir)));
+        try!(self.read_data_file(&format!("{}/orange.dat", data_dir)));
+        try!(self.read_data_file(&format!("{}/silver.dat", data_dir)));
+        try!(self.read_data_file(&format!("{}/commuter_rail.dat", data_dir)));
+        try!(self.read_connections(&format!("{}/{}", data_dir, connections_file)));
+        self.read_fares(&format!("{}/fares.dat", data_dir));
+        self.read_schedules(&format!("{}/schedules.dat", data_dir));
+        self.read_aliases(&format!("{}/aliases.dat", data_dir));
+        self.read_line_metadata(&format!("{}/line_metadata.dat", data_dir));
+        self.read_coordinates(&format!("{}/coordinates.dat", data_dir));
+        self.read_walking_connections(&format!("{}/walking_transfers.dat", data_dir));
         self.rebuild_graph();
+        Ok(())
+    }
+
+    /// Override the cost of a transfer from the compiled-in default. Must
+    /// be called before `load`/`load_from`/`load_gtfs`, since it only
+    /// takes effect the next time the graph is rebuilt.
+    pub fn set_transfer_cost(&mut self, cost: usize) {
+        self.transfer_cost = cost;
+    }
+
+    /// Enable or disable all-pairs shortest-path precomputation. When
+    /// enabled, every rebuild_graph computes shortest paths between every
+    /// pair of nodes up front, so find_path becomes a table lookup
+    /// instead of running Dijkstra per query -- a good trade for
+    /// latency-sensitive deployments willing to pay extra rebuild time
+    /// and memory. Must be called before load/load_from/load_gtfs, since
+    /// it only takes effect the next time the graph is rebuilt.
+    pub fn set_precompute_all_pairs(&mut self, enabled: bool) {
+        self.precompute_all_pairs = enabled;
+    }
+
+    /// Rough memory footprint of the all-pairs table in bytes, or None
+    /// if precomputation isn't enabled. Approximates each cached path by
+    /// the byte length of the station/line names it's made of, since
+    /// that's what actually scales with network size; it's not an exact
+    /// heap accounting.
+    pub fn all_pairs_memory_estimate(&self) -> Option<usize> {
+        self.current_snapshot().all_pairs.as_ref().map(|table| {
+            table.iter().map(|(key, &(ref path, _))| {
+                node_size(&key.0) + node_size(&key.1) +
+                    path.iter().fold(0, |acc, node| acc + node_size(node))
+            }).fold(0, |acc, size| acc + size)
+        })
     }
 
-    /// Load a specific data file into the T
-    fn read_data_file(&mut self, path: &str) {
-        let mut reader = open_file(path);
+    /// Load the T information from a GTFS static feed (stops.txt,
+    /// routes.txt, trips.txt, stop_times.txt) found in the given
+    /// directory, instead of the bespoke .dat format. This lets the
+    /// server model any transit agency that publishes a standard GTFS
+    /// feed, rather than only the hand-curated MBTA files. Takes one
+    /// representative trip per route to establish its station order and
+    /// travel times; doesn't model GTFS calendars, frequencies, or fares.
+    pub fn load_gtfs(&mut self, dir: &str) {
+        let stop_names = gtfs_stop_names(&read_gtfs_file(&format!("{}/stops.txt", dir)));
+        let route_names = gtfs_route_names(&read_gtfs_file(&format!("{}/routes.txt", dir)));
+        let representative_trips = gtfs_representative_trips(&read_gtfs_file(&format!("{}/trips.txt", dir)));
+        let stop_times_by_trip = gtfs_stop_times_by_trip(&read_gtfs_file(&format!("{}/stop_times.txt", dir)));
+
+        for &(ref route_id, ref trip_id) in representative_trips.iter() {
+            let route_name = route_names.get(route_id).cloned().unwrap_or(route_id.clone());
+            let mut sequence = match stop_times_by_trip.get(trip_id) {
+                Some(s) => s.clone(),
+                None => continue,
+            };
+            sequence.sort_by(|a, b| a.0.cmp(&b.0));
+
+            self.source_data.insert(route_name.clone(), Vec::new());
+            self.travel_times.insert(route_name.clone(), Vec::new());
+            let mut prev_seconds = 0;
+            for (i, &(_, ref stop_id, arrival_seconds)) in sequence.iter().enumerate() {
+                let stop_name = stop_names.get(stop_id).cloned().unwrap_or(stop_id.clone());
+                let minutes = if i == 0 { 0 } else { (arrival_seconds - prev_seconds) / 60 };
+                self.source_data.get_mut(&route_name).unwrap().push(stop_name);
+                self.travel_times.get_mut(&route_name).unwrap().push(minutes);
+                prev_seconds = arrival_seconds;
+            }
+        }
+        self.rebuild_graph();
+    }
+
+    /// Load a specific data file into the T. Each station line is
+    /// "name" or "name:minutes", where minutes is the travel time
+    /// from the previous station on that line, defaulting to 1
+    /// when not given.
+    fn read_data_file(&mut self, path: &str) -> Result<(), LoadError> {
+        let mut reader = try!(open_file(path));
         let mut rail_line = String::new();
+        let mut line_no = 0;
         while let Some(line) = reader.read_line().ok() {
+            line_no += 1;
             if line.starts_with("-") {
                 rail_line = line.trim_left_matches('-').trim().to_string();
                 self.source_data.insert(rail_line.clone(), Vec::new());
+                self.travel_times.insert(rail_line.clone(), Vec::new());
                 continue;
             }
-            let station_name = line.trim().to_string();
-            if !station_name.is_empty() {
-                self.source_data.get_mut(&rail_line).unwrap().push(station_name);
+            let trimmed = line.trim();
+            if !trimmed.is_empty() {
+                let (station_name, minutes) = try!(parse_station_line(path, line_no, trimmed));
+                match (self.source_data.get_mut(&rail_line), self.travel_times.get_mut(&rail_line)) {
+                    (Some(names), Some(times)) => {
+                        names.push(station_name);
+                        times.push(minutes);
+                    },
+                    _ => return Err(LoadError::MalformedLine(path.to_string(), line_no,
+                        "station line appears before any \"-line\" header".to_string()))
+                }
             }
         }
+        Ok(())
     }
 
     /// Load a connections file into the T
-    fn read_connections(&mut self, path: &str) {
-        let mut reader = open_file(path);
+    fn read_connections(&mut self, path: &str) -> Result<(), LoadError> {
+        let mut reader = try!(open_file(path));
+        let mut line_no = 0;
         while let Some(line) = reader.read_line().ok() {
+            line_no += 1;
             let mut line_split = line.split(',');
-            let one = line_split.next().unwrap().trim().to_string();
-            let two = line_split.next().unwrap().trim().to_string();
+            let one = match line_split.next() {
+                Some(s) => s.trim().to_string(),
+                None => return Err(LoadError::MalformedLine(path.to_string(), line_no,
+                    "expected at least two comma-separated station names".to_string()))
+            };
+            let two = match line_split.next() {
+                Some(s) => s.trim().to_string(),
+                None => return Err(LoadError::MalformedLine(path.to_string(), line_no,
+                    "expected at least two comma-separated station names".to_string()))
+            };
             let three = match line_split.next() {
                 Some(s) => Some(s.trim().to_string()),
                 None => None
             };
             self.connections.insert((one, two, three));
         }
+        Ok(())
+    }
+
+    /// Load a fares data file into the T. Each line is "line:price",
+    /// giving the one-way fare in dollars for riding that line.
+    fn read_fares(&mut self, path: &str) {
+        let mut reader = match open_file(path) {
+            Ok(r) => r,
+            Err(..) => return
+        };
+        while let Some(line) = reader.read_line().ok() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let i = trimmed.rfind(':').expect("fare line must be \"line:price\"");
+            let fare = trimmed.slice_from(i + 1).parse()
+                .expect("fare must be a valid dollar amount");
+            self.fares.insert(trimmed.slice_to(i).to_string(), fare);
+        }
+    }
+
+    /// Load an aliases data file into the T. Each line is
+    /// "alias:canonical station name", mapping a common shorthand or
+    /// abbreviation to the station it should resolve to. Lookups are
+    /// case-insensitive, so the alias is stored lowercased.
+    fn read_aliases(&mut self, path: &str) {
+        let mut reader = match open_file(path) {
+            Ok(r) => r,
+            Err(..) => return
+        };
+        while let Some(line) = reader.read_line().ok() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let i = trimmed.find(':').expect("alias line must be \"alias:station name\"");
+            let alias = trimmed.slice_to(i).to_ascii_lowercase();
+            let station = trimmed.slice_from(i + 1).to_string();
+            self.aliases.insert(alias, station);
+        }
+    }
+
+    /// Load a line metadata data file into the T. Each line is
+    /// "line:mode,color,fare_class", where mode is "subway", "bus", or
+    /// "rail". A line with no entry in this file is assumed to be a
+    /// standard subway line.
+    fn read_line_metadata(&mut self, path: &str) {
+        let mut reader = match open_file(path) {
+            Ok(r) => r,
+            Err(..) => return
+        };
+        while let Some(line) = reader.read_line().ok() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let i = trimmed.find(':').expect("line metadata line must be \"line:mode,color,fare_class\"");
+            let line_name = trimmed.slice_to(i).to_string();
+            let mut fields = trimmed.slice_from(i + 1).split(',');
+            let mode = fields.next().expect("line metadata must give a mode").trim().to_string();
+            let color = fields.next().expect("line metadata must give a color").trim().to_string();
+            let fare_class = fields.next().expect("line metadata must give a fare class").trim().to_string();
+            self.lines_metadata.insert(line_name, LineMetadata {
+                mode: mode,
+                color: color,
+                fare_class: fare_class
+            });
+        }
+    }
+
+    /// Load a station coordinates data file into the T. Each line is
+    /// "station name:lat,lon". Not every station needs an entry; a
+    /// station with no entry simply can't be returned by
+    /// nearest_stations.
+    fn read_coordinates(&mut self, path: &str) {
+        let mut reader = match open_file(path) {
+            Ok(r) => r,
+            Err(..) => return
+        };
+        while let Some(line) = reader.read_line().ok() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let i = trimmed.find(':').expect("coordinates line must be \"station:lat,lon\"");
+            let station = trimmed.slice_to(i).to_string();
+            let mut fields = trimmed.slice_from(i + 1).split(',');
+            let lat = fields.next().expect("coordinates must give a latitude").trim().parse()
+                .expect("latitude must be a valid number");
+            let lon = fields.next().expect("coordinates must give a longitude").trim().parse()
+                .expect("longitude must be a valid number");
+            self.coordinates.insert(station, (lat, lon));
+        }
+    }
+
+    /// Load a walking transfers data file into the T. Each line is
+    /// "station one,station two,meters", giving the straight-line
+    /// distance between two stations close enough to walk between
+    /// outside the system. Converted to minutes at WALK_METERS_PER_MINUTE and
+    /// rounded up so a walk always costs at least a minute.
+    fn read_walking_connections(&mut self, path: &str) {
+        let mut reader = match open_file(path) {
+            Ok(r) => r,
+            Err(..) => return
+        };
+        while let Some(line) = reader.read_line().ok() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let mut fields = trimmed.split(',');
+            let one = fields.next().expect("walking transfer must give a first station").trim().to_string();
+            let two = fields.next().expect("walking transfer must give a second station").trim().to_string();
+            let meters: f64 = fields.next().expect("walking transfer must give a distance in meters").trim().parse()
+                .expect("walking transfer distance must be a number");
+            let minutes = max(1, (meters / WALK_METERS_PER_MINUTE).ceil() as usize);
+            self.walking_connections.insert(segment_key(&one, &two), minutes);
+        }
+    }
+
+    /// Load a line-schedule data file into the T. Each line is
+    /// "line,first_train,last_train,headway_minutes", where first_train
+    /// and last_train are 24-hour clock times of the form "HH:MM"; a
+    /// last_train earlier than first_train means the line runs past
+    /// midnight into the next day.
+    fn read_schedules(&mut self, path: &str) {
+        let mut reader = match open_file(path) {
+            Ok(r) => r,
+            Err(..) => return
+        };
+        while let Some(line) = reader.read_line().ok() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let mut parts = trimmed.split(',');
+            let line_name = parts.next().unwrap().trim().to_string();
+            let first_minutes = parse_clock(parts.next().unwrap().trim());
+            let last_minutes = parse_clock(parts.next().unwrap().trim());
+            let headway_minutes = parts.next().unwrap().trim().parse()
+                .expect("headway must be a non-negative integer");
+            self.schedules.insert(line_name, LineSchedule {
+                first_minutes: first_minutes,
+                last_minutes: last_minutes,
+                headway_minutes: headway_minutes
+            });
+        }
     }
 
     /// Rebuilds the graph from source data, taking into account
-    /// the current disabled station list
+    /// the current disabled station list, and swaps it in as the new
+    /// current snapshot in one step. Built entirely off to the side --
+    /// nothing here touches the snapshot a concurrent reader might
+    /// still be holding an Arc clone of -- so replace_snapshot only
+    /// ever has to overwrite the pointer.
     fn rebuild_graph(&mut self) {
-        self.stations = HashMap::new();
-        self.graph = LabeledGraph::new();
-        self.inbound_connections = HashSet::new();
-        self.rebuild_lines();
-        self.rebuild_connections();
-        self.add_unbiased_nodes();
+        let mut graph = LabeledGraph::new();
+        let mut stations = HashMap::new();
+        let mut inbound_connections = HashSet::new();
+        self.rebuild_lines(&mut graph, &mut stations);
+        self.rebuild_connections(&mut graph, &stations, &mut inbound_connections);
+        self.rebuild_walking_connections(&mut graph, &stations);
+        add_unbiased_nodes(&mut graph, &mut stations);
+        // bump the generation so every find_path result cached against the
+        // old graph stops matching the cache key, instead of having to walk
+        // and evict them here
+        let generation = self.current_snapshot().generation + 1;
+        let all_pairs = if self.precompute_all_pairs {
+            Some(compute_all_pairs(&graph))
+        } else {
+            None
+        };
+        self.replace_snapshot(Snapshot {
+            graph: graph,
+            stations: stations,
+            inbound_connections: inbound_connections,
+            all_pairs: all_pairs,
+            generation: generation
+        });
     }
 
     /// Reconstruct the lines of the T (red, blue, green, orange)
     /// Helper function for rebuild_graph
-    fn rebuild_lines(&mut self) {
+    fn rebuild_lines(&self, graph: &mut LabeledGraph, stations: &mut HashMap<String, Vec<Node>>) {
         for (rail_line, station_vec) in self.source_data.iter() {
+            let travel_times = self.travel_times.get(rail_line).unwrap();
             let mut prev_node: Option<Node> = None;
-            for station_name in station_vec.iter() {
+            for (i, station_name) in station_vec.iter().enumerate() {
                 // Don't add disabled stations
                 if self.disabled.contains(station_name) {
                     continue;
@@ -227,20 +830,27 @@ impl<'a> T<'a> {
                 };
 
                 // If it's not already in the list of stations, add it
-                if !self.stations.contains_key(station_name) {
-                    self.stations.insert(station_name.clone(), Vec::new());
+                if !stations.contains_key(station_name) {
+                    stations.insert(station_name.clone(), Vec::new());
                 }
 
                 // Connect node instances for different lines at the same station
-                // using the correct transfer cost
-                let mut node_vec = self.stations.get_mut(station_name).unwrap();
+                // using the correct transfer cost, taking the more expensive of the
+                // two lines' modes (e.g. transferring between a subway and a
+                // commuter rail line costs the commuter rail transfer, not the
+                // cheaper subway one)
+                let mut node_vec = stations.get_mut(station_name).unwrap();
                 for existing_node in node_vec.iter() {
-                    self.graph.add_edge(existing_node, &this_node, TRANSFER_COST, false);
+                    let cost = max(mode_transfer_cost(&self.lines_metadata, self.transfer_cost, &existing_node.line),
+                                    mode_transfer_cost(&self.lines_metadata, self.transfer_cost, &this_node.line));
+                    graph.add_edge(existing_node, &this_node, cost, false);
                 }
                 node_vec.push(this_node.clone());
                 match prev_node {
                     Some(n) => {
-                        self.graph.add_edge(&n, &this_node, None, false);
+                        if !self.disabled_segments.contains(&segment_key(&n.station, &this_node.station)) {
+                            graph.add_edge(&n, &this_node, travel_times[i], false);
+                        }
                     },
                     None => {}
                 };
@@ -251,7 +861,13 @@ impl<'a> T<'a> {
 
     /// Rebuild the connections between lines of a particular color
     /// Necessary for the green and red lines
-    fn rebuild_connections(&mut self) {
+    /// Always uses the flat transfer_cost rather than mode_transfer_cost:
+    /// every connection in connections.dat links branch/trunk subway
+    /// lines of the same mode, so there's no mode-specific cost to pick
+    /// between here the way rebuild_lines has to for same-station
+    /// transfers between differently-moded lines.
+    fn rebuild_connections(&self, graph: &mut LabeledGraph, stations: &HashMap<String, Vec<Node>>,
+                           inbound_connections: &mut HashSet<(String, String)>) {
         for &(ref line_one_name, ref line_two_name, ref fallback) in self.connections.iter() {
             // Find the first non-disabled station in line 1
             let line_one = self.source_data.get(line_one_name).unwrap();
@@ -272,7 +888,7 @@ impl<'a> T<'a> {
                 !self.disabled.contains(*station)
             }).next() {
                 Some(s) => {
-                    self.inbound_connections.insert((line_one_name.clone(), line_two_name.clone()));
+                    inbound_connections.insert((line_one_name.clone(), line_two_name.clone()));
                     s
                 },
                 None => {
@@ -290,7 +906,7 @@ impl<'a> T<'a> {
                         !self.disabled.contains(*station)
                     }).next() {
                         Some(s) => {
-                            self.inbound_connections.insert((line_one_name.clone(), fback.clone()));
+                            inbound_connections.insert((line_one_name.clone(), fback.clone()));
                             s
                         }
                         None => { return; }
@@ -300,8 +916,8 @@ impl<'a> T<'a> {
 
             // For the case where we must connect directly to a transfer
             // station due to excess disabling
-            let node_vec_one = self.stations.get(station_one).unwrap();
-            let node_vec_two = self.stations.get(station_two).unwrap();
+            let node_vec_one = stations.get(station_one).unwrap();
+            let node_vec_two = stations.get(station_two).unwrap();
             assert!(!node_vec_one.is_empty());
             assert!(!node_vec_two.is_empty());
             for node_one in node_vec_one.iter() {
@@ -309,72 +925,744 @@ impl<'a> T<'a> {
                     // doesn't matter that we pay the transfer cost here in all cases,
                     // because there is no alternative path to a branch line that avoids
                     // this terminal station connection to the main line
-                    self.graph.add_edge(node_one, node_two, TRANSFER_COST, false);
+                    graph.add_edge(node_one, node_two, self.transfer_cost, false);
                 }
             }
         }
     }
 
-    /// Creates the unbiased nodes used for starting or ending a trip
-    /// at a transfer station.
-    pub fn add_unbiased_nodes(&mut self) {
-        for (station, ref mut node_vec) in self.stations.iter_mut() {
-            if node_vec.len() > 1 {
-                let start_node = Node {
-                    station: station.clone(),
-                    line: START_NODE_LABEL.to_string()
-                };
-                let end_node = Node {
-                    station: station.clone(),
-                    line: END_NODE_LABEL.to_string()
-                };
-                for node in node_vec.iter() {
-                    self.graph.add_edge(&start_node, node, NO_COST, true);
-                    self.graph.add_edge(node, &end_node, NO_COST, true);
+    /// Connect every node of one station to every node of the other for
+    /// each walking transfer on file, same as rebuild_connections does
+    /// for branch/trunk line connections, weighted by the walk's time in
+    /// minutes. A walking transfer to or from a station that's entirely
+    /// disabled (and so has no nodes) is simply skipped, like any other
+    /// connection in that situation.
+    fn rebuild_walking_connections(&self, graph: &mut LabeledGraph, stations: &HashMap<String, Vec<Node>>) {
+        for (&(ref station_one, ref station_two), &minutes) in self.walking_connections.iter() {
+            let node_vec_one = match stations.get(station_one) {
+                Some(v) => v,
+                None => continue
+            };
+            let node_vec_two = match stations.get(station_two) {
+                Some(v) => v,
+                None => continue
+            };
+            for node_one in node_vec_one.iter() {
+                for node_two in node_vec_two.iter() {
+                    graph.add_edge(node_one, node_two, minutes, false);
                 }
-                node_vec.push(start_node);
-                node_vec.push(end_node);
             }
         }
     }
 
     /// Find a path from the start to the destination
     pub fn find_path(&self, start: &str, dest: &str) -> TQueryResult {
+        let snap = self.current_snapshot();
+        let start = return_some_vec!(self.disambiguate_station(start), DisambiguateStart, NoSuchStart);
+        let dest = return_some_vec!(self.disambiguate_station(dest), DisambiguateDestination, NoSuchDest);
+        let start_node = get_node_from_vec!(snap.stations, start, START_NODE_POS, DisabledStart);
+        let dest_node = get_node_from_vec!(snap.stations, dest, END_NODE_POS, DisabledDest);
+
+        if let Some(ref table) = snap.all_pairs {
+            return match table.get(&(start_node.clone(), dest_node.clone())) {
+                Some(&(ref path, minutes)) => {
+                    let steps = self.interpret_path(path.clone(), &snap.inbound_connections);
+                    let fare = self.calculate_fare(&steps);
+                    TOk(steps, minutes, fare)
+                },
+                None => NoSuchPath
+            };
+        }
+
+        let key = (start_node.clone(), dest_node.clone(), snap.generation);
+        if let Some(cached) = self.path_cache.borrow_mut().get(&key) {
+            return cached;
+        }
+
+        let result = match snap.graph.find_shortest_path_with_cost(start_node, dest_node) {
+            Some((path, minutes)) => {
+                let steps = self.interpret_path(path, &snap.inbound_connections);
+                let fare = self.calculate_fare(&steps);
+                TOk(steps, minutes, fare)
+            },
+            None => NoSuchPath
+        };
+        self.path_cache.borrow_mut().insert(key, result.clone());
+        result
+    }
+
+    /// Find a path from the start to the destination that doesn't pass
+    /// through any of the given stations. Unlike `disable_station`, this
+    /// exclusion is scoped to this one query and never touches the
+    /// shared `disabled` set. Station names that don't resolve to a
+    /// single known station are ignored rather than excluded.
+    pub fn find_path_avoiding(&self, start: &str, dest: &str, avoid: Vec<&str>) -> TQueryResult {
+        let snap = self.current_snapshot();
         let start = return_some_vec!(self.disambiguate_station(start), DisambiguateStart, NoSuchStart);
         let dest = return_some_vec!(self.disambiguate_station(dest), DisambiguateDestination, NoSuchDest);
-        let start_node = get_node_from_vec!(self, start, START_NODE_POS, DisabledStart);
-        let dest_node = get_node_from_vec!(self, dest, END_NODE_POS, DisabledDest);
-        match self.graph.find_shortest_path(start_node, dest_node) {
-            Some(path) => {
-                TOk(self.interpret_path(path))
+        let start_node = get_node_from_vec!(snap.stations, start, START_NODE_POS, DisabledStart);
+        let dest_node = get_node_from_vec!(snap.stations, dest, END_NODE_POS, DisabledDest);
+
+        let mut working = snap.graph.clone();
+        for station in avoid.iter() {
+            if let DisambiguationResult::Station(name) = self.disambiguate_station(station) {
+                if let Some(node_vec) = snap.stations.get(&name) {
+                    for node in node_vec.iter() {
+                        working.remove_node(node);
+                    }
+                }
+            }
+        }
+
+        match working.find_shortest_path_with_cost(start_node, dest_node) {
+            Some((path, minutes)) => {
+                let steps = self.interpret_path(path, &snap.inbound_connections);
+                let fare = self.calculate_fare(&steps);
+                TOk(steps, minutes, fare)
             },
             None => NoSuchPath
         }
     }
 
-    /// Modify the given station to set it to be enabled/disabled
+    /// Find a path from the start to the destination for a trip leaving
+    /// at the given time of day (in minutes since midnight). If the first
+    /// line of the route isn't running yet at that time, the trip is
+    /// rejected with `LineNotRunning` rather than silently rerouted
+    /// around the down line; a caller that wants a workaround can retry
+    /// with `find_path_avoiding` once it knows which line is down.
+    /// Otherwise, the expected wait for that first departure is folded
+    /// into the returned travel time.
+    pub fn find_path_at(&self, start: &str, dest: &str, departure_minutes: usize) -> TQueryResult {
+        match self.find_path(start, dest) {
+            TOk(steps, minutes, fare) => {
+                match first_line(&steps) {
+                    Some(line) if !self.line_running_at(line.as_slice(), departure_minutes) => {
+                        LineNotRunning(line.clone(), self.wait_for_line(line.as_slice(), departure_minutes))
+                    },
+                    Some(line) => {
+                        let wait = self.wait_for_line(line.as_slice(), departure_minutes);
+                        TOk(steps, minutes + wait, fare)
+                    },
+                    None => TOk(steps, minutes, fare)
+                }
+            },
+            other => other
+        }
+    }
+
+    /// Chain find_path leg by leg through an ordered list of stops, each
+    /// paired with the dwell time in minutes to spend there before
+    /// boarding the next leg (the last stop's dwell is ignored). The
+    /// first leg to fail -- an ambiguous or unknown station, a disabled
+    /// one, or no path -- is returned as-is, the same as find_path_at
+    /// propagates a leg's failure, rather than trying the remaining legs.
+    /// A plan needs at least two stops; fewer than that has no leg to
+    /// find a path for, so it's reported as NoSuchPath.
+    pub fn find_planned_trip(&self, stops: Vec<(&str, usize)>) -> TQueryResult {
+        if stops.len() < 2 {
+            return NoSuchPath;
+        }
+        let mut legs = Vec::new();
+        let mut total_minutes = 0;
+        let mut total_fare = 0.0;
+        for i in 0..stops.len() - 1 {
+            let (start, dwell) = stops[i];
+            let (dest, _) = stops[i + 1];
+            match self.find_path(start, dest) {
+                TOk(steps, minutes, fare) => {
+                    total_minutes += minutes + dwell;
+                    total_fare += fare;
+                    legs.push((steps, minutes, fare));
+                },
+                other => return other
+            }
+        }
+        TPlan(legs, total_minutes, total_fare)
+    }
+
+    /// Return whether the given line is running at the given time of day
+    /// (in minutes since midnight). A line with no schedule on file is
+    /// assumed to run around the clock.
+    fn line_running_at(&self, line: &str, minutes: usize) -> bool {
+        match self.schedules.get(line) {
+            None => true,
+            Some(schedule) => {
+                if schedule.first_minutes <= schedule.last_minutes {
+                    minutes >= schedule.first_minutes && minutes <= schedule.last_minutes
+                } else {
+                    // service runs past midnight into the next day
+                    minutes >= schedule.first_minutes || minutes <= schedule.last_minutes
+                }
+            }
+        }
+    }
+
+    /// Return the expected number of minutes from the given time of day
+    /// until the given line's next departure: half its headway if it's
+    /// already running (the expected wait for a rider arriving at a
+    /// random moment), or the time until its service window opens if
+    /// it isn't. A line with no schedule on file has no wait.
+    fn wait_for_line(&self, line: &str, minutes: usize) -> usize {
+        match self.schedules.get(line) {
+            None => NO_COST,
+            Some(schedule) => {
+                if self.line_running_at(line, minutes) {
+                    schedule.headway_minutes / 2
+                } else if minutes < schedule.first_minutes {
+                    schedule.first_minutes - minutes
+                } else {
+                    (MINUTES_PER_DAY - minutes) + schedule.first_minutes
+                }
+            }
+        }
+    }
+
+    /// Find a path from the start to the destination, weighting
+    /// transfers much more heavily than the default so the cheapest
+    /// route favors fewer line changes, even at the cost of extra stops.
+    /// This temporarily rebuilds the graph with a raised transfer cost
+    /// for the query, then restores the normal one afterward.
+    pub fn find_path_preferring_fewer_transfers(&mut self, start: &str, dest: &str) -> TQueryResult {
+        let normal_cost = self.transfer_cost;
+        self.transfer_cost = normal_cost * PREFER_FEWER_TRANSFERS_MULTIPLIER;
+        self.rebuild_graph();
+        let result = self.find_path(start, dest);
+        self.transfer_cost = normal_cost;
+        self.rebuild_graph();
+        result
+    }
+
+    /// Find up to `k` distinct routes from the start to the destination,
+    /// cheapest first. Like `find_path`, but wraps the results in
+    /// `TOkMultiple` instead of `TOk` so riders can compare alternatives.
+    pub fn find_paths(&self, start: &str, dest: &str, k: usize) -> TQueryResult {
+        let snap = self.current_snapshot();
+        let start = return_some_vec!(self.disambiguate_station(start), DisambiguateStart, NoSuchStart);
+        let dest = return_some_vec!(self.disambiguate_station(dest), DisambiguateDestination, NoSuchDest);
+        let start_node = get_node_from_vec!(snap.stations, start, START_NODE_POS, DisabledStart);
+        let dest_node = get_node_from_vec!(snap.stations, dest, END_NODE_POS, DisabledDest);
+        let results = snap.graph.find_alternative_paths_with_cost(start_node, dest_node, k);
+        if results.is_empty() {
+            NoSuchPath
+        } else {
+            TOkMultiple(results.into_iter()
+                .map(|(path, minutes)| {
+                    let steps = self.interpret_path(path, &snap.inbound_connections);
+                    let fare = self.calculate_fare(&steps);
+                    (steps, minutes, fare)
+                }).collect())
+        }
+    }
+
+    /// Find the Pareto-optimal itineraries from the start to the
+    /// destination across three criteria: stops, transfers, and travel
+    /// time. An itinerary is non-dominated if no other itinerary is at
+    /// least as good on every criterion and strictly better on at least
+    /// one, so the result can surface trade-offs like "1 more stop but 1
+    /// fewer transfer" that a single additive cost can't express.
+    /// Candidates are drawn from `PARETO_CANDIDATE_COUNT` alternative
+    /// routes rather than every possible path, so an itinerary that
+    /// doesn't show up among those candidates can't appear here even if
+    /// it would otherwise be non-dominated.
+    pub fn find_pareto_paths(&self, start: &str, dest: &str) -> TQueryResult {
+        let snap = self.current_snapshot();
+        let start = return_some_vec!(self.disambiguate_station(start), DisambiguateStart, NoSuchStart);
+        let dest = return_some_vec!(self.disambiguate_station(dest), DisambiguateDestination, NoSuchDest);
+        let start_node = get_node_from_vec!(snap.stations, start, START_NODE_POS, DisabledStart);
+        let dest_node = get_node_from_vec!(snap.stations, dest, END_NODE_POS, DisabledDest);
+        let results = snap.graph.find_alternative_paths_with_cost(start_node, dest_node, PARETO_CANDIDATE_COUNT);
+        if results.is_empty() {
+            return NoSuchPath;
+        }
+        let candidates: Vec<(Vec<TStep>, usize, usize, usize, f64)> = results.into_iter()
+            .map(|(path, minutes)| {
+                let steps = self.interpret_path(path, &snap.inbound_connections);
+                let fare = self.calculate_fare(&steps);
+                let (stops, transfers) = itinerary_metrics(&steps);
+                (steps, stops, transfers, minutes, fare)
+            }).collect();
+        let metrics: Vec<(usize, usize, usize)> = candidates.iter()
+            .map(|&(_, stops, transfers, minutes, _)| (stops, transfers, minutes)).collect();
+        TOkPareto(candidates.into_iter().enumerate()
+            .filter(|&(i, _)| !dominated(metrics[i], metrics.as_slice(), i))
+            .map(|(_, candidate)| candidate)
+            .collect())
+    }
+
+    /// Modify the given station to set it to be enabled/disabled. Lines
+    /// that never feed into a cross-line connection (see `connections`)
+    /// are patched in place instead of triggering a full rebuild_graph,
+    /// since toggling one station on those lines can only ever add or
+    /// remove edges touching that one station's nodes -- O(degree)
+    /// instead of O(network). Lines that do feed a connection still pay
+    /// for a full rebuild: rebuild_connections picks the first/last
+    /// non-disabled station on those lines, and correctly patching that
+    /// search in place would mean duplicating its endpoint-finding logic
+    /// for what's already a comparatively rare, cheap case.
     fn modify_station(&mut self, station: &str, enable: bool) -> TOperationResult {
         let station_string = return_some_vec!(self.disambiguate_station(station), DisambiguateOp, NoSuchStationOp);
         if enable ^ self.disabled.contains(&station_string) {
             return Successful;
         }
+        let connection_relevant = self.station_is_connection_relevant(&station_string);
         if enable {
             self.disabled.remove(&station_string);
         } else {
-            self.disabled.insert(station_string);
+            self.disabled.insert(station_string.clone());
+        }
+        if connection_relevant {
+            self.rebuild_graph();
+        } else {
+            let mut snap = (*self.current_snapshot()).clone();
+            if enable {
+                self.incrementally_enable_station(&mut snap, &station_string);
+            } else {
+                self.incrementally_disable_station(&mut snap, &station_string);
+            }
+            self.finish_incremental_update(snap);
         }
-        self.rebuild_graph();
         Successful
     }
 
-    /// Enable the given station. This function is a wrapper for modify_station
+    /// Whether toggling `station_name` could change a cross-line
+    /// connection: true if any line the station runs on is one end (or
+    /// the fallback) of an entry in `connections`. Checked against
+    /// `source_data` rather than `stations`, so it gives the same answer
+    /// whether the station is currently enabled or disabled.
+    fn station_is_connection_relevant(&self, station_name: &str) -> bool {
+        let lines: Vec<&String> = self.source_data.iter()
+            .filter(|&(_, station_vec)| station_vec.iter().any(|s| s == station_name))
+            .map(|(line, _)| line)
+            .collect();
+        self.connections.iter().any(|&(ref one, ref two, ref fallback)| {
+            lines.iter().any(|&line| {
+                line == one || line == two || fallback.as_ref() == Some(line)
+            })
+        })
+    }
+
+    /// Bump the generation and, if enabled, refresh the all-pairs table --
+    /// the same graph-invalidation bookkeeping rebuild_graph does -- then
+    /// install `snap` as the new current snapshot. For callers that patch
+    /// a clone of the current snapshot in place instead of rebuilding
+    /// from scratch.
+    fn finish_incremental_update(&mut self, mut snap: Snapshot) {
+        snap.generation += 1;
+        snap.all_pairs = if self.precompute_all_pairs {
+            Some(compute_all_pairs(&snap.graph))
+        } else {
+            None
+        };
+        self.replace_snapshot(snap);
+    }
+
+    /// Remove `station_name`'s nodes from `snap`'s graph in place: every
+    /// node the station has across every line, plus its unbiased
+    /// start/end nodes if it had any. For each line the station was on,
+    /// bridges the nearest remaining enabled stations on either side
+    /// directly together, using the same travel time rebuild_lines would
+    /// have given that edge, so the rest of the line doesn't just become
+    /// unreachable across the gap.
+    fn incrementally_disable_station(&self, snap: &mut Snapshot, station_name: &str) {
+        let node_vec = match snap.stations.remove(station_name) {
+            Some(v) => v,
+            None => return
+        };
+        for node in node_vec.iter() {
+            snap.graph.remove_node(node);
+        }
+        for node in node_vec.iter() {
+            if node.line.as_slice() == START_NODE_LABEL || node.line.as_slice() == END_NODE_LABEL {
+                continue;
+            }
+            self.bridge_line_gap(snap, &node.line, station_name);
+        }
+    }
+
+    /// After `station_name`'s node on `line` is gone, connect its
+    /// nearest remaining enabled neighbors on either side directly to
+    /// each other, if both exist and the segment between them isn't
+    /// itself disabled.
+    fn bridge_line_gap(&self, snap: &mut Snapshot, line: &str, station_name: &str) {
+        let (prev, next) = self.line_neighbors(line, station_name);
+        if let (Some(prev_station), Some(next_station)) = (prev, next) {
+            if self.disabled_segments.contains(&segment_key(&prev_station, &next_station)) {
+                return;
+            }
+            let next_index = self.source_data.get(line).unwrap().iter()
+                .position(|s| *s == next_station).unwrap();
+            let weight = self.travel_times.get(line).unwrap()[next_index];
+            let prev_node = Node { station: prev_station, line: line.to_string() };
+            let next_node = Node { station: next_station, line: line.to_string() };
+            snap.graph.add_edge(&prev_node, &next_node, weight, false);
+        }
+    }
+
+    /// The nearest enabled station before and after `station_name` on
+    /// `line`'s station order, skipping over any disabled stations in
+    /// between. None on either side if `station_name` is the first or
+    /// last enabled station on the line, or if `line` doesn't run
+    /// through `station_name` at all.
+    fn line_neighbors(&self, line: &str, station_name: &str) -> (Option<String>, Option<String>) {
+        let station_vec = match self.source_data.get(line) {
+            Some(v) => v,
+            None => return (None, None)
+        };
+        let index = match station_vec.iter().position(|s| s == station_name) {
+            Some(i) => i,
+            None => return (None, None)
+        };
+        let prev = station_vec[..index].iter().rev()
+            .find(|s| !self.disabled.contains(*s)).cloned();
+        let next = station_vec[index + 1..].iter()
+            .find(|s| !self.disabled.contains(*s)).cloned();
+        (prev, next)
+    }
+
+    /// Add `station_name`'s nodes back into `snap`'s graph in place: one
+    /// node per line the station runs on, reconnected to its nearest
+    /// enabled neighbors on each line (removing the gap-bridging edge
+    /// incrementally_disable_station would have added, if any), plus
+    /// transfer edges to the station's other lines and unbiased
+    /// start/end nodes if it has more than one line.
+    fn incrementally_enable_station(&self, snap: &mut Snapshot, station_name: &str) {
+        let mut node_vec = Vec::new();
+        for (line, station_vec) in self.source_data.iter() {
+            if station_vec.iter().any(|s| s == station_name) {
+                node_vec.push(Node { station: station_name.to_string(), line: line.clone() });
+            }
+        }
+
+        for node in node_vec.iter() {
+            self.remove_bridge_if_present(snap, &node.line, station_name);
+            self.connect_line_neighbors(snap, &node.line, station_name);
+        }
+        for i in 0..node_vec.len() {
+            for j in 0..i {
+                let cost = max(mode_transfer_cost(&self.lines_metadata, self.transfer_cost, &node_vec[i].line),
+                                mode_transfer_cost(&self.lines_metadata, self.transfer_cost, &node_vec[j].line));
+                snap.graph.add_edge(&node_vec[i], &node_vec[j], cost, false);
+            }
+        }
+
+        snap.stations.insert(station_name.to_string(), node_vec);
+        if snap.stations.get(station_name).unwrap().len() > 1 {
+            let start_node = Node { station: station_name.to_string(), line: START_NODE_LABEL.to_string() };
+            let end_node = Node { station: station_name.to_string(), line: END_NODE_LABEL.to_string() };
+            for node in snap.stations.get(station_name).unwrap().clone().iter() {
+                snap.graph.add_edge(&start_node, node, NO_COST, true);
+                snap.graph.add_edge(node, &end_node, NO_COST, true);
+            }
+            let node_vec = snap.stations.get_mut(station_name).unwrap();
+            node_vec.push(start_node);
+            node_vec.push(end_node);
+        }
+    }
+
+    /// If `station_name`'s neighbors on `line` were bridged directly
+    /// together while it was disabled, remove that bridge so it doesn't
+    /// linger as a stale shortcut once the station's own edges are back.
+    fn remove_bridge_if_present(&self, snap: &mut Snapshot, line: &str, station_name: &str) {
+        let (prev, next) = self.line_neighbors(line, station_name);
+        if let (Some(prev_station), Some(next_station)) = (prev, next) {
+            let prev_node = Node { station: prev_station, line: line.to_string() };
+            let next_node = Node { station: next_station, line: line.to_string() };
+            snap.graph.remove_edge(&prev_node, &next_node);
+            snap.graph.remove_edge(&next_node, &prev_node);
+        }
+    }
+
+    /// Connect `station_name`'s node on `line` to its nearest enabled
+    /// neighbors in each direction, the same edges rebuild_lines would
+    /// add for it.
+    fn connect_line_neighbors(&self, snap: &mut Snapshot, line: &str, station_name: &str) {
+        let (prev, next) = self.line_neighbors(line, station_name);
+        let this_node = Node { station: station_name.to_string(), line: line.to_string() };
+
+        if let Some(prev_station) = prev {
+            if !self.disabled_segments.contains(&segment_key(&prev_station, station_name)) {
+                let this_index = self.source_data.get(line).unwrap().iter()
+                    .position(|s| s == station_name).unwrap();
+                let weight = self.travel_times.get(line).unwrap()[this_index];
+                let prev_node = Node { station: prev_station, line: line.to_string() };
+                snap.graph.add_edge(&prev_node, &this_node, weight, false);
+            }
+        }
+        if let Some(next_station) = next {
+            if !self.disabled_segments.contains(&segment_key(station_name, &next_station)) {
+                let next_index = self.source_data.get(line).unwrap().iter()
+                    .position(|s| *s == next_station).unwrap();
+                let weight = self.travel_times.get(line).unwrap()[next_index];
+                let next_node = Node { station: next_station, line: line.to_string() };
+                snap.graph.add_edge(&this_node, &next_node, weight, false);
+            }
+        }
+    }
+
+    /// Enable the given station, clearing any scheduled re-enable timer
+    /// set for it by disable_station_for/disable_station_until, since
+    /// it's back early. This function is a wrapper for modify_station
     pub fn enable_station(&mut self, station: &str) -> TOperationResult {
-        self.modify_station(station, true)
+        let resolved = return_some_vec!(self.disambiguate_station(station), DisambiguateOp, NoSuchStationOp);
+        let result = self.modify_station(&resolved, true);
+        if let Successful = result {
+            self.scheduled_disables.remove(&resolved);
+        }
+        result
     }
 
-    /// Disable the given station. This function is a wrapper for modify_station
+    /// Disable the given station indefinitely, clearing any scheduled
+    /// re-enable timer set for it by disable_station_for/
+    /// disable_station_until. This function is a wrapper for modify_station
     pub fn disable_station(&mut self, station: &str) -> TOperationResult {
-        self.modify_station(station, false)
+        let resolved = return_some_vec!(self.disambiguate_station(station), DisambiguateOp, NoSuchStationOp);
+        let result = self.modify_station(&resolved, false);
+        if let Successful = result {
+            self.scheduled_disables.remove(&resolved);
+        }
+        result
+    }
+
+    /// Disable the given station for `seconds` seconds from now. This
+    /// function is a wrapper for modify_station, the same as
+    /// apply_alert, but records an expiry instead of an alert
+    /// description.
+    pub fn disable_station_for(&mut self, station: &str, seconds: i64) -> TOperationResult {
+        let expiry = time::get_time().sec + seconds;
+        self.disable_station_until_timestamp(station, expiry)
+    }
+
+    /// Disable the given station until the next occurrence of
+    /// `clock_minutes` (minutes since midnight) -- today if that time
+    /// hasn't happened yet, tomorrow if it already has.
+    pub fn disable_station_until(&mut self, station: &str, clock_minutes: usize) -> TOperationResult {
+        let expiry = next_occurrence_of(clock_minutes);
+        self.disable_station_until_timestamp(station, expiry)
+    }
+
+    /// Shared implementation for disable_station_for/disable_station_until:
+    /// disable the station and record the unix timestamp it should be
+    /// automatically re-enabled at.
+    fn disable_station_until_timestamp(&mut self, station: &str, expiry: i64) -> TOperationResult {
+        let resolved = return_some_vec!(self.disambiguate_station(station), DisambiguateOp, NoSuchStationOp);
+        let result = self.modify_station(&resolved, false);
+        if let Successful = result {
+            self.scheduled_disables.insert(resolved, expiry);
+        }
+        result
+    }
+
+    /// Re-enable every station whose scheduled disable has expired as
+    /// of now. Called periodically by spawn_scheduled_disable_expirer.
+    pub fn expire_scheduled_disables(&mut self) {
+        let now = time::get_time().sec;
+        let expired: Vec<String> = self.scheduled_disables.iter()
+            .filter(|&(_, &expiry)| expiry <= now)
+            .map(|(station, _)| station.clone())
+            .collect();
+        for station in expired.into_iter() {
+            self.modify_station(&station, true);
+            self.scheduled_disables.remove(&station);
+        }
+    }
+
+    /// The stations currently under a scheduled disable, each paired
+    /// with the seconds remaining until they're automatically
+    /// re-enabled (zero if past due but not yet swept by
+    /// expire_scheduled_disables), sorted by station name.
+    pub fn scheduled_disables_remaining(&self) -> Vec<(String, i64)> {
+        let now = time::get_time().sec;
+        let mut remaining: Vec<(String, i64)> = self.scheduled_disables.iter()
+            .map(|(station, &expiry)| (station.clone(), max(0, expiry - now)))
+            .collect();
+        remaining.sort();
+        remaining
+    }
+
+    /// Modify the segment between the two given stations to set it
+    /// to be enabled/disabled. Unlike disabling a station, both
+    /// stations stay usable from other directions; only the direct
+    /// stretch of track between them is removed.
+    fn modify_segment(&mut self, a: &str, b: &str, enable: bool) -> TOperationResult {
+        let a_name = return_some_vec!(self.disambiguate_station(a), DisambiguateOp, NoSuchStationOp);
+        let b_name = return_some_vec!(self.disambiguate_station(b), DisambiguateOp, NoSuchStationOp);
+        let key = segment_key(&a_name, &b_name);
+        if enable ^ self.disabled_segments.contains(&key) {
+            return Successful;
+        }
+        if enable {
+            self.disabled_segments.remove(&key);
+        } else {
+            self.disabled_segments.insert(key);
+        }
+        self.rebuild_graph();
+        Successful
+    }
+
+    /// Enable the segment between the two given stations. This
+    /// function is a wrapper for modify_segment
+    pub fn enable_segment(&mut self, a: &str, b: &str) -> TOperationResult {
+        self.modify_segment(a, b, true)
+    }
+
+    /// Disable the segment between the two given stations. This
+    /// function is a wrapper for modify_segment
+    pub fn disable_segment(&mut self, a: &str, b: &str) -> TOperationResult {
+        self.modify_segment(a, b, false)
+    }
+
+    /// Return the sorted list of every line name loaded from the data
+    /// files, annotated with its mode (e.g. "Silver Line (bus)") for
+    /// lines with a line_metadata.dat entry; lines with no entry (every
+    /// line predating line_metadata.dat) are listed by name alone.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.source_data.keys().map(|line| {
+            match self.lines_metadata.get(line) {
+                Some(metadata) => format!("{} ({})", line, metadata.mode),
+                None => line.clone()
+            }
+        }).collect();
+        lines.sort();
+        lines
+    }
+
+    /// Return the stations on the given line, in the order they appear on
+    /// that line, or None if no such line was loaded. If no line is given,
+    /// return every station in the network, sorted alphabetically.
+    pub fn stations(&self, line: Option<&str>) -> Option<Vec<String>> {
+        match line {
+            Some(l) => self.source_data.get(l).map(|stations| stations.clone()),
+            None => {
+                let mut all: HashSet<String> = HashSet::new();
+                for stations in self.source_data.values() {
+                    for station in stations.iter() {
+                        all.insert(station.clone());
+                    }
+                }
+                let mut all: Vec<String> = all.into_iter().collect();
+                all.sort();
+                Some(all)
+            }
+        }
+    }
+
+    /// Return the sorted list of currently disabled stations
+    pub fn disabled_stations(&self) -> Vec<String> {
+        let mut disabled: Vec<String> = self.disabled.iter().cloned().collect();
+        disabled.sort();
+        disabled
+    }
+
+    /// Return the sorted list of currently disabled segments, each as a
+    /// pair of the two stations at either end of the segment
+    pub fn disabled_segments(&self) -> Vec<(String, String)> {
+        let mut disabled: Vec<(String, String)> = self.disabled_segments.iter().cloned().collect();
+        disabled.sort();
+        disabled
+    }
+
+    /// Write the current network graph to `path` in Graphviz dot format,
+    /// one node per (station, line) graph node and one edge per
+    /// connection. Disabled stations never make it into the graph in the
+    /// first place (see rebuild_lines), so the export omits them for
+    /// free rather than needing to filter or grey them out separately.
+    /// Edges between different lines' nodes -- same-station transfers
+    /// and the branch-to-trunk connections in rebuild_connections -- are
+    /// drawn dashed and grey, so they stand out from the solid ride
+    /// edges within a single line.
+    pub fn export_dot(&self, path: &str) -> IoResult<()> {
+        let snap = self.current_snapshot();
+        let mut file = try!(File::create(&Path::new(path)));
+        try!(write!(file, "digraph t {{\n"));
+        for node in snap.graph.labels().iter() {
+            try!(write!(file, "  \"{}\" [label=\"{}\\n{}\"];\n", dot_node_id(node), node.station, node.line));
+        }
+        for node in snap.graph.labels().iter() {
+            for (target, weight) in snap.graph.edges_from(node).into_iter() {
+                if node.line == target.line {
+                    try!(write!(file, "  \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                                dot_node_id(node), dot_node_id(&target), weight));
+                } else {
+                    try!(write!(file, "  \"{}\" -> \"{}\" [label=\"{}\", style=\"dashed\", color=\"gray\"];\n",
+                                dot_node_id(node), dot_node_id(&target), weight));
+                }
+            }
+        }
+        write!(file, "}}\n")
+    }
+
+    /// The up to `n` stations with known coordinates closest to (`lat`,
+    /// `lon`) by great-circle distance, nearest first, each paired with
+    /// its distance in miles. Stations with no entry in the coordinates
+    /// data file can't be measured and are left out rather than treated
+    /// as infinitely close or far.
+    pub fn nearest_stations(&self, lat: f64, lon: f64, n: usize) -> Vec<(String, f64)> {
+        let mut distances: Vec<(String, f64)> = self.coordinates.iter()
+            .map(|(station, &(station_lat, station_lon))| {
+                (station.clone(), haversine_miles(lat, lon, station_lat, station_lon))
+            })
+            .collect();
+        distances.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        distances.truncate(n);
+        distances
+    }
+
+    /// Disable the given station on behalf of a service alert, recording
+    /// the alert's description so it can be surfaced in query responses.
+    /// This function is a wrapper for modify_station
+    pub fn apply_alert(&mut self, station: &str, description: &str) -> TOperationResult {
+        let resolved = return_some_vec!(self.disambiguate_station(station), DisambiguateOp, NoSuchStationOp);
+        let result = self.modify_station(&resolved, false);
+        if let Successful = result {
+            self.alerts.insert(resolved, description.to_string());
+        }
+        result
+    }
+
+    /// Re-enable a station that was previously disabled by a service
+    /// alert, and clear its recorded alert description. This function is
+    /// a wrapper for modify_station
+    pub fn clear_alert(&mut self, station: &str) -> TOperationResult {
+        let resolved = return_some_vec!(self.disambiguate_station(station), DisambiguateOp, NoSuchStationOp);
+        let result = self.modify_station(&resolved, true);
+        if let Successful = result {
+            self.alerts.remove(&resolved);
+        }
+        result
+    }
+
+    /// Return the sorted list of currently active service alerts, each as
+    /// a pair of the affected station and its alert description
+    pub fn active_alerts(&self) -> Vec<(String, String)> {
+        let mut alerts: Vec<(String, String)> = self.alerts.iter()
+            .map(|(station, description)| (station.clone(), description.clone()))
+            .collect();
+        alerts.sort();
+        alerts
+    }
+
+    /// Look up `station`: the lines serving it, whether serving more
+    /// than one line makes it a transfer station, whether it's
+    /// currently disabled, and its nearest enabled neighbor in each
+    /// direction on every line it runs on, for 'info' to print. This
+    /// function is a wrapper for disambiguate_station, the same
+    /// resolution enable_station/disable_station use, but read-only.
+    pub fn station_info(&self, station: &str) -> TInfoResult {
+        let resolved = return_some_vec!(self.disambiguate_station(station), DisambiguateInfo, NoSuchStationInfo);
+        let mut lines: Vec<String> = self.source_data.iter()
+            .filter(|&(_, station_vec)| station_vec.iter().any(|s| s == &resolved))
+            .map(|(line, _)| line.clone())
+            .collect();
+        lines.sort();
+        let adjacent: Vec<(String, Option<String>, Option<String>)> = lines.iter()
+            .map(|line| {
+                let (prev, next) = self.line_neighbors(line, &resolved);
+                (line.clone(), prev, next)
+            })
+            .collect();
+        let transfer = lines.len() > 1;
+        let disabled = self.disabled.contains(&resolved);
+        Info(resolved, lines, transfer, disabled, adjacent)
     }
 
     /// Return a suggested station or list of sorted station suggestions if the
@@ -382,68 +1670,171 @@ impl<'a> T<'a> {
     /// (or set of actual stations)
     ///
     /// Assumption: 'Close but not a complete match' means that the given
-    ///             string is a substring of an actual station
+    ///             string is a substring of an actual station. An exact
+    ///             (case-insensitive) alias match takes priority over
+    ///             substring matching, since it's meant to resolve a
+    ///             shorthand straight to one station even when the
+    ///             shorthand itself also happens to be a substring of
+    ///             some other station's name. If no station's name
+    ///             contains it as a substring at all (e.g. it's
+    ///             misspelled), fall back to ranking every station by
+    ///             edit distance to the given string instead.
     fn disambiguate_station(&self, station: &str) -> DisambiguationResult {
+        if let Some(canonical) = self.aliases.get(&station.to_ascii_lowercase()) {
+            return DisambiguationResult::Station(canonical.clone());
+        }
+        let snap = self.current_snapshot();
         let mut ret_vec = Vec::new();
-        for maybe_match in self.stations.keys().chain(self.disabled.iter()) {
+        for maybe_match in snap.stations.keys().chain(self.disabled.iter()) {
             if maybe_match.contains(station) {
                 ret_vec.push(maybe_match.clone());
             }
         }
         if ret_vec.len() == 1 {
             DisambiguationResult::Station(ret_vec.pop().unwrap())
+        } else if ret_vec.is_empty() {
+            DisambiguationResult::Suggestions(
+                fuzzy::fuzzy_match(station, snap.stations.keys().chain(self.disabled.iter())))
         } else {
             ret_vec.sort();
             DisambiguationResult::Suggestions(ret_vec)
         }
     }
 
-    /// Interpret the path of Nodes as a list of TSteps
-    fn interpret_path(&self, path: Vec<Node>) -> Vec<TStep> {
+    /// Return the terminus to ride toward for a leg of travel made up of
+    /// `nodes`, all on `line`: whichever end of the line's known station
+    /// order the nodes are heading toward. None if `line` has no known
+    /// station order (e.g. a synthetic line with no source_data loaded),
+    /// or if the nodes don't span two different positions on it.
+    fn leg_direction(&self, line: &str, nodes: &[Node]) -> Option<String> {
+        let ordered_stations = match self.source_data.get(line) {
+            Some(stations) => stations,
+            None => return None
+        };
+        let mut positions = nodes.iter()
+            .filter_map(|node| ordered_stations.iter().position(|s| s == &node.station));
+        let first_pos = match positions.next() {
+            Some(p) => p,
+            None => return None
+        };
+        for pos in positions {
+            if pos > first_pos {
+                return ordered_stations.last().cloned();
+            } else if pos < first_pos {
+                return ordered_stations.first().cloned();
+            }
+        }
+        None
+    }
+
+    /// Compute a ride-toward direction for every node in a raw path, one
+    /// direction per maximal run of nodes on the same line, since the
+    /// direction a rider is heading doesn't change until they transfer
+    /// lines.
+    fn compute_directions(&self, path: &Vec<Node>) -> Vec<Option<String>> {
+        let mut directions = Vec::with_capacity(path.len());
+        let mut i = 0;
+        while i < path.len() {
+            let line = path[i].line.clone();
+            let mut j = i;
+            while j < path.len() && path[j].line == line {
+                j += 1;
+            }
+            let direction = self.leg_direction(line.as_slice(), &path[i..j]);
+            for _ in i..j {
+                directions.push(direction.clone());
+            }
+            i = j;
+        }
+        directions
+    }
+
+    /// Interpret the path of Nodes as a list of TSteps. `inbound_connections`
+    /// comes from the same snapshot the path was found in, so the steps it
+    /// produces are always consistent with the graph that produced the path.
+    fn interpret_path(&self, path: Vec<Node>, inbound_connections: &HashSet<(String, String)>) -> Vec<TStep> {
         // invariant: path.len() must be > 0
         assert!(path.len() > 0);
         if path.len() == 1 {
             return Vec::new();
         }
 
+        let mut directions = self.compute_directions(&path).into_iter();
         let mut path_iter = path.into_iter();
         let mut result_vec = Vec::new();
         let first_node = path_iter.next().unwrap();
+        let first_direction = directions.next().unwrap();
         let mut prev_node = path_iter.next().unwrap();
-        self.process_first_nodes(&mut result_vec, first_node, prev_node.clone());
+        let second_direction = directions.next().unwrap();
+        self.process_first_nodes(&mut result_vec, first_node, prev_node.clone(), first_direction, second_direction,
+                                  inbound_connections);
         for node in path_iter {
-            self.process_nodes(&mut result_vec, prev_node, node.clone());
+            let direction = directions.next().unwrap();
+            self.process_nodes(&mut result_vec, prev_node, node.clone(), direction, inbound_connections);
             prev_node = node;
         }
         prune_end(&mut result_vec);
         result_vec
     }
 
-    /// returns TSteps associated with a transition between two given nodes
+    /// Compute the one-way fare for a trip made up of the given steps, as
+    /// the most expensive single-line fare among all lines ridden.
+    /// Transfers between lines are free; lines with no fare on file don't
+    /// add to the total.
+    fn calculate_fare(&self, steps: &Vec<TStep>) -> f64 {
+        let mut fare = 0f64;
+        for step in steps.iter() {
+            let lines: Vec<&String> = match step {
+                &Station(_, ref line, _) => vec![line],
+                &Switch(ref from, ref to, _) => vec![from, to],
+                &Ensure(ref line, _) => vec![line],
+                &Walk(..) => vec![],
+            };
+            for line in lines.into_iter() {
+                if let Some(&line_fare) = self.fares.get(line) {
+                    if line_fare > fare {
+                        fare = line_fare;
+                    }
+                }
+            }
+        }
+        fare
+    }
+
+    /// returns TSteps associated with a transition between two given nodes.
+    /// `direction` is the terminus `node`'s line leg is heading toward.
     /// EFFECT: mutates steps
-    fn process_nodes(&self, steps: &mut Vec<TStep>, prev_node: Node, node: Node) {
-        if prev_node.line != node.line && prev_node.station != node.station {
-            if !self.inbound_connections.contains(&(prev_node.line.clone(), node.line.clone())) {
-                steps.push(Ensure(node.line.clone()));
+    fn process_nodes(&self, steps: &mut Vec<TStep>, prev_node: Node, node: Node, direction: Option<String>,
+                     inbound_connections: &HashSet<(String, String)>) {
+        if let Some(&minutes) = self.walking_connections.get(&segment_key(&prev_node.station, &node.station)) {
+            steps.push(Walk(prev_node.station, node.station, minutes));
+        } else if prev_node.line != node.line && prev_node.station != node.station {
+            if !inbound_connections.contains(&(prev_node.line.clone(), node.line.clone())) {
+                steps.push(Ensure(node.line.clone(), direction.clone()));
             }
-            steps.push(Station(node.station, node.line));
+            steps.push(Station(node.station, node.line, direction));
         } else if prev_node.line != node.line {
-            steps.push(Switch(prev_node.line, node.line));
+            steps.push(Switch(prev_node.line, node.line, direction));
         } else {
-            steps.push(Station(node.station, node.line));
+            steps.push(Station(node.station, node.line, direction));
         }
     }
 
     /// Ensure that the first "direction" does not include a Switch
-    /// (due to extra starting transition at a transfer station)
+    /// (due to extra starting transition at a transfer station).
+    /// `first_direction` is the terminus `prev_node`'s leg is heading
+    /// toward, used only for the initial Station pushed for `prev_node`;
+    /// `direction` is `node`'s, threaded through to process_nodes.
     /// EFFECT: mutates steps
-    fn process_first_nodes(&self, steps: &mut Vec<TStep>, prev_node: Node, node: Node) {
+    fn process_first_nodes(&self, steps: &mut Vec<TStep>, prev_node: Node, node: Node,
+                            first_direction: Option<String>, direction: Option<String>,
+                            inbound_connections: &HashSet<(String, String)>) {
         if prev_node.station == node.station {
-            steps.push(Station(node.station, node.line));
+            steps.push(Station(node.station, node.line, direction));
             return;
         }
-        steps.push(Station(prev_node.station.clone(), prev_node.line.clone()));
-        self.process_nodes(steps, prev_node, node);
+        steps.push(Station(prev_node.station.clone(), prev_node.line.clone(), first_direction));
+        self.process_nodes(steps, prev_node, node, direction, inbound_connections);
     }
 }
 
@@ -451,15 +1842,30 @@ impl<'a> T<'a> {
 mod t_tests {
     use super::T;
     use super::{TQueryResult, DisambiguationResult};
-    use super::TQueryResult::{TOk, DisambiguateStart, DisambiguateDestination, NoSuchStart, NoSuchDest, NoSuchPath};
+    use super::TQueryResult::{TOk, TOkMultiple, TOkPareto, TPlan, DisambiguateStart, DisambiguateDestination, NoSuchStart, NoSuchDest, DisabledDest, NoSuchPath, LineNotRunning};
+    use super::TInfoResult::{Info, NoSuchStationInfo};
     use super::TStep::{Station, Switch, Ensure};
-    use std::collections::HashSet;
-    use graph::Node;
+    use super::Snapshot;
+    use std::collections::{HashSet, HashMap};
+    use graph::{Node, LabeledGraph};
+
+    /// Install a graph and station map as the current snapshot, the way
+    /// a test would have poked `t.graph`/`t.stations` directly before
+    /// those became private fields of Snapshot.
+    fn install_snapshot(t: &mut T, graph: LabeledGraph, stations: HashMap<String, Vec<Node>>) {
+        t.replace_snapshot(Snapshot {
+            graph: graph,
+            stations: stations,
+            inbound_connections: HashSet::new(),
+            all_pairs: None,
+            generation: 0,
+        });
+    }
 
     #[test]
     fn test_read_data_file() {
         let mut t = T::new();
-        t.read_data_file("data/red.dat");
+        t.read_data_file("data/red.dat").unwrap();
         let expect = string_set![
             "Alewife Station", "Davis Station", "Porter Square Station",
             "Harvard Square Station", "Central Square Station",
@@ -484,11 +1890,22 @@ mod t_tests {
         assert_eq!(count, expect.len());
     }
 
+    #[test]
+    fn test_read_data_file_travel_times() {
+        let mut t = T::new();
+        t.read_data_file("data/red.dat").unwrap();
+        // every station on the data files was annotated with a
+        // 2-minute hop from the previous station
+        for minutes in t.travel_times.get("red").unwrap().iter() {
+            assert_eq!(*minutes, 2);
+        }
+    }
+
 
     #[test]
     fn test_read_connections() {
         let mut t = T::new();
-        t.read_connections("data/connections.dat");
+        t.read_connections("data/connections.dat").unwrap();
 
         macro_rules! set {
             ($( ($x:expr, $y:expr, $z:expr) ),* ) => {{
@@ -515,9 +1932,9 @@ mod t_tests {
     #[test]
     fn test_rebuild_graph() {
         let mut t = T::new();
-        t.load(); // load calls rebuild graph
+        t.load().unwrap(); // load calls rebuild graph
 
-        assert_eq!(t.stations.len(), 120);
+        assert_eq!(t.current_snapshot().stations.len(), 132);
 
         // disable_station calls rebuild_graph each time
         let mut to_disable = vec![];
@@ -537,17 +1954,17 @@ mod t_tests {
         }
         println!("done");
 
-        assert_eq!(t.stations.len(), 120 - count);
+        assert_eq!(t.current_snapshot().stations.len(), 132 - count);
     }
 
     #[test]
     fn test_find_path() {
         let expect1 = TOk(vec![Station("South Station".to_string(),
-                                      "red".to_string()),
+                                      "red".to_string(), Some("JFK/UMass Station".to_string())),
                               Station("Broadway Station".to_string(),
-                                      "red".to_string()),
+                                      "red".to_string(), Some("JFK/UMass Station".to_string())),
                               Station("Andrew Station".to_string(),
-                                      "red".to_string())]);
+                                      "red".to_string(), Some("JFK/UMass Station".to_string()))], 4, 2.40);
         run_find_path_test("South Station", "Andrew Station", expect1);
 
         let expect2 = DisambiguateStart(vec!["South Station".to_string(),
@@ -564,15 +1981,254 @@ mod t_tests {
         run_find_path_test("Downtown Crossing Station", "asdf", NoSuchDest);
 
         let mut t = T::new();
-        t.load();
+        t.load().unwrap();
         t.disable_station("Park Street Station");
         t.disable_station("Downtown Crossing Station");
         assert_eq!(t.find_path("Alewife Station", "Ruggles Station"), NoSuchPath);
     }
 
+    #[test]
+    fn test_find_path_caches_and_invalidates_on_rebuild() {
+        let mut t = T::new();
+        t.load().unwrap();
+
+        let first = t.find_path("South Station", "Andrew Station");
+        // served from the cache the second time, but still the same answer
+        let second = t.find_path("South Station", "Andrew Station");
+        assert_eq!(first, second);
+
+        // rebuilding the graph bumps the generation, so the old cache entry
+        // can't be served even though "South Station"/"Andrew Station"
+        // haven't changed -- the query has to notice Andrew is now disabled
+        t.disable_station("Andrew Station");
+        assert_eq!(t.find_path("South Station", "Andrew Station"), DisabledDest("Andrew Station".to_string()));
+    }
+
+    #[test]
+    fn test_find_path_with_precomputed_all_pairs() {
+        let mut t = T::new();
+        t.set_precompute_all_pairs(true);
+        t.load().unwrap();
+        assert!(t.all_pairs_memory_estimate().is_some());
+
+        assert_eq!(t.find_path("South Station", "Andrew Station"),
+                   TOk(vec![Station("South Station".to_string(),
+                                     "red".to_string(), Some("JFK/UMass Station".to_string())),
+                           Station("Broadway Station".to_string(),
+                                   "red".to_string(), Some("JFK/UMass Station".to_string())),
+                           Station("Andrew Station".to_string(),
+                                   "red".to_string(), Some("JFK/UMass Station".to_string()))], 4, 2.40));
+        match t.find_path("Alewife Station", "Harvard Square Station") {
+            TOk(..) => {},
+            _ => panic!("expected a path between Alewife and Harvard Square")
+        }
+    }
+
+    #[test]
+    fn test_find_path_avoiding() {
+        let mut t = T::new();
+        let a = Node { station: "A".to_string(), line: "red".to_string() };
+        let b = Node { station: "B".to_string(), line: "red".to_string() };
+        let c = Node { station: "C".to_string(), line: "red".to_string() };
+        let mut stations = HashMap::new();
+        stations.insert("A".to_string(), vec![a.clone()]);
+        stations.insert("B".to_string(), vec![b.clone()]);
+        stations.insert("C".to_string(), vec![c.clone()]);
+        let mut graph = LabeledGraph::new();
+        graph.add_edge(&a, &b, 2, true);
+        graph.add_edge(&a, &c, 3, true);
+        graph.add_edge(&c, &b, 3, true);
+        install_snapshot(&mut t, graph, stations);
+
+        // the direct route is the cheapest when nothing is avoided
+        assert_eq!(t.find_path_avoiding("A", "B", vec![]),
+                   TOk(vec![Station("A".to_string(), "red".to_string(), None),
+                           Station("B".to_string(), "red".to_string(), None)], 2, 0.0));
+
+        // avoiding C has no effect, since the cheapest route doesn't use it
+        assert_eq!(t.find_path_avoiding("A", "B", vec!["C"]),
+                   TOk(vec![Station("A".to_string(), "red".to_string(), None),
+                           Station("B".to_string(), "red".to_string(), None)], 2, 0.0));
+
+        // avoiding B (the destination) means there's no path left
+        assert_eq!(t.find_path_avoiding("A", "B", vec!["B"]), NoSuchPath);
+
+        // the shared disabled set is untouched by a per-query exclusion
+        assert!(t.disabled.is_empty());
+
+        // an avoid station that doesn't resolve to exactly one station is
+        // harmlessly ignored
+        assert_eq!(t.find_path_avoiding("A", "B", vec!["asdf"]),
+                   TOk(vec![Station("A".to_string(), "red".to_string(), None),
+                           Station("B".to_string(), "red".to_string(), None)], 2, 0.0));
+    }
+
+    #[test]
+    fn test_find_path_at() {
+        let mut t = T::new();
+        t.load().unwrap();
+
+        // the red line runs 05:00-00:30 with a 9 minute headway; riding
+        // it at 10am folds half that headway into the travel time as an
+        // expected wait for the first train
+        assert_eq!(t.find_path_at("South Station", "Andrew Station", 10 * 60),
+                   TOk(vec![Station("South Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string())),
+                           Station("Broadway Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string())),
+                           Station("Andrew Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string()))], 8, 2.40));
+
+        // at 2am the red line isn't running yet; the trip is rejected
+        // with the time until service starts, rather than silently
+        // rerouted around the down line
+        assert_eq!(t.find_path_at("South Station", "Andrew Station", 2 * 60),
+                   LineNotRunning("red".to_string(), 180));
+    }
+
+    #[test]
+    fn test_find_planned_trip() {
+        let mut t = T::new();
+        t.load().unwrap();
+
+        assert_eq!(t.find_planned_trip(vec![("South Station", 0),
+                                            ("Broadway Station", 5),
+                                            ("Andrew Station", 0)]),
+                   TPlan(vec![(vec![Station("South Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string())),
+                                   Station("Broadway Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string()))], 2, 2.40),
+                             (vec![Station("Broadway Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string())),
+                                  Station("Andrew Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string()))], 2, 2.40)],
+                        9, 4.80));
+
+        // too few stops to form any leg
+        assert_eq!(t.find_planned_trip(vec![("South Station", 0)]), NoSuchPath);
+
+        // the first leg to fail stops the whole plan there
+        assert_eq!(t.find_planned_trip(vec![("asdf", 0), ("Andrew Station", 0), ("South Station", 0)]),
+                   NoSuchStart);
+    }
+
+    #[test]
+    fn test_line_running_at() {
+        let mut t = T::new();
+        t.load().unwrap();
+        assert!(t.line_running_at("red", 10 * 60));
+        assert!(!t.line_running_at("red", 2 * 60));
+        // the red line's service window wraps past midnight
+        assert!(t.line_running_at("red", 15));
+        // a line with no schedule on file is assumed to always run
+        assert!(t.line_running_at("asdf", 2 * 60));
+    }
+
+    #[test]
+    fn test_find_path_preferring_fewer_transfers() {
+        let mut t = T::new();
+        // "red" runs straight A-B-C-D (3 minutes a hop); "express" is a
+        // B-D shortcut that shares station names with "red" at B and D,
+        // so rebuild_lines wires up a transfer between the two lines at
+        // each of those stations
+        t.source_data.insert("red".to_string(),
+                              vec!["A", "B", "C", "D"].iter().map(|s| s.to_string()).collect());
+        t.travel_times.insert("red".to_string(), vec![0, 3, 3, 3]);
+        t.source_data.insert("express".to_string(),
+                              vec!["B", "D"].iter().map(|s| s.to_string()).collect());
+        t.travel_times.insert("express".to_string(), vec![0, 1]);
+        t.rebuild_graph();
+
+        // taking the express shortcut (3 + transfer cost 2 + 1 = 6
+        // minutes, 1 transfer) beats staying on red the whole way (9
+        // minutes, 0 transfers), so the default query takes it
+        assert_eq!(t.find_path("A", "D"),
+                   TOk(vec![Station("A".to_string(), "red".to_string(), Some("D".to_string())),
+                           Station("B".to_string(), "red".to_string(), Some("D".to_string())),
+                           Switch("red".to_string(), "express".to_string(), Some("D".to_string())),
+                           Station("D".to_string(), "express".to_string(), Some("D".to_string()))], 6, 0.0));
+
+        // with transfers weighted heavily, the shortcut's transfer (now
+        // costing 20) makes it more expensive than just riding red the
+        // whole way, so that's what comes back instead
+        assert_eq!(t.find_path_preferring_fewer_transfers("A", "D"),
+                   TOk(vec![Station("A".to_string(), "red".to_string(), Some("D".to_string())),
+                           Station("B".to_string(), "red".to_string(), Some("D".to_string())),
+                           Station("C".to_string(), "red".to_string(), Some("D".to_string())),
+                           Station("D".to_string(), "red".to_string(), Some("D".to_string()))], 9, 0.0));
+
+        // the transfer cost is restored to normal afterward, so a plain
+        // query goes right back to taking the shortcut
+        assert_eq!(t.find_path("A", "D"),
+                   TOk(vec![Station("A".to_string(), "red".to_string(), Some("D".to_string())),
+                           Station("B".to_string(), "red".to_string(), Some("D".to_string())),
+                           Switch("red".to_string(), "express".to_string(), Some("D".to_string())),
+                           Station("D".to_string(), "express".to_string(), Some("D".to_string()))], 6, 0.0));
+    }
+
+    #[test]
+    fn test_find_pareto_paths() {
+        let mut t = T::new();
+        let a = Node { station: "A".to_string(), line: "red".to_string() };
+        let b = Node { station: "B".to_string(), line: "red".to_string() };
+        let b2 = Node { station: "B".to_string(), line: "blue".to_string() };
+        let c = Node { station: "C".to_string(), line: "blue".to_string() };
+        let mut stations = HashMap::new();
+        stations.insert("A".to_string(), vec![a.clone()]);
+        stations.insert("B".to_string(), vec![b.clone(), b2.clone()]);
+        stations.insert("C".to_string(), vec![c.clone()]);
+        let mut graph = LabeledGraph::new();
+        // a one-transfer route via B: 3 stops, 1 transfer, 5 minutes
+        graph.add_edge(&a, &b, 2, true);
+        graph.add_edge(&b, &b2, 2, true);
+        graph.add_edge(&b2, &c, 1, true);
+        // a direct one-transfer route straight onto blue: 2 stops, 1
+        // transfer, 10 minutes
+        graph.add_edge(&a, &c, 10, true);
+        install_snapshot(&mut t, graph, stations);
+
+        // neither route dominates the other: the route via B is fewer
+        // minutes but more stops, so both show up as non-dominated
+        // itineraries rather than being collapsed into a single "best" one
+        assert_eq!(t.find_pareto_paths("A", "C"),
+                   TOkPareto(vec![
+                       (vec![Station("A".to_string(), "red".to_string(), None),
+                            Station("B".to_string(), "red".to_string(), None),
+                            Switch("red".to_string(), "blue".to_string(), None),
+                            Station("C".to_string(), "blue".to_string(), None)], 3, 1, 5, 0.0),
+                       (vec![Station("A".to_string(), "red".to_string(), None),
+                            Ensure("blue".to_string(), None),
+                            Station("C".to_string(), "blue".to_string(), None)], 2, 1, 10, 0.0)]));
+    }
+
+    #[test]
+    fn test_find_paths() {
+        let mut t = T::new();
+        let a = Node { station: "A".to_string(), line: "red".to_string() };
+        let b = Node { station: "B".to_string(), line: "red".to_string() };
+        let c = Node { station: "C".to_string(), line: "red".to_string() };
+        let mut stations = HashMap::new();
+        stations.insert("A".to_string(), vec![a.clone()]);
+        stations.insert("B".to_string(), vec![b.clone()]);
+        stations.insert("C".to_string(), vec![c.clone()]);
+        let mut graph = LabeledGraph::new();
+        graph.add_edge(&a, &b, 2, true);
+        graph.add_edge(&a, &c, 3, true);
+        graph.add_edge(&c, &b, 3, true);
+        install_snapshot(&mut t, graph, stations);
+
+        assert_eq!(t.find_paths("A", "B", 2),
+                   TOkMultiple(vec![(vec![Station("A".to_string(), "red".to_string(), None),
+                                         Station("B".to_string(), "red".to_string(), None)], 2, 0.0),
+                                    (vec![Station("A".to_string(), "red".to_string(), None),
+                                         Station("C".to_string(), "red".to_string(), None),
+                                         Station("B".to_string(), "red".to_string(), None)], 6, 0.0)]));
+
+        // asking for more alternatives than exist just returns what's there
+        assert_eq!(t.find_paths("A", "B", 5),
+                   TOkMultiple(vec![(vec![Station("A".to_string(), "red".to_string(), None),
+                                         Station("B".to_string(), "red".to_string(), None)], 2, 0.0),
+                                    (vec![Station("A".to_string(), "red".to_string(), None),
+                                         Station("C".to_string(), "red".to_string(), None),
+                                         Station("B".to_string(), "red".to_string(), None)], 6, 0.0)]));
+    }
+
     fn run_find_path_test(start: &str, end: &str, expect: TQueryResult) {
         let mut t = T::new();
-        t.load();
+        t.load().unwrap();
         let result = t.find_path(start, end);
         assert_eq!(result, expect);
     }
@@ -581,7 +2237,7 @@ mod t_tests {
     fn test_modify_station() {
         let station = "South Station";
         let mut t = T::new();
-        t.load();
+        t.load().unwrap();
         assert!(!t.disabled.contains(station));
         t.modify_station(station, false);
         assert!(t.disabled.contains(station));
@@ -595,10 +2251,162 @@ mod t_tests {
         assert!(!t.disabled.contains(station));
     }
 
+    #[test]
+    fn test_modify_station_incremental_on_connection_irrelevant_line() {
+        // blue never feeds a connections.dat entry, so disabling/enabling
+        // a blue station takes the incremental path instead of a full
+        // rebuild_graph -- this checks that path round-trips to the same
+        // answer as before the station was ever touched.
+        let mut t = T::new();
+        t.load().unwrap();
+        assert!(!t.station_is_connection_relevant("Maverick Station"));
+
+        let before = t.find_path("Airport Station", "Aquarium Station");
+        let generation_before = t.current_snapshot().generation;
+
+        t.disable_station("Maverick Station");
+        assert_eq!(t.find_path("Wood Island Station", "Maverick Station"),
+                   DisabledDest("Maverick Station".to_string()));
+        // the gap left by the disabled station is bridged directly, so
+        // Airport and Aquarium are still connected even though the
+        // station between them is gone
+        match t.find_path("Airport Station", "Aquarium Station") {
+            TOk(..) => {},
+            _ => panic!("expected the gap left by the disabled station to be bridged")
+        }
+
+        t.enable_station("Maverick Station");
+        assert!(t.current_snapshot().generation > generation_before);
+        assert_eq!(t.find_path("Airport Station", "Aquarium Station"), before);
+    }
+
+    #[test]
+    fn test_modify_segment() {
+        let mut t = T::new();
+        t.load().unwrap();
+        assert_eq!(t.find_path("South Station", "Andrew Station"),
+                   TOk(vec![Station("South Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string())),
+                           Station("Broadway Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string())),
+                           Station("Andrew Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string()))], 4, 2.40));
+
+        t.disable_segment("South Station", "Broadway Station");
+        assert!(t.disabled_segments.contains(&("Broadway Station".to_string(),
+                                               "South Station".to_string())));
+        // there's no alternate stretch of track around this segment,
+        // so the two stations are no longer connected to each other...
+        assert_eq!(t.find_path("South Station", "Andrew Station"), NoSuchPath);
+        // ...but both stations are still usable from other directions
+        assert_eq!(t.find_path("Park Street Station", "South Station"),
+                   TOk(vec![Station("Park Street Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string())),
+                           Station("Downtown Crossing Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string())),
+                           Station("South Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string()))], 4, 2.40));
+        assert_eq!(t.find_path("Broadway Station", "Andrew Station"),
+                   TOk(vec![Station("Broadway Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string())),
+                           Station("Andrew Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string()))], 2, 2.40));
+
+        // disabling the same segment with either endpoint order is a no-op
+        t.disable_segment("Broadway Station", "South Station");
+        assert_eq!(t.disabled_segments.len(), 1);
+
+        t.enable_segment("South Station", "Broadway Station");
+        assert!(t.disabled_segments.is_empty());
+        assert_eq!(t.find_path("South Station", "Andrew Station"),
+                   TOk(vec![Station("South Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string())),
+                           Station("Broadway Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string())),
+                           Station("Andrew Station".to_string(), "red".to_string(), Some("JFK/UMass Station".to_string()))], 4, 2.40));
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut t = T::new();
+        t.load().unwrap();
+        let lines = t.lines();
+        assert!(lines.contains(&"red".to_string()));
+        assert!(lines.contains(&"green".to_string()));
+        assert!(lines.contains(&"blue".to_string()));
+        assert!(lines.contains(&"orange".to_string()));
+        let mut sorted = lines.clone();
+        sorted.sort();
+        assert_eq!(lines, sorted);
+    }
+
+    #[test]
+    fn test_stations() {
+        let mut t = T::new();
+        t.load().unwrap();
+        assert_eq!(t.stations(Some("red")), t.source_data.get("red").map(|v| v.clone()));
+        assert_eq!(t.stations(Some("asdf")), None);
+
+        let all = t.stations(None).unwrap();
+        assert!(all.contains(&"Andrew Station".to_string()));
+        assert!(all.contains(&"Government Center Station".to_string()));
+        let mut sorted = all.clone();
+        sorted.sort();
+        assert_eq!(all, sorted);
+    }
+
+    #[test]
+    fn test_disabled_stations_and_segments() {
+        let mut t = T::new();
+        t.load().unwrap();
+        assert_eq!(t.disabled_stations(), Vec::<String>::new());
+        assert_eq!(t.disabled_segments(), Vec::<(String, String)>::new());
+
+        t.disable_station("Andrew Station");
+        t.disable_station("Broadway Station");
+        t.disable_segment("South Station", "Park Street Station");
+        assert_eq!(t.disabled_stations(), vec!["Andrew Station".to_string(),
+                                               "Broadway Station".to_string()]);
+        assert_eq!(t.disabled_segments(), vec![("Park Street Station".to_string(),
+                                                "South Station".to_string())]);
+    }
+
+    #[test]
+    fn test_apply_and_clear_alert() {
+        let mut t = T::new();
+        t.load().unwrap();
+        assert_eq!(t.active_alerts(), Vec::<(String, String)>::new());
+
+        t.apply_alert("Andrew Station", "signal problem");
+        assert!(t.disabled.contains("Andrew Station"));
+        assert_eq!(t.active_alerts(), vec![("Andrew Station".to_string(),
+                                            "signal problem".to_string())]);
+
+        // a station disabled by hand doesn't show up as an alert
+        t.disable_station("Broadway Station");
+        assert_eq!(t.active_alerts(), vec![("Andrew Station".to_string(),
+                                            "signal problem".to_string())]);
+
+        t.clear_alert("Andrew Station");
+        assert!(!t.disabled.contains("Andrew Station"));
+        assert_eq!(t.active_alerts(), Vec::<(String, String)>::new());
+        // the manually disabled station is unaffected by clearing the alert
+        assert!(t.disabled.contains("Broadway Station"));
+    }
+
+    #[test]
+    fn test_station_info() {
+        let mut t = T::new();
+        t.load().unwrap();
+
+        assert_eq!(t.station_info("Andrew Station"),
+                   Info("Andrew Station".to_string(), vec!["red".to_string()], false, false,
+                        vec![("red".to_string(), Some("Broadway Station".to_string()),
+                              Some("JFK/UMass Station".to_string()))]));
+
+        t.disable_station("Andrew Station");
+        assert_eq!(t.station_info("Andrew Station"),
+                   Info("Andrew Station".to_string(), vec!["red".to_string()], false, true,
+                        vec![("red".to_string(), Some("Broadway Station".to_string()),
+                              Some("JFK/UMass Station".to_string()))]));
+
+        assert_eq!(t.station_info("asdf"), NoSuchStationInfo);
+    }
+
     #[test]
     fn test_disambiguate_station() {
         let mut t = T::new();
-        t.load();
+        t.load().unwrap();
         assert_eq!(t.disambiguate_station("Andrew Station"),
                    DisambiguationResult::Station("Andrew Station".to_string()));
         assert_eq!(t.disambiguate_station("Andrew"),
@@ -616,14 +2424,23 @@ mod t_tests {
         for station in suggestions.iter() {
             assert!(expect.contains(station.as_slice()));
         }
+
+        // "Haravrd" isn't a substring of any station, so this falls
+        // back to fuzzy matching
+        let suggestions = match t.disambiguate_station("Haravrd Square Station") {
+            DisambiguationResult::Suggestions(stations) => stations,
+            DisambiguationResult::Station(name) => vec![name]
+        };
+        assert_eq!(suggestions, vec!["Harvard Square Station".to_string()]);
+
+        // "Govt Center" is an alias, resolved case-insensitively
+        assert_eq!(t.disambiguate_station("govt center"),
+                   DisambiguationResult::Station("Government Center Station".to_string()));
     }
 
     #[test]
     fn test_add_unbiased_nodes() {
-        use std::collections::HashMap;
-        use graph::Node;
-
-        let mut t = T::new();
+        use super::add_unbiased_nodes;
 
         macro_rules! string_map {
             ($( ($x:expr, $y:expr) ),* ) => {{
@@ -635,7 +2452,7 @@ mod t_tests {
             }};
         }
 
-        let station_map = string_map![("A", vec![Node {
+        let mut station_map = string_map![("A", vec![Node {
             station: "A".to_string(),
             line: "red".to_string()
         }]), ("B", vec![Node {
@@ -646,12 +2463,12 @@ mod t_tests {
             line: "green".to_string()
         }])];
 
-        t.stations = station_map;
-        assert_eq!(t.stations.get("A").unwrap().len(), 1);
-        assert_eq!(t.stations.get("B").unwrap().len(), 2);
-        t.add_unbiased_nodes();
-        assert_eq!(t.stations.get("A").unwrap().len(), 1);
-        assert_eq!(t.stations.get("B").unwrap().len(), 4);
+        assert_eq!(station_map.get("A").unwrap().len(), 1);
+        assert_eq!(station_map.get("B").unwrap().len(), 2);
+        let mut graph = LabeledGraph::new();
+        add_unbiased_nodes(&mut graph, &mut station_map);
+        assert_eq!(station_map.get("A").unwrap().len(), 1);
+        assert_eq!(station_map.get("B").unwrap().len(), 4);
     }
 
     #[test]
@@ -661,29 +2478,30 @@ mod t_tests {
             station: "Downtown Crossing Station".to_string(),
             line: "orange".to_string()
         }];
-        assert_eq!(t.interpret_path(path.clone()), vec![]);
+        let inbound_connections = HashSet::new();
+        assert_eq!(t.interpret_path(path.clone(), &inbound_connections), vec![]);
         path.push(Node {
             station: "Ruggles Station".to_string(),
             line: "orange".to_string()
         });
         let mut expect = vec![Station("Downtown Crossing Station".to_string(),
-                                      "orange".to_string()),
+                                      "orange".to_string(), None),
                               Station("Ruggles Station".to_string(),
-                                      "orange".to_string())];
-        assert_eq!(t.interpret_path(path.clone()), expect);
+                                      "orange".to_string(), None)];
+        assert_eq!(t.interpret_path(path.clone(), &inbound_connections), expect);
         path.push(Node {
             station: "Ruggles Station".to_string(),
             line: "blue".to_string()
         });
-        assert_eq!(t.interpret_path(path.clone()), expect);
+        assert_eq!(t.interpret_path(path.clone(), &inbound_connections), expect);
         path.push(Node {
             station: "State Station".to_string(),
             line: "C".to_string()
         });
-        expect.push(Switch("orange".to_string(), "blue".to_string()));
-        expect.push(Ensure("C".to_string()));
-        expect.push(Station("State Station".to_string(), "C".to_string()));
-        assert_eq!(t.interpret_path(path.clone()), expect);
+        expect.push(Switch("orange".to_string(), "blue".to_string(), None));
+        expect.push(Ensure("C".to_string(), None));
+        expect.push(Station("State Station".to_string(), "C".to_string(), None));
+        assert_eq!(t.interpret_path(path.clone(), &inbound_connections), expect);
     }
 
     #[test]
@@ -698,32 +2516,34 @@ mod t_tests {
             line: "orange".to_string()
         };
         let mut steps = vec![];
-        t.process_nodes(&mut steps, prev.clone(), curr);
+        let inbound_connections = HashSet::new();
+        t.process_nodes(&mut steps, prev.clone(), curr, None, &inbound_connections);
         assert_eq!(steps, vec![Station("Ruggles Station".to_string(),
-                                       "orange".to_string())]);
+                                       "orange".to_string(), None)]);
         steps = vec![];
         let curr = Node {
             station: "Downtown Crossing Station".to_string(),
             line: "red".to_string()
         };
-        t.process_nodes(&mut steps, prev.clone(), curr);
+        t.process_nodes(&mut steps, prev.clone(), curr, None, &inbound_connections);
         assert_eq!(steps, vec![Switch("orange".to_string(),
-                                      "red".to_string())]);
+                                      "red".to_string(), None)]);
         steps = vec![];
         let curr = Node {
             station: "Ruggles Station".to_string(),
             line: "C".to_string()
         };
-        t.process_nodes(&mut steps, prev.clone(), curr);
-        assert_eq!(steps, vec![Ensure("C".to_string()),
+        t.process_nodes(&mut steps, prev.clone(), curr, None, &inbound_connections);
+        assert_eq!(steps, vec![Ensure("C".to_string(), None),
                                Station("Ruggles Station".to_string(),
-                                       "C".to_string())]);
+                                       "C".to_string(), None)]);
     }
 
     #[test]
     fn test_process_first_node() {
         let t = T::new();
         let mut steps = vec![];
+        let inbound_connections = HashSet::new();
         let prev = Node {
             station: "Downtown Crossing Station".to_string(),
             line: "orange".to_string()
@@ -732,19 +2552,19 @@ mod t_tests {
             station: "Ruggles Station".to_string(),
             line: "orange".to_string()
         };
-        t.process_first_nodes(&mut steps, prev.clone(), curr);
+        t.process_first_nodes(&mut steps, prev.clone(), curr, None, None, &inbound_connections);
         assert_eq!(steps, vec![Station("Downtown Crossing Station".to_string(),
-                                       "orange".to_string()),
+                                       "orange".to_string(), None),
                                Station("Ruggles Station".to_string(),
-                                       "orange".to_string())]);
+                                       "orange".to_string(), None)]);
         steps = vec![];
         let curr = Node {
             station: "Downtown Crossing Station".to_string(),
             line: "red".to_string()
         };
-        t.process_first_nodes(&mut steps, prev.clone(), curr);
+        t.process_first_nodes(&mut steps, prev.clone(), curr, None, None, &inbound_connections);
         assert_eq!(steps, vec![Station("Downtown Crossing Station".to_string(),
-                                       "red".to_string())]);
+                                       "red".to_string(), None)]);
 
     }
 }
@@ -754,7 +2574,7 @@ mod t_tests {
 /// EFFECT: mutates steps
 fn prune_end(steps: &mut Vec<TStep>) {
     match steps.pop().unwrap() {
-        Station(station, line) => { steps.push(Station(station, line)); },
+        Station(station, line, direction) => { steps.push(Station(station, line, direction)); },
         _ => {}
     };
 }
@@ -766,24 +2586,328 @@ mod prune_end_tests {
 
     #[test]
     fn test_prine_end() {
-        let mut steps = vec![Station("A".to_string(), "B".to_string())];
+        let mut steps = vec![Station("A".to_string(), "B".to_string(), None)];
         prune_end(&mut steps);
         assert_eq!(steps.len(), 1);
 
-        steps.push(Switch("B".to_string(), "C".to_string()));
+        steps.push(Switch("B".to_string(), "C".to_string(), None));
         assert_eq!(steps.len(), 2);
         prune_end(&mut steps);
         assert_eq!(steps.len(), 1);
 
-        steps.push(Ensure("B".to_string()));
+        steps.push(Ensure("B".to_string(), None));
         assert_eq!(steps.len(), 2);
         prune_end(&mut steps);
         assert_eq!(steps.len(), 1);
     }
 }
 
-/// Open the file as given by filename in the form of a Buffered Reader
-fn open_file(filename: &str) -> BufferedReader<File> {
-    let file = File::open(&Path::new(filename));
-    BufferedReader::new(file.ok().expect("couldn't open file"))
+/// Normalizes a pair of station names into a direction-agnostic key
+/// for `disabled_segments`, so "disable between A and B" and
+/// "disable between B and A" refer to the same segment.
+fn segment_key(a: &str, b: &str) -> (String, String) {
+    if a <= b { (a.to_string(), b.to_string()) } else { (b.to_string(), a.to_string()) }
+}
+
+/// Approximate byte size of a Node, for all_pairs_memory_estimate.
+fn node_size(node: &Node) -> usize {
+    node.station.len() + node.line.len()
+}
+
+/// Compute shortest paths between every pair of nodes in `graph`. Only
+/// called from rebuild_graph/finish_incremental_update when
+/// precompute_all_pairs is enabled: O(nodes) calls to Dijkstra, so this
+/// is meant to run once per rebuild rather than per query.
+fn compute_all_pairs(graph: &LabeledGraph) -> HashMap<(Node, Node), (Vec<Node>, usize)> {
+    let labels = graph.labels();
+    let mut table = HashMap::new();
+    for source in labels.iter() {
+        for target in labels.iter() {
+            if source == target {
+                continue;
+            }
+            if let Some((path, cost)) = graph.find_shortest_path_with_cost(source, target) {
+                table.insert((source.clone(), target.clone()), (path, cost));
+            }
+        }
+    }
+    table
+}
+
+/// Add the unbiased nodes used for starting or ending a trip at a
+/// transfer station, directly into `graph`/`stations`. A free function
+/// rather than a T method since it only ever needs the graph and
+/// stations being assembled for a new snapshot, never any other T state.
+fn add_unbiased_nodes(graph: &mut LabeledGraph, stations: &mut HashMap<String, Vec<Node>>) {
+    for (station, ref mut node_vec) in stations.iter_mut() {
+        if node_vec.len() > 1 {
+            let start_node = Node {
+                station: station.clone(),
+                line: START_NODE_LABEL.to_string()
+            };
+            let end_node = Node {
+                station: station.clone(),
+                line: END_NODE_LABEL.to_string()
+            };
+            for node in node_vec.iter() {
+                graph.add_edge(&start_node, node, NO_COST, true);
+                graph.add_edge(node, &end_node, NO_COST, true);
+            }
+            node_vec.push(start_node);
+            node_vec.push(end_node);
+        }
+    }
+}
+
+/// A dot-safe, unique identifier for a graph node, for export_dot.
+fn dot_node_id(node: &Node) -> String {
+    format!("{}__{}", node.station, node.line)
+}
+
+/// Mean radius of the Earth in miles, for haversine_miles.
+static EARTH_RADIUS_MILES: f64 = 3958.8;
+
+/// Great-circle distance in miles between two (lat, lon) points in
+/// degrees, via the haversine formula. Good enough for "nearest station"
+/// purposes over a city-sized network; doesn't account for elevation.
+fn haversine_miles(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let to_radians = |deg: f64| deg * PI / 180.0;
+    let (lat1, lat2) = (to_radians(lat1), to_radians(lat2));
+    let (dlat, dlon) = (lat2 - lat1, to_radians(lon2 - lon1));
+    let half_sin_dlat = (dlat / 2.0).sin();
+    let half_sin_dlon = (dlon / 2.0).sin();
+    let a = half_sin_dlat * half_sin_dlat + lat1.cos() * lat2.cos() * half_sin_dlon * half_sin_dlon;
+    EARTH_RADIUS_MILES * 2.0 * a.sqrt().asin()
+}
+
+/// Cost of a transfer to or from `line`, based on its mode in
+/// `lines_metadata`. Falls back to `transfer_cost` when `line` has no
+/// metadata entry, so lines loaded before line_metadata.dat existed (and
+/// every synthetic line built directly from source_data in a test) keep
+/// their old flat transfer cost.
+fn mode_transfer_cost(lines_metadata: &HashMap<String, LineMetadata>, transfer_cost: usize, line: &str) -> usize {
+    match lines_metadata.get(line) {
+        Some(metadata) => match metadata.mode.as_slice() {
+            "bus" => BUS_TRANSFER_COST,
+            "rail" => RAIL_TRANSFER_COST,
+            _ => transfer_cost
+        },
+        None => transfer_cost
+    }
+}
+
+/// Open the file as given by filename in the form of a Buffered Reader,
+/// or a LoadError::CouldNotOpen describing why it couldn't be opened.
+fn open_file(filename: &str) -> Result<BufferedReader<File>, LoadError> {
+    match File::open(&Path::new(filename)) {
+        Ok(file) => Ok(BufferedReader::new(file)),
+        Err(e) => Err(LoadError::CouldNotOpen(filename.to_string(), format!("{}", e)))
+    }
+}
+
+/// Split a trimmed station line of the form "name" or "name:minutes"
+/// into the station's name and the travel time from the previous
+/// station, defaulting to a travel time of 1 when no minutes are given.
+fn parse_station_line(path: &str, line_no: usize, line: &str) -> Result<(String, usize), LoadError> {
+    match line.rfind(':') {
+        Some(i) => {
+            let minutes = match line.slice_from(i + 1).parse() {
+                Ok(minutes) => minutes,
+                Err(..) => return Err(LoadError::MalformedLine(path.to_string(), line_no,
+                    "travel time must be a non-negative integer".to_string()))
+            };
+            Ok((line.slice_to(i).to_string(), minutes))
+        },
+        None => Ok((line.to_string(), 1)),
+    }
+}
+
+/// Return the name of the line used by the first step of a trip, or None
+/// for an empty (zero-length) trip or one that starts with a walk --
+/// walking isn't limited by a line's service hours, so find_path_at has
+/// nothing to check the departure time against in that case.
+fn first_line(steps: &Vec<TStep>) -> Option<&String> {
+    steps.first().and_then(|step| match step {
+        &Station(_, ref line, _) => Some(line),
+        &Switch(ref from, _, _) => Some(from),
+        &Ensure(ref line, _) => Some(line),
+        &Walk(..) => None
+    })
+}
+
+/// The next unix timestamp at which the wall clock reads `clock_minutes`
+/// minutes since midnight -- today if that time hasn't happened yet,
+/// tomorrow if it already has. Used by disable_station_until; treats
+/// get_time()'s seconds as already being in the service's local day, the
+/// same assumption schedules.dat's clock times make.
+fn next_occurrence_of(clock_minutes: usize) -> i64 {
+    let now = time::get_time().sec;
+    let seconds_since_midnight = now % 86400;
+    let midnight = now - seconds_since_midnight;
+    let mut expiry = midnight + clock_minutes as i64 * 60;
+    if clock_minutes as i64 <= seconds_since_midnight / 60 {
+        expiry += 86400;
+    }
+    expiry
+}
+
+/// Parse a 24-hour clock time of the form "HH:MM" into minutes since
+/// midnight.
+fn parse_clock(time: &str) -> usize {
+    let i = time.find(':').expect("clock time must be of the form \"HH:MM\"");
+    let hours: usize = time.slice_to(i).parse().expect("hours must be an integer");
+    let minutes: usize = time.slice_from(i + 1).parse().expect("minutes must be an integer");
+    hours * 60 + minutes
+}
+
+/// Count the stops and transfers in a trip: each `Station` is a stop,
+/// and each `Switch`, `Ensure`, or `Walk` is a transfer.
+pub fn itinerary_metrics(steps: &Vec<TStep>) -> (usize, usize) {
+    let mut stops: usize = 0;
+    let mut transfers: usize = 0;
+    for step in steps.iter() {
+        match step {
+            &Station(..) => stops += 1,
+            &Switch(..) => transfers += 1,
+            &Ensure(..) => transfers += 1,
+            &Walk(..) => transfers += 1
+        }
+    }
+    (stops, transfers)
+}
+
+/// Return whether the candidate at index `i` in `metrics` is dominated
+/// by another candidate: one that's at least as good on every criterion
+/// (stops, transfers, minutes) and strictly better on at least one.
+fn dominated(candidate: (usize, usize, usize), metrics: &[(usize, usize, usize)], i: usize) -> bool {
+    let (stops, transfers, minutes) = candidate;
+    metrics.iter().enumerate().any(|(j, &(s, t, m))| {
+        j != i && s <= stops && t <= transfers && m <= minutes &&
+            (s < stops || t < transfers || m < minutes)
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////
+//                          GTFS feed loading                            //
+////////////////////////////////////////////////////////////////////////////
+
+/// Parse a GTFS CSV file into a list of rows, each mapping column name to
+/// value, using the file's header line to label columns. This is a
+/// minimal CSV reader: it doesn't handle quoted fields containing commas,
+/// which is fine for the well-behaved feeds this loader targets.
+fn read_gtfs_file(path: &str) -> Vec<HashMap<String, String>> {
+    let mut reader = open_file(path).ok().expect("couldn't open file");
+    let header: Vec<String> = reader.read_line().ok().expect("GTFS file missing header")
+        .trim().split(',').map(|s| s.trim().to_string()).collect();
+    let mut rows = Vec::new();
+    while let Some(line) = reader.read_line().ok() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let mut row = HashMap::new();
+        for (name, value) in header.iter().zip(trimmed.split(',')) {
+            row.insert(name.clone(), value.trim().to_string());
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+/// Parse a GTFS "HH:MM:SS" timestamp into the number of seconds since
+/// midnight. Hours may exceed 24 for trips that run past midnight.
+fn parse_gtfs_time(time: &str) -> usize {
+    let parts: Vec<usize> = time.split(':')
+        .map(|s| s.parse().expect("GTFS time must be HH:MM:SS")).collect();
+    parts[0] * 3600 + parts[1] * 60 + parts[2]
+}
+
+/// Map each GTFS stop_id to its stop_name
+fn gtfs_stop_names(stops: &Vec<HashMap<String, String>>) -> HashMap<String, String> {
+    let mut names = HashMap::new();
+    for stop in stops.iter() {
+        names.insert(stop.get("stop_id").unwrap().clone(), stop.get("stop_name").unwrap().clone());
+    }
+    names
+}
+
+/// Map each GTFS route_id to a human-readable line name: its
+/// route_short_name if one is given, else its route_long_name, else the
+/// route_id itself
+fn gtfs_route_names(routes: &Vec<HashMap<String, String>>) -> HashMap<String, String> {
+    let mut names = HashMap::new();
+    for route in routes.iter() {
+        let route_id = route.get("route_id").unwrap().clone();
+        let name = match route.get("route_short_name") {
+            Some(s) if !s.is_empty() => s.clone(),
+            _ => match route.get("route_long_name") {
+                Some(s) if !s.is_empty() => s.clone(),
+                _ => route_id.clone(),
+            }
+        };
+        names.insert(route_id, name);
+    }
+    names
+}
+
+/// Pick one representative trip per route_id from GTFS trips.txt (the
+/// first one encountered), since we only need a single station ordering
+/// and set of travel times per line
+fn gtfs_representative_trips(trips: &Vec<HashMap<String, String>>) -> Vec<(String, String)> {
+    let mut seen = HashSet::new();
+    let mut representative = Vec::new();
+    for trip in trips.iter() {
+        let route_id = trip.get("route_id").unwrap().clone();
+        if seen.contains(&route_id) {
+            continue;
+        }
+        seen.insert(route_id.clone());
+        representative.push((route_id, trip.get("trip_id").unwrap().clone()));
+    }
+    representative
+}
+
+/// Group GTFS stop_times.txt rows by trip_id, as
+/// (stop_sequence, stop_id, arrival time in seconds since midnight) tuples
+fn gtfs_stop_times_by_trip(stop_times: &Vec<HashMap<String, String>>)
+    -> HashMap<String, Vec<(usize, String, usize)>> {
+    let mut by_trip: HashMap<String, Vec<(usize, String, usize)>> = HashMap::new();
+    for row in stop_times.iter() {
+        let trip_id = row.get("trip_id").unwrap().clone();
+        let stop_sequence = row.get("stop_sequence").unwrap().parse()
+            .expect("stop_sequence must be a non-negative integer");
+        let stop_id = row.get("stop_id").unwrap().clone();
+        let arrival_seconds = parse_gtfs_time(row.get("arrival_time").unwrap().as_slice());
+        if !by_trip.contains_key(&trip_id) {
+            by_trip.insert(trip_id.clone(), Vec::new());
+        }
+        by_trip.get_mut(&trip_id).unwrap().push((stop_sequence, stop_id, arrival_seconds));
+    }
+    by_trip
+}
+
+#[cfg(test)]
+mod gtfs_tests {
+    use super::T;
+
+    #[test]
+    fn test_load_gtfs() {
+        let mut t = T::new();
+        t.load_gtfs("data/gtfs");
+
+        assert_eq!(t.source_data.get("RED"),
+                   Some(&vec!["Alewife Station".to_string(),
+                             "Davis Station".to_string(),
+                             "Porter Square Station".to_string()]));
+        assert_eq!(t.travel_times.get("RED"), Some(&vec![0, 2, 3]));
+
+        let result = t.find_path("Alewife Station", "Porter Square Station");
+        match result {
+            super::TQueryResult::TOk(ref steps, minutes, _) => {
+                assert_eq!(minutes, 5);
+                assert_eq!(steps.len(), 3);
+            },
+            _ => panic!("expected a path between the GTFS-loaded stations"),
+        }
+    }
 }
@@ -0,0 +1,1314 @@
+#![allow(unstable)]
+
+#[doc="
+Module: graph_lib
+
+The weighted, labeled graph structure shared by graph_traversal and
+t_query, pulled out into its own crate so both binaries query and
+mutate graphs the same way instead of each maintaining their own
+near-identical copy of Dijkstra's algorithm. `LabeledGraph` is generic
+over the label type, so graph_traversal can key nodes by plain string
+labels while t_query keys them by its richer `Node` type.
+"]
+
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::collections::hash_map::Entry::{Vacant, Occupied};
+use std::hash::Hash;
+use std::io::{File, IoResult};
+use std::usize;
+use std::cmp::Ordering;
+
+// This is necessary for the min-priority queue used in Graph::find_shortest_path.
+// Deliberately carries no path - only the predecessor maps built up
+// during the search do, so the heap doesn't pay for cloning a
+// growing Vec<usize> into every entry.
+#[derive(Eq, PartialEq, PartialOrd)]
+struct State {
+    distance: usize,
+    position: usize,
+}
+
+// Flip the ordering so BinaryHeap finds mins, not maxes
+impl Ord for State {
+    fn cmp(&self, other: &State) -> Ordering {
+        other.distance.cmp(&self.distance)
+    }
+}
+
+/// Walks `predecessor` backward from `target` to `source`, building
+/// the path in order. Assumes `source` and `target` are connected
+/// in the search that produced `predecessor`.
+fn reconstruct_path(predecessor: &Vec<Option<usize>>, source: usize, target: usize) -> Vec<usize> {
+    let mut path = vec![target];
+    let mut node = target;
+    while node != source {
+        node = predecessor[node].expect("predecessor map missing a link on the path to source");
+        path.push(node);
+    }
+    path.reverse();
+    path
+}
+
+// Graph in weighted adjacency list representation
+// edges[index] represents the adjacency list for node # index
+// each entry maps a neighbor's index to the weight of the edge to it
+#[derive(Show, Eq, PartialEq, Clone)]
+struct Graph {
+    edges: Vec<HashMap<usize, usize>>,
+    bidirectional: bool,
+}
+
+impl Graph {
+    /// Create a new Graph structure. `find_shortest_path` uses
+    /// plain Dijkstra's algorithm.
+    fn new() -> Graph {
+        Graph {
+            edges: Vec::new(),
+            bidirectional: false,
+        }
+    }
+
+    /// Create a new Graph structure whose `find_shortest_path`
+    /// searches from the source and target simultaneously, which
+    /// touches roughly half as many nodes as plain Dijkstra on
+    /// large sparse graphs.
+    fn new_bidirectional() -> Graph {
+        Graph {
+            edges: Vec::new(),
+            bidirectional: true,
+        }
+    }
+
+    /// Adds a node and returns its index
+    fn add_node(&mut self) -> usize {
+        self.edges.push(HashMap::new());
+        self.edges.len() - 1
+    }
+
+    /// Edge addition, with the given weight
+    fn add_edge(&mut self, source: usize, target: usize, weight: usize) {
+        // checks to make sure that these nodes exist
+        assert!(source < self.edges.len());
+        assert!(target < self.edges.len());
+        self.edges[source].insert(target, weight);
+    }
+
+    /// Removes the edge from source to target, if present
+    fn remove_edge(&mut self, source: usize, target: usize) {
+        self.edges[source].remove(&target);
+    }
+
+    /// Tombstones `node`: removes every edge into or out of it, so
+    /// it's no longer reachable, without shifting any other node's
+    /// index
+    fn remove_node(&mut self, node: usize) {
+        self.edges[node] = HashMap::new();
+        for edges in self.edges.iter_mut() {
+            edges.remove(&node);
+        }
+    }
+
+    /// Finds the shortest path from the source to the target node,
+    /// via plain Dijkstra's algorithm or a bidirectional search,
+    /// according to how this Graph was constructed.
+    fn find_shortest_path(&self, source: usize, target: usize) -> Option<Vec<usize>> {
+        if self.bidirectional {
+            self.find_shortest_path_bidirectional(source, target)
+        } else {
+            self.find_shortest_path_dijkstra(source, target)
+        }
+    }
+
+    /// Uses Dijkstra's algorithm to find the shortest path from the
+    /// source to the target node. Tracks only a predecessor per
+    /// node rather than cloning a growing path into every heap
+    /// entry, so the heap stays O(V) instead of O(V * path_len).
+    fn find_shortest_path_dijkstra(&self, source: usize, target: usize) -> Option<Vec<usize>> {
+        // dist[node] is the length of the shortest path from source to node
+        let mut dist: Vec<usize> = (0..self.edges.len()).map(|_| usize::MAX).collect();
+        let mut predecessor: Vec<Option<usize>> = (0..self.edges.len()).map(|_| None).collect();
+
+        // we're currently at node `source`, zero distance
+        dist[source] = 0;
+
+        // create our min-priority queue
+        let mut queue = BinaryHeap::new();
+        queue.push(State { distance: 0, position: source });
+
+        // while let: https://github.com/rust-lang/rfcs/pull/214
+        while let Some(State { distance, position }) = queue.pop() {
+            if position == target {
+                return Some(reconstruct_path(&predecessor, source, target));
+            }
+
+            // if we've already found a better way, skip and keep going
+            if distance > dist[position] { continue; }
+
+            // For each node reachable from our current position,
+            // see if there exists a shorter path through our current position
+            // than currently calculated for that node
+            for (&edge, &weight) in self.edges[position].iter() {
+                let new_dist = distance + weight;
+                if new_dist < dist[edge] {
+                    // we've found a better way
+                    dist[edge] = new_dist;
+                    predecessor[edge] = Some(position);
+                    queue.push(State { distance: new_dist, position: edge });
+                }
+            }
+        }
+
+        // no path exists from source to target
+        None
+    }
+
+    /// Bidirectional Dijkstra: alternately expands the search
+    /// frontier forward from `source` and backward from `target`
+    /// (along reversed edges), tracking the best meeting point seen
+    /// so far and stopping once neither frontier can possibly beat
+    /// it. Explores roughly half as many nodes per side as a single
+    /// one-sided search on large sparse graphs. As with
+    /// `find_shortest_path_dijkstra`, each side tracks only a
+    /// predecessor per node instead of a cloned path.
+    fn find_shortest_path_bidirectional(&self, source: usize, target: usize) -> Option<Vec<usize>> {
+        if source == target { return Some(vec![source]); }
+
+        let reverse = self.reverse_edges();
+
+        let mut dist_forward: Vec<usize> = (0..self.edges.len()).map(|_| usize::MAX).collect();
+        let mut dist_backward: Vec<usize> = (0..self.edges.len()).map(|_| usize::MAX).collect();
+        let mut pred_forward: Vec<Option<usize>> = (0..self.edges.len()).map(|_| None).collect();
+        let mut pred_backward: Vec<Option<usize>> = (0..self.edges.len()).map(|_| None).collect();
+        dist_forward[source] = 0;
+        dist_backward[target] = 0;
+
+        let mut settled_forward: HashSet<usize> = HashSet::new();
+        let mut settled_backward: HashSet<usize> = HashSet::new();
+
+        let mut queue_forward = BinaryHeap::new();
+        queue_forward.push(State { distance: 0, position: source });
+        let mut queue_backward = BinaryHeap::new();
+        queue_backward.push(State { distance: 0, position: target });
+
+        // the shortest known distance through a node reached by both
+        // searches, and which node that was
+        let mut best: Option<(usize, usize)> = None;
+
+        while !queue_forward.is_empty() || !queue_backward.is_empty() {
+            let forward_min = queue_forward.peek().map(|s| s.distance).unwrap_or(usize::MAX);
+            let backward_min = queue_backward.peek().map(|s| s.distance).unwrap_or(usize::MAX);
+            if let Some((best_dist, _)) = best {
+                let frontier_sum = if forward_min == usize::MAX || backward_min == usize::MAX {
+                    usize::MAX
+                } else {
+                    forward_min + backward_min
+                };
+                if frontier_sum >= best_dist { break; }
+            }
+
+            if let Some(State { distance, position }) = queue_forward.pop() {
+                if !settled_forward.contains(&position) {
+                    settled_forward.insert(position);
+                    if settled_backward.contains(&position) {
+                        let total = distance + dist_backward[position];
+                        best = Some(match best {
+                            Some((best_dist, best_node)) if best_dist <= total => (best_dist, best_node),
+                            _ => (total, position),
+                        });
+                    }
+                    for (&edge, &weight) in self.edges[position].iter() {
+                        let new_dist = distance + weight;
+                        if new_dist < dist_forward[edge] {
+                            dist_forward[edge] = new_dist;
+                            pred_forward[edge] = Some(position);
+                            queue_forward.push(State { distance: new_dist, position: edge });
+                        }
+                        // the backward search may have reached `edge` without settling it
+                        if dist_backward[edge] != usize::MAX {
+                            let total = dist_forward[edge] + dist_backward[edge];
+                            best = Some(match best {
+                                Some((best_dist, best_node)) if best_dist <= total => (best_dist, best_node),
+                                _ => (total, edge),
+                            });
+                        }
+                    }
+                }
+            }
+
+            if let Some(State { distance, position }) = queue_backward.pop() {
+                if !settled_backward.contains(&position) {
+                    settled_backward.insert(position);
+                    if settled_forward.contains(&position) {
+                        let total = distance + dist_forward[position];
+                        best = Some(match best {
+                            Some((best_dist, best_node)) if best_dist <= total => (best_dist, best_node),
+                            _ => (total, position),
+                        });
+                    }
+                    for (&edge, &weight) in reverse[position].iter() {
+                        let new_dist = distance + weight;
+                        if new_dist < dist_backward[edge] {
+                            dist_backward[edge] = new_dist;
+                            pred_backward[edge] = Some(position);
+                            queue_backward.push(State { distance: new_dist, position: edge });
+                        }
+                        if dist_forward[edge] != usize::MAX {
+                            let total = dist_forward[edge] + dist_backward[edge];
+                            best = Some(match best {
+                                Some((best_dist, best_node)) if best_dist <= total => (best_dist, best_node),
+                                _ => (total, edge),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        match best {
+            Some((_, node)) => {
+                let mut path = reconstruct_path(&pred_forward, source, node);
+                let mut backward_path = reconstruct_path(&pred_backward, target, node);
+                // drop the meeting node before reversing, since it's
+                // already the last element of the forward half
+                backward_path.pop();
+                backward_path.reverse();
+                path.extend(backward_path);
+                Some(path)
+            },
+            None => None,
+        }
+    }
+
+    /// The same edges with every (source, target) pair flipped,
+    /// used by the bidirectional search to walk backward from the
+    /// target.
+    fn reverse_edges(&self) -> Vec<HashMap<usize, usize>> {
+        let mut reverse: Vec<HashMap<usize, usize>> = (0..self.edges.len())
+            .map(|_| HashMap::new()).collect();
+        for (source, edges) in self.edges.iter().enumerate() {
+            for (&target, &weight) in edges.iter() {
+                reverse[target].insert(source, weight);
+            }
+        }
+        reverse
+    }
+
+    /// The length of the shortest path from `source` to every node,
+    /// via Dijkstra's algorithm. Unreachable nodes are left at
+    /// `usize::MAX`.
+    fn shortest_distances(&self, source: usize) -> Vec<usize> {
+        let mut dist: Vec<usize> = (0..self.edges.len()).map(|_| usize::MAX).collect();
+        dist[source] = 0;
+
+        let mut queue = BinaryHeap::new();
+        queue.push(State { distance: 0, position: source });
+
+        while let Some(State { distance, position }) = queue.pop() {
+            if distance > dist[position] { continue; }
+            for (&edge, &weight) in self.edges[position].iter() {
+                let new_dist = distance + weight;
+                if new_dist < dist[edge] {
+                    dist[edge] = new_dist;
+                    queue.push(State { distance: new_dist, position: edge });
+                }
+            }
+        }
+
+        dist
+    }
+
+    /// Finds every path from `source` to `target` that achieves the
+    /// minimum distance, by computing shortest distances from
+    /// `source` and then enumerating every path through the
+    /// resulting shortest-path DAG (edges (u, v) where
+    /// dist[u] + weight(u, v) == dist[v]).
+    fn find_all_shortest_paths(&self, source: usize, target: usize) -> Vec<Vec<usize>> {
+        let dist = self.shortest_distances(source);
+        if dist[target] == usize::MAX { return Vec::new(); }
+
+        let mut paths = Vec::new();
+        let mut current = vec![source];
+        self.enumerate_shortest_paths(source, target, &dist, &mut current, &mut paths);
+        paths
+    }
+
+    /// Depth-first enumeration of the shortest-path DAG described by
+    /// `dist`, appending each complete path found to `paths`.
+    fn enumerate_shortest_paths(&self, position: usize, target: usize, dist: &Vec<usize>,
+                                 current: &mut Vec<usize>, paths: &mut Vec<Vec<usize>>) {
+        if position == target {
+            paths.push(current.clone());
+            return;
+        }
+        for (&edge, &weight) in self.edges[position].iter() {
+            if dist[position] + weight == dist[edge] {
+                current.push(edge);
+                self.enumerate_shortest_paths(edge, target, dist, current, paths);
+                current.pop();
+            }
+        }
+    }
+
+    /// Like `find_shortest_path`, but also returns the path's total
+    /// weight.
+    fn find_shortest_path_with_cost(&self, source: usize, target: usize)
+            -> Option<(Vec<usize>, usize)> {
+        self.find_shortest_path(source, target).map(|path| {
+            let cost = path.iter().zip(path.iter().skip(1))
+                .fold(0, |acc, (&from, &to)| acc + *self.edges[from].get(&to).unwrap());
+            (path, cost)
+        })
+    }
+
+    /// Edmonds-Karp max flow: repeatedly augments along the shortest
+    /// (fewest-hop) path in the residual graph, treating edge weights
+    /// as capacities, until no augmenting path remains. Returns the
+    /// total flow and the min-cut edge set, i.e. every original edge
+    /// crossing from the set of nodes still reachable from `source`
+    /// in the final residual graph to the set that isn't.
+    fn max_flow(&self, source: usize, target: usize) -> (usize, Vec<(usize, usize)>) {
+        let n = self.edges.len();
+        let mut residual: Vec<HashMap<usize, usize>> = (0..n).map(|_| HashMap::new()).collect();
+        for (u, edges) in self.edges.iter().enumerate() {
+            for (&v, &capacity) in edges.iter() {
+                match residual[u].entry(v) {
+                    Vacant(e) => { e.insert(capacity); },
+                    Occupied(mut e) => { *e.get_mut() += capacity; }
+                }
+                match residual[v].entry(u) {
+                    Vacant(e) => { e.insert(0); },
+                    Occupied(..) => {},
+                }
+            }
+        }
+
+        let mut flow = 0;
+        loop {
+            match self.find_augmenting_path(&residual, source, target) {
+                Some(parent) => {
+                    let mut bottleneck = usize::MAX;
+                    let mut v = target;
+                    while v != source {
+                        let u = parent[v].unwrap();
+                        bottleneck = std::cmp::min(bottleneck, *residual[u].get(&v).unwrap());
+                        v = u;
+                    }
+                    let mut v = target;
+                    while v != source {
+                        let u = parent[v].unwrap();
+                        *residual[u].get_mut(&v).unwrap() -= bottleneck;
+                        *residual[v].get_mut(&u).unwrap() += bottleneck;
+                        v = u;
+                    }
+                    flow += bottleneck;
+                },
+                None => break,
+            }
+        }
+
+        let reachable = self.reachable_in(&residual, source);
+        let mut cut = Vec::new();
+        for (u, edges) in self.edges.iter().enumerate() {
+            if reachable.contains(&u) {
+                for &v in edges.keys() {
+                    if !reachable.contains(&v) {
+                        cut.push((u, v));
+                    }
+                }
+            }
+        }
+        (flow, cut)
+    }
+
+    /// Breadth-first search for a path from `source` to `target`
+    /// along edges with remaining residual capacity, returning the
+    /// BFS parent pointers if `target` is reachable.
+    fn find_augmenting_path(&self, residual: &Vec<HashMap<usize, usize>>, source: usize,
+                             target: usize) -> Option<Vec<Option<usize>>> {
+        let mut parent: Vec<Option<usize>> = (0..residual.len()).map(|_| None).collect();
+        let mut visited = HashSet::new();
+        visited.insert(source);
+        let mut queue = VecDeque::new();
+        queue.push_back(source);
+        while let Some(u) = queue.pop_front() {
+            for (&v, &capacity) in residual[u].iter() {
+                if capacity > 0 && !visited.contains(&v) {
+                    visited.insert(v);
+                    parent[v] = Some(u);
+                    queue.push_back(v);
+                }
+            }
+        }
+        if visited.contains(&target) { Some(parent) } else { None }
+    }
+
+    /// Every node reachable from `source` along edges with remaining
+    /// residual capacity.
+    fn reachable_in(&self, residual: &Vec<HashMap<usize, usize>>, source: usize) -> HashSet<usize> {
+        let mut visited = HashSet::new();
+        visited.insert(source);
+        let mut queue = VecDeque::new();
+        queue.push_back(source);
+        while let Some(u) = queue.pop_front() {
+            for (&v, &capacity) in residual[u].iter() {
+                if capacity > 0 && !visited.contains(&v) {
+                    visited.insert(v);
+                    queue.push_back(v);
+                }
+            }
+        }
+        visited
+    }
+}
+
+#[cfg(test)]
+mod graph_test {
+    use super::Graph;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_add_node() {
+        let mut g = Graph::new();
+        assert!(g.edges.is_empty());
+        g.add_node();
+        assert_eq!(g.edges.len(), 1);
+    }
+
+    #[test]
+    fn test_add_edge() {
+        let mut g = Graph::new();
+        g.add_node();
+        g.add_node();
+        assert!(g.edges[0].is_empty());
+        assert!(g.edges[1].is_empty());
+        g.add_edge(0, 1, 3);
+        let mut expected = HashMap::new();
+        expected.insert(1, 3);
+        assert_eq!(g.edges[0], expected);
+        assert!(g.edges[1].is_empty());
+        g.add_edge(1, 0, 5);
+        let mut expected_back = HashMap::new();
+        expected_back.insert(0, 5);
+        assert_eq!(g.edges[0], expected);
+        assert_eq!(g.edges[1], expected_back);
+    }
+
+    #[test]
+    #[should_fail]
+    fn test_add_invalid_edge() {
+        let mut g = Graph::new();
+        g.add_node();
+        g.add_node();
+        g.add_edge(1, 2, 1);
+    }
+
+    #[test]
+    fn test_remove_edge() {
+        let mut g = Graph::new();
+        g.add_node();
+        g.add_node();
+        g.add_edge(0, 1, 3);
+        g.remove_edge(0, 1);
+        assert!(g.edges[0].is_empty());
+        // removing an edge that doesn't exist is a no-op
+        g.remove_edge(0, 1);
+        assert!(g.edges[0].is_empty());
+    }
+
+    #[test]
+    fn test_remove_node() {
+        let mut g = Graph::new();
+        g.add_node();
+        g.add_node();
+        g.add_node();
+        g.add_edge(0, 1, 1);
+        g.add_edge(1, 2, 1);
+        g.add_edge(2, 0, 1);
+        g.remove_node(1);
+        assert!(g.edges[1].is_empty());
+        assert!(g.edges[0].is_empty());
+        let mut expected = HashMap::new();
+        expected.insert(0, 1);
+        assert_eq!(g.edges[2], expected);
+    }
+
+    #[test]
+    fn test_shortest_path() {
+        let mut g = Graph::new();
+        g.add_node();
+        g.add_node();
+        g.add_node();
+        g.add_node();
+        g.add_edge(0, 1, 1);
+        g.add_edge(1, 2, 1);
+        g.add_edge(0, 2, 1);
+        g.add_edge(2, 3, 1);
+        assert_eq!(g.find_shortest_path(0, 1).unwrap().len(), 2);
+        assert_eq!(g.find_shortest_path(1, 2).unwrap().len(), 2);
+        assert_eq!(g.find_shortest_path(0, 2).unwrap().len(), 2);
+        assert_eq!(g.find_shortest_path(3, 2), None);
+        assert_eq!(g.find_shortest_path(0, 3).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_shortest_path_prefers_lower_weight() {
+        let mut g = Graph::new();
+        g.add_node();
+        g.add_node();
+        g.add_node();
+        g.add_edge(0, 1, 10);
+        g.add_edge(0, 2, 1);
+        g.add_edge(2, 1, 1);
+        assert_eq!(g.find_shortest_path(0, 1).unwrap(), vec![0, 2, 1]);
+    }
+
+    #[test]
+    fn test_shortest_path_bidirectional() {
+        let mut g = Graph::new_bidirectional();
+        g.add_node();
+        g.add_node();
+        g.add_node();
+        g.add_node();
+        g.add_edge(0, 1, 1);
+        g.add_edge(1, 2, 1);
+        g.add_edge(0, 2, 1);
+        g.add_edge(2, 3, 1);
+        assert_eq!(g.find_shortest_path(0, 1).unwrap().len(), 2);
+        assert_eq!(g.find_shortest_path(1, 2).unwrap().len(), 2);
+        assert_eq!(g.find_shortest_path(0, 2).unwrap().len(), 2);
+        assert_eq!(g.find_shortest_path(3, 2), None);
+        assert_eq!(g.find_shortest_path(0, 3).unwrap().len(), 3);
+        assert_eq!(g.find_shortest_path(0, 0).unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn test_shortest_path_bidirectional_prefers_lower_weight() {
+        let mut g = Graph::new_bidirectional();
+        g.add_node();
+        g.add_node();
+        g.add_node();
+        g.add_edge(0, 1, 10);
+        g.add_edge(0, 2, 1);
+        g.add_edge(2, 1, 1);
+        assert_eq!(g.find_shortest_path(0, 1).unwrap(), vec![0, 2, 1]);
+    }
+
+    #[test]
+    fn test_find_all_shortest_paths() {
+        let mut g = Graph::new();
+        g.add_node();
+        g.add_node();
+        g.add_node();
+        g.add_node();
+        g.add_edge(0, 1, 1);
+        g.add_edge(0, 2, 1);
+        g.add_edge(1, 3, 1);
+        g.add_edge(2, 3, 1);
+        let mut paths = g.find_all_shortest_paths(0, 3);
+        paths.sort();
+        assert_eq!(paths, vec![vec![0, 1, 3], vec![0, 2, 3]]);
+    }
+
+    #[test]
+    fn test_find_all_shortest_paths_no_path() {
+        let mut g = Graph::new();
+        g.add_node();
+        g.add_node();
+        assert_eq!(g.find_all_shortest_paths(0, 1), Vec::<Vec<usize>>::new());
+    }
+
+    #[test]
+    fn test_find_shortest_path_with_cost() {
+        let mut g = Graph::new();
+        g.add_node();
+        g.add_node();
+        g.add_node();
+        g.add_edge(0, 1, 10);
+        g.add_edge(0, 2, 1);
+        g.add_edge(2, 1, 1);
+        assert_eq!(g.find_shortest_path_with_cost(0, 1).unwrap(), (vec![0, 2, 1], 2));
+    }
+
+    #[test]
+    fn test_find_shortest_path_with_cost_no_path() {
+        let mut g = Graph::new();
+        g.add_node();
+        g.add_node();
+        assert_eq!(g.find_shortest_path_with_cost(0, 1), None);
+    }
+
+    #[test]
+    fn test_max_flow() {
+        let mut g = Graph::new();
+        g.add_node();
+        g.add_node();
+        g.add_node();
+        g.add_node();
+        g.add_edge(0, 1, 3);
+        g.add_edge(0, 2, 2);
+        g.add_edge(1, 3, 2);
+        g.add_edge(2, 3, 3);
+        let (flow, mut cut) = g.max_flow(0, 3);
+        cut.sort();
+        assert_eq!(flow, 4);
+        assert_eq!(cut, vec![(0, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn test_max_flow_no_path() {
+        let mut g = Graph::new();
+        g.add_node();
+        g.add_node();
+        assert_eq!(g.max_flow(0, 1), (0, Vec::new()));
+    }
+}
+
+/// LabeledGraph is a wrapper around Graph that supports nodes
+/// labeled by any `L`, so long as labels can be cloned, compared,
+/// and hashed (as required to key a HashMap).
+#[derive(Show, Eq, PartialEq, Clone)]
+pub struct LabeledGraph<L> where L: Clone + Eq + Hash {
+    labels: HashMap<L, usize>,
+    indices: Vec<L>,
+    graph: Graph,
+}
+
+impl<L: Clone + Eq + Hash> LabeledGraph<L> {
+    /// Create a new LabeledGraph
+    pub fn new() -> Self {
+        LabeledGraph {
+            labels: HashMap::new(),
+            indices: Vec::new(),
+            graph: Graph::new(),
+        }
+    }
+
+    /// Like `new`, but `find_shortest_path` uses a bidirectional
+    /// search instead of plain Dijkstra's algorithm.
+    pub fn new_bidirectional() -> Self {
+        LabeledGraph {
+            labels: HashMap::new(),
+            indices: Vec::new(),
+            graph: Graph::new_bidirectional(),
+        }
+    }
+
+    /// Add a node to the graph if it doesn't already exist
+    fn add_node_if_not_exists(&mut self, label: &L) {
+        if self.labels.contains_key(label) { return; }
+        let index = self.graph.add_node();
+        self.labels.insert(label.clone(), index);
+        self.indices.push(label.clone());
+    }
+
+    /// Adds an isolated node with no edges, if it doesn't already
+    /// exist. A no-op if `label` is already in the graph.
+    pub fn add_node(&mut self, label: &L) {
+        self.add_node_if_not_exists(label);
+    }
+
+    /// Every label currently present in the graph, in no particular
+    /// order.
+    pub fn labels(&self) -> Vec<L> {
+        self.labels.keys().map(|l| l.clone()).collect()
+    }
+
+    /// The (target, weight) pairs for every edge leading out of
+    /// `source`, or an empty Vec if `source` isn't in the graph.
+    pub fn edges_from(&self, source: &L) -> Vec<(L, usize)> {
+        match self.labels.get(source) {
+            Some(&idx) => self.graph.edges[idx].iter()
+                .map(|(&target, &weight)| (self.indices[target].clone(), weight))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Adds an edge from source label to target label, with the
+    /// given weight. With `directed` false, the reverse edge is
+    /// added as well, so the two nodes are mutually reachable.
+    /// Adds the associated nodes if they do not already exist
+    pub fn add_edge(&mut self, source: &L, target: &L, weight: usize, directed: bool) {
+        self.add_node_if_not_exists(source);
+        self.add_node_if_not_exists(target);
+        let source_idx = *self.labels.get(source).unwrap();
+        let target_idx = *self.labels.get(target).unwrap();
+        self.graph.add_edge(source_idx, target_idx, weight);
+        if !directed {
+            self.graph.add_edge(target_idx, source_idx, weight);
+        }
+    }
+
+    /// Removes the edge from source label to target label, if both
+    /// nodes and the edge between them exist. A no-op otherwise.
+    pub fn remove_edge(&mut self, source: &L, target: &L) {
+        if !self.labels.contains_key(source) || !self.labels.contains_key(target) {
+            return;
+        }
+        let source_idx = *self.labels.get(source).unwrap();
+        let target_idx = *self.labels.get(target).unwrap();
+        self.graph.remove_edge(source_idx, target_idx);
+    }
+
+    /// Removes `label` from the graph, along with every edge
+    /// touching it. The node's index is tombstoned rather than
+    /// compacted, so other labels' indices are unaffected; a no-op
+    /// if `label` doesn't exist.
+    pub fn remove_node(&mut self, label: &L) {
+        if !self.labels.contains_key(label) { return; }
+        let idx = *self.labels.get(label).unwrap();
+        self.graph.remove_node(idx);
+        self.labels.remove(label);
+    }
+
+    /// Finds the shortest path in a LabeledGraph
+    pub fn find_shortest_path(&self, source: &L, target: &L) -> Option<Vec<L>> {
+        if !self.labels.contains_key(source) || !self.labels.contains_key(target) {
+            return None;
+        }
+        let source_idx = *self.labels.get(source).unwrap();
+        let target_idx = *self.labels.get(target).unwrap();
+        match self.graph.find_shortest_path(source_idx, target_idx) {
+            Some(result) => {
+                Some(result.iter().map(|&n| self.indices[n].clone()).collect())
+            },
+            None => None
+        }
+    }
+
+    /// Like `find_shortest_path`, but also returns the path's total
+    /// weight.
+    pub fn find_shortest_path_with_cost(&self, source: &L, target: &L) -> Option<(Vec<L>, usize)> {
+        if !self.labels.contains_key(source) || !self.labels.contains_key(target) {
+            return None;
+        }
+        let source_idx = *self.labels.get(source).unwrap();
+        let target_idx = *self.labels.get(target).unwrap();
+        match self.graph.find_shortest_path_with_cost(source_idx, target_idx) {
+            Some((result, cost)) => {
+                Some((result.iter().map(|&n| self.indices[n].clone()).collect(), cost))
+            },
+            None => None
+        }
+    }
+
+    /// Finds every shortest path between two labels in a
+    /// LabeledGraph, rather than just the first one found.
+    pub fn find_all_shortest_paths(&self, source: &L, target: &L) -> Vec<Vec<L>> {
+        if !self.labels.contains_key(source) || !self.labels.contains_key(target) {
+            return Vec::new();
+        }
+        let source_idx = *self.labels.get(source).unwrap();
+        let target_idx = *self.labels.get(target).unwrap();
+        self.graph.find_all_shortest_paths(source_idx, target_idx).iter().map(|path| {
+            path.iter().map(|&n| self.indices[n].clone()).collect()
+        }).collect()
+    }
+
+    /// Finds up to `k` distinct routes from `source` to `target`,
+    /// cheapest first. After each route is found, its edges are
+    /// removed from a scratch copy of the graph, forcing the next
+    /// search down a different path; stops early if fewer than `k`
+    /// routes exist.
+    pub fn find_alternative_paths_with_cost(&self, source: &L, target: &L, k: usize)
+        -> Vec<(Vec<L>, usize)> {
+        let mut working = self.clone();
+        let mut results = Vec::new();
+        for _ in range(0, k) {
+            match working.find_shortest_path_with_cost(source, target) {
+                Some((path, cost)) => {
+                    for i in range(0, path.len() - 1) {
+                        working.remove_edge(&path[i], &path[i + 1]);
+                        working.remove_edge(&path[i + 1], &path[i]);
+                    }
+                    results.push((path, cost));
+                },
+                None => break,
+            }
+        }
+        results
+    }
+
+    /// Computes the maximum flow from `source` to `target`, treating
+    /// edge weights as capacities, via the Edmonds-Karp algorithm.
+    /// Returns the flow value along with the min-cut edge set.
+    pub fn max_flow(&self, source: &L, target: &L) -> Option<(usize, Vec<(L, L)>)> {
+        if !self.labels.contains_key(source) || !self.labels.contains_key(target) {
+            return None;
+        }
+        let source_idx = *self.labels.get(source).unwrap();
+        let target_idx = *self.labels.get(target).unwrap();
+        let (flow, cut) = self.graph.max_flow(source_idx, target_idx);
+        Some((flow, cut.iter().map(|&(u, v)| {
+            (self.indices[u].clone(), self.indices[v].clone())
+        }).collect()))
+    }
+
+    /// Visits nodes reachable from `start` in breadth-first order.
+    /// If `start` isn't in the graph, the iterator yields nothing.
+    pub fn bfs_from<'a>(&'a self, start: &L) -> BfsIter<'a, L> {
+        let mut queue = VecDeque::new();
+        let mut visited = HashSet::new();
+        if let Some(&idx) = self.labels.get(start) {
+            queue.push_back(idx);
+            visited.insert(idx);
+        }
+        BfsIter { graph: self, visited: visited, queue: queue }
+    }
+
+    /// Visits nodes reachable from `start` in depth-first order.
+    /// If `start` isn't in the graph, the iterator yields nothing.
+    pub fn dfs_from<'a>(&'a self, start: &L) -> DfsIter<'a, L> {
+        let mut stack = Vec::new();
+        let mut visited = HashSet::new();
+        if let Some(&idx) = self.labels.get(start) {
+            stack.push(idx);
+            visited.insert(idx);
+        }
+        DfsIter { graph: self, visited: visited, stack: stack }
+    }
+}
+
+impl LabeledGraph<String> {
+    /// Writes the graph to `path` in a compact binary format: the
+    /// bidirectional flag, then every node's label (tombstoned
+    /// labels included, so edge indices stay stable across a
+    /// save/load round trip) with a flag marking whether it's still
+    /// live, then every edge as a (source, target, weight) triple.
+    /// Lets a caller skip reparsing a large adjacency file on every
+    /// run by caching the built graph via `--save-cache` and
+    /// picking it back up via `--load-cache`.
+    pub fn serialize(&self, path: &str) -> IoResult<()> {
+        let mut file = try!(File::create(&Path::new(path)));
+        try!(file.write_u8(if self.graph.bidirectional { 1 } else { 0 }));
+        try!(file.write_le_uint(self.indices.len()));
+        for label in self.indices.iter() {
+            let bytes = label.as_bytes();
+            try!(file.write_le_uint(bytes.len()));
+            try!(file.write(bytes));
+            try!(file.write_u8(if self.labels.contains_key(label) { 1 } else { 0 }));
+        }
+        let edge_count = self.graph.edges.iter().map(|e| e.len()).fold(0, |a, b| a + b);
+        try!(file.write_le_uint(edge_count));
+        for (source, edges) in self.graph.edges.iter().enumerate() {
+            for (&target, &weight) in edges.iter() {
+                try!(file.write_le_uint(source));
+                try!(file.write_le_uint(target));
+                try!(file.write_le_uint(weight));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a graph previously written by `serialize`.
+    pub fn deserialize(path: &str) -> IoResult<LabeledGraph<String>> {
+        let mut file = try!(File::open(&Path::new(path)));
+        let bidirectional = try!(file.read_u8()) == 1;
+        let mut graph = if bidirectional { Graph::new_bidirectional() } else { Graph::new() };
+        let node_count = try!(file.read_le_uint());
+        let mut indices = Vec::with_capacity(node_count);
+        let mut labels = HashMap::new();
+        for _ in range(0, node_count) {
+            let len = try!(file.read_le_uint());
+            let bytes = try!(file.read_exact(len));
+            let label = String::from_utf8(bytes).ok()
+                .expect("corrupt cache file: label is not valid utf8");
+            let live = try!(file.read_u8()) == 1;
+            let index = graph.add_node();
+            if live { labels.insert(label.clone(), index); }
+            indices.push(label);
+        }
+        let edge_count = try!(file.read_le_uint());
+        for _ in range(0, edge_count) {
+            let source = try!(file.read_le_uint());
+            let target = try!(file.read_le_uint());
+            let weight = try!(file.read_le_uint());
+            graph.add_edge(source, target, weight);
+        }
+        Ok(LabeledGraph { labels: labels, indices: indices, graph: graph })
+    }
+}
+
+/// Iterator returned by `LabeledGraph::bfs_from`.
+pub struct BfsIter<'a, L: 'a + Clone + Eq + Hash> {
+    graph: &'a LabeledGraph<L>,
+    visited: HashSet<usize>,
+    queue: VecDeque<usize>,
+}
+
+impl<'a, L: Clone + Eq + Hash> Iterator for BfsIter<'a, L> {
+    type Item = L;
+
+    fn next(&mut self) -> Option<L> {
+        match self.queue.pop_front() {
+            Some(node) => {
+                for (&neighbor, _) in self.graph.graph.edges[node].iter() {
+                    if !self.visited.contains(&neighbor) {
+                        self.visited.insert(neighbor);
+                        self.queue.push_back(neighbor);
+                    }
+                }
+                Some(self.graph.indices[node].clone())
+            },
+            None => None,
+        }
+    }
+}
+
+/// Iterator returned by `LabeledGraph::dfs_from`.
+pub struct DfsIter<'a, L: 'a + Clone + Eq + Hash> {
+    graph: &'a LabeledGraph<L>,
+    visited: HashSet<usize>,
+    stack: Vec<usize>,
+}
+
+impl<'a, L: Clone + Eq + Hash> Iterator for DfsIter<'a, L> {
+    type Item = L;
+
+    fn next(&mut self) -> Option<L> {
+        match self.stack.pop() {
+            Some(node) => {
+                for (&neighbor, _) in self.graph.graph.edges[node].iter() {
+                    if !self.visited.contains(&neighbor) {
+                        self.visited.insert(neighbor);
+                        self.stack.push(neighbor);
+                    }
+                }
+                Some(self.graph.indices[node].clone())
+            },
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod labeled_graph_test {
+    use super::{Graph, LabeledGraph};
+    use std::io::TempDir;
+
+    #[test]
+    fn test_add_edge() {
+        let mut lg: LabeledGraph<String> = LabeledGraph::new();
+        let mut g = Graph::new();
+        assert!(lg.labels.is_empty());
+        assert!(lg.indices.is_empty());
+        assert_eq!(lg.graph, g);
+        lg.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        assert_eq!(*lg.labels.get(&"a".to_string()).unwrap(), 0);
+        assert_eq!(*lg.labels.get(&"b".to_string()).unwrap(), 1);
+        assert_eq!(lg.indices, vec!["a".to_string(), "b".to_string()]);
+        g.add_node();
+        g.add_node();
+        g.add_edge(0, 1, 1);
+        assert_eq!(lg.graph, g);
+        lg.add_edge(&"c".to_string(), &"b".to_string(), 1, true);
+        assert_eq!(*lg.labels.get(&"a".to_string()).unwrap(), 0);
+        assert_eq!(*lg.labels.get(&"b".to_string()).unwrap(), 1);
+        assert_eq!(*lg.labels.get(&"c".to_string()).unwrap(), 2);
+        assert_eq!(lg.indices, vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+        g.add_node();
+        g.add_edge(2, 1, 1);
+        assert_eq!(lg.graph, g);
+    }
+
+    #[test]
+    fn test_add_undirected_edge() {
+        let mut lg: LabeledGraph<String> = LabeledGraph::new();
+        let mut g = Graph::new();
+        lg.add_edge(&"a".to_string(), &"b".to_string(), 1, false);
+        g.add_node();
+        g.add_node();
+        g.add_edge(0, 1, 1);
+        g.add_edge(1, 0, 1);
+        assert_eq!(lg.graph, g);
+    }
+
+    #[test]
+    fn test_remove_edge() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.remove_edge(&"a".to_string(), &"b".to_string());
+        assert_eq!(g.find_shortest_path(&"a".to_string(), &"b".to_string()), None);
+        // removing an edge that doesn't exist, or between unknown
+        // labels, is a no-op
+        g.remove_edge(&"a".to_string(), &"b".to_string());
+        g.remove_edge(&"a".to_string(), &"nonexistent".to_string());
+    }
+
+    #[test]
+    fn test_remove_node() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.add_edge(&"b".to_string(), &"c".to_string(), 1, true);
+        g.remove_node(&"b".to_string());
+        assert_eq!(g.find_shortest_path(&"a".to_string(), &"c".to_string()), None);
+        assert!(g.bfs_from(&"b".to_string()).collect::<Vec<String>>().is_empty());
+        // removing a node that doesn't exist is a no-op
+        g.remove_node(&"nonexistent".to_string());
+    }
+
+    #[test]
+    fn test_shortest_path() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.add_edge(&"b".to_string(), &"c".to_string(), 1, true);
+        g.add_edge(&"c".to_string(), &"d".to_string(), 1, true);
+        assert_eq!(g.find_shortest_path(&"a".to_string(), &"b".to_string()).unwrap().len(), 2);
+        assert_eq!(g.find_shortest_path(&"b".to_string(), &"c".to_string()).unwrap().len(), 2);
+        assert_eq!(g.find_shortest_path(&"a".to_string(), &"c".to_string()).unwrap().len(), 3);
+        assert_eq!(g.find_shortest_path(&"c".to_string(), &"a".to_string()), None);
+        assert_eq!(g.find_shortest_path(&"d".to_string(), &"a".to_string()), None);
+        assert_eq!(g.find_shortest_path(&"a".to_string(), &"d".to_string()).unwrap(),
+                   vec!["a".to_string(), "b".to_string(), "c".to_string(), "d".to_string()]);
+    }
+
+    #[test]
+    fn test_shortest_path_undirected() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, false);
+        assert_eq!(g.find_shortest_path(&"b".to_string(), &"a".to_string()).unwrap(),
+                   vec!["b".to_string(), "a".to_string()]);
+    }
+
+    #[test]
+    fn test_shortest_path_prefers_lower_weight() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 10, true);
+        g.add_edge(&"a".to_string(), &"c".to_string(), 1, true);
+        g.add_edge(&"c".to_string(), &"b".to_string(), 1, true);
+        assert_eq!(g.find_shortest_path(&"a".to_string(), &"b".to_string()).unwrap(),
+                   vec!["a".to_string(), "c".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn test_shortest_path_bidirectional() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new_bidirectional();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.add_edge(&"b".to_string(), &"c".to_string(), 1, true);
+        g.add_edge(&"c".to_string(), &"d".to_string(), 1, true);
+        assert_eq!(g.find_shortest_path(&"a".to_string(), &"c".to_string()).unwrap().len(), 3);
+        assert_eq!(g.find_shortest_path(&"d".to_string(), &"a".to_string()), None);
+        assert_eq!(g.find_shortest_path(&"a".to_string(), &"d".to_string()).unwrap(),
+                   vec!["a".to_string(), "b".to_string(), "c".to_string(), "d".to_string()]);
+    }
+
+    #[test]
+    fn test_find_all_shortest_paths() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.add_edge(&"a".to_string(), &"c".to_string(), 1, true);
+        g.add_edge(&"b".to_string(), &"d".to_string(), 1, true);
+        g.add_edge(&"c".to_string(), &"d".to_string(), 1, true);
+        let mut paths = g.find_all_shortest_paths(&"a".to_string(), &"d".to_string());
+        paths.sort();
+        assert_eq!(paths, vec![vec!["a".to_string(), "b".to_string(), "d".to_string()],
+                                vec!["a".to_string(), "c".to_string(), "d".to_string()]]);
+    }
+
+    #[test]
+    fn test_find_all_shortest_paths_no_path() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        assert!(g.find_all_shortest_paths(&"b".to_string(), &"a".to_string()).is_empty());
+        assert!(g.find_all_shortest_paths(&"a".to_string(), &"nonexistent".to_string()).is_empty());
+    }
+
+    #[test]
+    fn test_bfs_from() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.add_edge(&"a".to_string(), &"c".to_string(), 1, true);
+        g.add_edge(&"b".to_string(), &"d".to_string(), 1, true);
+        let mut visited: Vec<String> = g.bfs_from(&"a".to_string()).collect();
+        visited.sort();
+        assert_eq!(visited, vec!["a".to_string(), "b".to_string(), "c".to_string(), "d".to_string()]);
+    }
+
+    #[test]
+    fn test_bfs_from_missing_node() {
+        let g: LabeledGraph<String> = LabeledGraph::new();
+        assert_eq!(g.bfs_from(&"nonexistent".to_string()).collect::<Vec<String>>(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_dfs_from() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.add_edge(&"a".to_string(), &"c".to_string(), 1, true);
+        g.add_edge(&"b".to_string(), &"d".to_string(), 1, true);
+        let mut visited: Vec<String> = g.dfs_from(&"a".to_string()).collect();
+        visited.sort();
+        assert_eq!(visited, vec!["a".to_string(), "b".to_string(), "c".to_string(), "d".to_string()]);
+    }
+
+    #[test]
+    fn test_dfs_from_missing_node() {
+        let g: LabeledGraph<String> = LabeledGraph::new();
+        assert_eq!(g.dfs_from(&"nonexistent".to_string()).collect::<Vec<String>>(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_find_shortest_path_with_cost() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 10, true);
+        g.add_edge(&"a".to_string(), &"c".to_string(), 1, true);
+        g.add_edge(&"c".to_string(), &"b".to_string(), 1, true);
+        assert_eq!(g.find_shortest_path_with_cost(&"a".to_string(), &"b".to_string()).unwrap(),
+                   (vec!["a".to_string(), "c".to_string(), "b".to_string()], 2));
+    }
+
+    #[test]
+    fn test_find_shortest_path_with_cost_no_path() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        assert_eq!(g.find_shortest_path_with_cost(&"b".to_string(), &"a".to_string()), None);
+        assert_eq!(g.find_shortest_path_with_cost(&"a".to_string(), &"nonexistent".to_string()), None);
+    }
+
+    #[test]
+    fn test_find_alternative_paths_with_cost() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        g.add_edge(&"a".to_string(), &"c".to_string(), 2, true);
+        g.add_edge(&"c".to_string(), &"b".to_string(), 2, true);
+        let paths = g.find_alternative_paths_with_cost(&"a".to_string(), &"b".to_string(), 2);
+        assert_eq!(paths, vec![(vec!["a".to_string(), "b".to_string()], 1),
+                               (vec!["a".to_string(), "c".to_string(), "b".to_string()], 4)]);
+    }
+
+    #[test]
+    fn test_find_alternative_paths_with_cost_fewer_than_k() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        let paths = g.find_alternative_paths_with_cost(&"a".to_string(), &"b".to_string(), 3);
+        assert_eq!(paths, vec![(vec!["a".to_string(), "b".to_string()], 1)]);
+    }
+
+    #[test]
+    fn test_find_alternative_paths_with_cost_no_path() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        assert!(g.find_alternative_paths_with_cost(&"b".to_string(), &"a".to_string(), 2).is_empty());
+    }
+
+    #[test]
+    fn test_max_flow() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 3, true);
+        g.add_edge(&"a".to_string(), &"c".to_string(), 2, true);
+        g.add_edge(&"b".to_string(), &"d".to_string(), 2, true);
+        g.add_edge(&"c".to_string(), &"d".to_string(), 3, true);
+        let (flow, mut cut) = g.max_flow(&"a".to_string(), &"d".to_string()).unwrap();
+        cut.sort();
+        assert_eq!(flow, 4);
+        assert_eq!(cut, vec![("a".to_string(), "c".to_string()), ("b".to_string(), "d".to_string())]);
+    }
+
+    #[test]
+    fn test_max_flow_missing_label() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, true);
+        assert_eq!(g.max_flow(&"a".to_string(), &"nonexistent".to_string()), None);
+    }
+
+    #[test]
+    fn test_add_node() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_node(&"a".to_string());
+        let mut labels = g.labels();
+        labels.sort();
+        assert_eq!(labels, vec!["a".to_string()]);
+        // adding a node that already exists is a no-op
+        g.add_node(&"a".to_string());
+        let mut labels = g.labels();
+        labels.sort();
+        assert_eq!(labels, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_edges_from() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 3, true);
+        g.add_edge(&"a".to_string(), &"c".to_string(), 1, true);
+        let mut edges = g.edges_from(&"a".to_string());
+        edges.sort();
+        assert_eq!(edges, vec![("b".to_string(), 3), ("c".to_string(), 1)]);
+        assert_eq!(g.edges_from(&"nonexistent".to_string()), Vec::new());
+    }
+
+    #[test]
+    fn test_integer_labels() {
+        // LabeledGraph is generic over any L: Clone + Eq + Hash, not
+        // just String or a custom struct like t_query's Node.
+        let mut g: LabeledGraph<usize> = LabeledGraph::new();
+        g.add_edge(&1, &2, 1, true);
+        g.add_edge(&2, &3, 1, true);
+        assert_eq!(g.find_shortest_path(&1, &3).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 3, true);
+        g.add_edge(&"a".to_string(), &"c".to_string(), 1, true);
+        g.remove_node(&"c".to_string());
+        g.add_node(&"d".to_string());
+
+        let dir = TempDir::new("graph_lib_serialize_test").unwrap();
+        let path = dir.path().join("graph.bin");
+        let path_str = path.as_str().unwrap();
+
+        g.serialize(path_str).unwrap();
+        let loaded = LabeledGraph::deserialize(path_str).unwrap();
+        assert_eq!(loaded, g);
+    }
+
+    #[test]
+    fn test_serialize_round_trip_bidirectional() {
+        let mut g: LabeledGraph<String> = LabeledGraph::new_bidirectional();
+        g.add_edge(&"a".to_string(), &"b".to_string(), 1, false);
+
+        let dir = TempDir::new("graph_lib_serialize_bidirectional_test").unwrap();
+        let path = dir.path().join("graph.bin");
+        let path_str = path.as_str().unwrap();
+
+        g.serialize(path_str).unwrap();
+        let loaded = LabeledGraph::deserialize(path_str).unwrap();
+        assert_eq!(loaded, g);
+    }
+}
+
+#[cfg(test)]
+mod graph_bench {
+    extern crate test;
+
+    use self::test::Bencher;
+    use super::LabeledGraph;
+
+    // Deterministic ~10,000 node, ~100,000 edge graph: each node
+    // connects forward to ten others via modular-arithmetic offsets,
+    // so the graph is large and dense enough to show the cost of
+    // cloning a path into every heap entry, without pulling in a
+    // random number generator.
+    fn build_bench_graph() -> LabeledGraph<usize> {
+        let nodes = 10000us;
+        let mut g: LabeledGraph<usize> = LabeledGraph::new();
+        for i in 0..nodes {
+            for k in 1..11us {
+                let target = (i + k * 37) % nodes;
+                if target != i {
+                    g.add_edge(&i, &target, k, true);
+                }
+            }
+        }
+        g
+    }
+
+    #[bench]
+    fn bench_find_shortest_path(b: &mut Bencher) {
+        let g = build_bench_graph();
+        b.iter(|| {
+            g.find_shortest_path(&0us, &9999us)
+        });
+    }
+}